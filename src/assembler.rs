@@ -0,0 +1,659 @@
+// A two-pass assembler for Intel's 8080 syntax. The encodings come from the disassembler's opcode
+// table, so whatever the disassembler prints assembles back to the same bytes.
+//
+//     BDOS    EQU   5
+//             ORG   100H
+//     START:  LXI   D,MSG
+//             MVI   C,9
+//             CALL  BDOS
+//             RET
+//     MSG:    DB    'HELLO$'
+//             END   START
+//
+// Labels are case-insensitive, start in the first column or end with a colon. Numbers are
+// decimal, or hex/octal/binary with an H, O/Q or B suffix, and 'c' is a character. Expressions
+// use + - * / MOD SHL SHR AND OR XOR NOT, parentheses, HIGH and LOW, and `$` for the address of
+// the current line.
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use crate::disassembler::OPCODES;
+use crate::loader::{Image, Segment};
+
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssembleError {}
+
+pub struct Assembly {
+    pub image: Image,
+    pub symbols: BTreeMap<String, u16>,
+}
+
+pub fn assemble(source: &str) -> Result<Assembly, AssembleError> {
+    let mut assembler = Assembler::new();
+
+    assembler.pass(source, false)?;
+    assembler.pass(source, true)?;
+
+    Ok(Assembly {
+        image: assembler.image,
+        symbols: assembler.symbols,
+    })
+}
+
+// (opcode, operand templates) for every documented encoding of a mnemonic
+type Encodings = HashMap<&'static str, Vec<(u8, Vec<&'static str>)>>;
+
+fn encodings() -> Encodings {
+    let mut encodings: Encodings = HashMap::new();
+
+    for (opcode, template) in OPCODES.iter().enumerate() {
+        if template.starts_with('*') {
+            continue;
+        }
+
+        let mut parts = template.splitn(2, ' ');
+        let mnemonic = parts.next().unwrap();
+        let operands = match parts.next() {
+            Some(operands) => operands.split(',').collect(),
+            None => Vec::new(),
+        };
+
+        encodings
+            .entry(mnemonic)
+            .or_default()
+            .push((opcode as u8, operands));
+    }
+
+    encodings
+}
+
+struct Assembler {
+    encodings: Encodings,
+    symbols: BTreeMap<String, u16>,
+    image: Image,
+    address: u16,
+    // where the current statement starts, `$` stays put while a DB or DW emits its operands
+    statement_address: u16,
+    // the second pass, where every symbol must be known and bytes are emitted
+    emitting: bool,
+    line: usize,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Assembler {
+            encodings: encodings(),
+            symbols: BTreeMap::new(),
+            image: Image::default(),
+            address: 0,
+            statement_address: 0,
+            emitting: false,
+            line: 0,
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, AssembleError> {
+        Err(AssembleError {
+            line: self.line,
+            message,
+        })
+    }
+
+    fn pass(&mut self, source: &str, emitting: bool) -> Result<(), AssembleError> {
+        self.emitting = emitting;
+        self.address = 0;
+
+        for (number, text) in source.lines().enumerate() {
+            self.line = number + 1;
+            self.statement_address = self.address;
+
+            let statement = parse_line(text).or_else(|message| self.error(message))?;
+            let label = statement.label.map(|label| label.to_uppercase());
+            let operation = statement.operation.map(|op| op.to_uppercase());
+
+            if operation.as_deref() == Some("EQU") {
+                let label = match label {
+                    Some(label) => label,
+                    None => return self.error("EQU without a name".to_string()),
+                };
+                // must be known on the first pass, like ORG and DS
+                let value = self.expression(statement.operands.trim(), true)?;
+                self.define(label, value)?;
+                continue;
+            }
+
+            if let Some(label) = label {
+                self.define(label, self.address)?;
+            }
+
+            if let Some(operation) = operation {
+                if operation == "END" {
+                    if self.emitting && !statement.operands.trim().is_empty() {
+                        self.image.start = Some(self.expression(statement.operands.trim(), false)?);
+                    }
+                    break;
+                }
+                self.statement(&operation, statement.operands)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn define(&mut self, name: String, value: u16) -> Result<(), AssembleError> {
+        if is_reserved(&name) {
+            return self.error(format!("{} is a reserved word", name));
+        }
+
+        match self.symbols.get(&name) {
+            // labels move between the passes only if something is wrong
+            Some(&old) if self.emitting && old != value => {
+                self.error(format!("{} changed value between passes", name))
+            }
+            Some(_) if !self.emitting => self.error(format!("{} is defined twice", name)),
+            _ => {
+                self.symbols.insert(name, value);
+                Ok(())
+            }
+        }
+    }
+
+    fn statement(&mut self, operation: &str, operands: &str) -> Result<(), AssembleError> {
+        let operands = split_operands(operands).or_else(|message| self.error(message))?;
+
+        match operation {
+            "ORG" => {
+                let operand = self.single(&operands)?;
+                self.address = self.expression(operand, true)?;
+            }
+            "DS" => {
+                let operand = self.single(&operands)?;
+                let size = self.expression(operand, true)?;
+                self.advance(size as usize)?;
+            }
+            "DB" => {
+                for operand in operands {
+                    match string_literal(operand) {
+                        Some(string) if string.len() != 1 => self.emit(&string)?,
+                        _ => {
+                            let value = self.expression(operand, false)?;
+                            let byte = self.byte(value)?;
+                            self.emit(&[byte])?;
+                        }
+                    }
+                }
+            }
+            "DW" => {
+                for operand in operands {
+                    let value = self.expression(operand, false)?;
+                    self.emit(&value.to_le_bytes())?;
+                }
+            }
+            _ => self.instruction(operation, &operands)?,
+        }
+
+        Ok(())
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operands: &[&str]) -> Result<(), AssembleError> {
+        let candidates = match self.encodings.get(mnemonic) {
+            Some(candidates) => candidates.clone(),
+            None => return self.error(format!("unknown instruction {}", mnemonic)),
+        };
+
+        if mnemonic == "RST" {
+            let operand = self.single(operands)?;
+            let n = self.expression(operand, false)?.to_string();
+            return match candidates.iter().find(|(_, templates)| templates[0] == n) {
+                Some((opcode, _)) => self.emit(&[*opcode]),
+                None => self.error(format!("RST {} doesn't exist", n)),
+            };
+        }
+
+        // the encoding whose register operands match, the others are expressions
+        let encoding = candidates.iter().find(|(_, templates)| {
+            templates.len() == operands.len()
+                && templates.iter().zip(operands).all(|(template, operand)| {
+                    is_placeholder(template) || template.eq_ignore_ascii_case(operand)
+                })
+        });
+        let (opcode, templates) = match encoding {
+            Some(encoding) => encoding,
+            None => {
+                return self.error(format!(
+                    "bad operands for {}: {}",
+                    mnemonic,
+                    operands.join(",")
+                ))
+            }
+        };
+
+        let mut bytes = vec![*opcode];
+        for (template, operand) in templates.iter().zip(operands) {
+            match *template {
+                "d8" => {
+                    let value = self.expression(operand, false)?;
+                    bytes.push(self.byte(value)?);
+                }
+                "d16" | "a16" | "j16" => {
+                    let value = self.expression(operand, false)?;
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                _ => (),
+            }
+        }
+
+        self.emit(&bytes)
+    }
+
+    fn single<'a>(&self, operands: &[&'a str]) -> Result<&'a str, AssembleError> {
+        match operands {
+            [operand] => Ok(operand),
+            _ => self.error("expected a single operand".to_string()),
+        }
+    }
+
+    // values from -128 to 255 fit in a byte
+    fn byte(&self, value: u16) -> Result<u8, AssembleError> {
+        if value <= 0xFF || value >= 0xFF80 {
+            Ok(value as u8)
+        } else {
+            self.error(format!("{:04X}H doesn't fit in a byte", value))
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), AssembleError> {
+        if self.emitting {
+            match self.image.segments.last_mut() {
+                Some(last) if last.address as usize + last.data.len() == self.address as usize => {
+                    last.data.extend_from_slice(bytes)
+                }
+                _ => self.image.segments.push(Segment {
+                    address: self.address,
+                    data: bytes.to_vec(),
+                }),
+            }
+        }
+
+        self.advance(bytes.len())
+    }
+
+    fn advance(&mut self, size: usize) -> Result<(), AssembleError> {
+        let end = self.address as usize + size;
+        if end > 0x10000 {
+            return self.error("past the end of memory".to_string());
+        }
+
+        self.address = end as u16;
+        Ok(())
+    }
+
+    // `required` is for operands the first pass needs (ORG, DS, EQU), everything else may refer to
+    // labels further down
+    fn expression(&self, text: &str, required: bool) -> Result<u16, AssembleError> {
+        let tokens = tokenize(text).or_else(|message| self.error(message))?;
+        if tokens.is_empty() {
+            return self.error("missing operand".to_string());
+        }
+
+        let mut parser = ExpressionParser {
+            tokens: &tokens,
+            position: 0,
+            assembler: self,
+            strict: required || self.emitting,
+        };
+
+        let value = parser.or().or_else(|message| self.error(message))?;
+        if parser.position != tokens.len() {
+            return self.error(format!("unexpected text in expression {}", text));
+        }
+        Ok(value as u16)
+    }
+}
+
+pub(crate) struct Statement<'a> {
+    pub label: Option<&'a str>,
+    pub operation: Option<&'a str>,
+    pub operands: &'a str,
+}
+
+pub(crate) fn parse_line(text: &str) -> Result<Statement<'_>, String> {
+    let code = strip_comment(text);
+    let mut rest = code.trim_start();
+    let mut label = None;
+
+    // a label starts in the first column or ends with a colon, an instruction or directive in the
+    // first column is still an instruction
+    let first_end = rest
+        .find(|c: char| c.is_whitespace() || c == ':')
+        .unwrap_or(rest.len());
+    let first = &rest[..first_end];
+    let has_colon = rest[first_end..].starts_with(':');
+
+    let in_first_column = !code.starts_with(char::is_whitespace) && !is_operation(first);
+    if !first.is_empty() && (has_colon || in_first_column) {
+        if !is_identifier(first) {
+            return Err(format!("bad label {}", first));
+        }
+
+        label = Some(first);
+        rest = &rest[first_end + has_colon as usize..];
+        rest = rest.trim_start();
+    }
+
+    if rest.is_empty() {
+        return Ok(Statement {
+            label,
+            operation: None,
+            operands: "",
+        });
+    }
+
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    Ok(Statement {
+        label,
+        operation: Some(&rest[..end]),
+        operands: rest[end..].trim(),
+    })
+}
+
+fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\'' => in_string = !in_string,
+            ';' if !in_string => return &text[..i],
+            _ => (),
+        }
+    }
+    text
+}
+
+// splits on the commas that aren't inside quotes
+fn split_operands(text: &str) -> Result<Vec<&str>, String> {
+    let mut operands = Vec::new();
+    let mut in_string = false;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '\'' => in_string = !in_string,
+            ',' if !in_string => {
+                operands.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    if in_string {
+        return Err("unterminated string".to_string());
+    }
+    if !text.trim().is_empty() {
+        operands.push(text[start..].trim());
+    }
+    if operands.iter().any(|operand| operand.is_empty()) {
+        return Err("empty operand".to_string());
+    }
+
+    Ok(operands)
+}
+
+// the bytes of an operand that is exactly one quoted string, '' stands for a quote
+fn string_literal(operand: &str) -> Option<Vec<u8>> {
+    let inner = operand.strip_prefix('\'')?.strip_suffix('\'')?;
+    if inner.replace("''", "").contains('\'') {
+        return None;
+    }
+    Some(inner.replace("''", "'").into_bytes())
+}
+
+pub(crate) fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '?' || c == '@' => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '?' || c == '@')
+}
+
+const DIRECTIVES: [&str; 6] = ["ORG", "EQU", "DB", "DW", "DS", "END"];
+
+fn is_operation(word: &str) -> bool {
+    let word = word.to_uppercase();
+
+    DIRECTIVES.contains(&word.as_str())
+        || OPCODES
+            .iter()
+            .any(|template| template.split(' ').next() == Some(word.as_str()))
+}
+
+fn is_placeholder(template: &str) -> bool {
+    matches!(template, "d8" | "d16" | "a16" | "j16")
+}
+
+const REGISTERS: [&str; 10] = ["A", "B", "C", "D", "E", "H", "L", "M", "SP", "PSW"];
+const OPERATORS: [&str; 10] = [
+    "MOD", "SHL", "SHR", "AND", "OR", "XOR", "NOT", "HIGH", "LOW", "$",
+];
+
+fn is_reserved(name: &str) -> bool {
+    REGISTERS.contains(&name) || OPERATORS.contains(&name)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i32),
+    Symbol(String),
+    Operator(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' {
+            // one or two characters, 'AB' is 4142H
+            let mut value = 0i32;
+            let mut count = 0;
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("unterminated character constant".to_string()),
+                    Some('\'') if chars.get(i + 1) == Some(&'\'') => i += 1,
+                    Some('\'') => break,
+                    Some(_) => (),
+                }
+                value = (value << 8) | (chars[i] as i32 & 0xFF);
+                count += 1;
+                i += 1;
+            }
+            i += 1;
+            if count == 0 || count > 2 {
+                return Err("character constants hold one or two characters".to_string());
+            }
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(parse_number(&literal)?));
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '?' || c == '@' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '_' | '?' | '@'))
+            {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect::<String>().to_uppercase();
+            if OPERATORS.contains(&word.as_str()) {
+                tokens.push(Token::Operator(word));
+            } else {
+                tokens.push(Token::Symbol(word));
+            }
+        } else if "+-*/()$".contains(c) {
+            tokens.push(Token::Operator(c.to_string()));
+            i += 1;
+        } else {
+            return Err(format!("unexpected character {:?} in expression", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(literal: &str) -> Result<i32, String> {
+    let upper = literal.to_uppercase();
+    let (digits, radix) = match upper.chars().last() {
+        Some('H') => (&upper[..upper.len() - 1], 16),
+        Some('O') | Some('Q') => (&upper[..upper.len() - 1], 8),
+        // a trailing B is a hex digit when the number ends in H, handled above
+        Some('B') => (&upper[..upper.len() - 1], 2),
+        Some('D') => (&upper[..upper.len() - 1], 10),
+        _ => (upper.as_str(), 10),
+    };
+
+    match u32::from_str_radix(digits, radix) {
+        Ok(value) if value <= 0xFFFF => Ok(value as i32),
+        _ => Err(format!("bad number {}", literal)),
+    }
+}
+
+// precedence from lowest to highest: OR XOR, AND, NOT, + -, * / MOD SHL SHR, and the unary
+// operators (-, HIGH, LOW)
+struct ExpressionParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    assembler: &'a Assembler,
+    // undefined symbols are an error, on the first pass they are taken as 0 where allowed
+    strict: bool,
+}
+
+impl ExpressionParser<'_> {
+    fn peek_operator(&self, operators: &[&str]) -> Option<String> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(op)) if operators.contains(&op.as_str()) => Some(op.clone()),
+            _ => None,
+        }
+    }
+
+    fn or(&mut self) -> Result<i32, String> {
+        let mut value = self.and()?;
+        while let Some(op) = self.peek_operator(&["OR", "XOR"]) {
+            self.position += 1;
+            let rhs = self.and()?;
+            value = if op == "OR" { value | rhs } else { value ^ rhs };
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<i32, String> {
+        let mut value = self.not()?;
+        while self.peek_operator(&["AND"]).is_some() {
+            self.position += 1;
+            value &= self.not()?;
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> Result<i32, String> {
+        if self.peek_operator(&["NOT"]).is_some() {
+            self.position += 1;
+            return Ok(!self.not()? & 0xFFFF);
+        }
+        self.sum()
+    }
+
+    fn sum(&mut self) -> Result<i32, String> {
+        let mut value = self.product()?;
+        while let Some(op) = self.peek_operator(&["+", "-"]) {
+            self.position += 1;
+            let rhs = self.product()?;
+            value = if op == "+" { value + rhs } else { value - rhs } & 0xFFFF;
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<i32, String> {
+        let mut value = self.unary()?;
+        while let Some(op) = self.peek_operator(&["*", "/", "MOD", "SHL", "SHR"]) {
+            self.position += 1;
+            let rhs = self.unary()?;
+            value = match op.as_str() {
+                "*" => value.wrapping_mul(rhs),
+                "SHL" => value << (rhs & 0x1F),
+                "SHR" => value >> (rhs & 0x1F),
+                _ if rhs == 0 => return Err("division by zero".to_string()),
+                "/" => value / rhs,
+                _ => value % rhs,
+            } & 0xFFFF;
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i32, String> {
+        match self.peek_operator(&["-", "+", "HIGH", "LOW"]).as_deref() {
+            Some(op) => {
+                self.position += 1;
+                let value = self.unary()?;
+                Ok(match op {
+                    "-" => -value & 0xFFFF,
+                    "HIGH" => (value >> 8) & 0xFF,
+                    "LOW" => value & 0xFF,
+                    _ => value,
+                })
+            }
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<i32, String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+
+        match token {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Operator(ref op)) if op == "$" => {
+                Ok(self.assembler.statement_address as i32)
+            }
+            Some(Token::Operator(ref op)) if op == "(" => {
+                let value = self.or()?;
+                if self.peek_operator(&[")"]).is_none() {
+                    return Err("missing )".to_string());
+                }
+                self.position += 1;
+                Ok(value)
+            }
+            Some(Token::Symbol(name)) => match self.assembler.symbols.get(&name) {
+                Some(value) => Ok(*value as i32),
+                None if !self.strict => Ok(0),
+                None if REGISTERS.contains(&name.as_str()) => {
+                    Err(format!("register {} where a value was expected", name))
+                }
+                None => Err(format!("undefined symbol {}", name)),
+            },
+            Some(Token::Operator(op)) => Err(format!("unexpected {}", op)),
+            None => Err("expression ends too early".to_string()),
+        }
+    }
+}
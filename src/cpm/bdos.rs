@@ -0,0 +1,791 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::console::Console;
+use crate::loader::Image;
+use crate::state::State8080;
+
+// ---- page zero and memory layout of the emulated system ----
+pub const WARM_BOOT: u16 = 0x0000; // `JMP 0` ends a program
+pub const BDOS_CALL: u16 = 0x0005; // programs `CALL 5` with the function number in C
+pub const DEFAULT_FCB: u16 = 0x005C;
+pub const DEFAULT_FCB2: u16 = 0x006C;
+pub const DEFAULT_DMA: u16 = 0x0080; // also holds the command tail
+pub const TPA_START: u16 = 0x0100; // where .COM files are loaded and started
+pub const BDOS_ENTRY: u16 = 0xFE06; // trapped, also marks the top of the TPA
+pub const TPA_END: u16 = BDOS_ENTRY - 2; // programs start with SP here, returning to the warm boot
+pub const BIOS_BASE: u16 = 0xFF00;
+
+const RECORD_SIZE: usize = 128;
+const RECORDS_PER_EXTENT: u32 = 128;
+const EOF_MARK: u8 = 0x1A; // ^Z, pads the last record of a text file
+const NOT_FOUND: u8 = 0xFF; // "directory code" for a failed file function
+const VERSION: u16 = 0x0022; // CP/M 2.2
+
+// FCB (file control block) field offsets
+const FCB_DRIVE: u16 = 0;
+const FCB_NAME: u16 = 1; // 8 bytes name + 3 bytes type, blank padded
+const FCB_EX: u16 = 12; // current extent (16K)
+const FCB_S2: u16 = 14; // extent high bits
+const FCB_RC: u16 = 15; // records used in the current extent
+const FCB_NEW_NAME: u16 = 17; // rename puts the new name in the second half of the FCB
+const FCB_CR: u16 = 32; // current record within the extent
+const FCB_R0: u16 = 33; // random record number, 3 bytes little-endian
+
+#[derive(Debug, PartialEq)]
+pub enum BdosStatus {
+    Continue,
+    Exit, // the program asked for a warm boot
+}
+
+// 8.3 name as stored in an FCB or directory entry, upper case and blank padded
+type CpmName = [u8; 11];
+
+// A CP/M 2.2 BDOS implemented in Rust. Calls to address 5 are trapped: the function number is in C,
+// the parameter in DE, and results are returned in A and HL like on the real system. Every drive
+// is backed by the same host directory.
+pub struct Bdos {
+    root: PathBuf,
+    console: Console,
+    dma: u16,
+    current_disk: u8,
+    user: u8,
+    iobyte: u8,
+    search_results: Vec<CpmName>, // matches not yet returned by "search next"
+}
+
+impl Bdos {
+    // console on stdin/stdout
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Bdos::with_console(root, Console::stdio())
+    }
+
+    pub fn with_console<P: Into<PathBuf>>(root: P, console: Console) -> Self {
+        Bdos {
+            root: root.into(),
+            console,
+            dma: DEFAULT_DMA,
+            current_disk: 0,
+            user: 0,
+            iobyte: 0,
+            search_results: Vec::new(),
+        }
+    }
+
+    // Sets up page zero for a program loaded at 0x100: the warm boot and BDOS jumps, the command
+    // tail and the two default FCBs parsed from it.
+    pub fn install(&mut self, state: &mut State8080, command_tail: &str) {
+        let memory = state.memory_mut();
+
+        // JMP WBOOT (BIOS + 3), the BIOS page is filled with HLTs
+        memory.poke(WARM_BOOT, 0xC3);
+        memory.poke(WARM_BOOT + 1, 0x03);
+        memory.poke(WARM_BOOT + 2, (BIOS_BASE >> 8) as u8);
+        for address in BIOS_BASE..=0xFFFF {
+            memory.poke(address, 0x76);
+        }
+
+        // JMP BDOS, the word at 6 doubles as the top of the TPA
+        memory.poke(BDOS_CALL, 0xC3);
+        memory.poke(BDOS_CALL + 1, BDOS_ENTRY as u8);
+        memory.poke(BDOS_CALL + 2, (BDOS_ENTRY >> 8) as u8);
+        memory.poke(BDOS_ENTRY, 0xC9); // RET, executed after the trap is serviced
+
+        // command tail: length byte, then the upper-cased text with a leading blank
+        let tail: Vec<u8> = if command_tail.is_empty() {
+            Vec::new()
+        } else {
+            format!(" {}", command_tail.to_uppercase())
+                .into_bytes()
+                .into_iter()
+                .take(RECORD_SIZE - 2)
+                .collect()
+        };
+        memory.poke(DEFAULT_DMA, tail.len() as u8);
+        for (i, byte) in tail.iter().enumerate() {
+            memory.poke(DEFAULT_DMA + 1 + i as u16, *byte);
+        }
+        memory.poke(DEFAULT_DMA + 1 + tail.len() as u16, 0);
+
+        // the second FCB overlaps the first one's allocation map, like with the real CCP
+        let mut args = command_tail.split_whitespace();
+        for fcb in &[DEFAULT_FCB, DEFAULT_FCB2] {
+            let (drive, name) = parse_file_name(args.next().unwrap_or(""));
+            memory.poke(fcb.wrapping_add(FCB_DRIVE), drive);
+            for (i, byte) in name.iter().enumerate() {
+                memory.poke(fcb.wrapping_add(FCB_NAME + i as u16), *byte);
+            }
+            for offset in FCB_EX..=FCB_RC {
+                memory.poke(fcb.wrapping_add(offset), 0);
+            }
+        }
+        for offset in FCB_CR..=FCB_R0 + 2 {
+            memory.poke(DEFAULT_FCB + offset, 0);
+        }
+
+        // a RET from the program's top level ends up at the warm boot
+        memory.poke(TPA_END, WARM_BOOT as u8);
+        memory.poke(TPA_END + 1, (WARM_BOOT >> 8) as u8);
+
+        self.dma = DEFAULT_DMA;
+        state.sp = TPA_END;
+        state.pc = TPA_START;
+    }
+
+    // Runs the program until it returns to CP/M (warm boot, function 0, or RET from the top level).
+    pub fn run(&mut self, state: &mut State8080) -> io::Result<()> {
+        while !state.halted() && self.trap(state)? {
+            state.emulate_cycle();
+
+            if let Some(opcode) = state.take_undocumented_opcode() {
+                self.console.flush()?;
+                return Err(opcode.into());
+            }
+        }
+
+        self.console.flush()
+    }
+
+    // To be called before every instruction: services the BDOS call when PC is at the BDOS entry
+    // point. `false` once the program has exited (through a warm boot or function 0).
+    pub fn trap(&mut self, state: &mut State8080) -> io::Result<bool> {
+        match state.pc() {
+            WARM_BOOT => Ok(false),
+            BDOS_ENTRY => {
+                let status = self.call(state)?;
+                state.cut_history();
+                Ok(status != BdosStatus::Exit)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    // Services the BDOS function in C. The caller is expected to be sitting at the BDOS entry point,
+    // whose RET then returns to the program.
+    pub fn call(&mut self, state: &mut State8080) -> io::Result<BdosStatus> {
+        let de = ((state.d as u16) << 8) | state.e as u16;
+
+        let result: u16 = match state.c {
+            // system reset
+            0 => return Ok(BdosStatus::Exit),
+            // console input (with echo)
+            1 => {
+                let c = self.console.read()?.unwrap_or(EOF_MARK);
+                self.console.write(&[c])?;
+                c as u16
+            }
+            // console output
+            2 => {
+                self.console.write(&[state.e])?;
+                0
+            }
+            // reader input, nothing is attached
+            3 => EOF_MARK as u16,
+            // punch and list output, both discarded
+            4 | 5 => 0,
+            // direct console I/O
+            6 => match state.e {
+                0xFF => {
+                    if self.console.ready()? {
+                        self.console.read()?.unwrap_or(0) as u16
+                    } else {
+                        0
+                    }
+                }
+                0xFE => self.console_status()?,
+                0xFD => self.console.read()?.unwrap_or(EOF_MARK) as u16,
+                c => {
+                    self.console.write(&[c])?;
+                    0
+                }
+            },
+            // get/set IOBYTE
+            7 => self.iobyte as u16,
+            8 => {
+                self.iobyte = state.e;
+                0
+            }
+            // print string terminated by '$'
+            9 => {
+                let mut address = de;
+                loop {
+                    let c = state.memory().peek(address);
+                    if c == b'$' {
+                        break;
+                    }
+                    self.console.write(&[c])?;
+                    address = address.wrapping_add(1);
+                }
+                0
+            }
+            // read console buffer
+            10 => {
+                self.read_line(state, de)?;
+                0
+            }
+            // get console status
+            11 => self.console_status()?,
+            // return version number
+            12 => VERSION,
+            // reset disk system
+            13 => {
+                self.dma = DEFAULT_DMA;
+                self.current_disk = 0;
+                0
+            }
+            // select disk
+            14 => {
+                self.current_disk = state.e & 0x0F;
+                0
+            }
+            15 => self.open_file(state, de) as u16,
+            // close file, data is always written through
+            16 => self.file_code(self.find(state, de)) as u16,
+            17 => self.search_first(state, de) as u16,
+            18 => self.search_next(state) as u16,
+            19 => self.delete_file(state, de) as u16,
+            20 => self.read_sequential(state, de) as u16,
+            21 => self.write_sequential(state, de) as u16,
+            22 => self.make_file(state, de) as u16,
+            23 => self.rename_file(state, de) as u16,
+            // return login vector, only the current drive is ever logged in
+            24 => 1 << self.current_disk,
+            // return current disk
+            25 => self.current_disk as u16,
+            // set DMA address
+            26 => {
+                self.dma = de;
+                0
+            }
+            // write protect disk, get R/O vector, set file attributes
+            28..=30 => 0,
+            // get/set user code
+            32 => {
+                if state.e == 0xFF {
+                    self.user as u16
+                } else {
+                    self.user = state.e & 0x0F;
+                    0
+                }
+            }
+            33 => self.read_random(state, de) as u16,
+            // write random (with zero fill)
+            34 | 40 => self.write_random(state, de) as u16,
+            35 => self.compute_file_size(state, de) as u16,
+            // set random record from the sequential position
+            36 => {
+                let record = get_sequential_record(state, de);
+                set_random_record(state, de, record);
+                0
+            }
+            // reset drive
+            37 => 0,
+            _ => {
+                eprintln!("BDOS: unsupported function {}", state.c);
+                NOT_FOUND as u16
+            }
+        };
+
+        self.console.flush()?;
+
+        // single byte results go in A and L, words in HL (and BA)
+        state.l = result as u8;
+        state.a = state.l;
+        state.h = (result >> 8) as u8;
+        state.b = state.h;
+
+        Ok(BdosStatus::Continue)
+    }
+
+    // ---- console ----
+
+    fn console_status(&mut self) -> io::Result<u16> {
+        Ok(if self.console.ready()? { 0xFF } else { 0 })
+    }
+
+    // buffer layout: max length, returned length, characters
+    fn read_line(&mut self, state: &mut State8080, buffer: u16) -> io::Result<()> {
+        let max = state.memory().peek(buffer) as usize;
+        let mut line: Vec<u8> = Vec::new();
+
+        while let Some(c) = self.console.read()? {
+            match c {
+                b'\r' => break,
+                // backspace and rubout
+                0x08 | 0x7F => {
+                    if line.pop().is_some() {
+                        self.console.write(b"\x08 \x08")?;
+                    }
+                }
+                // only a buffer with no room at all gets here, it still waits for the return
+                _ if line.len() >= max => (),
+                _ => {
+                    self.console.write(&[c])?;
+                    line.push(c);
+                    if line.len() >= max {
+                        break;
+                    }
+                }
+            }
+        }
+        self.console.write(b"\r")?;
+
+        let memory = state.memory_mut();
+        memory.poke(buffer.wrapping_add(1), line.len() as u8);
+        for (i, c) in line.iter().enumerate() {
+            memory.poke(buffer.wrapping_add(2 + i as u16), *c);
+        }
+
+        Ok(())
+    }
+
+    // ---- files ----
+
+    // every 8.3 file in the host directory, sorted by name
+    fn directory(&self) -> io::Result<Vec<(CpmName, PathBuf)>> {
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str().and_then(host_to_cpm_name) {
+                files.push((name, entry.path()));
+            }
+        }
+        files.sort();
+
+        Ok(files)
+    }
+
+    // host path of the first file matching the (possibly ambiguous) name in the FCB
+    fn find(&self, state: &State8080, fcb: u16) -> Option<PathBuf> {
+        let pattern = read_fcb_name(state, fcb.wrapping_add(FCB_NAME));
+
+        self.directory()
+            .ok()?
+            .into_iter()
+            .find(|(name, _)| name_matches(&pattern, name))
+            .map(|(_, path)| path)
+    }
+
+    fn file_code(&self, path: Option<PathBuf>) -> u8 {
+        if path.is_some() {
+            0
+        } else {
+            NOT_FOUND
+        }
+    }
+
+    fn open_file(&mut self, state: &mut State8080, fcb: u16) -> u8 {
+        let path = match self.find(state, fcb) {
+            Some(path) => path,
+            None => return NOT_FOUND,
+        };
+
+        // an ambiguous open takes the name of the file that matched
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            if let Some(name) = host_to_cpm_name(name) {
+                write_fcb_name(state, fcb.wrapping_add(FCB_NAME), &name);
+            }
+        }
+
+        state.memory_mut().poke(fcb.wrapping_add(FCB_S2), 0);
+        update_record_count(state, fcb, &path);
+        0
+    }
+
+    fn make_file(&mut self, state: &mut State8080, fcb: u16) -> u8 {
+        let name = read_fcb_name(state, fcb.wrapping_add(FCB_NAME));
+        if name.contains(&b'?') {
+            return NOT_FOUND;
+        }
+
+        let path = self.root.join(cpm_to_host_name(&name));
+        if File::create(&path).is_err() {
+            return NOT_FOUND;
+        }
+
+        let memory = state.memory_mut();
+        memory.poke(fcb.wrapping_add(FCB_EX), 0);
+        memory.poke(fcb.wrapping_add(FCB_S2), 0);
+        memory.poke(fcb.wrapping_add(FCB_RC), 0);
+        0
+    }
+
+    fn delete_file(&mut self, state: &mut State8080, fcb: u16) -> u8 {
+        let pattern = read_fcb_name(state, fcb.wrapping_add(FCB_NAME));
+        let mut deleted = false;
+
+        for (name, path) in self.directory().unwrap_or_default() {
+            if name_matches(&pattern, &name) && fs::remove_file(path).is_ok() {
+                deleted = true;
+            }
+        }
+
+        if deleted {
+            0
+        } else {
+            NOT_FOUND
+        }
+    }
+
+    fn rename_file(&mut self, state: &mut State8080, fcb: u16) -> u8 {
+        let path = match self.find(state, fcb) {
+            Some(path) => path,
+            None => return NOT_FOUND,
+        };
+        let new_name = read_fcb_name(state, fcb.wrapping_add(FCB_NEW_NAME));
+
+        match fs::rename(path, self.root.join(cpm_to_host_name(&new_name))) {
+            Ok(()) => 0,
+            Err(_) => NOT_FOUND,
+        }
+    }
+
+    fn search_first(&mut self, state: &mut State8080, fcb: u16) -> u8 {
+        let pattern = read_fcb_name(state, fcb.wrapping_add(FCB_NAME));
+
+        // a '?' in the drive byte matches everything
+        let match_all = state.memory().peek(fcb.wrapping_add(FCB_DRIVE)) == b'?';
+
+        self.search_results = self
+            .directory()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| match_all || name_matches(&pattern, name))
+            .rev()
+            .collect();
+
+        self.search_next(state)
+    }
+
+    // the directory entry is placed at the start of the DMA buffer (directory code 0)
+    fn search_next(&mut self, state: &mut State8080) -> u8 {
+        let name = match self.search_results.pop() {
+            Some(name) => name,
+            None => return NOT_FOUND,
+        };
+
+        let records = match self.find_by_name(&name) {
+            Some(path) => file_records(&path).min(RECORDS_PER_EXTENT),
+            None => 0,
+        };
+
+        let memory = state.memory_mut();
+        for offset in 0..32 {
+            memory.poke(self.dma.wrapping_add(offset), 0);
+        }
+        memory.poke(self.dma, self.user);
+        for (i, byte) in name.iter().enumerate() {
+            memory.poke(self.dma.wrapping_add(1 + i as u16), *byte);
+        }
+        memory.poke(self.dma.wrapping_add(FCB_RC), records as u8);
+
+        0
+    }
+
+    fn find_by_name(&self, name: &CpmName) -> Option<PathBuf> {
+        self.directory()
+            .ok()?
+            .into_iter()
+            .find(|(entry, _)| entry == name)
+            .map(|(_, path)| path)
+    }
+
+    fn read_sequential(&mut self, state: &mut State8080, fcb: u16) -> u8 {
+        let record = get_sequential_record(state, fcb);
+
+        let code = self.read_record(state, fcb, record);
+        if code == 0 {
+            set_sequential_record(state, fcb, record + 1);
+        }
+        code
+    }
+
+    fn write_sequential(&mut self, state: &mut State8080, fcb: u16) -> u8 {
+        let record = get_sequential_record(state, fcb);
+
+        let code = self.write_record(state, fcb, record);
+        if code == 0 {
+            set_sequential_record(state, fcb, record + 1);
+        }
+        code
+    }
+
+    // random access leaves the sequential position at the record that was accessed
+    fn read_random(&mut self, state: &mut State8080, fcb: u16) -> u8 {
+        let record = match get_random_record(state, fcb) {
+            Some(record) => record,
+            None => return 6, // seek past physical end of disk
+        };
+
+        set_sequential_record(state, fcb, record);
+        self.read_record(state, fcb, record)
+    }
+
+    fn write_random(&mut self, state: &mut State8080, fcb: u16) -> u8 {
+        let record = match get_random_record(state, fcb) {
+            Some(record) => record,
+            None => return 6,
+        };
+
+        set_sequential_record(state, fcb, record);
+        self.write_record(state, fcb, record)
+    }
+
+    fn compute_file_size(&mut self, state: &mut State8080, fcb: u16) -> u8 {
+        match self.find(state, fcb) {
+            Some(path) => {
+                set_random_record(state, fcb, file_records(&path));
+                0
+            }
+            None => NOT_FOUND,
+        }
+    }
+
+    // 0 = ok, 1 = end of file (reading unwritten data)
+    fn read_record(&mut self, state: &mut State8080, fcb: u16, record: u32) -> u8 {
+        let path = match self.find(state, fcb) {
+            Some(path) => path,
+            None => return NOT_FOUND,
+        };
+
+        let mut buffer = [EOF_MARK; RECORD_SIZE];
+        let read = File::open(&path).and_then(|mut file| {
+            file.seek(SeekFrom::Start(record as u64 * RECORD_SIZE as u64))?;
+            read_up_to(&mut file, &mut buffer)
+        });
+
+        match read {
+            Ok(0) | Err(_) => return 1,
+            Ok(_) => (),
+        }
+
+        let memory = state.memory_mut();
+        for (i, byte) in buffer.iter().enumerate() {
+            memory.poke(self.dma.wrapping_add(i as u16), *byte);
+        }
+        update_record_count(state, fcb, &path);
+
+        0
+    }
+
+    // 0 = ok, 2 = disk full (the host refused the write)
+    fn write_record(&mut self, state: &mut State8080, fcb: u16, record: u32) -> u8 {
+        let path = match self.find(state, fcb) {
+            Some(path) => path,
+            None => return NOT_FOUND,
+        };
+
+        let mut buffer = [0; RECORD_SIZE];
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = state.memory().peek(self.dma.wrapping_add(i as u16));
+        }
+
+        let written = OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(record as u64 * RECORD_SIZE as u64))?;
+                file.write_all(&buffer)
+            });
+        if written.is_err() {
+            return 2;
+        }
+
+        update_record_count(state, fcb, &path);
+        0
+    }
+}
+
+// Everything has to load between page zero and the stack the BDOS sets up below itself.
+pub fn check_in_tpa(image: &Image) -> io::Result<()> {
+    for segment in &image.segments {
+        let end = segment.address as usize + segment.data.len();
+        if segment.address < TPA_START || end > TPA_END as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} bytes at {:04X}h don't fit in the TPA ({:04X}h-{:04X}h)",
+                    segment.data.len(),
+                    segment.address,
+                    TPA_START,
+                    TPA_END - 1
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// fills as much of `buffer` as the file still has
+fn read_up_to(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+
+    while total < buffer.len() {
+        match file.read(&mut buffer[total..])? {
+            0 => break,
+            read => total += read,
+        }
+    }
+
+    Ok(total)
+}
+
+fn file_records(path: &Path) -> u32 {
+    let size = fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+
+    size.div_ceil(RECORD_SIZE as u64) as u32
+}
+
+// sets RC to the number of records of the file that fall into the FCB's current extent
+fn update_record_count(state: &mut State8080, fcb: u16, path: &Path) {
+    let extent_start = get_sequential_record(state, fcb) & !(RECORDS_PER_EXTENT - 1);
+    let records = file_records(path)
+        .saturating_sub(extent_start)
+        .min(RECORDS_PER_EXTENT);
+
+    state
+        .memory_mut()
+        .poke(fcb.wrapping_add(FCB_RC), records as u8);
+}
+
+// record number from S2 (extent high bits), EX (extent) and CR (record in extent)
+fn get_sequential_record(state: &State8080, fcb: u16) -> u32 {
+    let memory = state.memory();
+    let s2 = (memory.peek(fcb.wrapping_add(FCB_S2)) & 0x3F) as u32;
+    let ex = (memory.peek(fcb.wrapping_add(FCB_EX)) & 0x1F) as u32;
+    let cr = (memory.peek(fcb.wrapping_add(FCB_CR)) & 0x7F) as u32;
+
+    (s2 << 12) | (ex << 7) | cr
+}
+
+fn set_sequential_record(state: &mut State8080, fcb: u16, record: u32) {
+    let memory = state.memory_mut();
+    memory.poke(fcb.wrapping_add(FCB_CR), (record & 0x7F) as u8);
+    memory.poke(fcb.wrapping_add(FCB_EX), ((record >> 7) & 0x1F) as u8);
+    memory.poke(fcb.wrapping_add(FCB_S2), ((record >> 12) & 0x3F) as u8);
+}
+
+// `None` if the record number is beyond what CP/M 2.2 can address (R2 must be 0)
+fn get_random_record(state: &State8080, fcb: u16) -> Option<u32> {
+    let memory = state.memory();
+    if memory.peek(fcb.wrapping_add(FCB_R0 + 2)) != 0 {
+        return None;
+    }
+
+    Some(
+        (memory.peek(fcb.wrapping_add(FCB_R0)) as u32)
+            | ((memory.peek(fcb.wrapping_add(FCB_R0 + 1)) as u32) << 8),
+    )
+}
+
+fn set_random_record(state: &mut State8080, fcb: u16, record: u32) {
+    let memory = state.memory_mut();
+    memory.poke(fcb.wrapping_add(FCB_R0), record as u8);
+    memory.poke(fcb.wrapping_add(FCB_R0 + 1), (record >> 8) as u8);
+    memory.poke(fcb.wrapping_add(FCB_R0 + 2), (record >> 16) as u8);
+}
+
+// attribute bits (bit 7) are ignored
+fn read_fcb_name(state: &State8080, address: u16) -> CpmName {
+    let mut name = [b' '; 11];
+
+    for (i, byte) in name.iter_mut().enumerate() {
+        *byte = (state.memory().peek(address.wrapping_add(i as u16)) & 0x7F).to_ascii_uppercase();
+    }
+
+    name
+}
+
+fn write_fcb_name(state: &mut State8080, address: u16, name: &CpmName) {
+    for (i, byte) in name.iter().enumerate() {
+        state
+            .memory_mut()
+            .poke(address.wrapping_add(i as u16), *byte);
+    }
+}
+
+fn name_matches(pattern: &CpmName, name: &CpmName) -> bool {
+    pattern
+        .iter()
+        .zip(name.iter())
+        .all(|(p, n)| *p == b'?' || p == n)
+}
+
+// `None` for host names that don't fit CP/M's 8.3 scheme
+fn host_to_cpm_name(host_name: &str) -> Option<CpmName> {
+    let (name, extension) = match host_name.rfind('.') {
+        Some(dot) => (&host_name[..dot], &host_name[dot + 1..]),
+        None => (host_name, ""),
+    };
+
+    let valid = |part: &str, max: usize| {
+        part.len() <= max
+            && part
+                .bytes()
+                .all(|c| c.is_ascii_graphic() && !b"<>.,;:=?*[]".contains(&c))
+    };
+    if name.is_empty() || !valid(name, 8) || !valid(extension, 3) {
+        return None;
+    }
+
+    let mut cpm_name = [b' '; 11];
+    for (i, c) in name.bytes().enumerate() {
+        cpm_name[i] = c.to_ascii_uppercase();
+    }
+    for (i, c) in extension.bytes().enumerate() {
+        cpm_name[8 + i] = c.to_ascii_uppercase();
+    }
+
+    Some(cpm_name)
+}
+
+fn cpm_to_host_name(name: &CpmName) -> String {
+    let base = String::from_utf8_lossy(&name[..8]).trim_end().to_string();
+    let extension = String::from_utf8_lossy(&name[8..]).trim_end().to_string();
+
+    if extension.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, extension)
+    }
+}
+
+// "B:NAME.TYP" into a drive code (0 = default, 1 = A:, ...) and a blank padded name, with `*`
+// expanded to `?`s like the CCP does
+fn parse_file_name(text: &str) -> (u8, CpmName) {
+    let mut name = [b' '; 11];
+    let text = text.to_uppercase();
+
+    // only A: to P: are drives, anything else before a colon is left as part of the name
+    let (drive, text) = match (text.find(':'), text.as_bytes().first()) {
+        (Some(1), Some(&letter @ b'A'..=b'P')) => (letter - b'A' + 1, &text[2..]),
+        _ => (0, &text[..]),
+    };
+
+    let (base, extension) = match text.find('.') {
+        Some(dot) => (&text[..dot], &text[dot + 1..]),
+        None => (text, ""),
+    };
+
+    for (field, (offset, length)) in [base, extension].iter().zip(&[(0, 8), (8, 3)]) {
+        for (i, c) in field.bytes().take(*length).enumerate() {
+            if c == b'*' {
+                for byte in name.iter_mut().skip(offset + i).take(length - i) {
+                    *byte = b'?';
+                }
+                break;
+            }
+            name[offset + i] = c;
+        }
+    }
+
+    (drive, name)
+}
@@ -0,0 +1,256 @@
+use std::io;
+
+use super::console::Console;
+use super::disk::{DiskImage, SECTOR_SIZE};
+use crate::state::State8080;
+
+pub const MAX_DRIVES: usize = 4;
+
+// number of entries in the CP/M 2.2 BIOS jump table (BOOT through SECTRAN)
+const FUNCTIONS: u16 = 17;
+
+// layout of the BIOS page, relative to the BIOS base
+const TRAPS: u16 = 0x40; // one RET per function, the jump table points here
+const XLT: u16 = 0x60; // sector translation table (26 bytes)
+const DPB: u16 = 0x80; // disk parameter block (15 bytes)
+const DPH: u16 = 0x90; // one disk parameter header (16 bytes) per drive
+const DIRBUF: u16 = DPH + 16 * MAX_DRIVES as u16; // 128 byte directory buffer shared by all drives
+const CSV: u16 = DIRBUF + 128; // directory check vectors (16 bytes per drive)
+const ALV: u16 = CSV + 16 * MAX_DRIVES as u16; // allocation vectors (31 bytes per drive)
+
+// everything above, from the jump table to the last allocation vector
+pub const BIOS_SIZE: u16 = ALV + 31 * MAX_DRIVES as u16;
+
+// the standard skew of 6 for IBM 3740 disks (logical sector -> physical sector)
+#[rustfmt::skip]
+const SKEW: [u8; 26] = [
+    1, 7, 13, 19, 25, 5, 11, 17, 23, 3, 9, 15, 21,
+    2, 8, 14, 20, 26, 6, 12, 18, 24, 4, 10, 16, 22,
+];
+
+// disk parameter block for 8" SSSD: SPT, BSH, BLM, EXM, DSM, DRM, AL0, AL1, CKS, OFF
+#[rustfmt::skip]
+const DPB_8_INCH_SSSD: [u8; 15] = [
+    26, 0,  // 26 sectors per track
+    3, 7,   // 1K blocks
+    0,      // extent mask
+    242, 0, // 243 blocks
+    63, 0,  // 64 directory entries
+    0xC0, 0, // 2 blocks reserved for the directory
+    16, 0,  // check vector size
+    2, 0,   // 2 system tracks
+];
+
+#[derive(Debug, PartialEq)]
+pub enum BiosStatus {
+    Continue,
+    ColdBoot,
+    WarmBoot,
+    Exit, // console input was requested after the scripted input ran out
+}
+
+// The BIOS jump table of a CP/M 2.2 system, serviced in Rust. Each entry jumps to a trap address
+// holding a RET, so programs that patch the jump table keep working.
+pub struct Bios {
+    base: u16,
+    console: Console,
+    disks: Vec<Option<DiskImage>>,
+    drive: usize,
+    track: u16,
+    sector: u16,
+    dma: u16,
+}
+
+impl Bios {
+    pub fn new(base: u16, console: Console) -> Self {
+        Bios {
+            base,
+            console,
+            disks: (0..MAX_DRIVES).map(|_| None).collect(),
+            drive: 0,
+            track: 0,
+            sector: 1,
+            dma: 0x0080,
+        }
+    }
+
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    // puts `disk` in drive 0 (A:) to 3 (D:)
+    pub fn insert(&mut self, drive: usize, disk: DiskImage) {
+        assert!(
+            drive < MAX_DRIVES,
+            "no drive {}, the BIOS has drives 0 to {}",
+            drive,
+            MAX_DRIVES - 1
+        );
+        self.disks[drive] = Some(disk);
+    }
+
+    pub fn disk(&self, drive: usize) -> Option<&DiskImage> {
+        self.disks.get(drive).and_then(|disk| disk.as_ref())
+    }
+
+    pub fn console(&mut self) -> &mut Console {
+        &mut self.console
+    }
+
+    // writes the jump table, the trap RETs and the disk parameter tables into memory
+    pub fn install(&self, state: &mut State8080) {
+        let memory = state.memory_mut();
+
+        for function in 0..FUNCTIONS {
+            let entry = self.base + function * 3;
+            let trap = self.base + TRAPS + function;
+
+            memory.poke(entry, 0xC3); // JMP trap
+            memory.poke(entry + 1, trap as u8);
+            memory.poke(entry + 2, (trap >> 8) as u8);
+            memory.poke(trap, 0xC9); // RET
+        }
+
+        for (i, sector) in SKEW.iter().enumerate() {
+            memory.poke(self.base + XLT + i as u16, *sector);
+        }
+        for (i, byte) in DPB_8_INCH_SSSD.iter().enumerate() {
+            memory.poke(self.base + DPB + i as u16, *byte);
+        }
+
+        // XLT, 3 scratch words for the BDOS, DIRBUF, DPB, CSV, ALV
+        for drive in 0..MAX_DRIVES as u16 {
+            let dph = self.base + DPH + drive * 16;
+            let words = [
+                self.base + XLT,
+                0,
+                0,
+                0,
+                self.base + DIRBUF,
+                self.base + DPB,
+                self.base + CSV + drive * 16,
+                self.base + ALV + drive * 31,
+            ];
+
+            for (i, word) in words.iter().enumerate() {
+                memory.poke(dph + i as u16 * 2, *word as u8);
+                memory.poke(dph + i as u16 * 2 + 1, (*word >> 8) as u8);
+            }
+        }
+    }
+
+    // the BIOS function whose trap is at `pc`, if any
+    pub fn trap(&self, pc: u16) -> Option<u16> {
+        let start = self.base + TRAPS;
+
+        if pc >= start && pc < start + FUNCTIONS {
+            Some(pc - start)
+        } else {
+            None
+        }
+    }
+
+    // Services BIOS `function` with the parameters in BC (and DE for SECTRAN). Results go in A or
+    // HL. Booting is left to the caller, since it involves the rest of the machine.
+    pub fn call(&mut self, function: u16, state: &mut State8080) -> io::Result<BiosStatus> {
+        let bc = ((state.b as u16) << 8) | state.c as u16;
+        let de = ((state.d as u16) << 8) | state.e as u16;
+
+        match function {
+            // BOOT
+            0 => return Ok(BiosStatus::ColdBoot),
+            // WBOOT
+            1 => return Ok(BiosStatus::WarmBoot),
+            // CONST
+            2 => state.a = if self.console.ready()? { 0xFF } else { 0 },
+            // CONIN
+            3 => match self.console.read()? {
+                Some(c) => state.a = c & 0x7F,
+                None => return Ok(BiosStatus::Exit),
+            },
+            // CONOUT
+            4 => {
+                self.console.write(&[state.c & 0x7F])?;
+                self.console.flush()?;
+            }
+            // LIST and PUNCH, nothing is attached
+            5 | 6 => (),
+            // READER
+            7 => state.a = 0x1A,
+            // HOME
+            8 => self.track = 0,
+            // SELDSK, HL = DPH or 0 if there is no such drive
+            9 => {
+                let drive = state.c as usize;
+                let dph = if self.disk(drive).is_some() {
+                    self.drive = drive;
+                    self.base + DPH + drive as u16 * 16
+                } else {
+                    0
+                };
+
+                state.h = (dph >> 8) as u8;
+                state.l = dph as u8;
+            }
+            // SETTRK
+            10 => self.track = bc,
+            // SETSEC
+            11 => self.sector = bc,
+            // SETDMA
+            12 => self.dma = bc,
+            // READ, A = 0 on success
+            13 => state.a = self.read(state),
+            // WRITE
+            14 => state.a = self.write(state),
+            // LISTST, the (absent) printer is always ready
+            15 => state.a = 0xFF,
+            // SECTRAN, HL = physical sector for logical sector BC through the table at DE
+            16 => {
+                let sector = if de == 0 {
+                    bc
+                } else {
+                    state.memory().peek(de.wrapping_add(bc)) as u16
+                };
+
+                state.h = (sector >> 8) as u8;
+                state.l = sector as u8;
+            }
+            _ => unreachable!("the BIOS has {} functions", FUNCTIONS),
+        }
+
+        Ok(BiosStatus::Continue)
+    }
+
+    fn read(&mut self, state: &mut State8080) -> u8 {
+        let disk = match self.disk(self.drive) {
+            Some(disk) => disk,
+            None => return 1,
+        };
+
+        match disk.read_sector(self.track, self.sector) {
+            Some(bytes) => {
+                let memory = state.memory_mut();
+                for (i, byte) in bytes.iter().enumerate() {
+                    memory.poke(self.dma.wrapping_add(i as u16), *byte);
+                }
+                0
+            }
+            None => 1,
+        }
+    }
+
+    fn write(&mut self, state: &mut State8080) -> u8 {
+        let mut bytes = [0; SECTOR_SIZE];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = state.memory().peek(self.dma.wrapping_add(i as u16));
+        }
+
+        match self.disks[self.drive].as_mut() {
+            Some(disk) => match disk.write_sector(self.track, self.sector, &bytes) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            },
+            None => 1,
+        }
+    }
+}
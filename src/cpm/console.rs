@@ -0,0 +1,50 @@
+use std::io::{self, BufRead, BufReader, Write};
+
+// The CP/M console device, shared by the BDOS and the BIOS. Any reader/writer pair works, so
+// programs can be driven headless with scripted input.
+pub struct Console {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+impl Console {
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Console { input, output }
+    }
+
+    pub fn stdio() -> Self {
+        Console::new(
+            Box::new(BufReader::new(io::stdin())),
+            Box::new(io::stdout()),
+        )
+    }
+
+    // a key is waiting (never true once the input is exhausted)
+    pub fn ready(&mut self) -> io::Result<bool> {
+        Ok(!self.input.fill_buf()?.is_empty())
+    }
+
+    // Next key, `None` once the input is exhausted. Host line endings (LF or CR LF) are turned
+    // into the CR a CP/M program expects from the return key.
+    pub fn read(&mut self) -> io::Result<Option<u8>> {
+        let c = match self.input.fill_buf()?.first() {
+            Some(c) => *c,
+            None => return Ok(None),
+        };
+        self.input.consume(1);
+
+        if c == b'\r' && self.input.fill_buf()?.first() == Some(&b'\n') {
+            self.input.consume(1);
+        }
+
+        Ok(Some(if c == b'\n' { b'\r' } else { c }))
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.write_all(bytes)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// IBM 3740 8" single-sided single-density, the CP/M 2.2 distribution format
+pub const TRACKS: u16 = 77;
+pub const SECTORS_PER_TRACK: u16 = 26; // numbered from 1
+pub const SECTOR_SIZE: usize = 128;
+pub const DISK_SIZE: usize = TRACKS as usize * SECTORS_PER_TRACK as usize * SECTOR_SIZE;
+
+// value of a freshly formatted byte, also marks unused directory entries
+const FORMAT_FILL: u8 = 0xE5;
+
+// A disk image stored track by track, sector by sector (the usual .dsk layout). Images backed by a
+// file are written through on every sector write.
+pub struct DiskImage {
+    data: Vec<u8>,
+    path: Option<PathBuf>,
+}
+
+impl DiskImage {
+    // a freshly formatted disk that only lives in memory
+    pub fn blank() -> Self {
+        DiskImage {
+            data: vec![FORMAT_FILL; DISK_SIZE],
+            path: None,
+        }
+    }
+
+    // a short file is treated as a disk whose remaining sectors are still blank
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut data = fs::read(&path)?;
+
+        if data.len() > DISK_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} bytes is too large for an 8\" SSSD image ({} bytes)",
+                    data.len(),
+                    DISK_SIZE
+                ),
+            ));
+        }
+        data.resize(DISK_SIZE, FORMAT_FILL);
+
+        Ok(DiskImage {
+            data,
+            path: Some(path.as_ref().to_path_buf()),
+        })
+    }
+
+    pub fn from_bytes(mut data: Vec<u8>) -> Self {
+        data.resize(DISK_SIZE, FORMAT_FILL);
+
+        DiskImage { data, path: None }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    // `None` if the track or sector doesn't exist
+    fn offset(track: u16, sector: u16) -> Option<usize> {
+        if track >= TRACKS || sector == 0 || sector > SECTORS_PER_TRACK {
+            return None;
+        }
+
+        Some((track as usize * SECTORS_PER_TRACK as usize + (sector - 1) as usize) * SECTOR_SIZE)
+    }
+
+    pub fn read_sector(&self, track: u16, sector: u16) -> Option<&[u8]> {
+        DiskImage::offset(track, sector).map(|offset| &self.data[offset..offset + SECTOR_SIZE])
+    }
+
+    pub fn write_sector(&mut self, track: u16, sector: u16, bytes: &[u8]) -> io::Result<()> {
+        let offset = DiskImage::offset(track, sector).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no sector {} on track {}", sector, track),
+            )
+        })?;
+        if bytes.len() != SECTOR_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} bytes for a {} byte sector", bytes.len(), SECTOR_SIZE),
+            ));
+        }
+
+        self.data[offset..offset + SECTOR_SIZE].copy_from_slice(bytes);
+
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().write(true).open(path)?;
+            file.seek(SeekFrom::Start(offset as u64))?;
+            file.write_all(bytes)?;
+        }
+
+        Ok(())
+    }
+}
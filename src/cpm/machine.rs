@@ -0,0 +1,174 @@
+use std::io;
+
+use super::bios::{Bios, BiosStatus, BIOS_SIZE};
+use super::console::Console;
+use super::disk::{DiskImage, SECTORS_PER_TRACK};
+use crate::state::State8080;
+
+// where the CCP sits in a 64K system, the BDOS and BIOS follow at fixed offsets
+pub const DEFAULT_CCP_BASE: u16 = 0xE400;
+const BDOS_OFFSET: u16 = 0x0800;
+const BIOS_OFFSET: u16 = 0x1600;
+
+// CCP + BDOS as stored on the system tracks, right after the cold start loader in T0 S1
+const SYSTEM_SECTORS: u16 = 44;
+const SYSTEM_FIRST_SECTOR: u16 = 2;
+
+const DEFAULT_DMA: u16 = 0x0080;
+const IOBYTE: u16 = 0x0003;
+const CURRENT_DISK: u16 = 0x0004; // user number in the high nibble, drive in the low one
+
+// A CP/M 2.2 computer: the 8080, up to four 8" drives, and a BIOS serviced in Rust. The CCP and
+// BDOS are the real ones, loaded from the system tracks of drive A: (or from a separate image).
+pub struct CpmMachine {
+    pub state: State8080,
+    bios: Bios,
+    ccp_base: u16,
+    system_image: Option<Vec<u8>>,
+}
+
+impl CpmMachine {
+    // `ccp_base` must match the memory size the system on the boot disk was configured for, and
+    // leave room for the BIOS below the top of memory
+    pub fn new(ccp_base: u16, console: Console) -> io::Result<Self> {
+        let bios_base = ccp_base
+            .checked_add(BIOS_OFFSET)
+            .filter(|base| base.checked_add(BIOS_SIZE - 1).is_some())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "no room for the BIOS above a CCP at {:04X}h, it can start at {:04X}h at most",
+                        ccp_base,
+                        0xFFFF - (BIOS_SIZE - 1) - BIOS_OFFSET
+                    ),
+                )
+            })?;
+
+        Ok(CpmMachine {
+            state: Default::default(),
+            bios: Bios::new(bios_base, console),
+            ccp_base,
+            system_image: None,
+        })
+    }
+
+    pub fn insert(&mut self, drive: usize, disk: DiskImage) {
+        self.bios.insert(drive, disk);
+    }
+
+    pub fn disk(&self, drive: usize) -> Option<&DiskImage> {
+        self.bios.disk(drive)
+    }
+
+    // boot from a CCP + BDOS image (e.g. CPM.SYS) instead of the system tracks of drive A:
+    pub fn set_system_image(&mut self, image: Vec<u8>) {
+        self.system_image = Some(image);
+    }
+
+    // Cold boots and runs until the CPU halts or a program waits for console input that the
+    // scripted input can't provide anymore. Strict mode stopping at an undocumented opcode is an
+    // error.
+    pub fn run(&mut self) -> io::Result<()> {
+        self.cold_boot()?;
+
+        loop {
+            if self.state.halted() {
+                break;
+            }
+
+            if let Some(function) = self.bios.trap(self.state.pc()) {
+                let status = self.bios.call(function, &mut self.state)?;
+                self.state.cut_history();
+                match status {
+                    BiosStatus::Continue => (),
+                    BiosStatus::ColdBoot => {
+                        self.cold_boot()?;
+                        continue;
+                    }
+                    BiosStatus::WarmBoot => {
+                        self.warm_boot()?;
+                        continue;
+                    }
+                    BiosStatus::Exit => break,
+                }
+            }
+
+            self.state.emulate_cycle();
+
+            if let Some(opcode) = self.state.take_undocumented_opcode() {
+                self.bios.console().flush()?;
+                return Err(opcode.into());
+            }
+        }
+
+        self.bios.console().flush()
+    }
+
+    pub fn cold_boot(&mut self) -> io::Result<()> {
+        self.bios.install(&mut self.state);
+
+        let memory = self.state.memory_mut();
+        memory.poke(IOBYTE, 0);
+        memory.poke(CURRENT_DISK, 0);
+
+        self.warm_boot()
+    }
+
+    // reloads the CCP and BDOS (a program may have overwritten them) and enters the CCP
+    pub fn warm_boot(&mut self) -> io::Result<()> {
+        self.load_system()?;
+
+        let bios = self.bios.base();
+        let bdos_entry = self.ccp_base + BDOS_OFFSET + 6;
+        let memory = self.state.memory_mut();
+
+        // JMP WBOOT at 0 and JMP BDOS at 5, the BIOS and BDOS addresses programs look up
+        memory.poke(0x0000, 0xC3);
+        memory.poke(0x0001, (bios + 3) as u8);
+        memory.poke(0x0002, ((bios + 3) >> 8) as u8);
+        memory.poke(0x0005, 0xC3);
+        memory.poke(0x0006, bdos_entry as u8);
+        memory.poke(0x0007, (bdos_entry >> 8) as u8);
+
+        // the CCP expects the current drive in C
+        self.state.c = memory.peek(CURRENT_DISK);
+        self.state.sp = DEFAULT_DMA;
+        self.state.pc = self.ccp_base;
+
+        Ok(())
+    }
+
+    fn load_system(&mut self) -> io::Result<()> {
+        let mut image = Vec::new();
+
+        match &self.system_image {
+            Some(system_image) => image.extend_from_slice(system_image),
+            None => {
+                let disk = self.bios.disk(0).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "no boot disk in drive A:")
+                })?;
+
+                for i in 0..SYSTEM_SECTORS {
+                    let sector = SYSTEM_FIRST_SECTOR - 1 + i;
+                    let track = sector / SECTORS_PER_TRACK;
+                    let sector = sector % SECTORS_PER_TRACK + 1;
+
+                    // `read_sector` only fails for sectors that don't exist
+                    image.extend_from_slice(disk.read_sector(track, sector).unwrap());
+                }
+            }
+        }
+
+        if image.len() > BIOS_OFFSET as usize {
+            image.truncate(BIOS_OFFSET as usize);
+        }
+
+        let memory = self.state.memory_mut();
+        for (i, byte) in image.iter().enumerate() {
+            memory.poke(self.ccp_base + i as u16, *byte);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+// CP/M 2.2 support: a BDOS emulated in Rust for running single .COM programs against a host
+// directory, and a complete machine that boots the real CCP and BDOS from 8" disk images.
+pub mod bdos;
+pub mod bios;
+pub mod console;
+pub mod disk;
+pub mod machine;
@@ -0,0 +1,663 @@
+// An interactive monitor: stepping, breakpoints, registers, memory and disassembly. Works on any
+// reader/writer pair so sessions can be scripted.
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use crate::disassembler::{decode_memory, Instruction};
+use crate::state::State8080;
+use crate::watch::{WatchKind, Watchpoint};
+
+const HELP: &str = "commands (addresses and values are hex or symbol names, optionally +/- hex,
+counts of instructions and cycles are decimal):
+    s, step [n]               execute n instructions (default 1)
+    n, next                   step, running through CALLs and RSTs
+    c, continue               run until a breakpoint, watchpoint, HLT or the program exits
+    bs, back [n]              undo n instructions (default 1)
+    rc, reverse               run backwards until a breakpoint or the start of the history
+    rw, rewind <n>            go back n cycles
+                              (going back stops after the last BDOS or BIOS call, what it did
+                              can't be undone)
+    b, break [addr]           set a breakpoint, or list them
+    d, delete <addr>|all      remove breakpoints
+    w, watch [addr[-end] [r|w|c] [=value]]
+                              stop on reads, writes or changes (the default) to memory, only
+                              when the value read or written is `value` if given; or list them
+    u, unwatch <id>|all       remove watchpoints
+    r, regs                   show registers and flags
+    r, regs <reg> <value>     set A B C D E H L F BC DE HL SP PC
+    x, dump [addr] [len]      hex dump memory (default 80h bytes from where the last dump ended)
+    e, edit <addr> <byte>...  write bytes to memory
+    l, list [addr] [n]        disassemble n instructions (default: around PC)
+    h, help                   this text
+    q, quit                   leave the debugger
+an empty line repeats the last step, next, continue, dump or list";
+
+const DEFAULT_DUMP_LENGTH: u16 = 0x80;
+const DEFAULT_LIST_LENGTH: usize = 10;
+// how many instructions `list` shows before PC
+const LIST_BEFORE: usize = 4;
+
+// Called before every instruction, for machines that service calls outside the CPU (like the
+// BDOS). Returns `false` once the program has exited.
+pub type Trap<'a> = dyn FnMut(&mut State8080) -> io::Result<bool> + 'a;
+
+#[derive(PartialEq)]
+enum Stop {
+    Breakpoint,
+    Watchpoint,
+    // at an undocumented opcode in strict mode
+    Undocumented,
+    Halted,
+    Exited,
+    Done,
+}
+
+pub struct Debugger<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    breakpoints: BTreeSet<u16>,
+    exited: bool,
+    last_command: String,
+    next_dump: u16,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Debugger {
+            input,
+            output,
+            breakpoints: BTreeSet::new(),
+            exited: false,
+            last_command: String::new(),
+            next_dump: 0,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    // runs commands until `quit` or the end of the input
+    pub fn run(&mut self, state: &mut State8080, trap: &mut Trap) -> io::Result<()> {
+        self.show_position(state)?;
+
+        loop {
+            write!(self.output, "> ")?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                writeln!(self.output)?;
+                return Ok(());
+            }
+
+            let mut line = line.trim().to_string();
+            if line.is_empty() {
+                line = self.last_command.clone();
+            }
+
+            match self.command(state, trap, &line) {
+                Ok(true) => (),
+                Ok(false) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                    writeln!(self.output, "{}", e)?
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // `false` to quit
+    fn command(&mut self, state: &mut State8080, trap: &mut Trap, line: &str) -> io::Result<bool> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (command.to_lowercase(), args),
+            None => return Ok(true),
+        };
+
+        // commands that can go on where they left off
+        if [
+            "s", "step", "n", "next", "c", "continue", "bs", "back", "rc", "reverse", "x", "dump",
+            "l", "list",
+        ]
+        .contains(&command.as_str())
+        {
+            self.last_command = command.clone();
+        }
+
+        match command.as_str() {
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(count) => parse_count(count)?,
+                    None => 1,
+                };
+                for _ in 0..count {
+                    if self.step(state, trap)? != Stop::Done {
+                        break;
+                    }
+                }
+                self.show_position(state)?;
+            }
+            "n" | "next" => {
+                let instruction = decode_memory(state.memory(), state.pc);
+                if is_call(&instruction) {
+                    self.run_until(state, trap, Some(instruction.next_address()))?;
+                } else {
+                    self.step(state, trap)?;
+                }
+                self.show_position(state)?;
+            }
+            "c" | "continue" => {
+                self.run_until(state, trap, None)?;
+                self.show_position(state)?;
+            }
+            "bs" | "back" => {
+                let count = match args.first() {
+                    Some(count) => parse_count(count)?,
+                    None => 1,
+                };
+                for _ in 0..count {
+                    if !self.step_back(state)? {
+                        break;
+                    }
+                }
+                self.show_position(state)?;
+            }
+            "rc" | "reverse" => {
+                while self.step_back(state)? {
+                    if self.breakpoints.contains(&state.pc) {
+                        writeln!(self.output, "breakpoint at {}", describe(state, state.pc))?;
+                        break;
+                    }
+                }
+                self.show_position(state)?;
+            }
+            "rw" | "rewind" => {
+                let cycles = match args.first() {
+                    Some(cycles) => parse_count(cycles)?,
+                    None => return Err(invalid("rewind how many cycles?")),
+                };
+                self.check_history(state)?;
+                let rewound = state.rewind_cycles(cycles);
+                if rewound < cycles {
+                    writeln!(self.output, "start of the history")?;
+                }
+                self.exited &= rewound == 0;
+                self.show_position(state)?;
+            }
+            "b" | "break" => match args.first() {
+                Some(address) => {
+                    let address = parse_value(state, address)?;
+                    self.breakpoints.insert(address);
+                }
+                None => {
+                    for address in &self.breakpoints {
+                        writeln!(self.output, "{}", describe(state, *address))?;
+                    }
+                }
+            },
+            "d" | "delete" => match args.first() {
+                Some(&"all") => self.breakpoints.clear(),
+                Some(address) => {
+                    let address = parse_value(state, address)?;
+                    if !self.breakpoints.remove(&address) {
+                        return Err(invalid("no breakpoint there"));
+                    }
+                }
+                None => return Err(invalid("delete what?")),
+            },
+            "w" | "watch" => match args.split_first() {
+                Some((range, options)) => {
+                    let watchpoint = parse_watchpoint(state, range, options)?;
+                    let id = state.add_watchpoint(watchpoint);
+                    writeln!(self.output, "watchpoint {}", id)?;
+                }
+                None => {
+                    for (id, watchpoint) in state.watchpoints().iter() {
+                        writeln!(
+                            self.output,
+                            "{}: {:04X}-{:04X} {:?}{}",
+                            id,
+                            watchpoint.start,
+                            watchpoint.end,
+                            watchpoint.kind,
+                            if watchpoint.is_conditional() {
+                                " (conditional)"
+                            } else {
+                                ""
+                            }
+                        )?;
+                    }
+                }
+            },
+            "u" | "unwatch" => match args.first() {
+                Some(&"all") => state.watchpoints_mut().clear(),
+                Some(id) => {
+                    let id = id.parse().map_err(|_| invalid("bad watchpoint number"))?;
+                    if !state.remove_watchpoint(id) {
+                        return Err(invalid("no such watchpoint"));
+                    }
+                }
+                None => return Err(invalid("unwatch what?")),
+            },
+            "r" | "regs" => match args {
+                [] => self.show_registers(state)?,
+                [register, value] => {
+                    let value = parse_value(state, value)?;
+                    set_register(state, register, value)?;
+                    self.show_registers(state)?;
+                }
+                _ => return Err(invalid("usage: regs [<register> <value>]")),
+            },
+            "x" | "dump" => {
+                let start = match args.first() {
+                    Some(address) => parse_value(state, address)?,
+                    None => self.next_dump,
+                };
+                let length = match args.get(1) {
+                    Some(length) => parse_hex(length)?,
+                    None => DEFAULT_DUMP_LENGTH,
+                };
+                self.dump(state, start, length)?;
+                self.next_dump = start.wrapping_add(length);
+            }
+            "e" | "edit" => {
+                let (address, bytes) = match args.split_first() {
+                    Some((address, bytes)) if !bytes.is_empty() => (address, bytes),
+                    _ => return Err(invalid("usage: edit <addr> <byte>...")),
+                };
+                let address = parse_value(state, address)?;
+
+                for (i, byte) in bytes.iter().enumerate() {
+                    let value = parse_value(state, byte)?;
+                    if value > 0xFF {
+                        return Err(invalid("bytes go up to FF"));
+                    }
+                    state
+                        .memory_mut()
+                        .poke(address.wrapping_add(i as u16), value as u8);
+                }
+            }
+            "l" | "list" => match args.first() {
+                Some(address) => {
+                    let address = parse_value(state, address)?;
+                    let count = match args.get(1) {
+                        Some(count) => parse_count(count)? as usize,
+                        None => DEFAULT_LIST_LENGTH,
+                    };
+                    self.list(state, address, count)?;
+                }
+                None => {
+                    let start = list_start(state, state.pc, LIST_BEFORE);
+                    self.list(state, start, DEFAULT_LIST_LENGTH)?;
+                }
+            },
+            "h" | "help" | "?" => writeln!(self.output, "{}", HELP)?,
+            "q" | "quit" => return Ok(false),
+            _ => return Err(invalid("unknown command, try help")),
+        }
+
+        Ok(true)
+    }
+
+    // runs one instruction, giving the machine its chance to trap first
+    fn step(&mut self, state: &mut State8080, trap: &mut Trap) -> io::Result<Stop> {
+        if self.exited {
+            writeln!(self.output, "the program has exited")?;
+            return Ok(Stop::Exited);
+        }
+
+        if !trap(state)? {
+            self.exited = true;
+            writeln!(self.output, "the program has exited")?;
+            return Ok(Stop::Exited);
+        }
+
+        // nothing here raises interrupts, so a HLT would never end
+        if state.halted() {
+            writeln!(self.output, "halted")?;
+            return Ok(Stop::Halted);
+        }
+
+        state.emulate_cycle();
+
+        if let Some(hit) = state.take_watch_hit() {
+            writeln!(self.output, "{}", hit)?;
+            return Ok(Stop::Watchpoint);
+        }
+        if let Some(opcode) = state.take_undocumented_opcode() {
+            writeln!(self.output, "{}", opcode)?;
+            return Ok(Stop::Undocumented);
+        }
+        Ok(Stop::Done)
+    }
+
+    // `false` at the start of the history
+    fn step_back(&mut self, state: &mut State8080) -> io::Result<bool> {
+        self.check_history(state)?;
+
+        if !state.step_back() {
+            writeln!(self.output, "start of the history")?;
+            return Ok(false);
+        }
+        self.exited = false;
+        Ok(true)
+    }
+
+    fn check_history(&self, state: &State8080) -> io::Result<()> {
+        match state.rewind() {
+            Some(_) => Ok(()),
+            None => Err(invalid("execution history isn't being recorded")),
+        }
+    }
+
+    // runs until a breakpoint (not counting the one at the starting PC) or `until`
+    fn run_until(
+        &mut self,
+        state: &mut State8080,
+        trap: &mut Trap,
+        until: Option<u16>,
+    ) -> io::Result<Stop> {
+        loop {
+            let stop = self.step(state, trap)?;
+            if stop != Stop::Done {
+                return Ok(stop);
+            }
+
+            if Some(state.pc) == until {
+                return Ok(Stop::Done);
+            }
+            if self.breakpoints.contains(&state.pc) {
+                writeln!(self.output, "breakpoint at {}", describe(state, state.pc))?;
+                return Ok(Stop::Breakpoint);
+            }
+        }
+    }
+
+    fn show_position(&mut self, state: &State8080) -> io::Result<()> {
+        self.show_registers(state)?;
+        let instruction = decode_memory(state.memory(), state.pc);
+        writeln!(self.output, "{}", format_line(state, &instruction, true))
+    }
+
+    fn show_registers(&mut self, state: &State8080) -> io::Result<()> {
+        let flags = state.flags();
+        let flag_names: String = [
+            (0x80, 'S'),
+            (0x40, 'Z'),
+            (0x10, 'A'),
+            (0x04, 'P'),
+            (0x01, 'C'),
+        ]
+        .iter()
+        .map(|&(bit, name)| if flags & bit != 0 { name } else { '-' })
+        .collect();
+
+        writeln!(
+            self.output,
+            "A={:02X} BC={:02X}{:02X} DE={:02X}{:02X} HL={:02X}{:02X} SP={:04X} PC={} F={:02X} {} INTE={} cycles={}",
+            state.a,
+            state.b,
+            state.c,
+            state.d,
+            state.e,
+            state.h,
+            state.l,
+            state.sp,
+            describe(state, state.pc),
+            flags,
+            flag_names,
+            state.interrupts_enabled() as u8,
+            state.cycles()
+        )
+    }
+
+    fn dump(&mut self, state: &State8080, start: u16, length: u16) -> io::Result<()> {
+        let mut offset = 0u16;
+
+        while offset < length {
+            let address = start.wrapping_add(offset);
+            let count = (length - offset).min(16);
+            let bytes: Vec<u8> = (0..count)
+                .map(|i| state.memory().peek(address.wrapping_add(i)))
+                .collect();
+
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&b| {
+                    if (0x20..0x7F).contains(&b) {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+
+            writeln!(
+                self.output,
+                "{:04X}  {:<48} {}",
+                address,
+                hex.join(" "),
+                ascii
+            )?;
+            offset += count;
+        }
+
+        Ok(())
+    }
+
+    fn list(&mut self, state: &State8080, start: u16, count: usize) -> io::Result<()> {
+        let mut address = start;
+
+        for _ in 0..count {
+            let instruction = decode_memory(state.memory(), address);
+            if let Some(name) = state.symbols().name_at(address) {
+                writeln!(self.output, "{}:", name)?;
+            }
+            writeln!(
+                self.output,
+                "{}",
+                format_line(state, &instruction, address == state.pc)
+            )?;
+            address = instruction.next_address();
+        }
+
+        Ok(())
+    }
+}
+
+// CALLs, conditional CALLs and RSTs come back to the next instruction
+fn is_call(instruction: &Instruction) -> bool {
+    instruction.mnemonic.starts_with('C') && instruction.target.is_some()
+        || instruction.mnemonic == "RST"
+}
+
+// Instructions can't be decoded backwards, so this looks for an address up to 3 * `before` bytes
+// back from which decoding lands exactly on `pc`, preferring the one showing the most
+// instructions (at most `before`).
+fn list_start(state: &State8080, pc: u16, before: usize) -> u16 {
+    let mut best = pc;
+    let mut best_count = 0;
+
+    for distance in 1..=(before * 3) as u16 {
+        let start = pc.wrapping_sub(distance);
+        let mut address = start;
+        let mut count = 0;
+
+        while address != pc && count < before {
+            address = decode_memory(state.memory(), address).next_address();
+            count += 1;
+            // stepped over `pc`
+            if address.wrapping_sub(start) > distance {
+                break;
+            }
+        }
+
+        if address == pc && count > best_count {
+            best = start;
+            best_count = count;
+        }
+    }
+
+    best
+}
+
+fn format_line(state: &State8080, instruction: &Instruction, current: bool) -> String {
+    let bytes: Vec<String> = instruction
+        .bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    let text = instruction.format_with(|value| state.symbols().name_at(value).map(str::to_string));
+
+    format!(
+        "{} {:04X}  {:<9} {}",
+        if current { "=>" } else { "  " },
+        instruction.address,
+        bytes.join(" "),
+        text
+    )
+}
+
+fn describe(state: &State8080, address: u16) -> String {
+    match state.symbols().describe(address) {
+        Some(name) => format!("{:04X} ({})", address, name),
+        None => format!("{:04X}", address),
+    }
+}
+
+// `addr[-end] [r|w|c] [=value]`
+fn parse_watchpoint(state: &State8080, range: &str, options: &[&str]) -> io::Result<Watchpoint> {
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) if !start.is_empty() => {
+            (parse_value(state, start)?, parse_value(state, end)?)
+        }
+        _ => {
+            let address = parse_value(state, range)?;
+            (address, address)
+        }
+    };
+    if end < start {
+        return Err(invalid("the range ends before it starts"));
+    }
+
+    let mut kind = WatchKind::Change;
+    let mut value = None;
+    for option in options {
+        match option.to_lowercase().as_str() {
+            "r" => kind = WatchKind::Read,
+            "w" => kind = WatchKind::Write,
+            "c" => kind = WatchKind::Change,
+            option if option.starts_with('=') => {
+                let expected = parse_value(state, &option[1..])?;
+                if expected > 0xFF {
+                    return Err(invalid("bytes go up to FF"));
+                }
+                value = Some(expected as u8);
+            }
+            _ => return Err(invalid("expected r, w, c or =value")),
+        }
+    }
+
+    let watchpoint = Watchpoint::new(start, end, kind);
+    Ok(match value {
+        Some(expected) => watchpoint.when(move |value| value == expected),
+        None => watchpoint,
+    })
+}
+
+fn set_register(state: &mut State8080, register: &str, value: u16) -> io::Result<()> {
+    let byte = || {
+        if value > 0xFF {
+            Err(invalid("8-bit registers go up to FF"))
+        } else {
+            Ok(value as u8)
+        }
+    };
+
+    match register.to_uppercase().as_str() {
+        "A" => state.a = byte()?,
+        "B" => state.b = byte()?,
+        "C" => state.c = byte()?,
+        "D" => state.d = byte()?,
+        "E" => state.e = byte()?,
+        "H" => state.h = byte()?,
+        "L" => state.l = byte()?,
+        "F" => state.set_flags(byte()?),
+        "BC" => {
+            state.b = (value >> 8) as u8;
+            state.c = value as u8;
+        }
+        "DE" => {
+            state.d = (value >> 8) as u8;
+            state.e = value as u8;
+        }
+        "HL" => {
+            state.h = (value >> 8) as u8;
+            state.l = value as u8;
+        }
+        "SP" => state.sp = value,
+        "PC" => state.pc = value,
+        _ => return Err(invalid("unknown register")),
+    }
+
+    Ok(())
+}
+
+// a symbol or hex number, plus or minus hex offsets, e.g. `PRINT+3`
+fn parse_value(state: &State8080, text: &str) -> io::Result<u16> {
+    let mut value = 0u16;
+    let mut rest = text;
+    let mut negative = false;
+
+    loop {
+        // the first character is never an operator, even if it's a sign (or not ASCII at all)
+        let first = rest.chars().next().map_or(0, char::len_utf8);
+        let end = rest[first..]
+            .find(['+', '-'])
+            .map(|i| i + first)
+            .unwrap_or(rest.len());
+        let term = &rest[..end];
+
+        let term_value = match state.symbols().lookup(term) {
+            Some(address) => address,
+            None => parse_hex(term)?,
+        };
+        value = if negative {
+            value.wrapping_sub(term_value)
+        } else {
+            value.wrapping_add(term_value)
+        };
+
+        if end == rest.len() {
+            return Ok(value);
+        }
+        negative = rest[end..].starts_with('-');
+        rest = &rest[end + 1..];
+        if rest.is_empty() {
+            return Err(invalid("bad value"));
+        }
+    }
+}
+
+// hex with an optional 0x/$ prefix or H suffix
+fn parse_hex(text: &str) -> io::Result<u16> {
+    let upper = text.to_uppercase();
+    let digits = upper
+        .strip_prefix("0X")
+        .or_else(|| upper.strip_prefix('$'))
+        .or_else(|| upper.strip_suffix('H'))
+        .unwrap_or(&upper);
+
+    u16::from_str_radix(digits, 16).map_err(|_| invalid(&format!("bad value {}", text)))
+}
+
+fn parse_count(text: &str) -> io::Result<u64> {
+    text.parse()
+        .map_err(|_| invalid(&format!("bad count {}", text)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -1,1006 +1,465 @@
+// Instruction decoding for the disassembler, the debugger, the tracer and the assembler. Decoding
+// gives a structured `Instruction`, turning it into text is left to `Display`/`format_with`.
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
-use std::fs::File;
-use std::io::Read;
+use std::fmt;
+use std::fs;
 
-pub fn disassemble(file_path: &String) -> Result<(), Box<dyn Error>> {
-    let mut file = File::open(file_path)?;
-    let mut buffer = Vec::new();
-    let bytes = file.read_to_end(&mut buffer)?;
+use crate::assembler::is_identifier;
+use crate::memory::MemoryBus;
+use crate::symbols::SymbolTable;
 
-    println!("ROM size: {} bytes", bytes);
+// Every opcode as written in Intel's syntax, with placeholders for the operand bytes: `d8` and
+// `d16` for immediate data, `a16` for a memory address and `j16` for a jump or call target.
+// Undocumented opcodes are marked with a `*`, they are aliases of a documented instruction and
+// the assembler never picks them.
+#[rustfmt::skip]
+pub const OPCODES: [&str; 256] = [
+    "NOP", "LXI B,d16", "STAX B", "INX B", "INR B", "DCR B", "MVI B,d8", "RLC", // 0x00
+    "*NOP", "DAD B", "LDAX B", "DCX B", "INR C", "DCR C", "MVI C,d8", "RRC", // 0x08
+    "*NOP", "LXI D,d16", "STAX D", "INX D", "INR D", "DCR D", "MVI D,d8", "RAL", // 0x10
+    "*NOP", "DAD D", "LDAX D", "DCX D", "INR E", "DCR E", "MVI E,d8", "RAR", // 0x18
+    "*NOP", "LXI H,d16", "SHLD a16", "INX H", "INR H", "DCR H", "MVI H,d8", "DAA", // 0x20
+    "*NOP", "DAD H", "LHLD a16", "DCX H", "INR L", "DCR L", "MVI L,d8", "CMA", // 0x28
+    "*NOP", "LXI SP,d16", "STA a16", "INX SP", "INR M", "DCR M", "MVI M,d8", "STC", // 0x30
+    "*NOP", "DAD SP", "LDA a16", "DCX SP", "INR A", "DCR A", "MVI A,d8", "CMC", // 0x38
+    "MOV B,B", "MOV B,C", "MOV B,D", "MOV B,E", "MOV B,H", "MOV B,L", "MOV B,M", "MOV B,A", // 0x40
+    "MOV C,B", "MOV C,C", "MOV C,D", "MOV C,E", "MOV C,H", "MOV C,L", "MOV C,M", "MOV C,A", // 0x48
+    "MOV D,B", "MOV D,C", "MOV D,D", "MOV D,E", "MOV D,H", "MOV D,L", "MOV D,M", "MOV D,A", // 0x50
+    "MOV E,B", "MOV E,C", "MOV E,D", "MOV E,E", "MOV E,H", "MOV E,L", "MOV E,M", "MOV E,A", // 0x58
+    "MOV H,B", "MOV H,C", "MOV H,D", "MOV H,E", "MOV H,H", "MOV H,L", "MOV H,M", "MOV H,A", // 0x60
+    "MOV L,B", "MOV L,C", "MOV L,D", "MOV L,E", "MOV L,H", "MOV L,L", "MOV L,M", "MOV L,A", // 0x68
+    "MOV M,B", "MOV M,C", "MOV M,D", "MOV M,E", "MOV M,H", "MOV M,L", "HLT", "MOV M,A", // 0x70
+    "MOV A,B", "MOV A,C", "MOV A,D", "MOV A,E", "MOV A,H", "MOV A,L", "MOV A,M", "MOV A,A", // 0x78
+    "ADD B", "ADD C", "ADD D", "ADD E", "ADD H", "ADD L", "ADD M", "ADD A", // 0x80
+    "ADC B", "ADC C", "ADC D", "ADC E", "ADC H", "ADC L", "ADC M", "ADC A", // 0x88
+    "SUB B", "SUB C", "SUB D", "SUB E", "SUB H", "SUB L", "SUB M", "SUB A", // 0x90
+    "SBB B", "SBB C", "SBB D", "SBB E", "SBB H", "SBB L", "SBB M", "SBB A", // 0x98
+    "ANA B", "ANA C", "ANA D", "ANA E", "ANA H", "ANA L", "ANA M", "ANA A", // 0xA0
+    "XRA B", "XRA C", "XRA D", "XRA E", "XRA H", "XRA L", "XRA M", "XRA A", // 0xA8
+    "ORA B", "ORA C", "ORA D", "ORA E", "ORA H", "ORA L", "ORA M", "ORA A", // 0xB0
+    "CMP B", "CMP C", "CMP D", "CMP E", "CMP H", "CMP L", "CMP M", "CMP A", // 0xB8
+    "RNZ", "POP B", "JNZ j16", "JMP j16", "CNZ j16", "PUSH B", "ADI d8", "RST 0", // 0xC0
+    "RZ", "RET", "JZ j16", "*JMP j16", "CZ j16", "CALL j16", "ACI d8", "RST 1", // 0xC8
+    "RNC", "POP D", "JNC j16", "OUT d8", "CNC j16", "PUSH D", "SUI d8", "RST 2", // 0xD0
+    "RC", "*RET", "JC j16", "IN d8", "CC j16", "*CALL j16", "SBI d8", "RST 3", // 0xD8
+    "RPO", "POP H", "JPO j16", "XTHL", "CPO j16", "PUSH H", "ANI d8", "RST 4", // 0xE0
+    "RPE", "PCHL", "JPE j16", "XCHG", "CPE j16", "*CALL j16", "XRI d8", "RST 5", // 0xE8
+    "RP", "POP PSW", "JP j16", "DI", "CP j16", "PUSH PSW", "ORI d8", "RST 6", // 0xF0
+    "RM", "SPHL", "JM j16", "EI", "CM j16", "*CALL j16", "CPI d8", "RST 7", // 0xF8
+];
 
-    let mut pc: usize = 0;
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    // B, C, D, E, H, L, M or A, or a register pair: B, D, H, SP or PSW
+    Register(&'static str),
+    Byte(u8),
+    Word(u16),
+    // a memory address (LDA, SHLD...) or a branch target (JMP, CALL...)
+    Address(u16),
+    Restart(u8),
+}
 
-    while pc < buffer.len() {
-        let opcode = buffer[pc];
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+    pub bytes: Vec<u8>,
+    // where a JMP, CALL, RST or one of their conditional forms goes (if taken)
+    pub target: Option<u16>,
+    pub undocumented: bool,
+}
 
-        match opcode {
-            0x00 | 0x08 | 0x10 | 0x18 | 0x28 | 0x38 | 0xCB | 0xD9 | 0xDD | 0xED | 0xFD => {
-                println!("NOP");
-                pc += 1;
-            }
-            0x01 => {
-                println!("LXI   B,#${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0x02 => {
-                println!("STAX  B");
-                pc += 1;
-            }
-            0x03 => {
-                println!("INX   B");
-                pc += 1;
-            }
-            0x04 => {
-                println!("INR   B");
-                pc += 1;
-            }
-            0x05 => {
-                println!("DCR   B");
-                pc += 1;
-            }
-            0x06 => {
-                println!("MVI   B,#${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0x07 => {
-                println!("RLC");
-                pc += 1;
-            }
-            0x09 => {
-                println!("DAD   B");
-                pc += 1;
-            }
-            0x0A => {
-                println!("LDAX  B");
-                pc += 1;
-            }
-            0x0B => {
-                println!("DCX   B");
-                pc += 1;
-            }
-            0x0C => {
-                println!("INR   C");
-                pc += 1;
-            }
-            0x0D => {
-                println!("DCR   C");
-                pc += 1;
-            }
-            0x0E => {
-                println!("MVI   C,#${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0x0F => {
-                println!("RRC");
-                pc += 1;
-            }
-            0x11 => {
-                println!("LXI   D,#${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0x12 => {
-                println!("STAX  D");
-                pc += 1;
-            }
-            0x13 => {
-                println!("INX   D");
-                pc += 1;
-            }
-            0x14 => {
-                println!("INR   D");
-                pc += 1;
-            }
-            0x15 => {
-                println!("DCR   D");
-                pc += 1;
-            }
-            0x16 => {
-                println!("MVI   D,#${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0x17 => {
-                println!("RAL");
-                pc += 1;
-            }
-            0x19 => {
-                println!("DAD   D");
-                pc += 1;
-            }
-            0x1A => {
-                println!("LDAX  D");
-                pc += 1;
-            }
-            0x1B => {
-                println!("DCX   D");
-                pc += 1;
-            }
-            0x1C => {
-                println!("INR   E");
-                pc += 1;
-            }
-            0x1D => {
-                println!("DCR   E");
-                pc += 1;
-            }
-            0x1E => {
-                println!("MVI   E,#${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0x1F => {
-                println!("RAR");
-                pc += 1;
-            }
-            0x20 => {
-                println!("NOP/RIM"); // NOP in 8080, RIM in 8085
-                pc += 1;
-            }
-            0x21 => {
-                println!("LXI   H,#${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0x22 => {
-                println!("SHLD  ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0x23 => {
-                println!("INX   H");
-                pc += 1;
-            }
-            0x24 => {
-                println!("INR   H");
-                pc += 1;
-            }
-            0x25 => {
-                println!("DCR   H");
-                pc += 1;
-            }
-            0x26 => {
-                println!("MVI   H,#${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0x27 => {
-                println!("DAA");
-                pc += 1;
-            }
-            0x29 => {
-                println!("DAD   H");
-                pc += 1;
-            }
-            0x2A => {
-                println!("LHLD  ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0x2B => {
-                println!("DCX   H");
-                pc += 1;
-            }
-            0x2C => {
-                println!("INR   L");
-                pc += 1;
-            }
-            0x2D => {
-                println!("DCR   L");
-                pc += 1;
-            }
-            0x2E => {
-                println!("MVI   L,#${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0x2F => {
-                println!("CMA");
-                pc += 1;
-            }
-            0x30 => {
-                println!("NOP/SIM"); // NOP in 8080, SIM in 8085
-                pc += 1;
-            }
-            0x31 => {
-                println!("LXI   SP,#${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0x32 => {
-                println!("STA   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0x33 => {
-                println!("INX   SP");
-                pc += 1;
-            }
-            0x34 => {
-                println!("INR   M");
-                pc += 1;
-            }
-            0x35 => {
-                println!("DCR   M");
-                pc += 1;
-            }
-            0x36 => {
-                println!("MVI   M,#${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0x37 => {
-                println!("STC");
-                pc += 1;
-            }
-            0x39 => {
-                println!("DAD   SP");
-                pc += 1;
-            }
-            0x3A => {
-                println!("LDA   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0x3B => {
-                println!("DCX   SP");
-                pc += 1;
-            }
-            0x3C => {
-                println!("INR   A");
-                pc += 1;
-            }
-            0x3D => {
-                println!("DCR   A");
-                pc += 1;
-            }
-            0x3E => {
-                println!("MVI   A,#${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0x3F => {
-                println!("CMC");
-                pc += 1;
-            }
-            0x40 => {
-                println!("MOV   B,B");
-                pc += 1;
-            }
-            0x41 => {
-                println!("MOV   B,C");
-                pc += 1;
-            }
-            0x42 => {
-                println!("MOV   B,D");
-                pc += 1;
-            }
-            0x43 => {
-                println!("MOV   B,E");
-                pc += 1;
-            }
-            0x44 => {
-                println!("MOV   B,H");
-                pc += 1;
-            }
-            0x45 => {
-                println!("MOV   B,L");
-                pc += 1;
-            }
-            0x46 => {
-                println!("MOV   B,M");
-                pc += 1;
-            }
-            0x47 => {
-                println!("MOV   B,A");
-                pc += 1;
-            }
-            0x48 => {
-                println!("MOV   C,B");
-                pc += 1;
-            }
-            0x49 => {
-                println!("MOV   C,C");
-                pc += 1;
-            }
-            0x4A => {
-                println!("MOV   C,D");
-                pc += 1;
-            }
-            0x4B => {
-                println!("MOV   C,E");
-                pc += 1;
-            }
-            0x4C => {
-                println!("MOV   C,H");
-                pc += 1;
-            }
-            0x4D => {
-                println!("MOV   C,L");
-                pc += 1;
-            }
-            0x4E => {
-                println!("MOV   C,M");
-                pc += 1;
-            }
-            0x4F => {
-                println!("MOV   C,A");
-                pc += 1;
-            }
-            0x50 => {
-                println!("MOV   D,B");
-                pc += 1;
-            }
-            0x51 => {
-                println!("MOV   D,C");
-                pc += 1;
-            }
-            0x52 => {
-                println!("MOV   D,D");
-                pc += 1;
-            }
-            0x53 => {
-                println!("MOV   D,E");
-                pc += 1;
-            }
-            0x54 => {
-                println!("MOV   D,H");
-                pc += 1;
-            }
-            0x55 => {
-                println!("MOV   D,L");
-                pc += 1;
-            }
-            0x56 => {
-                println!("MOV   D,M");
-                pc += 1;
-            }
-            0x57 => {
-                println!("MOV   D,A");
-                pc += 1;
-            }
-            0x58 => {
-                println!("MOV   E,B");
-                pc += 1;
-            }
-            0x59 => {
-                println!("MOV   E,C");
-                pc += 1;
-            }
-            0x5A => {
-                println!("MOV   E,D");
-                pc += 1;
-            }
-            0x5B => {
-                println!("MOV   E,E");
-                pc += 1;
-            }
-            0x5C => {
-                println!("MOV   E,H");
-                pc += 1;
-            }
-            0x5D => {
-                println!("MOV   E,L");
-                pc += 1;
-            }
-            0x5E => {
-                println!("MOV   E,M");
-                pc += 1;
-            }
-            0x5F => {
-                println!("MOV   E,A");
-                pc += 1;
-            }
-            0x60 => {
-                println!("MOV   H,B");
-                pc += 1;
-            }
-            0x61 => {
-                println!("MOV   H,C");
-                pc += 1;
-            }
-            0x62 => {
-                println!("MOV   H,D");
-                pc += 1;
-            }
-            0x63 => {
-                println!("MOV   H,E");
-                pc += 1;
-            }
-            0x64 => {
-                println!("MOV   H,H");
-                pc += 1;
-            }
-            0x65 => {
-                println!("MOV   H,L");
-                pc += 1;
-            }
-            0x66 => {
-                println!("MOV   H,M");
-                pc += 1;
-            }
-            0x67 => {
-                println!("MOV   H,A");
-                pc += 1;
-            }
-            0x68 => {
-                println!("MOV   L,B");
-                pc += 1;
-            }
-            0x69 => {
-                println!("MOV   L,C");
-                pc += 1;
-            }
-            0x6A => {
-                println!("MOV   L,D");
-                pc += 1;
-            }
-            0x6B => {
-                println!("MOV   L,E");
-                pc += 1;
-            }
-            0x6C => {
-                println!("MOV   L,H");
-                pc += 1;
-            }
-            0x6D => {
-                println!("MOV   L,L");
-                pc += 1;
-            }
-            0x6E => {
-                println!("MOV   L,M");
-                pc += 1;
-            }
-            0x6F => {
-                println!("MOV   L,A");
-                pc += 1;
-            }
-            0x70 => {
-                println!("MOV   M,B");
-                pc += 1;
-            }
-            0x71 => {
-                println!("MOV   M,C");
-                pc += 1;
-            }
-            0x72 => {
-                println!("MOV   M,D");
-                pc += 1;
-            }
-            0x73 => {
-                println!("MOV   M,E");
-                pc += 1;
-            }
-            0x74 => {
-                println!("MOV   M,H");
-                pc += 1;
-            }
-            0x75 => {
-                println!("MOV   M,L");
-                pc += 1;
-            }
-            0x76 => {
-                println!("HLT");
-                pc += 1;
-            }
-            0x77 => {
-                println!("MOV   M,A");
-                pc += 1;
-            }
-            0x78 => {
-                println!("MOV   A,B");
-                pc += 1;
-            }
-            0x79 => {
-                println!("MOV   A,C");
-                pc += 1;
-            }
-            0x7A => {
-                println!("MOV   A,D");
-                pc += 1;
-            }
-            0x7B => {
-                println!("MOV   A,E");
-                pc += 1;
-            }
-            0x7C => {
-                println!("MOV   A,H");
-                pc += 1;
-            }
-            0x7D => {
-                println!("MOV   A,L");
-                pc += 1;
-            }
-            0x7E => {
-                println!("MOV   A,M");
-                pc += 1;
-            }
-            0x7F => {
-                println!("MOV   A,A");
-                pc += 1;
-            }
-            0x80 => {
-                println!("ADD   B");
-                pc += 1;
-            }
-            0x81 => {
-                println!("ADD   C");
-                pc += 1;
-            }
-            0x82 => {
-                println!("ADD   D");
-                pc += 1;
-            }
-            0x83 => {
-                println!("ADD   E");
-                pc += 1;
-            }
-            0x84 => {
-                println!("ADD   H");
-                pc += 1;
-            }
-            0x85 => {
-                println!("ADD   L");
-                pc += 1;
-            }
-            0x86 => {
-                println!("ADD   M");
-                pc += 1;
-            }
-            0x87 => {
-                println!("ADD   A");
-                pc += 1;
-            }
-            0x88 => {
-                println!("ADC   B");
-                pc += 1;
-            }
-            0x89 => {
-                println!("ADC   C");
-                pc += 1;
-            }
-            0x8A => {
-                println!("ADC   D");
-                pc += 1;
-            }
-            0x8B => {
-                println!("ADC   E");
-                pc += 1;
-            }
-            0x8C => {
-                println!("ADC   H");
-                pc += 1;
-            }
-            0x8D => {
-                println!("ADC   L");
-                pc += 1;
-            }
-            0x8E => {
-                println!("ADC   M");
-                pc += 1;
-            }
-            0x8F => {
-                println!("ADC   A");
-                pc += 1;
-            }
-            0x90 => {
-                println!("SUB   B");
-                pc += 1;
-            }
-            0x91 => {
-                println!("SUB   C");
-                pc += 1;
-            }
-            0x92 => {
-                println!("SUB   D");
-                pc += 1;
-            }
-            0x93 => {
-                println!("SUB   E");
-                pc += 1;
-            }
-            0x94 => {
-                println!("SUB   H");
-                pc += 1;
-            }
-            0x95 => {
-                println!("SUB   L");
-                pc += 1;
-            }
-            0x96 => {
-                println!("SUB   M");
-                pc += 1;
-            }
-            0x97 => {
-                println!("SUB   A");
-                pc += 1;
-            }
-            0x98 => {
-                println!("SBB   B");
-                pc += 1;
-            }
-            0x99 => {
-                println!("SBB   C");
-                pc += 1;
-            }
-            0x9A => {
-                println!("SBB   D");
-                pc += 1;
-            }
-            0x9B => {
-                println!("SBB   E");
-                pc += 1;
-            }
-            0x9C => {
-                println!("SBB   H");
-                pc += 1;
-            }
-            0x9D => {
-                println!("SBB   L");
-                pc += 1;
-            }
-            0x9E => {
-                println!("SBB   M");
-                pc += 1;
-            }
-            0x9F => {
-                println!("SBB   A");
-                pc += 1;
-            }
-            0xA0 => {
-                println!("ANA   B");
-                pc += 1;
-            }
-            0xA1 => {
-                println!("ANA   C");
-                pc += 1;
-            }
-            0xA2 => {
-                println!("ANA   D");
-                pc += 1;
-            }
-            0xA3 => {
-                println!("ANA   E");
-                pc += 1;
-            }
-            0xA4 => {
-                println!("ANA   H");
-                pc += 1;
-            }
-            0xA5 => {
-                println!("ANA   L");
-                pc += 1;
-            }
-            0xA6 => {
-                println!("ANA   M");
-                pc += 1;
-            }
-            0xA7 => {
-                println!("ANA   A");
-                pc += 1;
-            }
-            0xA8 => {
-                println!("XRA   B");
-                pc += 1;
-            }
-            0xA9 => {
-                println!("XRA   C");
-                pc += 1;
-            }
-            0xAA => {
-                println!("XRA   D");
-                pc += 1;
-            }
-            0xAB => {
-                println!("XRA   E");
-                pc += 1;
-            }
-            0xAC => {
-                println!("XRA   H");
-                pc += 1;
-            }
-            0xAD => {
-                println!("XRA   L");
-                pc += 1;
-            }
-            0xAE => {
-                println!("XRA   M");
-                pc += 1;
-            }
-            0xAF => {
-                println!("XRA   A");
-                pc += 1;
-            }
-            0xB0 => {
-                println!("ORA   B");
-                pc += 1;
-            }
-            0xB1 => {
-                println!("ORA   C");
-                pc += 1;
-            }
-            0xB2 => {
-                println!("ORA   D");
-                pc += 1;
-            }
-            0xB3 => {
-                println!("ORA   E");
-                pc += 1;
-            }
-            0xB4 => {
-                println!("ORA   H");
-                pc += 1;
-            }
-            0xB5 => {
-                println!("ORA   L");
-                pc += 1;
-            }
-            0xB6 => {
-                println!("ORA   M");
-                pc += 1;
-            }
-            0xB7 => {
-                println!("ORA   A");
-                pc += 1;
-            }
-            0xB8 => {
-                println!("CMP   B");
-                pc += 1;
-            }
-            0xB9 => {
-                println!("CMP   C");
-                pc += 1;
-            }
-            0xBA => {
-                println!("CMP   D");
-                pc += 1;
-            }
-            0xBB => {
-                println!("CMP   E");
-                pc += 1;
-            }
-            0xBC => {
-                println!("CMP   H");
-                pc += 1;
-            }
-            0xBD => {
-                println!("CMP   L");
-                pc += 1;
-            }
-            0xBE => {
-                println!("CMP   M");
-                pc += 1;
-            }
-            0xBF => {
-                println!("CMP   A");
-                pc += 1;
-            }
-            0xC0 => {
-                println!("RNZ");
-                pc += 1;
-            }
-            0xC1 => {
-                println!("POP   B");
-                pc += 1;
-            }
-            0xC2 => {
-                println!("JNZ   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xC3 => {
-                println!("JMP   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xC4 => {
-                println!("CNZ   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xC5 => {
-                println!("PUSH  B");
-                pc += 1;
-            }
-            0xC6 => {
-                println!("ADI   ${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0xC7 => {
-                println!("RST   0");
-                pc += 1;
-            }
-            0xC8 => {
-                println!("RZ");
-                pc += 1;
-            }
-            0xC9 => {
-                println!("RET");
-                pc += 1;
-            }
-            0xCA => {
-                println!("JZ    ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xCC => {
-                println!("CZ    ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xCD => {
-                println!("CALL  ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xCE => {
-                println!("ACI   ${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0xCF => {
-                println!("RST   1");
-                pc += 1;
-            }
-            0xD0 => {
-                println!("RNC");
-                pc += 1;
-            }
-            0xD1 => {
-                println!("POP   D");
-                pc += 1;
-            }
-            0xD2 => {
-                println!("JNC   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xD3 => {
-                println!("OUT   ${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0xD4 => {
-                println!("CNC   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xD5 => {
-                println!("PUSH  D");
-                pc += 1;
-            }
-            0xD6 => {
-                println!("SUI   ${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0xD7 => {
-                println!("RST   2");
-                pc += 1;
-            }
-            0xD8 => {
-                println!("RC");
-                pc += 1;
-            }
-            0xDA => {
-                println!("JC    ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xDB => {
-                println!("IN    ${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0xDC => {
-                println!("CC    ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xDE => {
-                println!("SBI   ${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0xDF => {
-                println!("RST   3");
-                pc += 1;
-            }
-            0xE0 => {
-                println!("RPO");
-                pc += 1;
-            }
-            0xE1 => {
-                println!("POP   H");
-                pc += 1;
-            }
-            0xE2 => {
-                println!("JPO   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xE3 => {
-                println!("XTHL");
-                pc += 1;
-            }
-            0xE4 => {
-                println!("CPO   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xE5 => {
-                println!("PUSH  H");
-                pc += 1;
-            }
-            0xE6 => {
-                println!("ANI   ${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0xE7 => {
-                println!("RST   4");
-                pc += 1;
-            }
-            0xE8 => {
-                println!("RPE");
-                pc += 1;
-            }
-            0xE9 => {
-                println!("PCHL");
-                pc += 1;
-            }
-            0xEA => {
-                println!("JPE   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xEB => {
-                println!("XCHG");
-                pc += 1;
-            }
-            0xEC => {
-                println!("CPE   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xEE => {
-                println!("XRI   ${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0xEF => {
-                println!("RST   5");
-                pc += 1;
-            }
-            0xF0 => {
-                println!("RP");
-                pc += 1;
-            }
-            0xF1 => {
-                println!("POP   PSW");
-                pc += 1;
-            }
-            0xF2 => {
-                println!("JP    ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xF3 => {
-                println!("DI");
-                pc += 1;
-            }
-            0xF4 => {
-                println!("CP    ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xF5 => {
-                println!("PUSH  PSW");
-                pc += 1;
-            }
-            0xF6 => {
-                println!("ORI   ${:02x}", buffer[pc + 1]);
-                pc += 2;
-            }
-            0xF7 => {
-                println!("RST   6");
-                pc += 1;
-            }
-            0xF8 => {
-                println!("RM");
-                pc += 1;
-            }
-            0xF9 => {
-                println!("SPHL");
-                pc += 1;
-            }
-            0xFA => {
-                println!("JE    ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
-            }
-            0xFB => {
-                println!("EI");
-                pc += 1;
+impl Instruction {
+    pub fn length(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    // the address of the instruction that follows in memory
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.length())
+    }
+
+    // Formats in Intel syntax, giving `label` a chance to name every address and 16-bit
+    // immediate, e.g. `CALL  PRINT` instead of `CALL  0123H`.
+    pub fn format_with<F: Fn(u16) -> Option<String>>(&self, label: F) -> String {
+        let operands: Vec<String> = self
+            .operands
+            .iter()
+            .map(|operand| match *operand {
+                Operand::Register(name) => name.to_string(),
+                Operand::Byte(value) => hex8(value),
+                Operand::Word(value) | Operand::Address(value) => {
+                    label(value).unwrap_or_else(|| hex16(value))
+                }
+                Operand::Restart(n) => n.to_string(),
+            })
+            .collect();
+
+        if operands.is_empty() {
+            self.mnemonic.to_string()
+        } else {
+            format!("{:<6}{}", self.mnemonic, operands.join(","))
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.format_with(|_| None))
+    }
+}
+
+// Decodes the instruction at `address`, reading its bytes through `read`. Operand bytes wrap
+// around at the top of memory like the CPU's fetches do.
+pub fn decode_with<F: FnMut(u16) -> u8>(address: u16, mut read: F) -> Instruction {
+    let opcode = read(address);
+    let template = OPCODES[opcode as usize];
+    let undocumented = template.starts_with('*');
+    let template = template.trim_start_matches('*');
+
+    let (mnemonic, operand_templates) = match template.find(' ') {
+        Some(space) => (&template[..space], &template[space + 1..]),
+        None => (template, ""),
+    };
+
+    let mut bytes = vec![opcode];
+    let mut operands = Vec::new();
+    let mut target = None;
+
+    for operand in operand_templates.split(',').filter(|s| !s.is_empty()) {
+        let operand = match operand {
+            "d8" => {
+                bytes.push(read(address.wrapping_add(1)));
+                Operand::Byte(bytes[1])
+            }
+            "d16" | "a16" | "j16" => {
+                bytes.push(read(address.wrapping_add(1)));
+                bytes.push(read(address.wrapping_add(2)));
+                let word = u16::from_le_bytes([bytes[1], bytes[2]]);
+
+                match operand {
+                    "d16" => Operand::Word(word),
+                    "a16" => Operand::Address(word),
+                    _ => {
+                        target = Some(word);
+                        Operand::Address(word)
+                    }
+                }
+            }
+            _ if mnemonic == "RST" => {
+                // the only operand that is neither a register nor a placeholder
+                let n = operand.parse::<u8>().unwrap();
+                target = Some(n as u16 * 8);
+                Operand::Restart(n)
+            }
+            register => Operand::Register(register),
+        };
+        operands.push(operand);
+    }
+
+    Instruction {
+        address,
+        opcode,
+        mnemonic,
+        operands,
+        bytes,
+        target,
+        undocumented,
+    }
+}
+
+// Decodes the instruction at `address` in `code`, which is loaded at `origin`. `None` if the
+// address is outside of `code` or the instruction is cut off by its end.
+pub fn decode(code: &[u8], origin: u16, address: u16) -> Option<Instruction> {
+    let offset = address.checked_sub(origin)? as usize;
+    if offset >= code.len() {
+        return None;
+    }
+
+    let instruction = decode_with(address, |a| {
+        code.get(a.wrapping_sub(origin) as usize)
+            .copied()
+            .unwrap_or(0)
+    });
+
+    if offset + instruction.bytes.len() > code.len() {
+        return None;
+    }
+    Some(instruction)
+}
+
+// decodes from memory without side effects on memory-mapped devices
+pub fn decode_memory(memory: &dyn MemoryBus, address: u16) -> Instruction {
+    decode_with(address, |a| memory.peek(a))
+}
+
+// The length of the instruction starting with `opcode`, 1 to 3 bytes.
+pub fn instruction_length(opcode: u8) -> u16 {
+    let template = OPCODES[opcode as usize];
+    if template.ends_with("d8") {
+        2
+    } else if template.ends_with("16") {
+        3
+    } else {
+        1
+    }
+}
+
+// Numbers as Intel's assembler wants them: hex with an H suffix, and a leading 0 when the first
+// digit is a letter.
+pub fn hex8(value: u8) -> String {
+    with_leading_zero(format!("{:02X}H", value))
+}
+
+pub fn hex16(value: u16) -> String {
+    with_leading_zero(format!("{:04X}H", value))
+}
+
+fn with_leading_zero(number: String) -> String {
+    if number.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        format!("0{}", number)
+    } else {
+        number
+    }
+}
+
+// Prints a listing of a raw binary loaded at `origin`: address, bytes and the instruction, with
+// `symbols` naming addresses and marking where they are defined.
+pub fn disassemble(
+    file_path: &str,
+    origin: u16,
+    symbols: &SymbolTable,
+) -> Result<(), Box<dyn Error>> {
+    let code = fs::read(file_path)?;
+
+    let mut address = origin;
+    let mut offset = 0;
+    while offset < code.len() {
+        if let Some(name) = symbols.name_at(address) {
+            println!("{}:", name);
+        }
+
+        let instruction = match decode(&code, origin, address) {
+            Some(instruction) => instruction,
+            // cut off at the end of the file
+            None => {
+                println!(
+                    "{:04X}  {:02X}        DB    {}",
+                    address,
+                    code[offset],
+                    hex8(code[offset])
+                );
+                address = address.wrapping_add(1);
+                offset += 1;
+                continue;
+            }
+        };
+
+        let bytes: Vec<String> = instruction
+            .bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        let text = instruction.format_with(|value| symbols.name_at(value).map(str::to_string));
+        println!("{:04X}  {:<9} {}", address, bytes.join(" "), text);
+
+        address = instruction.next_address();
+        offset += instruction.bytes.len();
+    }
+
+    Ok(())
+}
+
+// what the recursive disassembler found out about each byte
+#[derive(Clone, Copy, PartialEq)]
+enum ByteKind {
+    Data,
+    Opcode,
+    Operand,
+}
+
+// The result of following the control flow through a program: which bytes are code, and a label
+// for every branch target inside the program.
+pub struct Disassembly {
+    origin: u16,
+    code: Vec<u8>,
+    kinds: Vec<ByteKind>,
+    labels: BTreeMap<u16, String>,
+    // named addresses outside the program, defined with EQU when they are used
+    externals: BTreeMap<u16, String>,
+}
+
+impl Disassembly {
+    // Decodes everything reachable from `entry_points` by following jumps, calls and RSTs, both
+    // ways of conditional branches, and falling through everything but JMP, RET and PCHL. Bytes
+    // that are never reached are data.
+    pub fn new(code: &[u8], origin: u16, entry_points: &[u16]) -> Self {
+        let mut kinds = vec![ByteKind::Data; code.len()];
+        let mut targets = Vec::new();
+        let mut pending: Vec<u16> = entry_points.to_vec();
+
+        while let Some(mut address) = pending.pop() {
+            loop {
+                let offset = address.wrapping_sub(origin) as usize;
+                if offset >= code.len() || kinds[offset] != ByteKind::Data {
+                    break;
+                }
+
+                let instruction = match decode(code, origin, address) {
+                    Some(instruction) => instruction,
+                    None => break,
+                };
+                // jumping into the middle of other code, leave the bytes as they are
+                if kinds[offset..offset + instruction.bytes.len()]
+                    .iter()
+                    .any(|kind| *kind != ByteKind::Data)
+                {
+                    break;
+                }
+
+                kinds[offset] = ByteKind::Opcode;
+                for kind in &mut kinds[offset + 1..offset + instruction.bytes.len()] {
+                    *kind = ByteKind::Operand;
+                }
+
+                if let Some(target) = instruction.target {
+                    targets.push(target);
+                    pending.push(target);
+                }
+
+                match instruction.mnemonic {
+                    "JMP" | "RET" | "PCHL" => break,
+                    _ => address = instruction.next_address(),
+                }
             }
-            0xFC => {
-                println!("CM    ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-                pc += 3;
+        }
+
+        // only targets that ended up at the start of an instruction can carry a label
+        let labels = targets
+            .into_iter()
+            .filter(|target| {
+                let offset = target.wrapping_sub(origin) as usize;
+                offset < code.len() && kinds[offset] == ByteKind::Opcode
+            })
+            .map(|target| (target, format!("L{:04X}", target)))
+            .collect();
+
+        Disassembly {
+            origin,
+            code: code.to_vec(),
+            kinds,
+            labels,
+            externals: BTreeMap::new(),
+        }
+    }
+
+    // Names labels after `symbols` where it has a name for them. Symbols at the start of an
+    // instruction or on data become labels too, symbols outside the program are used through EQUs.
+    pub fn use_symbols(&mut self, symbols: &SymbolTable) {
+        for (address, name) in symbols.iter() {
+            if !is_identifier(name) {
+                continue;
             }
-            0xFE => {
-                println!("CPI   ${:02x}", buffer[pc + 1]);
-                pc += 2;
+
+            let offset = address.wrapping_sub(self.origin) as usize;
+            if offset >= self.code.len() {
+                self.externals.insert(address, name.to_string());
+            } else if self.kinds[offset] != ByteKind::Operand {
+                self.labels.insert(address, name.to_string());
             }
-            0xFF => {
-                println!("RST   7");
-                pc += 1;
+        }
+    }
+
+    pub fn labels(&self) -> &BTreeMap<u16, String> {
+        &self.labels
+    }
+
+    pub fn is_code(&self, address: u16) -> bool {
+        let offset = address.wrapping_sub(self.origin) as usize;
+        offset < self.code.len() && self.kinds[offset] != ByteKind::Data
+    }
+
+    // Assembler source that rebuilds the program byte for byte. Undocumented opcodes are written
+    // as DB, an assembler would pick the documented encoding for their mnemonic.
+    pub fn to_source(&self) -> String {
+        let used_externals = RefCell::new(BTreeSet::new());
+        let name = |value: u16| {
+            if let Some(label) = self.labels.get(&value) {
+                return Some(label.clone());
+            }
+            let external = self.externals.get(&value)?;
+            used_externals.borrow_mut().insert(value);
+            Some(external.clone())
+        };
+
+        let mut body = String::new();
+        let mut offset = 0;
+
+        while offset < self.code.len() {
+            let address = self.origin.wrapping_add(offset as u16);
+
+            if self.kinds[offset] == ByteKind::Data {
+                // up to 8 bytes per DB line, and a new line at every label
+                let end = (offset + 1..self.code.len())
+                    .take(7)
+                    .find(|&i| {
+                        self.kinds[i] != ByteKind::Data
+                            || self
+                                .labels
+                                .contains_key(&self.origin.wrapping_add(i as u16))
+                    })
+                    .unwrap_or_else(|| (offset + 8).min(self.code.len()));
+                body += &self.line(address, &db(&self.code[offset..end]), None);
+                offset = end;
+                continue;
             }
+
+            let instruction = decode(&self.code, self.origin, address).unwrap();
+            if instruction.undocumented {
+                body += &self.line(
+                    address,
+                    &db(&instruction.bytes),
+                    Some(&instruction.to_string()),
+                );
+            } else {
+                body += &self.line(address, &instruction.format_with(name), None);
+            }
+            offset += instruction.bytes.len();
+        }
+
+        let mut source = String::new();
+        for address in used_externals.borrow().iter() {
+            source += &format!(
+                "{:<7} {:<6}{}\n",
+                self.externals[address],
+                "EQU",
+                hex16(*address)
+            );
         }
+        source += &format!("{:<8}{:<6}{}\n", "", "ORG", hex16(self.origin));
+
+        source + &body + &format!("{:<8}END\n", "")
     }
 
-    Ok(())
+    fn line(&self, address: u16, text: &str, comment: Option<&str>) -> String {
+        let label = match self.labels.get(&address) {
+            Some(label) => format!("{}:", label),
+            None => String::new(),
+        };
+
+        // long labels still need a space before the mnemonic
+        match comment {
+            Some(comment) => format!("{:<7} {:<24}; {}\n", label, text, comment),
+            None => format!("{:<7} {}\n", label, text),
+        }
+    }
+}
+
+fn db(bytes: &[u8]) -> String {
+    let values: Vec<String> = bytes.iter().map(|byte| hex8(*byte)).collect();
+    format!("{:<6}{}", "DB", values.join(","))
 }
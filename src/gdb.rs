@@ -0,0 +1,487 @@
+// A GDB remote serial protocol stub, so a debugger front-end can drive the core over TCP.
+//
+// gdb has no 8080 target, so front-ends need to know the register layout. `g`/`G` transfer all
+// registers in this order, and `p`/`P` take these numbers:
+//
+//     0 A     1 F     2 B     3 C     4 D     5 E     6 H     7 L     (8 bits each)
+//     8 SP    9 PC                                                    (16 bits, little-endian)
+//
+// F is the flags byte as PUSH PSW stores it (S Z 0 AC 0 P 1 CY).
+//
+// Supported packets: ? g G p P m M s c k D, Z0/Z1 (software and hardware breakpoints, both kept
+// by the stub without patching memory), Z2/Z3/Z4 (write, read and access watchpoints) and Ctrl-C
+// to interrupt a `c`. Anything else gets the empty "unsupported" reply.
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::debugger::Trap;
+use crate::state::State8080;
+use crate::watch::{WatchKind, Watchpoint};
+
+pub const REGISTER_COUNT: usize = 10;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+// how many instructions `c` runs between checks for a Ctrl-C from the client
+const INTERRUPT_CHECK_INTERVAL: u32 = 10_000;
+// the longest packet data the client is told it may send or get back (hex in `qSupported`)
+const PACKET_SIZE: usize = 0x1000;
+
+enum Reply {
+    Packet(String),
+    // already answered
+    Sent,
+    // stop talking to this client
+    Close(Option<String>),
+}
+
+pub struct GdbStub {
+    breakpoints: BTreeSet<u16>,
+    // core watchpoint ids for every Z2/Z3/Z4 (type, address, length)
+    watchpoints: HashMap<(u8, u16, u16), Vec<usize>>,
+    ack: bool,
+    exited: bool,
+}
+
+impl Default for GdbStub {
+    fn default() -> Self {
+        GdbStub::new()
+    }
+}
+
+impl GdbStub {
+    pub fn new() -> Self {
+        GdbStub {
+            breakpoints: BTreeSet::new(),
+            watchpoints: HashMap::new(),
+            ack: true,
+            exited: false,
+        }
+    }
+
+    // waits for one client on `listener` and serves it until it detaches or kills the program
+    pub fn serve(
+        &mut self,
+        listener: &TcpListener,
+        state: &mut State8080,
+        trap: &mut Trap,
+    ) -> io::Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        self.run(&mut stream, state, trap)
+    }
+
+    pub fn run(
+        &mut self,
+        stream: &mut TcpStream,
+        state: &mut State8080,
+        trap: &mut Trap,
+    ) -> io::Result<()> {
+        while let Some(packet) = self.receive(stream)? {
+            match self.handle(&packet, stream, state, trap)? {
+                Reply::Packet(reply) => self.send(stream, &reply)?,
+                Reply::Sent => (),
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        self.send(stream, &reply)?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // the next packet's data, `None` once the client hangs up
+    fn receive(&mut self, stream: &mut TcpStream) -> io::Result<Option<String>> {
+        loop {
+            // skip acks, interrupts outside of `c` and noise up to the start of a packet
+            match read_byte(stream)? {
+                Some(b'$') => (),
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match read_byte(stream)? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+
+            let mut checksum = [0u8; 2];
+            stream.read_exact(&mut checksum)?;
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+            let valid = expected == Some(checksum_of(&data));
+
+            if self.ack {
+                stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn send(&mut self, stream: &mut TcpStream, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+
+        loop {
+            stream.write_all(packet.as_bytes())?;
+            stream.flush()?;
+
+            if !self.ack {
+                return Ok(());
+            }
+            match read_byte(stream)? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn handle(
+        &mut self,
+        packet: &str,
+        stream: &mut TcpStream,
+        state: &mut State8080,
+        trap: &mut Trap,
+    ) -> io::Result<Reply> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+
+        let reply = match command {
+            "?" => self.stop_reply(SIGTRAP),
+            "g" => registers(state)
+                .iter()
+                .enumerate()
+                .map(|(register, value)| hex_register(register, *value))
+                .collect(),
+            "G" => {
+                let bytes = match decode_hex(args) {
+                    Some(bytes) if bytes.len() == 12 => bytes,
+                    _ => return Ok(error(1)),
+                };
+                let values = [
+                    bytes[0] as u16,
+                    bytes[1] as u16,
+                    bytes[2] as u16,
+                    bytes[3] as u16,
+                    bytes[4] as u16,
+                    bytes[5] as u16,
+                    bytes[6] as u16,
+                    bytes[7] as u16,
+                    u16::from_le_bytes([bytes[8], bytes[9]]),
+                    u16::from_le_bytes([bytes[10], bytes[11]]),
+                ];
+                for (register, value) in values.iter().enumerate() {
+                    set_register(state, register, *value);
+                }
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(register) if register < REGISTER_COUNT => {
+                    hex_register(register, registers(state)[register])
+                }
+                _ => return Ok(error(1)),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(register, value)| {
+                    let register = usize::from_str_radix(register, 16).ok()?;
+                    let bytes = decode_hex(value)?;
+                    let value = match (register, bytes.as_slice()) {
+                        (0..=7, [byte]) => *byte as u16,
+                        (8..=9, [low, high]) => u16::from_le_bytes([*low, *high]),
+                        _ => return None,
+                    };
+                    Some((register, value))
+                });
+                match parsed {
+                    Some((register, value)) => {
+                        set_register(state, register, value);
+                        "OK".to_string()
+                    }
+                    None => return Ok(error(1)),
+                }
+            }
+            // as much of the range as fits in a packet, gdb asks again for the rest
+            "m" => match parse_address_length(args) {
+                Some((address, length)) => (0..length.min(PACKET_SIZE as u16 / 2))
+                    .map(|i| {
+                        let byte = state.memory().peek(address.wrapping_add(i));
+                        format!("{:02x}", byte)
+                    })
+                    .collect(),
+                None => return Ok(error(1)),
+            },
+            "M" => {
+                let parsed = args.split_once(':').and_then(|(range, data)| {
+                    let (address, length) = parse_address_length(range)?;
+                    let bytes = decode_hex(data)?;
+                    if bytes.len() != length as usize {
+                        return None;
+                    }
+                    Some((address, bytes))
+                });
+                match parsed {
+                    Some((address, bytes)) => {
+                        for (i, byte) in bytes.iter().enumerate() {
+                            state
+                                .memory_mut()
+                                .poke(address.wrapping_add(i as u16), *byte);
+                        }
+                        "OK".to_string()
+                    }
+                    None => return Ok(error(1)),
+                }
+            }
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match u16::from_str_radix(args, 16) {
+                        Ok(address) => state.pc = address,
+                        Err(_) => return Ok(error(1)),
+                    }
+                }
+                if command == "s" {
+                    self.step(state, trap)?
+                } else {
+                    self.resume(stream, state, trap)?
+                }
+            }
+            "Z" | "z" => match self.breakpoint(command == "Z", args, state) {
+                Some(reply) => reply,
+                None => return Ok(error(1)),
+            },
+            "q" if args.starts_with("Supported") => format!("PacketSize={:x}", PACKET_SIZE),
+            "q" if args == "Attached" => "1".to_string(),
+            "Q" if args == "StartNoAckMode" => {
+                // the OK still gets acknowledged
+                self.send(stream, "OK")?;
+                self.ack = false;
+                return Ok(Reply::Sent);
+            }
+            "H" => "OK".to_string(),
+            "k" => return Ok(Reply::Close(None)),
+            "D" => return Ok(Reply::Close(Some("OK".to_string()))),
+            _ => String::new(),
+        };
+
+        Ok(Reply::Packet(reply))
+    }
+
+    fn stop_reply(&self, signal: u8) -> String {
+        if self.exited {
+            "W00".to_string()
+        } else {
+            format!("S{:02x}", signal)
+        }
+    }
+
+    // `None` once the program can't go on
+    fn execute(&mut self, state: &mut State8080, trap: &mut Trap) -> io::Result<Option<String>> {
+        if self.exited || !trap(state)? {
+            self.exited = true;
+            return Ok(Some("W00".to_string()));
+        }
+        // nothing raises interrupts here, a HLT is the end of the line
+        if state.halted() {
+            return Ok(Some(self.stop_reply(SIGTRAP)));
+        }
+
+        state.emulate_cycle();
+
+        if let Some(hit) = state.take_watch_hit() {
+            // a Z4 is a read and a write watchpoint in the core, either one is an access
+            let z_type = self
+                .watchpoints
+                .iter()
+                .find(|(_, ids)| ids.contains(&hit.id))
+                .map(|((z_type, _, _), _)| *z_type);
+            let reason = match (z_type, hit.kind) {
+                (Some(4), _) => "awatch",
+                (_, WatchKind::Read) => "rwatch",
+                _ => "watch",
+            };
+            return Ok(Some(format!(
+                "T{:02x}{}:{:04x};",
+                SIGTRAP, reason, hit.address
+            )));
+        }
+        // strict mode stopped in front of an undocumented opcode
+        if state.take_undocumented_opcode().is_some() {
+            return Ok(Some(self.stop_reply(SIGILL)));
+        }
+
+        Ok(None)
+    }
+
+    fn step(&mut self, state: &mut State8080, trap: &mut Trap) -> io::Result<String> {
+        Ok(self
+            .execute(state, trap)?
+            .unwrap_or_else(|| self.stop_reply(SIGTRAP)))
+    }
+
+    fn resume(
+        &mut self,
+        stream: &mut TcpStream,
+        state: &mut State8080,
+        trap: &mut Trap,
+    ) -> io::Result<String> {
+        let mut count = 0;
+
+        loop {
+            if let Some(reply) = self.execute(state, trap)? {
+                return Ok(reply);
+            }
+            if self.breakpoints.contains(&state.pc) {
+                return Ok(self.stop_reply(SIGTRAP));
+            }
+
+            count += 1;
+            if count == INTERRUPT_CHECK_INTERVAL {
+                count = 0;
+                if interrupted(stream)? {
+                    return Ok(self.stop_reply(SIGINT));
+                }
+            }
+        }
+    }
+
+    // Z/z type,address,kind
+    fn breakpoint(&mut self, insert: bool, args: &str, state: &mut State8080) -> Option<String> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?.parse::<u8>().ok()?;
+        let address = u16::from_str_radix(fields.next()?, 16).ok()?;
+        let length = u16::from_str_radix(fields.next()?.split(';').next()?, 16).ok()?;
+
+        match (kind, insert) {
+            (0..=1, true) => {
+                self.breakpoints.insert(address);
+            }
+            (0..=1, false) => {
+                self.breakpoints.remove(&address);
+            }
+            (2..=4, true) => {
+                let end = address.wrapping_add(length.max(1) - 1);
+                let kinds: &[WatchKind] = match kind {
+                    2 => &[WatchKind::Write],
+                    3 => &[WatchKind::Read],
+                    _ => &[WatchKind::Read, WatchKind::Write],
+                };
+                let ids = kinds
+                    .iter()
+                    .map(|kind| state.add_watchpoint(Watchpoint::new(address, end, *kind)))
+                    .collect();
+                self.watchpoints.insert((kind, address, length), ids);
+            }
+            (2..=4, false) => {
+                for id in self
+                    .watchpoints
+                    .remove(&(kind, address, length))
+                    .unwrap_or_default()
+                {
+                    state.remove_watchpoint(id);
+                }
+            }
+            _ => return Some(String::new()),
+        }
+
+        Some("OK".to_string())
+    }
+}
+
+// A, F, B, C, D, E, H, L, SP, PC
+fn registers(state: &State8080) -> [u16; REGISTER_COUNT] {
+    [
+        state.a as u16,
+        state.flags() as u16,
+        state.b as u16,
+        state.c as u16,
+        state.d as u16,
+        state.e as u16,
+        state.h as u16,
+        state.l as u16,
+        state.sp,
+        state.pc,
+    ]
+}
+
+fn set_register(state: &mut State8080, register: usize, value: u16) {
+    match register {
+        0 => state.a = value as u8,
+        1 => state.set_flags(value as u8),
+        2 => state.b = value as u8,
+        3 => state.c = value as u8,
+        4 => state.d = value as u8,
+        5 => state.e = value as u8,
+        6 => state.h = value as u8,
+        7 => state.l = value as u8,
+        8 => state.sp = value,
+        _ => state.pc = value,
+    }
+}
+
+// 8-bit registers as one byte, SP and PC as two in target (little-endian) order
+fn hex_register(register: usize, value: u16) -> String {
+    if register < 8 {
+        format!("{:02x}", value)
+    } else {
+        format!("{:02x}{:02x}", value as u8, value >> 8)
+    }
+}
+
+fn error(code: u8) -> Reply {
+    Reply::Packet(format!("E{:02x}", code))
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn read_byte(stream: &mut TcpStream) -> io::Result<Option<u8>> {
+    let mut byte = [0u8];
+    match stream.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+// a Ctrl-C (0x03) waiting on the connection
+fn interrupted(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut byte = [0u8];
+    let result = stream.read(&mut byte);
+    stream.set_nonblocking(false)?;
+
+    match result {
+        Ok(1) => Ok(byte[0] == 0x03),
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn parse_address_length(args: &str) -> Option<(u16, u16)> {
+    let (address, length) = args.split_once(',')?;
+    Some((
+        u16::from_str_radix(address, 16).ok()?,
+        u16::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+fn decode_hex(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::VIDEO_RAM_SIZE;
+use crate::image;
+
+// the monitor is mounted on its side, so the 256x224 raster ends up as a 224x256 portrait screen
+pub const SCREEN_WIDTH: usize = 224;
+pub const SCREEN_HEIGHT: usize = 256;
+
+const ON: u8 = 0xFF;
+const OFF: u8 = 0x00;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name.to_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+// The screen as the player sees it, one gray byte per pixel, top row first. There is no color
+// overlay, the cabinet's colored gels aren't part of the video signal.
+pub struct Framebuffer {
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    // Video RAM holds 224 columns of 32 bytes, each column scanned from the bottom of the screen
+    // upwards with the least significant bit first.
+    pub fn from_video_ram(video_ram: &[u8]) -> Self {
+        assert_eq!(video_ram.len(), VIDEO_RAM_SIZE);
+
+        let mut pixels = vec![OFF; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+        for (offset, byte) in video_ram.iter().enumerate() {
+            let x = offset / 32;
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    let y = SCREEN_HEIGHT - 1 - ((offset % 32) * 8 + bit);
+                    pixels[y * SCREEN_WIDTH + x] = ON;
+                }
+            }
+        }
+
+        Framebuffer { pixels }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * SCREEN_WIDTH + x] == ON
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        match format {
+            ImageFormat::Ppm => {
+                image::write_ppm(&mut out, SCREEN_WIDTH, SCREEN_HEIGHT, &self.pixels)?
+            }
+            ImageFormat::Png => {
+                image::write_png(&mut out, SCREEN_WIDTH, SCREEN_HEIGHT, &self.pixels)?
+            }
+        }
+
+        // dropping the writer would flush it too, but swallow any error doing so
+        out.flush()
+    }
+}
@@ -0,0 +1,104 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::{Button, Invaders};
+
+// how long a `tap` holds the button down, long enough for the game's input polling to see it
+const TAP_FRAMES: u64 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub button: Button,
+    pub pressed: bool,
+}
+
+// A scripted input sequence for headless runs, one event per line:
+//
+//     # insert a coin and start a one player game
+//     60  coin    tap
+//     120 start   down
+//     124 start   up
+//
+// Events fire at the start of the given frame, `tap` is a press followed by a release.
+pub struct InputScript {
+    events: Vec<InputEvent>,
+    next: usize,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> io::Result<InputScript> {
+        let mut events = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = |reason: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("input script line {}: {}", number + 1, reason),
+                )
+            };
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(invalid("expected `<frame> <button> <down|up|tap>`"));
+            }
+
+            let frame = fields[0]
+                .parse::<u64>()
+                .map_err(|_| invalid("bad frame number"))?;
+            let button = Button::from_name(fields[1]).ok_or_else(|| invalid("unknown button"))?;
+
+            match fields[2] {
+                "down" | "up" => events.push(InputEvent {
+                    frame,
+                    button,
+                    pressed: fields[2] == "down",
+                }),
+                "tap" => {
+                    events.push(InputEvent {
+                        frame,
+                        button,
+                        pressed: true,
+                    });
+                    events.push(InputEvent {
+                        frame: frame + TAP_FRAMES,
+                        button,
+                        pressed: false,
+                    });
+                }
+                _ => return Err(invalid("expected down, up or tap")),
+            }
+        }
+
+        // stable, so events on the same frame keep their order
+        events.sort_by_key(|event| event.frame);
+
+        Ok(InputScript { events, next: 0 })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<InputScript> {
+        InputScript::parse(&fs::read_to_string(path)?)
+    }
+
+    // presses and releases everything that is due by the machine's current frame
+    pub fn apply(&mut self, machine: &mut Invaders) {
+        while let Some(event) = self.events.get(self.next) {
+            if event.frame > machine.frame() {
+                break;
+            }
+
+            machine.set_button(event.button, event.pressed);
+            self.next += 1;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+}
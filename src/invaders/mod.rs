@@ -0,0 +1,326 @@
+// Midway's Space Invaders board: 8K of ROM, 1K of work RAM, 7K of 1bpp video RAM, a hardware shift
+// register for the sprite routines, and two interrupts per 60Hz frame.
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use self::framebuffer::Framebuffer;
+use crate::memory::MappedMemory;
+use crate::ports::PortBus;
+use crate::state::State8080;
+
+pub mod framebuffer;
+pub mod input;
+
+pub const CLOCK_HZ: u64 = 1_996_800;
+pub const FRAMES_PER_SECOND: u64 = 60;
+pub const CYCLES_PER_FRAME: u64 = CLOCK_HZ / FRAMES_PER_SECOND;
+
+pub const ROM_SIZE: usize = 0x2000;
+pub const VIDEO_RAM_START: u16 = 0x2400;
+pub const VIDEO_RAM_SIZE: usize = 0x1C00;
+
+// the ROM set is usually split in four 2K chips, loaded in this order from 0x0000
+const ROM_FILES: [&str; 4] = ["invaders.h", "invaders.g", "invaders.f", "invaders.e"];
+
+const RST_1: u8 = 0xCF; // when the beam reaches the middle of the screen
+const RST_2: u8 = 0xD7; // at the start of vertical blank
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    Coin,
+    P1Start,
+    P2Start,
+    P1Fire,
+    P1Left,
+    P1Right,
+    P2Fire,
+    P2Left,
+    P2Right,
+    Tilt,
+}
+
+impl Button {
+    // (input port, bit) the button is wired to, all of them are active high
+    fn wiring(self) -> (u8, u8) {
+        match self {
+            Button::Coin => (1, 0),
+            Button::P2Start => (1, 1),
+            Button::P1Start => (1, 2),
+            Button::P1Fire => (1, 4),
+            Button::P1Left => (1, 5),
+            Button::P1Right => (1, 6),
+            Button::Tilt => (2, 2),
+            Button::P2Fire => (2, 4),
+            Button::P2Left => (2, 5),
+            Button::P2Right => (2, 6),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Button> {
+        match name.to_lowercase().as_str() {
+            "coin" => Some(Button::Coin),
+            "p1start" | "start" => Some(Button::P1Start),
+            "p2start" => Some(Button::P2Start),
+            "p1fire" | "fire" => Some(Button::P1Fire),
+            "p1left" | "left" => Some(Button::P1Left),
+            "p1right" | "right" => Some(Button::P1Right),
+            "p2fire" => Some(Button::P2Fire),
+            "p2left" => Some(Button::P2Left),
+            "p2right" => Some(Button::P2Right),
+            "tilt" => Some(Button::Tilt),
+            _ => None,
+        }
+    }
+}
+
+// the operator settings, read by the game through input port 2
+#[derive(Clone, Copy, Debug)]
+pub struct DipSwitches {
+    pub ships: u8,                // 3 to 6
+    pub extra_ship_at_1000: bool, // otherwise at 1500 points
+    pub coin_info: bool,          // show the coin info in attract mode
+}
+
+impl Default for DipSwitches {
+    fn default() -> Self {
+        DipSwitches {
+            ships: 3,
+            extra_ship_at_1000: false,
+            coin_info: true,
+        }
+    }
+}
+
+impl DipSwitches {
+    fn port2_bits(&self) -> u8 {
+        let mut bits = self.ships.clamp(3, 6) - 3; // bits 0-1
+        if self.extra_ship_at_1000 {
+            bits |= 0x08;
+        }
+        if !self.coin_info {
+            bits |= 0x80;
+        }
+        bits
+    }
+}
+
+// Everything on the I/O ports: inputs, DIP switches, the shift register, sound latches and the
+// watchdog.
+pub struct InvadersIo {
+    port1: u8,
+    port2: u8,
+    dips: DipSwitches,
+    shift_register: u16,
+    shift_offset: u8,
+    sound1: u8,
+    sound2: u8,
+}
+
+impl InvadersIo {
+    fn new(dips: DipSwitches) -> Self {
+        InvadersIo {
+            port1: 0x08, // bit 3 is always 1
+            port2: 0,
+            dips,
+            shift_register: 0,
+            shift_offset: 0,
+            sound1: 0,
+            sound2: 0,
+        }
+    }
+
+    fn set_button(&mut self, button: Button, pressed: bool) {
+        let (port, bit) = button.wiring();
+        let register = if port == 1 {
+            &mut self.port1
+        } else {
+            &mut self.port2
+        };
+
+        if pressed {
+            *register |= 1 << bit;
+        } else {
+            *register &= !(1 << bit);
+        }
+    }
+
+    // latched sound triggers (ports 3 and 5), there is no audio output
+    pub fn sound_latches(&self) -> (u8, u8) {
+        (self.sound1, self.sound2)
+    }
+}
+
+impl PortBus for InvadersIo {
+    fn input(&mut self, port: u8) -> u8 {
+        match port {
+            0 => 0x0E, // not used by the game, bits 1-3 are pulled high
+            1 => self.port1,
+            2 => self.port2 | self.dips.port2_bits(),
+            // the 8 bits of the 16-bit shift register starting `shift_offset` bits from the top
+            3 => (self.shift_register >> (8 - self.shift_offset)) as u8,
+            _ => 0,
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            2 => self.shift_offset = value & 0x07,
+            3 => self.sound1 = value,
+            4 => self.shift_register = (self.shift_register >> 8) | ((value as u16) << 8),
+            5 => self.sound2 = value,
+            6 => (), // watchdog reset, the watchdog itself isn't emulated
+            _ => (),
+        }
+    }
+
+    // the DIP switches are left alone, they're settings rather than state
+    fn save_state(&self) -> Vec<u8> {
+        let shift = self.shift_register.to_le_bytes();
+        vec![
+            self.port1,
+            self.port2,
+            shift[0],
+            shift[1],
+            self.shift_offset,
+            self.sound1,
+            self.sound2,
+        ]
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        match data {
+            [port1, port2, shift_low, shift_high, shift_offset, sound1, sound2, ..] => {
+                self.port1 = *port1;
+                self.port2 = *port2;
+                self.shift_register = u16::from_le_bytes([*shift_low, *shift_high]);
+                self.shift_offset = *shift_offset;
+                self.sound1 = *sound1;
+                self.sound2 = *sound2;
+                Ok(())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a Space Invaders I/O state",
+            )),
+        }
+    }
+}
+
+pub struct Invaders {
+    pub state: State8080,
+    io: Rc<RefCell<InvadersIo>>,
+    frame: u64,
+}
+
+impl Invaders {
+    pub fn new(rom: Vec<u8>, dips: DipSwitches) -> Self {
+        let mut memory = MappedMemory::new();
+        memory.map_rom(0x0000, rom);
+        memory.map_ram(0x2000, 0x3FFF);
+        // A14 and A15 aren't decoded, so the first 16K repeat over the whole address space
+        memory.map_mirror(0x4000, 0xFFFF, 0x0000, 0x4000);
+
+        let io = Rc::new(RefCell::new(InvadersIo::new(dips)));
+
+        let mut state: State8080 = Default::default();
+        state.attach_memory(memory);
+        state.attach_ports(io.clone());
+
+        Invaders {
+            state,
+            io,
+            frame: 0,
+        }
+    }
+
+    // `path` is either a directory with the four ROM chips or a single 8K image
+    pub fn load_roms<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+        let path = path.as_ref();
+
+        let rom = if path.is_dir() {
+            let mut rom = Vec::with_capacity(ROM_SIZE);
+            for file in ROM_FILES.iter() {
+                rom.extend(fs::read(path.join(file))?);
+            }
+            rom
+        } else {
+            fs::read(path)?
+        };
+
+        if rom.len() != ROM_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected {} bytes of ROM, got {}", ROM_SIZE, rom.len()),
+            ));
+        }
+
+        Ok(rom)
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.io.borrow_mut().set_button(button, pressed);
+    }
+
+    pub fn io(&self) -> &Rc<RefCell<InvadersIo>> {
+        &self.io
+    }
+
+    // frames completed since power-on
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Runs one 60Hz frame: the first half of the screen, the mid-screen interrupt, the second
+    // half, and the vertical blank interrupt. Fails when strict mode stops the CPU, which wouldn't
+    // get any further in the next frame either.
+    pub fn run_frame(&mut self) -> io::Result<()> {
+        let frame_start = self.frame * CYCLES_PER_FRAME;
+
+        self.run_until(frame_start + CYCLES_PER_FRAME / 2)?;
+        self.state.interrupt(RST_1);
+        self.run_until(frame_start + CYCLES_PER_FRAME)?;
+        self.state.interrupt(RST_2);
+
+        self.frame += 1;
+        Ok(())
+    }
+
+    // keeps the CPU in step with the beam, whatever an instruction overshot is taken off the next
+    // stretch
+    fn run_until(&mut self, cycle: u64) -> io::Result<()> {
+        let now = self.state.cycles();
+
+        if now < cycle {
+            self.state.run_cycles(cycle - now);
+        }
+
+        match self.state.take_undocumented_opcode() {
+            Some(opcode) => Err(opcode.into()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn save_state<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.state.save_state(path)
+    }
+
+    // picks up at the frame the state was saved in
+    pub fn load_state<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.state.load_state(path)?;
+        self.frame = self.state.cycles() / CYCLES_PER_FRAME;
+        Ok(())
+    }
+
+    pub fn video_ram(&self) -> Vec<u8> {
+        (0..VIDEO_RAM_SIZE as u16)
+            .map(|offset| self.state.memory().peek(VIDEO_RAM_START + offset))
+            .collect()
+    }
+
+    pub fn framebuffer(&self) -> Framebuffer {
+        Framebuffer::from_video_ram(&self.video_ram())
+    }
+}
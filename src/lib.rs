@@ -0,0 +1,19 @@
+pub mod assembler;
+pub mod cpm;
+pub mod debugger;
+pub mod disassembler;
+pub mod gdb;
+pub mod image;
+pub mod invaders;
+pub mod json;
+pub mod loader;
+pub mod memory;
+pub mod ports;
+pub mod rewind;
+pub mod savestate;
+pub mod single_step;
+pub mod state;
+pub mod symbols;
+pub mod trace;
+pub mod tracediff;
+pub mod watch;
@@ -0,0 +1,311 @@
+// Program loaders: raw binaries at a chosen base address and Intel HEX files.
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::memory::{MemoryBus, MEMORY_SIZE};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    // a malformed Intel HEX record, `line` counts from 1
+    Syntax {
+        line: usize,
+        reason: String,
+    },
+    Checksum {
+        line: usize,
+        expected: u8,
+        found: u8,
+    },
+    // `len` bytes at `address` don't fit in the 64K address space
+    Overflow {
+        address: u32,
+        len: usize,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Syntax { line, reason } => write!(f, "line {}: {}", line, reason),
+            LoadError::Checksum {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: bad checksum, expected {:02X} but the record has {:02X}",
+                line, expected, found
+            ),
+            LoadError::Overflow { address, len } => write!(
+                f,
+                "{} bytes at {:04X}h run past the end of memory ({:04X}h)",
+                len,
+                address,
+                MEMORY_SIZE - 1
+            ),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<LoadError> for io::Error {
+    fn from(e: LoadError) -> Self {
+        match e {
+            LoadError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub address: u16,
+    pub data: Vec<u8>,
+}
+
+// What a loader produced: the bytes to place in memory and, if the file names one, where the
+// program starts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Image {
+    pub segments: Vec<Segment>,
+    pub start: Option<u16>,
+}
+
+impl Image {
+    pub fn from_binary(data: Vec<u8>, base: u16) -> Result<Image, LoadError> {
+        check_fits(base as u32, data.len())?;
+
+        Ok(Image {
+            segments: vec![Segment {
+                address: base,
+                data,
+            }],
+            start: None,
+        })
+    }
+
+    // Data (00), end of file (01), extended segment/linear address (02/04) and start address
+    // (03/05) records. Extended addresses are accepted as long as the data lands below 64K.
+    pub fn from_hex(text: &str) -> Result<Image, LoadError> {
+        let mut image = Image::default();
+        let mut upper: u32 = 0; // from the last 02 or 04 record
+        let mut seen_eof = false;
+
+        for (number, line) in text.lines().enumerate() {
+            let line_number = number + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let syntax = |reason: &str| LoadError::Syntax {
+                line: line_number,
+                reason: reason.to_string(),
+            };
+
+            if seen_eof {
+                return Err(syntax("record after the end of file record"));
+            }
+            if !line.starts_with(':') {
+                return Err(syntax("record doesn't start with ':'"));
+            }
+
+            let bytes = parse_hex_bytes(&line[1..]).ok_or_else(|| syntax("bad hex digits"))?;
+            if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+                return Err(syntax("record length doesn't match its byte count"));
+            }
+
+            let (record, checksum) = bytes.split_at(bytes.len() - 1);
+            let expected = record
+                .iter()
+                .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+                .wrapping_neg();
+            if expected != checksum[0] {
+                return Err(LoadError::Checksum {
+                    line: line_number,
+                    expected,
+                    found: checksum[0],
+                });
+            }
+
+            let offset = u16::from_be_bytes([record[1], record[2]]) as u32;
+            let data = &record[4..];
+
+            match record[3] {
+                0x00 => {
+                    let address = upper + offset;
+                    check_fits(address, data.len())?;
+
+                    // merge consecutive records into one segment
+                    match image.segments.last_mut() {
+                        Some(last) if last.address as u32 + last.data.len() as u32 == address => {
+                            last.data.extend_from_slice(data)
+                        }
+                        _ => image.segments.push(Segment {
+                            address: address as u16,
+                            data: data.to_vec(),
+                        }),
+                    }
+                }
+                0x01 => seen_eof = true,
+                0x02 | 0x04 if data.len() == 2 => {
+                    let value = u16::from_be_bytes([data[0], data[1]]) as u32;
+                    upper = if record[3] == 0x02 {
+                        value << 4
+                    } else {
+                        value << 16
+                    };
+                }
+                0x03 | 0x05 if data.len() == 4 => {
+                    let start = if record[3] == 0x03 {
+                        // CS:IP
+                        (u16::from_be_bytes([data[0], data[1]]) as u32) * 16
+                            + u16::from_be_bytes([data[2], data[3]]) as u32
+                    } else {
+                        u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+                    };
+                    check_fits(start, 1)?;
+                    image.start = Some(start as u16);
+                }
+                0x02..=0x05 => return Err(syntax("wrong data length for the record type")),
+                _ => return Err(syntax("unknown record type")),
+            }
+        }
+
+        if !seen_eof {
+            return Err(LoadError::Syntax {
+                line: text.lines().count(),
+                reason: "missing end of file record".to_string(),
+            });
+        }
+
+        Ok(image)
+    }
+
+    // `.hex`, `.ihx` and `.ihex` files are read as Intel HEX, anything else as a raw binary
+    // loaded at `base`
+    pub fn load<P: AsRef<Path>>(path: P, base: u16) -> Result<Image, LoadError> {
+        let path = path.as_ref();
+        let is_hex = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| {
+                let extension = extension.to_lowercase();
+                extension == "hex" || extension == "ihx" || extension == "ihex"
+            })
+            .unwrap_or(false);
+
+        if is_hex {
+            Image::from_hex(&fs::read_to_string(path)?)
+        } else {
+            Image::from_binary(fs::read(path)?, base)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|segment| segment.data.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Everything from the lowest to the highest address that has data, gaps (e.g. from DS)
+    // filled with zeros. The first byte belongs at `lowest_address`.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let start = self.lowest_address() as usize;
+        let end = self
+            .segments
+            .iter()
+            .map(|segment| segment.address as usize + segment.data.len())
+            .max()
+            .unwrap_or(start);
+
+        let mut binary = vec![0; end - start];
+        for segment in &self.segments {
+            let offset = segment.address as usize - start;
+            binary[offset..offset + segment.data.len()].copy_from_slice(&segment.data);
+        }
+        binary
+    }
+
+    pub fn lowest_address(&self) -> u16 {
+        self.segments
+            .iter()
+            .map(|segment| segment.address)
+            .min()
+            .unwrap_or(0)
+    }
+
+    // Intel HEX with 16 data bytes per record, a start address record if there is a start, and
+    // the end of file record.
+    pub fn to_hex(&self) -> String {
+        let mut hex = String::new();
+
+        for segment in &self.segments {
+            for (i, chunk) in segment.data.chunks(16).enumerate() {
+                let address = segment.address + (i * 16) as u16;
+                hex += &hex_record(0x00, address, chunk);
+            }
+        }
+        if let Some(start) = self.start {
+            // CS = 0, IP = start
+            hex += &hex_record(0x03, 0, &[0, 0, (start >> 8) as u8, start as u8]);
+        }
+
+        hex + &hex_record(0x01, 0, &[])
+    }
+
+    pub fn write_to(&self, memory: &mut dyn MemoryBus) {
+        for segment in &self.segments {
+            for (i, byte) in segment.data.iter().enumerate() {
+                memory.poke(segment.address + i as u16, *byte);
+            }
+        }
+    }
+}
+
+fn check_fits(address: u32, len: usize) -> Result<(), LoadError> {
+    if address as usize + len > MEMORY_SIZE {
+        return Err(LoadError::Overflow { address, len });
+    }
+    Ok(())
+}
+
+fn hex_record(kind: u8, address: u16, data: &[u8]) -> String {
+    let mut record = vec![data.len() as u8, (address >> 8) as u8, address as u8, kind];
+    record.extend_from_slice(data);
+
+    let checksum = record
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+    record.push(checksum);
+
+    let digits: Vec<String> = record.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(":{}\n", digits.concat())
+}
+
+fn parse_hex_bytes(digits: &str) -> Option<Vec<u8>> {
+    // from_str_radix alone would take a sign, "+1" included
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
@@ -1,56 +1,419 @@
-use std::io;
-use std::{env, process};
+use std::env;
+use std::fs;
+use std::io::{self, BufReader};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process;
 
-use crate::disassembler::disassemble;
-use crate::state::State8080;
+use intel_8080_emu::assembler::assemble;
+use intel_8080_emu::cpm::bdos::{check_in_tpa, Bdos, TPA_START};
+use intel_8080_emu::cpm::bios::MAX_DRIVES;
+use intel_8080_emu::cpm::console::Console;
+use intel_8080_emu::cpm::disk::DiskImage;
+use intel_8080_emu::cpm::machine::{CpmMachine, DEFAULT_CCP_BASE};
+use intel_8080_emu::debugger::Debugger;
+use intel_8080_emu::disassembler::{disassemble, Disassembly};
+use intel_8080_emu::gdb::GdbStub;
+use intel_8080_emu::invaders::framebuffer::ImageFormat;
+use intel_8080_emu::invaders::input::InputScript;
+use intel_8080_emu::invaders::{DipSwitches, Invaders, FRAMES_PER_SECOND};
+use intel_8080_emu::loader::Image;
+use intel_8080_emu::rewind::Rewind;
+use intel_8080_emu::state::State8080;
+use intel_8080_emu::symbols::SymbolTable;
+use intel_8080_emu::trace::{TraceFormat, TraceReader, Tracer};
+use intel_8080_emu::tracediff::{self, Comparison, DiffOptions};
 
-mod disassembler;
-mod state;
+const USAGE: &str = "usage:
+    cargo run [--debug | --gdb <port>] [--base <hex address>] [--symbols <file>] [--strict]
+              [TRACE] <COM, binary or Intel HEX file> [command tail...]
+    cargo run disasm [--origin <hex address>] [--entry <hex address>]... [--linear]
+                     [--symbols <symbol file or listing>] <binary>
+    cargo run asm [--hex] [--sym] [-o <output>] <source>
+    cargo run tracediff [--context <n>] [--cycles] <trace a> <trace b>
+    cargo run cpm [--system <CCP+BDOS image>] [--ccp <hex address>] [--strict] [TRACE]
+                  <A: disk image> [B: ...]
+    cargo run invaders [--frames <n>] [--input <script>] [--ships <3-6>] [--extra-ship-at-1000]
+                       [--no-coin-info] [--dump-every <n>] [--dump-at-cycle <n>]...
+                       [--dump-dir <dir>] [--dump-format <png|ppm>] [--load-state <file>]
+                       [--save-state <file>] [--strict] [TRACE] <ROM directory or 8K image>
+where TRACE is: --trace <file> [--trace-format <text|reference|binary>]";
 
 fn main() -> Result<(), io::Error> {
-    if env::args().len() != 2 {
-        panic!("usage: cargo run <path-to-ROM-file>");
+    if env::args().len() < 2 {
+        panic!("{}", USAGE);
     }
 
     let args: Vec<String> = env::args().collect();
-    let file_path = &args[1];
 
-    // // for disassembly
-    // if let Err(e) = disassemble(file_path) {
-    //     eprintln!("Application error: {}", e);
-    //     process::exit(1);
-    // }
+    match args[1].as_str() {
+        "disasm" => run_disasm(&args[2..]),
+        "asm" => run_asm(&args[2..]),
+        "tracediff" => run_tracediff(&args[2..]),
+        "cpm" => run_cpm(&args[2..]),
+        "invaders" => run_invaders(&args[2..]),
+        _ => run_com(&args[1..]),
+    }
+}
+
+// Runs a single .COM file with the current directory standing in for the CP/M drives. Raw
+// binaries are loaded and started at `--base` (the TPA by default), Intel HEX files go where they
+// say. Either has to fit in the TPA.
+// `--debug` starts it in the debugger (recording history to go back with), `--gdb` waits for a
+// GDB remote protocol client on localhost. `--strict` stops at undocumented opcodes instead of
+// running them.
+fn run_com(args: &[String]) -> Result<(), io::Error> {
+    let mut base = TPA_START;
+    let mut symbols = None;
+    let mut debug = false;
+    let mut strict = false;
+    let mut gdb_port = None;
+    let mut trace = None;
+    let mut trace_format = TraceFormat::Text;
+    let mut args = args;
+    loop {
+        match args.first().map(|arg| arg.as_str()) {
+            Some("--base") => base = parse_address(args.get(1)),
+            Some("--trace") => trace = Some(args.get(1).expect(USAGE).clone()),
+            Some("--trace-format") => trace_format = parse_trace_format(args.get(1)),
+            Some("--symbols") => symbols = Some(SymbolTable::load(args.get(1).expect(USAGE))?),
+            Some("--gdb") => {
+                gdb_port = Some(
+                    args.get(1)
+                        .and_then(|port| port.parse::<u16>().ok())
+                        .expect(USAGE),
+                )
+            }
+            Some("--debug") => {
+                debug = true;
+                args = &args[1..];
+                continue;
+            }
+            Some("--strict") => {
+                strict = true;
+                args = &args[1..];
+                continue;
+            }
+            // the program, or nothing at all which the split below reports
+            _ => break,
+        }
+        args = &args[2..];
+    }
+    let (file_path, tail) = args.split_first().expect(USAGE);
 
     let mut state: State8080 = Default::default();
 
-    state.load_rom(file_path)?;
-    state.init();
+    // page zero and the BDOS first, the program has to stay clear of them
+    let mut bdos = Bdos::new(env::current_dir()?);
+    bdos.install(&mut state, &tail.join(" "));
 
-    loop {
-        if state.halted() {
-            break;
-        }
-        state.emulate_cycle();
-
-        // FOR TESTING PURPOSES ONLY
-        if state.pc() == 5 {
-            if state.c() == 9 {
-                let mut i = ((state.d() as usize) << 8) | (state.e() as usize);
-                while (state.memory()[i] as char) != '$' {
-                    print!("{}", state.memory()[i] as char);
-                    i += 1;
-                }
-                print!("\n");
-            } else if state.c() == 2 {
-                println!("{}", state.e() as char);
-            }
+    let image = Image::load(file_path, base)?;
+    check_in_tpa(&image)?;
+    image.write_to(state.memory_mut());
+    // raw binaries start where they were loaded, HEX files at their start record if they have one
+    state.pc = image.start.unwrap_or(base);
+
+    if let Some(symbols) = symbols {
+        state.set_symbols(symbols);
+    }
+    state.set_strict(strict);
+    if let Some(path) = trace {
+        state.set_tracer(Tracer::create(path, trace_format)?);
+    }
+    if let Some(port) = gdb_port {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        eprintln!("waiting for gdb on {}", listener.local_addr()?);
+        GdbStub::new().serve(&listener, &mut state, &mut |state| bdos.trap(state))?;
+    } else if debug {
+        state.enable_rewind(Rewind::default());
+        let mut debugger = Debugger::new(BufReader::new(io::stdin()), io::stdout());
+        debugger.run(&mut state, &mut |state| bdos.trap(state))?;
+    } else {
+        bdos.run(&mut state)?;
+    }
+    finish_trace(&mut state)?;
+
+    println!();
+
+    Ok(())
+}
+
+// Disassembles a raw binary, following the control flow from the entry points (the origin by
+// default) unless `--linear` asks for a plain sweep.
+fn run_disasm(args: &[String]) -> Result<(), io::Error> {
+    let mut origin = TPA_START;
+    let mut entry_points = Vec::new();
+    let mut linear = false;
+    let mut symbols = SymbolTable::new();
+    let mut file_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--origin" => origin = parse_address(args.next()),
+            "--entry" => entry_points.push(parse_address(args.next())),
+            "--linear" => linear = true,
+            "--symbols" => symbols = SymbolTable::load(args.next().expect(USAGE))?,
+            path => file_path = Some(path),
         }
+    }
+    let file_path = file_path.expect(USAGE);
+
+    if linear {
+        return disassemble(file_path, origin, &symbols)
+            .map_err(|e| io::Error::other(e.to_string()));
+    }
+
+    if entry_points.is_empty() {
+        entry_points.push(origin);
+    }
+
+    let code = fs::read(file_path)?;
+    let mut disassembly = Disassembly::new(&code, origin, &entry_points);
+    disassembly.use_symbols(&symbols);
+    print!("{}", disassembly.to_source());
+
+    Ok(())
+}
+
+// Assembles a source file to a raw binary (starting at its lowest address) or Intel HEX, next to
+// the source unless `-o` says otherwise. `--sym` also writes the symbols to a .sym file.
+fn run_asm(args: &[String]) -> Result<(), io::Error> {
+    let mut hex = false;
+    let mut sym = false;
+    let mut output = None;
+    let mut source_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--hex" => hex = true,
+            "--sym" => sym = true,
+            "-o" => output = Some(PathBuf::from(args.next().expect(USAGE))),
+            path => source_path = Some(PathBuf::from(path)),
+        }
+    }
+    let source_path = source_path.expect(USAGE);
+    let output =
+        output.unwrap_or_else(|| source_path.with_extension(if hex { "hex" } else { "bin" }));
+
+    let source = fs::read_to_string(&source_path)?;
+    let assembly = assemble(&source).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", source_path.display(), e),
+        )
+    })?;
+
+    if sym {
+        let symbols: SymbolTable = assembly.symbols.iter().collect();
+        fs::write(source_path.with_extension("sym"), symbols.to_sym())?;
+    }
+
+    if hex {
+        fs::write(output, assembly.image.to_hex())
+    } else {
+        fs::write(output, assembly.image.to_binary())
+    }
+}
+
+// Compares two instruction traces (in any of the trace formats) and shows where they first differ,
+// exiting with 1 if they do.
+fn run_tracediff(args: &[String]) -> Result<(), io::Error> {
+    let mut options = DiffOptions::default();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => options.context = args.next().and_then(|n| n.parse().ok()).expect(USAGE),
+            "--cycles" => options.cycles = true,
+            path => paths.push(path),
+        }
+    }
+    if paths.len() != 2 {
+        panic!("{}", USAGE);
+    }
 
-        if state.pc() == 0 {
-            println!("");
-            break;
+    let a = TraceReader::open(paths[0])?;
+    let b = TraceReader::open(paths[1])?;
+
+    match tracediff::diff(a, b, &options)? {
+        Comparison::Same(count) => println!("the traces match ({} instructions)", count),
+        Comparison::Truncated { count, a_ended } => println!(
+            "the traces match for {} instructions, then {} ends",
+            count,
+            if a_ended { paths[0] } else { paths[1] }
+        ),
+        Comparison::Diverged(divergence) => {
+            print!("{}", divergence);
+            process::exit(1);
         }
     }
 
     Ok(())
 }
+
+// boots CP/M from the disk in drive A:
+fn run_cpm(args: &[String]) -> Result<(), io::Error> {
+    let mut ccp_base = DEFAULT_CCP_BASE;
+    let mut system_image = None;
+    let mut disks = Vec::new();
+    let mut strict = false;
+    let mut trace = None;
+    let mut trace_format = TraceFormat::Text;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--system" => system_image = Some(fs::read(args.next().expect(USAGE))?),
+            "--trace" => trace = Some(args.next().expect(USAGE)),
+            "--trace-format" => trace_format = parse_trace_format(args.next()),
+            "--ccp" => ccp_base = parse_address(args.next()),
+            "--strict" => strict = true,
+            path => disks.push(DiskImage::open(path)?),
+        }
+    }
+
+    if disks.is_empty() || disks.len() > MAX_DRIVES {
+        panic!("{}", USAGE);
+    }
+
+    let mut machine = CpmMachine::new(ccp_base, Console::stdio())?;
+    for (drive, disk) in disks.into_iter().enumerate() {
+        machine.insert(drive, disk);
+    }
+    if let Some(image) = system_image {
+        machine.set_system_image(image);
+    }
+    machine.state.set_strict(strict);
+    if let Some(path) = trace {
+        machine
+            .state
+            .set_tracer(Tracer::create(path, trace_format)?);
+    }
+
+    machine.run()?;
+    finish_trace(&mut machine.state)
+}
+
+// Runs Space Invaders headless up to a frame number, dumping screenshots every `--dump-every`
+// frames and at the end of the frame in which each `--dump-at-cycle` is reached. `--load-state`
+// picks up from a save state, `--save-state` writes one at the end.
+fn run_invaders(args: &[String]) -> Result<(), io::Error> {
+    let mut frames = 10 * FRAMES_PER_SECOND;
+    let mut script = None;
+    let mut dips = DipSwitches::default();
+    let mut rom_path = None;
+    let mut dump_every = None;
+    let mut dump_at_cycles: Vec<u64> = Vec::new();
+    let mut dump_dir = PathBuf::from(".");
+    let mut dump_format = ImageFormat::Png;
+    let mut load_state = None;
+    let mut save_state = None;
+    let mut strict = false;
+    let mut trace = None;
+    let mut trace_format = TraceFormat::Text;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = args.next().and_then(|n| n.parse().ok()).expect(USAGE),
+            "--input" => script = Some(InputScript::load(args.next().expect(USAGE))?),
+            "--ships" => dips.ships = args.next().and_then(|n| n.parse().ok()).expect(USAGE),
+            "--extra-ship-at-1000" => dips.extra_ship_at_1000 = true,
+            "--no-coin-info" => dips.coin_info = false,
+            "--dump-every" => {
+                dump_every = Some(
+                    args.next()
+                        .and_then(|n| n.parse::<u64>().ok())
+                        .expect(USAGE),
+                )
+            }
+            "--dump-at-cycle" => {
+                dump_at_cycles.push(args.next().and_then(|n| n.parse().ok()).expect(USAGE))
+            }
+            "--dump-dir" => dump_dir = PathBuf::from(args.next().expect(USAGE)),
+            "--dump-format" => {
+                dump_format = args
+                    .next()
+                    .and_then(|name| ImageFormat::from_name(name))
+                    .expect(USAGE)
+            }
+            "--load-state" => load_state = Some(args.next().expect(USAGE)),
+            "--save-state" => save_state = Some(args.next().expect(USAGE)),
+            "--strict" => strict = true,
+            "--trace" => trace = Some(args.next().expect(USAGE)),
+            "--trace-format" => trace_format = parse_trace_format(args.next()),
+            path => rom_path = Some(path),
+        }
+    }
+
+    if dump_every == Some(0) {
+        panic!("{}", USAGE);
+    }
+    dump_at_cycles.sort_unstable();
+    let mut dump_at_cycles = dump_at_cycles.into_iter().peekable();
+
+    let rom = Invaders::load_roms(rom_path.expect(USAGE))?;
+    let mut machine = Invaders::new(rom, dips);
+    if let Some(path) = load_state {
+        machine.load_state(path)?;
+    }
+    machine.state.set_strict(strict);
+    if let Some(path) = trace {
+        machine
+            .state
+            .set_tracer(Tracer::create(path, trace_format)?);
+    }
+
+    if dump_every.is_some() || dump_at_cycles.peek().is_some() {
+        fs::create_dir_all(&dump_dir)?;
+    }
+
+    while machine.frame() < frames {
+        if let Some(script) = script.as_mut() {
+            script.apply(&mut machine);
+        }
+        machine.run_frame()?;
+
+        let mut dump = dump_every.is_some_and(|n| machine.frame().is_multiple_of(n));
+        while dump_at_cycles
+            .next_if(|&cycle| cycle <= machine.state.cycles())
+            .is_some()
+        {
+            dump = true;
+        }
+
+        if dump {
+            let file_name = format!("frame-{:06}.{}", machine.frame(), dump_format.extension());
+            machine
+                .framebuffer()
+                .save(dump_dir.join(file_name), dump_format)?;
+        }
+    }
+
+    if let Some(path) = save_state {
+        machine.save_state(path)?;
+    }
+
+    finish_trace(&mut machine.state)
+}
+
+fn parse_trace_format(arg: Option<&String>) -> TraceFormat {
+    arg.and_then(|name| TraceFormat::from_name(name))
+        .expect(USAGE)
+}
+
+fn finish_trace(state: &mut State8080) -> Result<(), io::Error> {
+    match state.take_tracer() {
+        Some(tracer) => tracer.finish(),
+        None => Ok(()),
+    }
+}
+
+// a hex address option, with or without a 0x prefix
+fn parse_address(arg: Option<&String>) -> u16 {
+    let address = arg.expect(USAGE).trim_start_matches("0x");
+    u16::from_str_radix(address, 16).expect(USAGE)
+}
@@ -0,0 +1,360 @@
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+pub const MEMORY_SIZE: usize = 65_536; // 2 ^ 16, 16-bit addresses
+
+// Everything the CPU can see on its 16-bit address bus. `read` and `write` are the CPU's own
+// accesses and may have side effects on devices, `peek` and `poke` are for tooling (loaders,
+// debuggers, disassemblers) and must not.
+pub trait MemoryBus {
+    fn read(&mut self, address: u16) -> u8;
+
+    fn write(&mut self, address: u16, value: u8);
+
+    // side-effect free read
+    fn peek(&self, address: u16) -> u8;
+
+    // write that ignores write protection, e.g. for loading a ROM image
+    fn poke(&mut self, address: u16, value: u8);
+
+    // Addresses backed by a device rather than storage. Save states leave their bytes alone and
+    // rely on the devices' own state instead.
+    fn is_device(&self, _address: u16) -> bool {
+        false
+    }
+
+    // whatever the memory-mapped devices need to carry on where they were, for save states
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    // the counterpart of `save_state`, gets exactly what it returned
+    fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        no_state_expected(data)
+    }
+}
+
+// A memory-mapped device. `offset` is relative to the start of the range the device is mapped at.
+pub trait MemoryDevice {
+    fn read(&mut self, offset: u16) -> u8;
+
+    fn write(&mut self, offset: u16, value: u8);
+
+    // side-effect free read, devices without readable state just return the open bus value
+    fn peek(&self, _offset: u16) -> u8 {
+        OPEN_BUS
+    }
+
+    // side-effect free write, for devices with state that tooling can set; ignored by default
+    fn poke(&mut self, _offset: u16, _value: u8) {}
+
+    // for save states, like `PortBus::save_state`
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        no_state_expected(data)
+    }
+}
+
+fn no_state_expected(data: &[u8]) -> io::Result<()> {
+    if data.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "state saved for devices that aren't mapped",
+        ))
+    }
+}
+
+// lets a machine keep a handle to its devices after mapping them
+impl<T: MemoryDevice> MemoryDevice for Rc<RefCell<T>> {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.borrow_mut().read(offset)
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        self.borrow_mut().write(offset, value)
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        self.borrow().peek(offset)
+    }
+
+    fn poke(&mut self, offset: u16, value: u8) {
+        self.borrow_mut().poke(offset, value)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.borrow().save_state()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        self.borrow_mut().load_state(data)
+    }
+}
+
+// value read from addresses nothing responds to (pulled-up data bus)
+pub const OPEN_BUS: u8 = 0xFF;
+
+// The default: 64K of plain RAM.
+pub struct FlatMemory {
+    bytes: Box<[u8; MEMORY_SIZE]>,
+}
+
+impl Default for FlatMemory {
+    fn default() -> Self {
+        FlatMemory {
+            bytes: Box::new([0; MEMORY_SIZE]),
+        }
+    }
+}
+
+impl MemoryBus for FlatMemory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.bytes[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.bytes[address as usize] = value;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.bytes[address as usize]
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.bytes[address as usize] = value;
+    }
+}
+
+enum RegionKind {
+    Ram(Vec<u8>),
+    Rom(Vec<u8>),
+    // accesses are redirected to `target + (offset % size)`
+    Mirror { target: u16, size: u16 },
+    Device(Box<dyn MemoryDevice>),
+}
+
+struct Region {
+    start: u16,
+    end: u16, // inclusive
+    kind: RegionKind,
+}
+
+// where an address ends up after mirrors are resolved
+enum Resolved {
+    Region(usize, u16), // region index, offset into the region
+    Unmapped,
+}
+
+// An address space assembled from RAM, ROM, mirrored and device regions. Addresses that no region
+// covers are unmapped: writes are dropped and reads return the open bus value. When regions
+// overlap, the one mapped last wins.
+pub struct MappedMemory {
+    regions: Vec<Region>,
+    open_bus: u8,
+}
+
+impl Default for MappedMemory {
+    fn default() -> Self {
+        MappedMemory {
+            regions: Vec::new(),
+            open_bus: OPEN_BUS,
+        }
+    }
+}
+
+impl MappedMemory {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // value returned when reading an unmapped address
+    pub fn set_open_bus(&mut self, value: u8) {
+        self.open_bus = value;
+    }
+
+    // zero-filled RAM at `start..=end`
+    pub fn map_ram(&mut self, start: u16, end: u16) {
+        // a backwards range has no size, `map` turns it down
+        let size = end.checked_sub(start).map_or(0, |size| size as usize + 1);
+        self.map(start, end, RegionKind::Ram(vec![0; size]));
+    }
+
+    // read-only `data` starting at `start`
+    pub fn map_rom(&mut self, start: u16, data: Vec<u8>) {
+        assert!(!data.is_empty(), "ROM image is empty");
+        assert!(
+            start as usize + data.len() <= MEMORY_SIZE,
+            "ROM image does not fit in the address space"
+        );
+
+        let end = (start as usize + data.len() - 1) as u16;
+        self.map(start, end, RegionKind::Rom(data));
+    }
+
+    // makes `start..=end` an image of the `size` bytes at `target`, repeated as needed
+    pub fn map_mirror(&mut self, start: u16, end: u16, target: u16, size: u16) {
+        assert!(size > 0, "mirrored range is empty");
+        self.map(start, end, RegionKind::Mirror { target, size });
+    }
+
+    // hands every access to `start..=end` to `device`
+    pub fn map_device<D: MemoryDevice + 'static>(&mut self, start: u16, end: u16, device: D) {
+        self.map(start, end, RegionKind::Device(Box::new(device)));
+    }
+
+    fn map(&mut self, start: u16, end: u16, kind: RegionKind) {
+        assert!(
+            start <= end,
+            "region end {:04x} is before its start {:04x}",
+            end,
+            start
+        );
+        self.regions.push(Region { start, end, kind });
+    }
+
+    fn find(&self, address: u16) -> Option<usize> {
+        self.regions
+            .iter()
+            .rposition(|region| region.start <= address && address <= region.end)
+    }
+
+    fn devices(&self) -> impl Iterator<Item = &dyn MemoryDevice> {
+        self.regions.iter().filter_map(|region| match &region.kind {
+            RegionKind::Device(device) => Some(device.as_ref()),
+            _ => None,
+        })
+    }
+
+    fn resolve(&self, address: u16) -> Resolved {
+        let index = match self.find(address) {
+            Some(index) => index,
+            None => return Resolved::Unmapped,
+        };
+        let region = &self.regions[index];
+
+        match region.kind {
+            // a mirror of a mirror is not followed any further
+            RegionKind::Mirror { target, size } => {
+                let address = target.wrapping_add((address - region.start) % size);
+
+                match self.find(address) {
+                    Some(index) => match self.regions[index].kind {
+                        RegionKind::Mirror { .. } => Resolved::Unmapped,
+                        _ => Resolved::Region(index, address - self.regions[index].start),
+                    },
+                    None => Resolved::Unmapped,
+                }
+            }
+            _ => Resolved::Region(index, address - region.start),
+        }
+    }
+}
+
+impl MemoryBus for MappedMemory {
+    fn read(&mut self, address: u16) -> u8 {
+        match self.resolve(address) {
+            Resolved::Region(index, offset) => match &mut self.regions[index].kind {
+                RegionKind::Ram(bytes) | RegionKind::Rom(bytes) => bytes[offset as usize],
+                RegionKind::Device(device) => device.read(offset),
+                RegionKind::Mirror { .. } => self.open_bus,
+            },
+            Resolved::Unmapped => self.open_bus,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if let Resolved::Region(index, offset) = self.resolve(address) {
+            match &mut self.regions[index].kind {
+                RegionKind::Ram(bytes) => bytes[offset as usize] = value,
+                RegionKind::Device(device) => device.write(offset, value),
+                // writes to ROM are silently ignored, like on the real bus
+                RegionKind::Rom(_) | RegionKind::Mirror { .. } => (),
+            }
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match self.resolve(address) {
+            Resolved::Region(index, offset) => match &self.regions[index].kind {
+                RegionKind::Ram(bytes) | RegionKind::Rom(bytes) => bytes[offset as usize],
+                RegionKind::Device(device) => device.peek(offset),
+                RegionKind::Mirror { .. } => self.open_bus,
+            },
+            Resolved::Unmapped => self.open_bus,
+        }
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        if let Resolved::Region(index, offset) = self.resolve(address) {
+            match &mut self.regions[index].kind {
+                RegionKind::Ram(bytes) | RegionKind::Rom(bytes) => bytes[offset as usize] = value,
+                RegionKind::Device(device) => device.poke(offset, value),
+                RegionKind::Mirror { .. } => (),
+            }
+        }
+    }
+
+    fn is_device(&self, address: u16) -> bool {
+        match self.resolve(address) {
+            Resolved::Region(index, _) => {
+                matches!(self.regions[index].kind, RegionKind::Device(_))
+            }
+            Resolved::Unmapped => false,
+        }
+    }
+
+    // each device's state in the order they were mapped, preceded by its length (u32)
+    fn save_state(&self) -> Vec<u8> {
+        let states: Vec<Vec<u8>> = self.devices().map(|device| device.save_state()).collect();
+
+        // devices without state leave nothing to save, same as memory without devices
+        let mut data = Vec::new();
+        if states.iter().any(|state| !state.is_empty()) {
+            for state in states {
+                data.extend_from_slice(&(state.len() as u32).to_le_bytes());
+                data.extend_from_slice(&state);
+            }
+        }
+        data
+    }
+
+    // every device's slice is found before any device gets one, so a short or overlong state
+    // leaves them all alone
+    fn load_state(&mut self, mut data: &[u8]) -> io::Result<()> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut states = Vec::new();
+        for _ in self.devices() {
+            if data.is_empty() {
+                states.push(&[][..]);
+                continue;
+            }
+            if data.len() < 4 {
+                return Err(invalid("truncated device state"));
+            }
+            let length = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+            if data.len() < 4 + length {
+                return Err(invalid("truncated device state"));
+            }
+            states.push(&data[4..4 + length]);
+            data = &data[4 + length..];
+        }
+        if !data.is_empty() {
+            return Err(invalid("state saved for more devices than are mapped"));
+        }
+
+        let mut states = states.into_iter();
+        for region in &mut self.regions {
+            if let RegionKind::Device(device) = &mut region.kind {
+                device.load_state(states.next().unwrap())?;
+            }
+        }
+        Ok(())
+    }
+}
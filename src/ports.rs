@@ -0,0 +1,61 @@
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+// Devices wired to the 8080's 256 I/O ports. The CPU only knows the port number (the operand of
+// `IN d8`/`OUT d8`) and the accumulator, everything else is up to the machine definition.
+pub trait PortBus {
+    // `IN d8`, the returned byte is loaded into the accumulator
+    fn input(&mut self, port: u8) -> u8;
+
+    // `OUT d8`, `value` is the accumulator
+    fn output(&mut self, port: u8, value: u8);
+
+    // whatever the devices need to carry on where they were, for save states
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    // the counterpart of `save_state`, gets exactly what it returned
+    fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "state saved for devices that aren't attached",
+            ))
+        }
+    }
+}
+
+// Nothing attached: reads see a floating data bus (all 1s) and writes are dropped.
+#[derive(Default)]
+pub struct NullPortBus;
+
+impl PortBus for NullPortBus {
+    fn input(&mut self, _port: u8) -> u8 {
+        0xFF
+    }
+
+    fn output(&mut self, _port: u8, _value: u8) {}
+}
+
+// lets a machine keep a handle to its devices after handing them to the CPU
+impl<T: PortBus> PortBus for Rc<RefCell<T>> {
+    fn input(&mut self, port: u8) -> u8 {
+        self.borrow_mut().input(port)
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.borrow_mut().output(port, value)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.borrow().save_state()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        self.borrow_mut().load_state(data)
+    }
+}
@@ -0,0 +1,187 @@
+// Execution history for going backwards. Every instruction gets a journal entry with the CPU state
+// before it and the old value of each byte it wrote, so recent history can be undone one
+// instruction at a time. Entries that fall off the end of the journal are folded into snapshots
+// covering `snapshot_interval` instructions each, which only keep the first old value of every
+// address written in their interval: older history can still be rewound, in coarser jumps.
+//
+// Only the CPU and memory are recorded. Whatever the program did to devices, the console or files
+// stays done, and memory changed from outside (debugger edits, BDOS calls) isn't undone. Machines
+// that service calls on the host `cut` the history there, so going back stops after the call
+// rather than pretending to undo it.
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::memory::MemoryBus;
+use crate::state::CpuState;
+
+// instructions kept individually, about 40 bytes each
+pub const DEFAULT_JOURNAL_LENGTH: usize = 250_000;
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 50_000;
+// snapshots kept beyond the journal (another 100 million instructions by default)
+pub const DEFAULT_SNAPSHOT_COUNT: usize = 2000;
+
+struct Step {
+    before: CpuState,
+    // how many of the journal's writes belong to this instruction
+    writes: u8,
+}
+
+struct Snapshot {
+    start: CpuState,
+    steps: u64,
+    // address -> value at `start`
+    memory: BTreeMap<u16, u8>,
+}
+
+pub struct Rewind {
+    journal_length: usize,
+    snapshot_interval: u64,
+    snapshot_count: usize,
+    steps: VecDeque<Step>,
+    // (address, old value), in execution order
+    writes: VecDeque<(u16, u8)>,
+    // oldest first, the last one fills up as the journal overflows
+    snapshots: VecDeque<Snapshot>,
+    // leave the next instruction out, see `cut`
+    skip_next: bool,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Rewind::new(
+            DEFAULT_JOURNAL_LENGTH,
+            DEFAULT_SNAPSHOT_INTERVAL,
+            DEFAULT_SNAPSHOT_COUNT,
+        )
+    }
+}
+
+impl Rewind {
+    pub fn new(journal_length: usize, snapshot_interval: u64, snapshot_count: usize) -> Self {
+        Rewind {
+            journal_length: journal_length.max(1),
+            snapshot_interval: snapshot_interval.max(1),
+            snapshot_count,
+            steps: VecDeque::new(),
+            writes: VecDeque::new(),
+            snapshots: VecDeque::new(),
+            skip_next: false,
+        }
+    }
+
+    // instructions that can be undone
+    pub fn len(&self) -> u64 {
+        self.steps.len() as u64
+            + self
+                .snapshots
+                .iter()
+                .map(|snapshot| snapshot.steps)
+                .sum::<u64>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty() && self.snapshots.is_empty()
+    }
+
+    // instructions that can be undone one at a time
+    pub fn journal_len(&self) -> usize {
+        self.steps.len()
+    }
+
+    // the cycle count as far back as the history goes
+    pub fn oldest_cycle(&self) -> Option<u64> {
+        self.snapshots
+            .front()
+            .map(|snapshot| snapshot.start.cycles)
+            .or_else(|| self.steps.front().map(|step| step.before.cycles))
+    }
+
+    pub fn clear(&mut self) {
+        self.steps.clear();
+        self.writes.clear();
+        self.snapshots.clear();
+    }
+
+    // Forgets everything up to and including the next instruction, which is the RET back from a
+    // BDOS or BIOS call that has just been serviced. Stepping back onto it would run the call a
+    // second time.
+    pub fn cut(&mut self) {
+        self.clear();
+        self.skip_next = true;
+    }
+
+    // called by the core before every instruction
+    pub(crate) fn record_step(&mut self, before: CpuState) {
+        if self.skip_next {
+            self.skip_next = false;
+            return;
+        }
+        self.steps.push_back(Step { before, writes: 0 });
+
+        if self.steps.len() > self.journal_length {
+            self.compact();
+        }
+    }
+
+    // called by the core before every memory write
+    pub(crate) fn record_write(&mut self, address: u16, old: u8) {
+        if let Some(step) = self.steps.back_mut() {
+            step.writes += 1;
+            self.writes.push_back((address, old));
+        }
+    }
+
+    // Undoes the last instruction, or the last snapshot's worth of them once the journal has run
+    // out, returning the CPU state to go back to.
+    pub(crate) fn step_back(&mut self, memory: &mut dyn MemoryBus) -> Option<CpuState> {
+        if let Some(step) = self.steps.pop_back() {
+            for _ in 0..step.writes {
+                if let Some((address, old)) = self.writes.pop_back() {
+                    memory.poke(address, old);
+                }
+            }
+            return Some(step.before);
+        }
+
+        let snapshot = self.snapshots.pop_back()?;
+        for (&address, &old) in &snapshot.memory {
+            memory.poke(address, old);
+        }
+        Some(snapshot.start)
+    }
+
+    // moves the oldest journal entry into the newest snapshot
+    fn compact(&mut self) {
+        let step = match self.steps.pop_front() {
+            Some(step) => step,
+            None => return,
+        };
+
+        let full = self
+            .snapshots
+            .back()
+            .is_none_or(|snapshot| snapshot.steps == self.snapshot_interval);
+        if full {
+            self.snapshots.push_back(Snapshot {
+                start: step.before,
+                steps: 0,
+                memory: BTreeMap::new(),
+            });
+            if self.snapshots.len() > self.snapshot_count {
+                self.snapshots.pop_front();
+            }
+        }
+
+        let writes = self.writes.drain(..step.writes as usize);
+        match self.snapshots.back_mut() {
+            Some(snapshot) => {
+                snapshot.steps += 1;
+                for (address, old) in writes {
+                    // the earliest write knows what was there when the snapshot started
+                    snapshot.memory.entry(address).or_insert(old);
+                }
+            }
+            // no snapshots wanted, the history just ends here
+            None => drop(writes),
+        }
+    }
+}
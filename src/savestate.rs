@@ -0,0 +1,303 @@
+// Save states: the whole machine (CPU, memory and whatever the port devices keep) in one file.
+//
+//     "I8080SAV"                      magic
+//     u16 version                     FORMAT_VERSION of the writer
+//     u16 compatible                  the oldest FORMAT_VERSION that can read the file
+//     sections...                     4-byte tag, u32 length, data
+//     "END " 0
+//
+// Numbers are little-endian. Like PNG chunks, sections with an uppercase first letter are needed to
+// restore the machine, readers refuse files with ones they don't know, lowercase ones can be
+// skipped. Sections can also grow at the end, readers ignore what they don't know of them.
+//
+//     "CPU "  A B C D E H L F, SP, PC, INTE, EI delay, halted, pending interrupt (flag, opcode),
+//             cycles (u64)
+//     "MEM "  the 64K address space as the CPU sees it
+//     "PORT"  the port devices' own data (see `PortBus::save_state`)
+//     "DEVS"  the memory-mapped devices' own data (see `MemoryBus::save_state`), only written when
+//             there is some
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::memory::MEMORY_SIZE;
+use crate::state::{CpuState, State8080};
+
+pub const FORMAT_VERSION: u16 = 1;
+// bump to FORMAT_VERSION when a change means older readers would restore the machine wrong
+const COMPATIBLE_VERSION: u16 = 1;
+
+const MAGIC: &[u8; 8] = b"I8080SAV";
+
+const CPU: &[u8; 4] = b"CPU ";
+const MEMORY: &[u8; 4] = b"MEM ";
+const PORTS: &[u8; 4] = b"PORT";
+const DEVICES: &[u8; 4] = b"DEVS";
+const END: &[u8; 4] = b"END ";
+
+const CPU_SECTION_LENGTH: usize = 25;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(io::Error),
+    NotASaveState,
+    // written by a newer version that older ones can't restore
+    TooNew { version: u16, compatible: u16 },
+    // a section this version doesn't know but can't do without
+    UnknownSection(String),
+    Corrupt(String),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::Io(e) => write!(f, "{}", e),
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::TooNew {
+                version,
+                compatible,
+            } => write!(
+                f,
+                "save state format {} needs a reader for format {} or later, this one reads up to {}",
+                version, compatible, FORMAT_VERSION
+            ),
+            SaveStateError::UnknownSection(tag) => write!(f, "unknown save state section {:?}", tag),
+            SaveStateError::Corrupt(reason) => write!(f, "corrupt save state: {}", reason),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+impl From<io::Error> for SaveStateError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => SaveStateError::Corrupt("truncated".to_string()),
+            _ => SaveStateError::Io(e),
+        }
+    }
+}
+
+impl From<SaveStateError> for io::Error {
+    fn from(e: SaveStateError) -> Self {
+        match e {
+            SaveStateError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaveState {
+    pub cpu: CpuState,
+    pub memory: Vec<u8>,
+    pub ports: Vec<u8>,
+    pub devices: Vec<u8>,
+}
+
+impl SaveState {
+    pub fn capture(state: &State8080) -> SaveState {
+        SaveState {
+            cpu: state.cpu_state(),
+            memory: (0..MEMORY_SIZE)
+                .map(|address| state.memory().peek(address as u16))
+                .collect(),
+            ports: state.ports().save_state(),
+            devices: state.memory().save_state(),
+        }
+    }
+
+    // Puts the machine back the way it was, or leaves it as it is if the state doesn't fit it.
+    // Memory is written with `poke`, so ROM is restored as well. Memory-mapped devices get their
+    // own state back instead of the bytes they showed.
+    pub fn restore(&self, state: &mut State8080) -> Result<(), SaveStateError> {
+        // Only the devices can make sense of their data, so they're handed it before anything
+        // else is touched. If some refuse, the ones that took theirs get their old state back.
+        let ports = state.ports().save_state();
+        let devices = state.memory().save_state();
+        if let Err(e) = self.restore_devices(state) {
+            let _ = state.ports_mut().load_state(&ports);
+            let _ = state.memory_mut().load_state(&devices);
+            return Err(e);
+        }
+
+        let memory = state.memory_mut();
+        for (address, byte) in self.memory.iter().enumerate() {
+            if !memory.is_device(address as u16) {
+                memory.poke(address as u16, *byte);
+            }
+        }
+        state.set_cpu_state(&self.cpu);
+
+        Ok(())
+    }
+
+    fn restore_devices(&self, state: &mut State8080) -> Result<(), SaveStateError> {
+        state
+            .ports_mut()
+            .load_state(&self.ports)
+            .map_err(|e| SaveStateError::Corrupt(format!("port devices: {}", e)))?;
+        state
+            .memory_mut()
+            .load_state(&self.devices)
+            .map_err(|e| SaveStateError::Corrupt(format!("memory-mapped devices: {}", e)))
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        out.write_all(&COMPATIBLE_VERSION.to_le_bytes())?;
+
+        let cpu = &self.cpu;
+        let mut data = vec![cpu.a, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.flags];
+        data.extend_from_slice(&cpu.sp.to_le_bytes());
+        data.extend_from_slice(&cpu.pc.to_le_bytes());
+        data.push(cpu.int_enable as u8);
+        data.push(cpu.int_delay as u8);
+        data.push(cpu.halted as u8);
+        data.push(cpu.pending_interrupt.is_some() as u8);
+        data.push(cpu.pending_interrupt.unwrap_or(0));
+        data.extend_from_slice(&cpu.cycles.to_le_bytes());
+
+        write_section(out, CPU, &data)?;
+        write_section(out, MEMORY, &self.memory)?;
+        write_section(out, PORTS, &self.ports)?;
+        if !self.devices.is_empty() {
+            write_section(out, DEVICES, &self.devices)?;
+        }
+        write_section(out, END, &[])
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> Result<SaveState, SaveStateError> {
+        let mut magic = [0u8; 8];
+        input
+            .read_exact(&mut magic)
+            .map_err(|_| SaveStateError::NotASaveState)?;
+        if &magic != MAGIC {
+            return Err(SaveStateError::NotASaveState);
+        }
+
+        let version = read_u16(input)?;
+        let compatible = read_u16(input)?;
+        if compatible > FORMAT_VERSION {
+            return Err(SaveStateError::TooNew {
+                version,
+                compatible,
+            });
+        }
+
+        let mut cpu = None;
+        let mut memory = None;
+        let mut ports = Vec::new();
+        let mut devices = Vec::new();
+
+        loop {
+            let mut tag = [0u8; 4];
+            input.read_exact(&mut tag)?;
+            let length = read_u32(input)? as usize;
+
+            let mut data = Vec::new();
+            input.take(length as u64).read_to_end(&mut data)?;
+            if data.len() != length {
+                return Err(SaveStateError::Corrupt("truncated".to_string()));
+            }
+
+            match &tag {
+                CPU => cpu = Some(parse_cpu(&data)?),
+                MEMORY => {
+                    if length != MEMORY_SIZE {
+                        return Err(SaveStateError::Corrupt(format!(
+                            "{} bytes of memory",
+                            length
+                        )));
+                    }
+                    memory = Some(data);
+                }
+                PORTS => ports = data,
+                DEVICES => devices = data,
+                END => break,
+                tag if tag[0].is_ascii_lowercase() => (),
+                tag => {
+                    return Err(SaveStateError::UnknownSection(
+                        String::from_utf8_lossy(tag).into_owned(),
+                    ))
+                }
+            }
+        }
+
+        Ok(SaveState {
+            cpu: cpu.ok_or_else(|| SaveStateError::Corrupt("no CPU section".to_string()))?,
+            memory: memory
+                .ok_or_else(|| SaveStateError::Corrupt("no memory section".to_string()))?,
+            ports,
+            devices,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut data = Vec::new();
+        self.write_to(&mut data)?;
+        fs::write(path, data)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SaveState, SaveStateError> {
+        let data = fs::read(path)?;
+        SaveState::read_from(&mut data.as_slice())
+    }
+}
+
+fn write_section<W: Write>(out: &mut W, tag: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(tag)?;
+    out.write_all(&(data.len() as u32).to_le_bytes())?;
+    out.write_all(data)
+}
+
+fn parse_cpu(data: &[u8]) -> Result<CpuState, SaveStateError> {
+    if data.len() < CPU_SECTION_LENGTH {
+        return Err(SaveStateError::Corrupt(format!(
+            "{} bytes of CPU state",
+            data.len()
+        )));
+    }
+
+    let flag = |index: usize| match data[index] {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(SaveStateError::Corrupt("bad CPU flag".to_string())),
+    };
+
+    let mut cycles = [0u8; 8];
+    cycles.copy_from_slice(&data[17..25]);
+
+    Ok(CpuState {
+        a: data[0],
+        b: data[1],
+        c: data[2],
+        d: data[3],
+        e: data[4],
+        h: data[5],
+        l: data[6],
+        flags: data[7],
+        sp: u16::from_le_bytes([data[8], data[9]]),
+        pc: u16::from_le_bytes([data[10], data[11]]),
+        int_enable: flag(12)?,
+        int_delay: flag(13)?,
+        halted: flag(14)?,
+        pending_interrupt: if flag(15)? { Some(data[16]) } else { None },
+        cycles: u64::from_le_bytes(cycles),
+    })
+}
+
+fn read_u16<R: Read>(input: &mut R) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    input.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
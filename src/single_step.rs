@@ -0,0 +1,485 @@
+// Single-step test vectors: one instruction each, run from a given machine state and compared
+// against the state it should leave behind. The files are JSON arrays of tests laid out like the
+// SingleStepTests processor tests:
+//
+//     {
+//         "name": "3e 42 00",
+//         "initial": {
+//             "pc": 256, "sp": 61440, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 2, "h": 0, "l": 0,
+//             "ram": [[256, 62], [257, 66]]
+//         },
+//         "final": { ... the same ... },
+//         "cycles": [[256, 62, "r--m"], ...],
+//         "ports": [[16, 90, "r"], ...]
+//     }
+//
+// Registers are numbers, `ram` lists [address, value] for every byte the test cares about (the
+// rest reads as 0). `cycles` has one entry per state (only the count is compared, this core doesn't
+// model the bus) and `ports` the IN ("r") and OUT ("w") accesses in order. Both are only compared
+// when they're there, as is "inte" (0 or 1, or a boolean) in the states.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::json::Json;
+use crate::memory::MemoryBus;
+use crate::ports::PortBus;
+use crate::state::{CpuState, State8080};
+use crate::tracediff::flag_names;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineState {
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub f: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub inte: Option<bool>,
+    pub ram: Vec<(u16, u8)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PortAccess {
+    pub port: u8,
+    pub value: u8,
+    pub write: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestVector {
+    pub name: String,
+    pub initial: MachineState,
+    pub expected: MachineState,
+    // states the instruction takes, if the vector has its bus cycles
+    pub cycles: Option<u32>,
+    // the port accesses, if the vector has them
+    pub ports: Option<Vec<PortAccess>>,
+}
+
+impl TestVector {
+    pub fn from_json(json: &Json) -> io::Result<TestVector> {
+        let name = json
+            .get("name")
+            .and_then(Json::as_str)
+            .unwrap_or("(unnamed)")
+            .to_string();
+        let context = |e: io::Error| invalid(format!("test {:?}: {}", name, e));
+
+        let cycles = match json.get("cycles") {
+            Some(cycles) => Some(
+                cycles
+                    .as_array()
+                    .ok_or_else(|| invalid("\"cycles\" isn't an array".to_string()))
+                    .map_err(context)?
+                    .len() as u32,
+            ),
+            None => None,
+        };
+
+        let ports = match json.get("ports") {
+            Some(accesses) => Some(
+                accesses
+                    .as_array()
+                    .ok_or_else(|| invalid("\"ports\" isn't an array".to_string()))
+                    .and_then(|accesses| accesses.iter().map(parse_port_access).collect())
+                    .map_err(context)?,
+            ),
+            None => None,
+        };
+
+        Ok(TestVector {
+            initial: parse_state(json, "initial").map_err(context)?,
+            expected: parse_state(json, "final").map_err(context)?,
+            name,
+            cycles,
+            ports,
+        })
+    }
+}
+
+// every test in a file, which holds either an array of them or just one
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<TestVector>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    let json = Json::parse(&text).map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+
+    let tests = match &json {
+        Json::Array(tests) => tests.iter().map(TestVector::from_json).collect(),
+        test => TestVector::from_json(test).map(|test| vec![test]),
+    };
+    tests.map_err(|e| invalid(format!("{}: {}", path.display(), e)))
+}
+
+fn parse_state(json: &Json, key: &str) -> io::Result<MachineState> {
+    let state = json
+        .get(key)
+        .ok_or_else(|| invalid(format!("no \"{}\" state", key)))?;
+
+    let number = |name: &str, max: u64| {
+        state
+            .get(name)
+            .and_then(Json::as_u64)
+            .filter(|&n| n <= max)
+            .ok_or_else(|| invalid(format!("bad or missing \"{}\" in \"{}\"", name, key)))
+    };
+    let byte = |name: &str| number(name, 0xFF).map(|n| n as u8);
+    let word = |name: &str| number(name, 0xFFFF).map(|n| n as u16);
+
+    let mut machine = MachineState {
+        a: byte("a")?,
+        b: byte("b")?,
+        c: byte("c")?,
+        d: byte("d")?,
+        e: byte("e")?,
+        f: byte("f")?,
+        h: byte("h")?,
+        l: byte("l")?,
+        sp: word("sp")?,
+        pc: word("pc")?,
+        inte: None,
+        ram: Vec::new(),
+    };
+
+    machine.inte = match state.get("inte") {
+        Some(inte) => Some(
+            inte.as_bool()
+                .or_else(|| inte.as_u64().filter(|&n| n <= 1).map(|n| n == 1))
+                .ok_or_else(|| invalid(format!("bad \"inte\" in \"{}\"", key)))?,
+        ),
+        None => None,
+    };
+
+    let entries = state
+        .get("ram")
+        .and_then(Json::as_array)
+        .ok_or_else(|| invalid(format!("bad or missing \"ram\" in \"{}\"", key)))?;
+    for entry in entries {
+        match entry.as_array().map(|pair| (pair, pair.len())) {
+            Some((pair, 2)) => match (pair[0].as_u64(), pair[1].as_u64()) {
+                (Some(address), Some(value)) if address <= 0xFFFF && value <= 0xFF => {
+                    machine.ram.push((address as u16, value as u8))
+                }
+                _ => return Err(invalid(format!("bad \"ram\" entry in \"{}\"", key))),
+            },
+            _ => return Err(invalid(format!("bad \"ram\" entry in \"{}\"", key))),
+        }
+    }
+
+    Ok(machine)
+}
+
+fn parse_port_access(json: &Json) -> io::Result<PortAccess> {
+    let bad = || invalid("bad \"ports\" entry".to_string());
+
+    let entry = json
+        .as_array()
+        .filter(|entry| entry.len() == 3)
+        .ok_or_else(bad)?;
+    let port = entry[0].as_u64().filter(|&n| n <= 0xFF).ok_or_else(bad)?;
+    let value = entry[1].as_u64().filter(|&n| n <= 0xFF).ok_or_else(bad)?;
+    let write = match entry[2].as_str() {
+        Some("r") => false,
+        Some("w") => true,
+        _ => return Err(bad()),
+    };
+
+    Ok(PortAccess {
+        port: port as u8,
+        value: value as u8,
+        write,
+    })
+}
+
+pub struct RunOptions {
+    // compare the states taken against the vector's bus cycles
+    pub cycles: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions { cycles: true }
+    }
+}
+
+// one field that came out different
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.field, self.expected, self.actual
+        )
+    }
+}
+
+pub struct Failure {
+    pub name: String,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}:", self.name)?;
+        for mismatch in &self.mismatches {
+            writeln!(f, "    {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+// Memory with only the bytes a test lists, shared with the runner. Writes are logged so that ones
+// the test doesn't expect show up.
+#[derive(Clone, Default)]
+struct VectorMemory(Rc<RefCell<MemoryContents>>);
+
+#[derive(Default)]
+struct MemoryContents {
+    bytes: HashMap<u16, u8>,
+    writes: Vec<u16>,
+}
+
+impl MemoryBus for VectorMemory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let mut contents = self.0.borrow_mut();
+        contents.bytes.insert(address, value);
+        contents.writes.push(address);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.0.borrow().bytes.get(&address).copied().unwrap_or(0)
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.0.borrow_mut().bytes.insert(address, value);
+    }
+}
+
+// IN gets the values the test lists, in order, OUT is recorded
+#[derive(Default)]
+struct VectorPorts {
+    inputs: Vec<u8>,
+    accesses: Vec<PortAccess>,
+}
+
+impl PortBus for VectorPorts {
+    fn input(&mut self, port: u8) -> u8 {
+        let reads = self.accesses.iter().filter(|access| !access.write).count();
+        let value = self.inputs.get(reads).copied().unwrap_or(0xFF);
+        self.accesses.push(PortAccess {
+            port,
+            value,
+            write: false,
+        });
+        value
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.accesses.push(PortAccess {
+            port,
+            value,
+            write: true,
+        });
+    }
+}
+
+// Runs tests one after the other on the same machine, which is quicker than setting one up for
+// each of the thousands there usually are in a file.
+pub struct Runner {
+    state: State8080,
+    memory: VectorMemory,
+    ports: Rc<RefCell<VectorPorts>>,
+    options: RunOptions,
+}
+
+impl Runner {
+    pub fn new(options: RunOptions) -> Runner {
+        let memory = VectorMemory::default();
+        let ports = Rc::new(RefCell::new(VectorPorts::default()));
+
+        let mut state = State8080::default();
+        state.attach_memory(memory.clone());
+        state.attach_ports(ports.clone());
+
+        Runner {
+            state,
+            memory,
+            ports,
+            options,
+        }
+    }
+
+    // sets the machine up as `test` says, executes one instruction and returns what differs
+    pub fn run(&mut self, test: &TestVector) -> Vec<Mismatch> {
+        let initial = &test.initial;
+        {
+            let mut contents = self.memory.0.borrow_mut();
+            contents.bytes = initial.ram.iter().copied().collect();
+            contents.writes.clear();
+        }
+        {
+            let mut ports = self.ports.borrow_mut();
+            ports.inputs = test
+                .ports
+                .iter()
+                .flatten()
+                .filter(|access| !access.write)
+                .map(|access| access.value)
+                .collect();
+            ports.accesses.clear();
+        }
+        self.state.set_cpu_state(&CpuState {
+            a: initial.a,
+            b: initial.b,
+            c: initial.c,
+            d: initial.d,
+            e: initial.e,
+            h: initial.h,
+            l: initial.l,
+            sp: initial.sp,
+            pc: initial.pc,
+            flags: initial.f,
+            int_enable: initial.inte.unwrap_or(false),
+            int_delay: false,
+            pending_interrupt: None,
+            halted: false,
+            cycles: 0,
+        });
+
+        let cycles = self.state.emulate_cycle();
+
+        self.compare(test, cycles)
+    }
+
+    // runs them all, returning the ones that failed
+    pub fn run_all(&mut self, tests: &[TestVector]) -> Vec<Failure> {
+        tests
+            .iter()
+            .filter_map(|test| {
+                let mismatches = self.run(test);
+                if mismatches.is_empty() {
+                    None
+                } else {
+                    Some(Failure {
+                        name: test.name.clone(),
+                        mismatches,
+                    })
+                }
+            })
+            .collect()
+    }
+
+    fn compare(&self, test: &TestVector, cycles: u32) -> Vec<Mismatch> {
+        let expected = &test.expected;
+        let cpu = self.state.cpu_state();
+        let mut mismatches = Vec::new();
+        let mut check = |field: &str, expected: String, actual: String| {
+            if expected != actual {
+                mismatches.push(Mismatch {
+                    field: field.to_string(),
+                    expected,
+                    actual,
+                });
+            }
+        };
+
+        let registers = [
+            ("A", expected.a, cpu.a),
+            ("B", expected.b, cpu.b),
+            ("C", expected.c, cpu.c),
+            ("D", expected.d, cpu.d),
+            ("E", expected.e, cpu.e),
+            ("H", expected.h, cpu.h),
+            ("L", expected.l, cpu.l),
+        ];
+        for (name, expected, actual) in registers {
+            check(name, format!("{:02X}", expected), format!("{:02X}", actual));
+        }
+        let flags = |flags: u8| format!("{:02X} ({})", flags, flag_names(flags));
+        check("F", flags(expected.f), flags(cpu.flags));
+        check(
+            "SP",
+            format!("{:04X}", expected.sp),
+            format!("{:04X}", cpu.sp),
+        );
+        check(
+            "PC",
+            format!("{:04X}", expected.pc),
+            format!("{:04X}", cpu.pc),
+        );
+        if let Some(inte) = expected.inte {
+            check("INTE", inte.to_string(), cpu.int_enable.to_string());
+        }
+        if let (true, Some(expected)) = (self.options.cycles, test.cycles) {
+            check("states", expected.to_string(), cycles.to_string());
+        }
+
+        for &(address, value) in &expected.ram {
+            check(
+                &format!("[{:04X}]", address),
+                format!("{:02X}", value),
+                format!("{:02X}", self.memory.peek(address)),
+            );
+        }
+        let contents = self.memory.0.borrow();
+        let mut unexpected: Vec<u16> = contents
+            .writes
+            .iter()
+            .copied()
+            .filter(|address| !expected.ram.iter().any(|&(listed, _)| listed == *address))
+            .collect();
+        unexpected.sort_unstable();
+        unexpected.dedup();
+        for address in unexpected {
+            check(
+                &format!("[{:04X}]", address),
+                "no write".to_string(),
+                format!("{:02X} written", contents.bytes[&address]),
+            );
+        }
+
+        let ports = self.ports.borrow();
+        let accesses = |accesses: &[PortAccess]| {
+            let accesses: Vec<String> = accesses
+                .iter()
+                .map(|access| {
+                    let kind = if access.write { "OUT" } else { "IN" };
+                    format!("{} {:02X}={:02X}", kind, access.port, access.value)
+                })
+                .collect();
+            if accesses.is_empty() {
+                "none".to_string()
+            } else {
+                accesses.join(", ")
+            }
+        };
+        if let Some(expected) = &test.ports {
+            check("ports", accesses(expected), accesses(&ports.accesses));
+        }
+
+        mismatches
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
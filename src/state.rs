@@ -1,7 +1,50 @@
+use std::error::Error;
+use std::fmt;
 use std::io;
-use std::{fs::File, io::Read};
-
-pub const MEMORY_SIZE: usize = 65_536; // 2 ^ 16, 16-bit addresses
+use std::path::Path;
+
+use crate::loader::{Image, LoadError};
+use crate::memory::{FlatMemory, MemoryBus};
+use crate::ports::{NullPortBus, PortBus};
+use crate::rewind::Rewind;
+use crate::savestate::{SaveState, SaveStateError};
+use crate::symbols::SymbolTable;
+use crate::trace::Tracer;
+use crate::watch::{WatchHit, WatchKind, Watchpoint, Watchpoints};
+
+// number of states (clock periods) per opcode, conditional CALLs and RETs are listed with their
+// cost when the condition is NOT met (see `BRANCH_TAKEN_CYCLES`)
+#[rustfmt::skip]
+const CYCLES: [u8; 256] = [
+    //  0   1   2   3   4   5   6   7   8   9   A   B   C   D   E   F
+        4, 10,  7,  5,  5,  5,  7,  4,  4, 10,  7,  5,  5,  5,  7,  4, // 0x00
+        4, 10,  7,  5,  5,  5,  7,  4,  4, 10,  7,  5,  5,  5,  7,  4, // 0x10
+        4, 10, 16,  5,  5,  5,  7,  4,  4, 10, 16,  5,  5,  5,  7,  4, // 0x20
+        4, 10, 13,  5, 10, 10, 10,  4,  4, 10, 13,  5,  5,  5,  7,  4, // 0x30
+        5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5, // 0x40
+        5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5, // 0x50
+        5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5, // 0x60
+        7,  7,  7,  7,  7,  7,  7,  7,  5,  5,  5,  5,  5,  5,  7,  5, // 0x70
+        4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0x80
+        4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0x90
+        4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0xA0
+        4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 0xB0
+        5, 10, 10, 10, 11, 11,  7, 11,  5, 10, 10, 10, 11, 17,  7, 11, // 0xC0
+        5, 10, 10, 10, 11, 11,  7, 11,  5, 10, 10, 10, 11, 17,  7, 11, // 0xD0
+        5, 10, 10, 18, 11, 11,  7, 11,  5,  5, 10,  4, 11, 17,  7, 11, // 0xE0
+        5, 10, 10,  4, 11, 11,  7, 11,  5,  5, 10,  4, 11, 17,  7, 11, // 0xF0
+];
+
+// extra states spent by a conditional CALL (11 -> 17) or RET (5 -> 11) when the condition holds
+const BRANCH_TAKEN_CYCLES: u32 = 6;
+
+// states burned per `emulate_cycle` while halted, so that timed loops keep advancing
+const HALTED_CYCLES: u32 = 4;
+
+// the opcodes Intel left out of the instruction set, all of them aliases of a documented one
+const UNDOCUMENTED: [u8; 12] = [
+    0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0xCB, 0xD9, 0xDD, 0xED, 0xFD,
+];
 
 // on C++ `z:1`, the `:1` is a bit field!
 struct ConditionCodes {
@@ -10,7 +53,51 @@ struct ConditionCodes {
     p: u8,
     cy: u8,
     ac: u8, // Space Invaders doesn't use this
-    pad: u8,
+}
+
+// Everything about the CPU besides memory, as recorded for rewinding.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpuState {
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub flags: u8, // as PUSH PSW stores them
+    pub int_enable: bool,
+    pub int_delay: bool,
+    pub pending_interrupt: Option<u8>,
+    pub halted: bool,
+    pub cycles: u64,
+}
+
+// An undocumented opcode fetched in strict mode (see `State8080::set_strict`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UndocumentedOpcode {
+    pub pc: u16,
+    pub opcode: u8,
+}
+
+impl fmt::Display for UndocumentedOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "undocumented opcode {:02X} at {:04X}",
+            self.opcode, self.pc
+        )
+    }
+}
+
+impl Error for UndocumentedOpcode {}
+
+impl From<UndocumentedOpcode> for io::Error {
+    fn from(e: UndocumentedOpcode) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+    }
 }
 
 pub struct State8080 {
@@ -24,9 +111,20 @@ pub struct State8080 {
     pub l: u8,
     pub sp: u16, // stack pointer
     pub pc: u16, // program counter
-    memory: [u8; MEMORY_SIZE],
+    memory: Box<dyn MemoryBus>,
     cc: ConditionCodes,
-    int_enable: u8, // TODO: figure out what this is
+    int_enable: bool,              // interrupt-enable flip-flop (INTE)
+    int_delay: bool,               // set by EI, interrupts are held off for one more instruction
+    pending_interrupt: Option<u8>, // instruction supplied by the interrupting device
+    cycles: u64,                   // states executed since power-on
+    ports: Box<dyn PortBus>,
+    symbols: SymbolTable, // names for addresses in traces and the debugger
+    watchpoints: Watchpoints,
+    instruction_pc: u16, // where the instruction being executed started
+    rewind: Option<Rewind>,
+    tracer: Option<Tracer>,
+    strict: bool,
+    undocumented_opcode: Option<UndocumentedOpcode>,
 }
 
 fn get_z(num: u8) -> u8 {
@@ -56,7 +154,7 @@ fn get_p(mut num: u8) -> u8 {
         num >>= 1;
     }
 
-    if total % 2 == 0 {
+    if total.is_multiple_of(2) {
         1
     } else {
         0
@@ -95,7 +193,7 @@ fn get_ac_sub(num: u8, subtrahend: u8, cy_opt: Option<u8>) -> u8 {
     }
 }
 
-// TODO: verify if this is the correct implementation
+// checked against the manual in tests/opcodes.rs
 fn get_ac_and(a: u8, b: u8) -> u8 {
     // not sure what logical property can simplify this to ((a | b) & 0x08) that's why I go for the complex but readable solution based on this reference: (see next line)
     // https://retrocomputing.stackexchange.com/questions/14977/auxiliary-carry-and-the-intel-8080s-logical-instructions
@@ -115,16 +213,26 @@ impl Default for State8080 {
             l: 0,
             sp: 0,
             pc: 0,
-            memory: [0; MEMORY_SIZE],
+            memory: Box::new(FlatMemory::default()),
             cc: ConditionCodes {
                 z: 0,
                 s: 0,
                 p: 0,
                 cy: 0,
                 ac: 0,
-                pad: 0,
             },
-            int_enable: 0,
+            int_enable: false,
+            int_delay: false,
+            pending_interrupt: None,
+            cycles: 0,
+            ports: Box::new(NullPortBus),
+            symbols: SymbolTable::new(),
+            watchpoints: Default::default(),
+            instruction_pc: 0,
+            rewind: None,
+            tracer: None,
+            strict: false,
+            undocumented_opcode: None,
         }
     }
 }
@@ -150,41 +258,218 @@ impl State8080 {
         self.pc
     }
 
-    pub fn memory(&self) -> [u8; MEMORY_SIZE] {
-        self.memory
+    pub fn memory(&self) -> &dyn MemoryBus {
+        self.memory.as_ref()
     }
 
-    pub fn init(&mut self) {
-        self.pc = 0x100;
-        self.memory[5] = 0xC9;
+    pub fn memory_mut(&mut self) -> &mut dyn MemoryBus {
+        self.memory.as_mut()
+    }
+
+    // replaces the address space, e.g. with a `MappedMemory` describing a machine's ROM and RAM
+    pub fn attach_memory<M: MemoryBus + 'static>(&mut self, memory: M) {
+        self.memory = Box::new(memory);
+    }
 
-        // TODO: For cpudiag.bin testing only
-        // self.memory[0] = 0xC3;
-        // self.memory[1] = 0x0;
-        // self.memory[2] = 0x01;
+    pub fn ports(&self) -> &dyn PortBus {
+        self.ports.as_ref()
+    }
 
-        // self.memory[368] = 0x7;
+    pub fn ports_mut(&mut self) -> &mut dyn PortBus {
+        self.ports.as_mut()
+    }
 
-        // self.memory[0x59C] = 0xC3;
-        // self.memory[0x59D] = 0xC2;
-        // self.memory[0x59E] = 0x05;
+    // replaces whatever is currently wired to the I/O ports
+    pub fn attach_ports<P: PortBus + 'static>(&mut self, ports: P) {
+        self.ports = Box::new(ports);
     }
 
-    pub fn load_rom(&mut self, file_path: &String) -> Result<(), io::Error> {
-        let mut file = File::open(file_path)?;
-        let mut buffer = Vec::new();
-        // need `std::io::Read`
-        let bytes = file.read_to_end(&mut buffer)?;
+    // the flags as PUSH PSW stores them: S Z 0 AC 0 P 1 CY
+    pub fn flags(&self) -> u8 {
+        (self.cc.s << 7)
+            | (self.cc.z << 6)
+            | (self.cc.ac << 4)
+            | (self.cc.p << 2)
+            | self.cc.cy
+            | 0x02
+    }
+
+    pub fn set_flags(&mut self, flags: u8) {
+        self.cc.s = (flags & 0x80) >> 7;
+        self.cc.z = (flags & 0x40) >> 6;
+        self.cc.ac = (flags & 0x10) >> 4;
+        self.cc.p = (flags & 0x04) >> 2;
+        self.cc.cy = flags & 0x01;
+    }
 
-        println!("rom size: {} bytes", bytes);
-        // TODO: maybe check if ROM size is too large to fit in memory?
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.add(watchpoint)
+    }
+
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        self.watchpoints.remove(id)
+    }
+
+    pub fn watchpoints(&self) -> &Watchpoints {
+        &self.watchpoints
+    }
+
+    pub fn watchpoints_mut(&mut self) -> &mut Watchpoints {
+        &mut self.watchpoints
+    }
+
+    // The first stopping watchpoint hit since the last call. The instruction that caused it has
+    // completed, `run_cycles` returns early when there is one.
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watchpoints.take_hit()
+    }
 
-        for i in 0..bytes {
-            // is the offset (0x100 currently) correct?
-            // TODO: I think we only need to use 0x100 for testing purposes
-            self.memory[i + 0x100] = buffer[i];
+    pub fn cpu_state(&self) -> CpuState {
+        CpuState {
+            a: self.a,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            sp: self.sp,
+            pc: self.pc,
+            flags: self.flags(),
+            int_enable: self.int_enable,
+            int_delay: self.int_delay,
+            pending_interrupt: self.pending_interrupt,
+            halted: self.halted,
+            cycles: self.cycles,
         }
+    }
+
+    pub fn set_cpu_state(&mut self, cpu: &CpuState) {
+        self.a = cpu.a;
+        self.b = cpu.b;
+        self.c = cpu.c;
+        self.d = cpu.d;
+        self.e = cpu.e;
+        self.h = cpu.h;
+        self.l = cpu.l;
+        self.sp = cpu.sp;
+        self.pc = cpu.pc;
+        self.set_flags(cpu.flags);
+        self.int_enable = cpu.int_enable;
+        self.int_delay = cpu.int_delay;
+        self.pending_interrupt = cpu.pending_interrupt;
+        self.halted = cpu.halted;
+        self.cycles = cpu.cycles;
+    }
+
+    // starts recording history for `step_back` and `rewind_cycles`, from the current state on
+    pub fn enable_rewind(&mut self, rewind: Rewind) {
+        self.rewind = Some(rewind);
+    }
+
+    pub fn disable_rewind(&mut self) -> Option<Rewind> {
+        self.rewind.take()
+    }
+
+    pub fn rewind(&self) -> Option<&Rewind> {
+        self.rewind.as_ref()
+    }
+
+    // for machines servicing a call outside the CPU, see `Rewind::cut`
+    pub fn cut_history(&mut self) {
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.cut();
+        }
+    }
+
+    // Undoes the last instruction (or, far enough back, the last snapshot's worth of them).
+    // `false` once there's no more history.
+    pub fn step_back(&mut self) -> bool {
+        let cpu = match self.rewind.as_mut() {
+            Some(rewind) => rewind.step_back(self.memory.as_mut()),
+            None => None,
+        };
+
+        match cpu {
+            Some(cpu) => {
+                self.set_cpu_state(&cpu);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Goes back to the latest recorded point at least `cycles` states ago (or as far as the
+    // history goes) and returns how many states were actually undone.
+    pub fn rewind_cycles(&mut self, cycles: u64) -> u64 {
+        let now = self.cycles;
+        let target = now.saturating_sub(cycles);
+
+        while self.cycles > target && self.step_back() {}
+
+        now - self.cycles
+    }
+
+    // traces every instruction from now on
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    // stops tracing, `Tracer::finish` flushes what's left
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    // In strict mode an undocumented opcode isn't executed: the CPU stops in front of it and
+    // `take_undocumented_opcode` has it. Test programs shouldn't depend on the aliases.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    // The undocumented opcode strict mode stopped at since the last call, `run_cycles` returns
+    // early when there is one.
+    pub fn take_undocumented_opcode(&mut self) -> Option<UndocumentedOpcode> {
+        self.undocumented_opcode.take()
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    pub fn init(&mut self) {
+        self.pc = 0x100;
+        self.memory.poke(5, 0xC9);
+    }
+
+    // Loads a raw binary at `base` or an Intel HEX file (by its extension), returning the start
+    // address the file asks for, if any.
+    pub fn load_rom(&mut self, file_path: &str, base: u16) -> Result<Option<u16>, LoadError> {
+        let image = Image::load(file_path, base)?;
+        image.write_to(self.memory.as_mut());
+
+        Ok(image.start)
+    }
 
+    pub fn save_state<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        SaveState::capture(self).save(path)
+    }
+
+    // Restores a save state. The machine must be set up like the one that saved it (memory map and
+    // devices), any rewind history is dropped.
+    pub fn load_state<P: AsRef<Path>>(&mut self, path: P) -> Result<(), SaveStateError> {
+        SaveState::load(path)?.restore(self)?;
+
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
         Ok(())
     }
 
@@ -195,7 +480,7 @@ impl State8080 {
         // flags
         self.cc.z = get_z(ans);
         self.cc.s = get_s(ans);
-        self.cc.p = get_p(ans & 0xFF);
+        self.cc.p = get_p(ans);
         self.cc.cy = get_cy(has_overflowed);
         self.cc.ac = get_ac_add(lhs, rhs, None);
 
@@ -204,12 +489,15 @@ impl State8080 {
 
     // for ADC (and ACI) instructions
     fn adc(&mut self, lhs: u8, rhs: u8) -> u8 {
-        let (ans, has_overflowed) = lhs.wrapping_add(rhs).overflowing_add(self.cc.cy);
+        // the carry can come out of either addition
+        let (sum, first_overflow) = lhs.overflowing_add(rhs);
+        let (ans, second_overflow) = sum.overflowing_add(self.cc.cy);
+        let has_overflowed = first_overflow || second_overflow;
 
         // flags
         self.cc.z = get_z(ans);
         self.cc.s = get_s(ans);
-        self.cc.p = get_p(ans & 0xFF);
+        self.cc.p = get_p(ans);
         self.cc.ac = get_ac_add(lhs, rhs, Some(self.cc.cy));
         self.cc.cy = get_cy(has_overflowed);
 
@@ -223,7 +511,7 @@ impl State8080 {
         // flags
         self.cc.z = get_z(ans);
         self.cc.s = get_s(ans);
-        self.cc.p = get_p(ans & 0xFF);
+        self.cc.p = get_p(ans);
         self.cc.ac = get_ac_sub(lhs, rhs, None);
         self.cc.cy = get_cy(has_overflowed);
 
@@ -237,13 +525,51 @@ impl State8080 {
         // flags
         self.cc.z = get_z(ans);
         self.cc.s = get_s(ans);
-        self.cc.p = get_p(ans & 0xFF);
+        self.cc.p = get_p(ans);
         self.cc.ac = get_ac_sub(lhs, rhs, Some(self.cc.cy));
-        self.cc.cy = get_cy(lhs < rhs + self.cc.cy);
+        self.cc.cy = get_cy((lhs as u16) < rhs as u16 + self.cc.cy as u16);
 
         ans
     }
 
+    fn read_byte(&mut self, address: u16) -> u8 {
+        let value = self.memory.read(address);
+
+        if !self.watchpoints.is_empty() {
+            let pc = self.instruction_pc;
+            self.watchpoints
+                .check(WatchKind::Read, pc, address, value, value);
+        }
+
+        value
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.record_write(address, self.memory.peek(address));
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.record_write(address, value);
+        }
+
+        if !self.watchpoints.is_empty() {
+            let old = self.memory.peek(address);
+            let pc = self.instruction_pc;
+            self.watchpoints
+                .check(WatchKind::Write, pc, address, old, value);
+        }
+
+        self.memory.write(address, value);
+    }
+
+    // little-endian, low byte at `address`
+    fn read_word(&mut self, address: u16) -> u16 {
+        let low = self.read_byte(address) as u16;
+        let high = self.read_byte(address.wrapping_add(1)) as u16;
+
+        (high << 8) | low
+    }
+
     // update `b` and `c`
     fn update_bc(&mut self, bc: u16) {
         self.c = bc as u8; // this should JUST truncate the higher byte
@@ -262,27 +588,121 @@ impl State8080 {
         self.h = (hl >> 8) as u8;
     }
 
-    pub fn emulate_cycle(&mut self) {
+    pub fn interrupts_enabled(&self) -> bool {
+        self.int_enable
+    }
+
+    // Raises the INT line with `opcode` as the instruction the interrupting device jams onto the
+    // data bus (usually one of the `RST n` opcodes). The request stays pending until the CPU
+    // accepts it, which only happens while the interrupt-enable flip-flop is set.
+    pub fn interrupt(&mut self, opcode: u8) {
+        self.pending_interrupt = Some(opcode);
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // Runs instructions until at least `budget` states have elapsed and returns the number of
+    // states actually spent, which can overshoot the budget by up to one instruction.
+    pub fn run_cycles(&mut self, budget: u64) -> u64 {
+        let mut spent: u64 = 0;
+
+        while spent < budget {
+            spent += self.emulate_cycle() as u64;
+
+            if self.watchpoints.has_hit() || self.undocumented_opcode.is_some() {
+                break;
+            }
+        }
+
+        spent
+    }
+
+    // Executes a single instruction and returns the number of states it took. In strict mode that's
+    // 0 in front of an undocumented opcode, and stays 0 until something moves PC: loops have to
+    // check `take_undocumented_opcode` after every instruction.
+    pub fn emulate_cycle(&mut self) -> u32 {
+        let before = self.rewind.as_ref().map(|_| self.cpu_state());
+        if let (Some(rewind), Some(before)) = (self.rewind.as_mut(), before) {
+            rewind.record_step(before);
+        }
+
+        let cycles = self.step();
+        self.cycles += cycles as u64;
+
+        cycles
+    }
+
+    fn step(&mut self) -> u32 {
+        self.instruction_pc = self.pc;
+
+        // interrupts are only sampled once the instruction following EI has completed
+        if self.int_enable && !self.int_delay {
+            if let Some(opcode) = self.pending_interrupt.take() {
+                // accepting an interrupt resets the flip-flop and ends a HLT,
+                // the jammed instruction runs without advancing PC
+                self.int_enable = false;
+                self.halted = false;
+                return self.execute_traced(opcode, true);
+            }
+        }
+        self.int_delay = false;
+
+        // HLT stops instruction fetching until an interrupt is accepted
+        if self.halted {
+            return HALTED_CYCLES;
+        }
+
         // fetch opcode
-        let pc = self.pc as usize;
-        let sp = self.sp as usize;
-        let opcode = self.memory[pc];
-        let bc = ((self.b as usize) << 8) | self.c as usize; // TODO: research more on `.into()`
-        let de = ((self.d as usize) << 8) | self.e as usize;
-        let hl = ((self.h as usize) << 8) | self.l as usize;
+        let opcode = self.read_byte(self.pc);
+        if self.strict && UNDOCUMENTED.contains(&opcode) {
+            self.undocumented_opcode = Some(UndocumentedOpcode {
+                pc: self.pc,
+                opcode,
+            });
+            return 0;
+        }
+        self.execute_traced(opcode, false)
+    }
+
+    // `execute`, with PC still at the start of the instruction for a fetched `opcode`, as a trace
+    // record if there's a tracer
+    fn execute_traced(&mut self, opcode: u8, interrupt: bool) -> u32 {
+        if self.tracer.is_some() {
+            let cpu = self.cpu_state();
+            let memory = [0u16, 1, 2, 3].map(|i| self.memory.peek(self.pc.wrapping_add(i)));
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.begin(&cpu, opcode, memory, interrupt);
+            }
+        }
 
-        let idx_pc_add1 = self.pc.wrapping_add(1) as usize;
-        let idx_pc_add2 = self.pc.wrapping_add(2) as usize;
+        if !interrupt {
+            self.pc = self.pc.wrapping_add(1);
+        }
+        let cycles = self.execute(opcode);
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.end(&self.symbols);
+        }
+        cycles
+    }
 
-        let idx_sp_add1 = self.sp.wrapping_add(1) as usize;
-        let idx_sp_sub1 = self.sp.wrapping_sub(1) as usize;
-        let idx_sp_sub2 = self.sp.wrapping_sub(2) as usize;
+    // executes `opcode` with PC already pointing past it (at byte 2 of the instruction, if any)
+    fn execute(&mut self, opcode: u8) -> u32 {
+        let mut cycles = CYCLES[opcode as usize] as u32;
 
-        // FOR DEBUGGING PURPOSES ONLY
-        println!("opcode: {:02x}, pc: {:04x}, sp: {:04x}, a: {:02x}, b: {:02x}, c: {:02x}, d: {:02x}, e: {:02x}, h: {:02x}, l: {:02x}, z: {}, s:{}, p: {}, c: {}, a: {}", opcode, self.pc, self.sp, self.a, self.b, self.c, self.d, self.e, self.h, self.l, self.cc.z, self.cc.s, self.cc.p, self.cc.cy, self.cc.ac);
-        // END
+        let sp = self.sp;
+        let bc = ((self.b as u16) << 8) | self.c as u16;
+        let de = ((self.d as u16) << 8) | self.e as u16;
+        let hl = ((self.h as u16) << 8) | self.l as u16;
 
-        self.pc = self.pc.wrapping_add(1);
+        let idx_byte2 = self.pc;
+        let idx_byte3 = self.pc.wrapping_add(1);
+
+        let idx_sp_add1 = self.sp.wrapping_add(1);
+        let idx_sp_sub1 = self.sp.wrapping_sub(1);
+        let idx_sp_sub2 = self.sp.wrapping_sub(2);
 
         match opcode {
             // ---- stack, I/O, and machine control group ----
@@ -294,243 +714,247 @@ impl State8080 {
             }
             // POP B
             0xC1 => {
-                self.c = self.memory[sp];
-                self.b = self.memory[idx_sp_add1];
+                self.c = self.read_byte(sp);
+                self.b = self.read_byte(idx_sp_add1);
                 self.sp = self.sp.wrapping_add(2);
             }
             // PUSH B
             0xC5 => {
-                self.memory[idx_sp_sub2] = self.c;
-                self.memory[idx_sp_sub1] = self.b;
+                self.write_byte(idx_sp_sub2, self.c);
+                self.write_byte(idx_sp_sub1, self.b);
                 self.sp = self.sp.wrapping_sub(2);
             }
             // POP D
             0xD1 => {
-                self.e = self.memory[sp];
-                self.d = self.memory[idx_sp_add1];
+                self.e = self.read_byte(sp);
+                self.d = self.read_byte(idx_sp_add1);
                 self.sp = self.sp.wrapping_add(2);
             }
-            0xD3 => (), // OUT d8 (special)
+            // OUT d8 (special)
+            0xD3 => {
+                let port = self.read_byte(idx_byte2);
+                self.ports.output(port, self.a);
+                self.pc = self.pc.wrapping_add(1);
+            }
             // PUSH D
             0xD5 => {
-                self.memory[idx_sp_sub2] = self.e;
-                self.memory[idx_sp_sub1] = self.d;
+                self.write_byte(idx_sp_sub2, self.e);
+                self.write_byte(idx_sp_sub1, self.d);
                 self.sp = self.sp.wrapping_sub(2);
             }
-            0xDB => (), // IN d8 (special)
+            // IN d8 (special)
+            0xDB => {
+                let port = self.read_byte(idx_byte2);
+                self.a = self.ports.input(port);
+                self.pc = self.pc.wrapping_add(1);
+            }
             // POP H
             0xE1 => {
-                self.l = self.memory[sp];
-                self.h = self.memory[idx_sp_add1];
+                self.l = self.read_byte(sp);
+                self.h = self.read_byte(idx_sp_add1);
                 self.sp = self.sp.wrapping_add(2);
             }
             // XTHL (swap (SP) with HL)
             0xE3 => {
-                let temp_high = self.memory[idx_sp_add1];
-                let temp_low = self.memory[sp];
-                self.memory[idx_sp_add1] = self.h;
-                self.memory[sp] = self.l;
+                let temp_high = self.read_byte(idx_sp_add1);
+                let temp_low = self.read_byte(sp);
+                self.write_byte(idx_sp_add1, self.h);
+                self.write_byte(sp, self.l);
                 self.h = temp_high;
                 self.l = temp_low;
             }
             // PUSH H
             0xE5 => {
-                self.memory[idx_sp_sub2] = self.l;
-                self.memory[idx_sp_sub1] = self.h;
+                self.write_byte(idx_sp_sub2, self.l);
+                self.write_byte(idx_sp_sub1, self.h);
                 self.sp = self.sp.wrapping_sub(2);
             }
             // POP PSW
             0xF1 => {
-                let flags = self.memory[sp];
+                let flags = self.read_byte(sp);
+                self.set_flags(flags);
 
-                self.cc.s = (flags & 0x80) >> 7;
-                self.cc.z = (flags & 0x40) >> 6;
-                self.cc.p = (flags & 0x04) >> 2;
-                self.cc.cy = flags & 0x01;
-
-                self.a = self.memory[idx_sp_add1];
+                self.a = self.read_byte(idx_sp_add1);
                 self.sp = self.sp.wrapping_add(2);
             }
-            0xF3 => (), // DI (special)
+            0xF3 => self.int_enable = false, // DI (special)
             // PUSH PSW
             0xF5 => {
-                let flags =
-                    (self.cc.s << 7) | (self.cc.z << 6) | (self.cc.p << 2) | (self.cc.cy) | 0x02;
-
-                self.memory[idx_sp_sub2] = flags;
-                self.memory[idx_sp_sub1] = self.a;
+                self.write_byte(idx_sp_sub2, self.flags());
+                self.write_byte(idx_sp_sub1, self.a);
                 self.sp = self.sp.wrapping_sub(2);
             }
-            0xF9 => self.sp = hl as u16, // SPHL
-            0xFB => (),                  // EI (special)
+            0xF9 => self.sp = hl, // SPHL
+            // EI (special)
+            0xFB => {
+                self.int_enable = true;
+                self.int_delay = true;
+            }
             // ---- illegal/undocumented group ----
-            0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0xCB | 0xD9 | 0xDD | 0xED | 0xFD => (),
+            // the other undocumented opcodes are with the JMP, RET and CALL they copy
+            0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => (),
             // ---- data transfer group ----
             // LXI B,d16
             0x01 => {
-                self.c = self.memory[idx_pc_add1];
-                self.b = self.memory[idx_pc_add2];
+                self.c = self.read_byte(idx_byte2);
+                self.b = self.read_byte(idx_byte3);
                 self.pc = self.pc.wrapping_add(2);
             }
-            0x02 => self.memory[bc] = self.a, // STAX B
+            0x02 => self.write_byte(bc, self.a), // STAX B
             // MVI B,d8
             0x06 => {
-                self.b = self.memory[idx_pc_add1];
+                self.b = self.read_byte(idx_byte2);
                 self.pc = self.pc.wrapping_add(1);
             }
-            0x0A => self.a = self.memory[bc], // LDAX B
+            0x0A => self.a = self.read_byte(bc), // LDAX B
             // MVI C,d8
             0x0E => {
-                self.c = self.memory[idx_pc_add1];
+                self.c = self.read_byte(idx_byte2);
                 self.pc = self.pc.wrapping_add(1);
             }
             // LXI D,d16
             0x11 => {
-                self.e = self.memory[idx_pc_add1];
-                self.d = self.memory[idx_pc_add2];
+                self.e = self.read_byte(idx_byte2);
+                self.d = self.read_byte(idx_byte3);
                 self.pc = self.pc.wrapping_add(2);
             }
-            0x12 => self.memory[de] = self.a, // STAX D
+            0x12 => self.write_byte(de, self.a), // STAX D
             // MVI D,d8
             0x16 => {
-                self.d = self.memory[idx_pc_add1];
+                self.d = self.read_byte(idx_byte2);
                 self.pc = self.pc.wrapping_add(1);
             }
-            0x1A => self.a = self.memory[de], // LDAX D
+            0x1A => self.a = self.read_byte(de), // LDAX D
             // MVI E,d8
             0x1E => {
-                self.e = self.memory[idx_pc_add1];
+                self.e = self.read_byte(idx_byte2);
                 self.pc = self.pc.wrapping_add(1);
             }
             // LXI H,d16
             0x21 => {
-                self.l = self.memory[idx_pc_add1];
-                self.h = self.memory[idx_pc_add2];
+                self.l = self.read_byte(idx_byte2);
+                self.h = self.read_byte(idx_byte3);
                 self.pc = self.pc.wrapping_add(2);
             }
             // SHLD a16
             0x22 => {
-                let l_idx = ((self.memory[idx_pc_add2] as usize) << 8)
-                    | (self.memory[idx_pc_add1] as usize);
-                let h_idx = l_idx + 1; // for readability
-                self.memory[l_idx] = self.l;
-                self.memory[h_idx] = self.h;
+                let l_idx = self.read_word(idx_byte2);
+                let h_idx = l_idx.wrapping_add(1); // for readability
+                self.write_byte(l_idx, self.l);
+                self.write_byte(h_idx, self.h);
                 self.pc = self.pc.wrapping_add(2);
             }
             // MVI H,d8
             0x26 => {
-                self.h = self.memory[idx_pc_add1];
+                self.h = self.read_byte(idx_byte2);
                 self.pc = self.pc.wrapping_add(1);
             }
             // LHLD D
             0x2A => {
-                let l_idx = ((self.memory[idx_pc_add2] as usize) << 8)
-                    | (self.memory[idx_pc_add1] as usize);
-                let h_idx = l_idx + 1; // for readability
-                self.l = self.memory[l_idx];
-                self.h = self.memory[h_idx];
+                let l_idx = self.read_word(idx_byte2);
+                let h_idx = l_idx.wrapping_add(1); // for readability
+                self.l = self.read_byte(l_idx);
+                self.h = self.read_byte(h_idx);
                 self.pc = self.pc.wrapping_add(2);
             }
             // MVI L,d8
             0x2E => {
-                self.l = self.memory[idx_pc_add1];
+                self.l = self.read_byte(idx_byte2);
                 self.pc = self.pc.wrapping_add(1);
             }
             // LXI SP,d16
             0x31 => {
-                let value =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let value = self.read_word(idx_byte2);
                 self.sp = value;
                 self.pc = self.pc.wrapping_add(2);
             }
             // STA a16
             0x32 => {
-                let address = ((self.memory[idx_pc_add2] as usize) << 8)
-                    | (self.memory[idx_pc_add1] as usize);
-                self.memory[address] = self.a;
+                let address = self.read_word(idx_byte2);
+                self.write_byte(address, self.a);
                 self.pc = self.pc.wrapping_add(2);
             }
             // MVI M,d8
             0x36 => {
                 // `M` is memory location pointed by `HL` pair
-                self.memory[hl] = self.memory[idx_pc_add1];
+                let value = self.read_byte(idx_byte2);
+                self.write_byte(hl, value);
                 self.pc = self.pc.wrapping_add(1);
             }
             // LDA a16
             0x3A => {
-                let address = ((self.memory[idx_pc_add2] as usize) << 8)
-                    | (self.memory[idx_pc_add1] as usize);
-                self.a = self.memory[address];
+                let address = self.read_word(idx_byte2);
+                self.a = self.read_byte(address);
                 self.pc = self.pc.wrapping_add(2);
             }
             // MVI A,d8
             0x3E => {
-                self.a = self.memory[idx_pc_add1];
+                self.a = self.read_byte(idx_byte2);
                 self.pc = self.pc.wrapping_add(1);
             }
-            0x40 => self.b = self.b, // MOV B,B (does this makes sense to implement???)
-            0x41 => self.b = self.c, // MOV B,C
-            0x42 => self.b = self.d, // MOV B,D
-            0x43 => self.b = self.e, // MOV B,E
-            0x44 => self.b = self.h, // MOV B,H
-            0x45 => self.b = self.l, // MOV B,L
-            0x46 => self.b = self.memory[hl], // MOV B,M (move to B the value at memory[HL])
-            0x47 => self.b = self.a, // MOV B,A
-            0x48 => self.c = self.b, // MOV C,B
-            0x49 => self.c = self.c, // MOV C,C (does this makes sense to implement???)
-            0x4A => self.c = self.d, // MOV C,D
-            0x4B => self.c = self.e, // MOV C,E
-            0x4C => self.c = self.h, // MOV C,H
-            0x4D => self.c = self.l, // MOV C,L
-            0x4E => self.c = self.memory[hl], // MOV C,M (move to C the value at memory[HL])
-            0x4F => self.c = self.a, // MOV C,A
-            0x50 => self.d = self.b, // MOV D,B
-            0x51 => self.d = self.c, // MOV D,C
-            0x52 => self.d = self.d, // MOV D,D (does this makes sense to implement???)
-            0x53 => self.d = self.e, // MOV D,E
-            0x54 => self.d = self.h, // MOV D,H
-            0x55 => self.d = self.l, // MOV D,L
-            0x56 => self.d = self.memory[hl], // MOV D,M (move to D the value at memory[HL])
-            0x57 => self.d = self.a, // MOV D,A
-            0x58 => self.e = self.b, // MOV E,B
-            0x59 => self.e = self.c, // MOV E,C
-            0x5A => self.e = self.d, // MOV E,D
-            0x5B => self.e = self.e, // MOV E,E (does this makes sense to implement???)
-            0x5C => self.e = self.h, // MOV E,H
-            0x5D => self.e = self.l, // MOV E,L
-            0x5E => self.e = self.memory[hl], // MOV E,M (move to E the value at memory[HL])
-            0x5F => self.e = self.a, // MOV E,A
-            0x60 => self.h = self.b, // MOV H,B
-            0x61 => self.h = self.c, // MOV H,C
-            0x62 => self.h = self.d, // MOV H,D
-            0x63 => self.h = self.e, // MOV H,E
-            0x64 => self.h = self.h, // MOV H,H (does this makes sense to implement???)
-            0x65 => self.h = self.l, // MOV H,L
-            0x66 => self.h = self.memory[hl], // MOV H,M (move to H the value at memory[HL])
-            0x67 => self.h = self.a, // MOV H,A
-            0x68 => self.l = self.b, // MOV L,B
-            0x69 => self.l = self.c, // MOV L,C
-            0x6A => self.l = self.d, // MOV L,D
-            0x6B => self.l = self.e, // MOV L,E
-            0x6C => self.l = self.h, // MOV L,H
-            0x6D => self.l = self.l, // MOV L,L (does this makes sense to implement???)
-            0x6E => self.l = self.memory[hl], // MOV L,M (move to L the value at memory[HL])
-            0x6F => self.l = self.a, // MOV L,A
-            0x70 => self.memory[hl] = self.b, // MOV M,B
-            0x71 => self.memory[hl] = self.c, // MOV M,C
-            0x72 => self.memory[hl] = self.d, // MOV M,D
-            0x73 => self.memory[hl] = self.e, // MOV M,E
-            0x74 => self.memory[hl] = self.h, // MOV M,H
-            0x75 => self.memory[hl] = self.l, // MOV M,L
-            0x77 => self.memory[hl] = self.a, // MOV M,A
-            0x78 => self.a = self.b, // MOV A,B
-            0x79 => self.a = self.c, // MOV A,C
-            0x7A => self.a = self.d, // MOV A,D
-            0x7B => self.a = self.e, // MOV A,E
-            0x7C => self.a = self.h, // MOV A,H
-            0x7D => self.a = self.l, // MOV A,L
-            0x7E => self.a = self.memory[hl], // MOV A,M
-            0x7F => self.a = self.a, // MOV A,A (does this makes sense to implement???)
+            0x40 => (),                          // MOV B,B (no-op)
+            0x41 => self.b = self.c,             // MOV B,C
+            0x42 => self.b = self.d,             // MOV B,D
+            0x43 => self.b = self.e,             // MOV B,E
+            0x44 => self.b = self.h,             // MOV B,H
+            0x45 => self.b = self.l,             // MOV B,L
+            0x46 => self.b = self.read_byte(hl), // MOV B,M (move to B the value at memory[HL])
+            0x47 => self.b = self.a,             // MOV B,A
+            0x48 => self.c = self.b,             // MOV C,B
+            0x49 => (),                          // MOV C,C (no-op)
+            0x4A => self.c = self.d,             // MOV C,D
+            0x4B => self.c = self.e,             // MOV C,E
+            0x4C => self.c = self.h,             // MOV C,H
+            0x4D => self.c = self.l,             // MOV C,L
+            0x4E => self.c = self.read_byte(hl), // MOV C,M (move to C the value at memory[HL])
+            0x4F => self.c = self.a,             // MOV C,A
+            0x50 => self.d = self.b,             // MOV D,B
+            0x51 => self.d = self.c,             // MOV D,C
+            0x52 => (),                          // MOV D,D (no-op)
+            0x53 => self.d = self.e,             // MOV D,E
+            0x54 => self.d = self.h,             // MOV D,H
+            0x55 => self.d = self.l,             // MOV D,L
+            0x56 => self.d = self.read_byte(hl), // MOV D,M (move to D the value at memory[HL])
+            0x57 => self.d = self.a,             // MOV D,A
+            0x58 => self.e = self.b,             // MOV E,B
+            0x59 => self.e = self.c,             // MOV E,C
+            0x5A => self.e = self.d,             // MOV E,D
+            0x5B => (),                          // MOV E,E (no-op)
+            0x5C => self.e = self.h,             // MOV E,H
+            0x5D => self.e = self.l,             // MOV E,L
+            0x5E => self.e = self.read_byte(hl), // MOV E,M (move to E the value at memory[HL])
+            0x5F => self.e = self.a,             // MOV E,A
+            0x60 => self.h = self.b,             // MOV H,B
+            0x61 => self.h = self.c,             // MOV H,C
+            0x62 => self.h = self.d,             // MOV H,D
+            0x63 => self.h = self.e,             // MOV H,E
+            0x64 => (),                          // MOV H,H (no-op)
+            0x65 => self.h = self.l,             // MOV H,L
+            0x66 => self.h = self.read_byte(hl), // MOV H,M (move to H the value at memory[HL])
+            0x67 => self.h = self.a,             // MOV H,A
+            0x68 => self.l = self.b,             // MOV L,B
+            0x69 => self.l = self.c,             // MOV L,C
+            0x6A => self.l = self.d,             // MOV L,D
+            0x6B => self.l = self.e,             // MOV L,E
+            0x6C => self.l = self.h,             // MOV L,H
+            0x6D => (),                          // MOV L,L (no-op)
+            0x6E => self.l = self.read_byte(hl), // MOV L,M (move to L the value at memory[HL])
+            0x6F => self.l = self.a,             // MOV L,A
+            0x70 => self.write_byte(hl, self.b), // MOV M,B
+            0x71 => self.write_byte(hl, self.c), // MOV M,C
+            0x72 => self.write_byte(hl, self.d), // MOV M,D
+            0x73 => self.write_byte(hl, self.e), // MOV M,E
+            0x74 => self.write_byte(hl, self.h), // MOV M,H
+            0x75 => self.write_byte(hl, self.l), // MOV M,L
+            0x77 => self.write_byte(hl, self.a), // MOV M,A
+            0x78 => self.a = self.b,             // MOV A,B
+            0x79 => self.a = self.c,             // MOV A,C
+            0x7A => self.a = self.d,             // MOV A,D
+            0x7B => self.a = self.e,             // MOV A,E
+            0x7C => self.a = self.h,             // MOV A,H
+            0x7D => self.a = self.l,             // MOV A,L
+            0x7E => self.a = self.read_byte(hl), // MOV A,M
+            0x7F => (),                          // MOV A,A (no-op)
             // XCHG (swap DE and HL)
             0xEB => {
                 let temp_high = self.h;
@@ -543,7 +967,7 @@ impl State8080 {
             // ---- arithmetic group ----
             // INX B
             0x03 => {
-                let new_bc = (bc as u16).wrapping_add(1);
+                let new_bc = bc.wrapping_add(1);
 
                 self.update_bc(new_bc);
             }
@@ -573,7 +997,7 @@ impl State8080 {
             }
             // DAD B
             0x09 => {
-                let (new_hl, has_overflowed) = (hl as u16).overflowing_add(bc as u16);
+                let (new_hl, has_overflowed) = hl.overflowing_add(bc);
 
                 // flags
                 self.cc.cy = get_cy(has_overflowed);
@@ -582,7 +1006,7 @@ impl State8080 {
             }
             // DCX B
             0x0B => {
-                let new_bc = (bc as u16).wrapping_sub(1);
+                let new_bc = bc.wrapping_sub(1);
                 self.c = new_bc as u8; // this should JUST truncate the higher byte
                 self.b = (new_bc >> 8) as u8;
             }
@@ -612,7 +1036,7 @@ impl State8080 {
             }
             // INX D
             0x13 => {
-                let new_de = (de as u16).wrapping_add(1);
+                let new_de = de.wrapping_add(1);
 
                 self.update_de(new_de);
             }
@@ -642,7 +1066,7 @@ impl State8080 {
             }
             // DAD D
             0x19 => {
-                let (new_hl, has_overflowed) = (hl as u16).overflowing_add(de as u16);
+                let (new_hl, has_overflowed) = hl.overflowing_add(de);
 
                 // flags
                 self.cc.cy = get_cy(has_overflowed);
@@ -651,7 +1075,7 @@ impl State8080 {
             }
             // DCX D
             0x1B => {
-                let new_de = (de as u16).wrapping_sub(1);
+                let new_de = de.wrapping_sub(1);
                 self.e = new_de as u8; // this should JUST truncate the higher byte
                 self.d = (new_de >> 8) as u8;
             }
@@ -681,7 +1105,7 @@ impl State8080 {
             }
             // INX H
             0x23 => {
-                let new_hl = (hl as u16).wrapping_add(1);
+                let new_hl = hl.wrapping_add(1);
 
                 self.update_hl(new_hl);
             }
@@ -711,8 +1135,8 @@ impl State8080 {
             }
             // DAD H
             0x29 => {
-                let hl_u16 = hl as u16;
-                let (new_hl, has_overflowed) = hl_u16.overflowing_add(hl_u16); // TODO: not sure if emulator101.com has a typo but I'm assuming it's `HL` and not `HI`
+                let hl_u16 = hl;
+                let (new_hl, has_overflowed) = hl_u16.overflowing_add(hl_u16);
 
                 // flags
                 self.cc.cy = get_cy(has_overflowed);
@@ -721,7 +1145,7 @@ impl State8080 {
             }
             // DCX H
             0x2B => {
-                let new_hl = (hl as u16).wrapping_sub(1);
+                let new_hl = hl.wrapping_sub(1);
                 self.l = new_hl as u8; // this should JUST truncate the higher byte
                 self.h = (new_hl >> 8) as u8;
             }
@@ -757,31 +1181,33 @@ impl State8080 {
             }
             // INR M
             0x34 => {
-                let new_hl_mem = self.memory[hl].wrapping_add(1);
+                let hl_mem = self.read_byte(hl);
+                let new_hl_mem = hl_mem.wrapping_add(1);
 
                 // flags
                 self.cc.z = get_z(new_hl_mem);
                 self.cc.s = get_s(new_hl_mem);
                 self.cc.p = get_p(new_hl_mem);
-                self.cc.ac = get_ac_add(self.memory[hl], 1, None);
+                self.cc.ac = get_ac_add(hl_mem, 1, None);
 
-                self.memory[hl] = new_hl_mem;
+                self.write_byte(hl, new_hl_mem);
             }
             // DCR M
             0x35 => {
-                let new_hl_mem = self.memory[hl].wrapping_sub(1);
+                let hl_mem = self.read_byte(hl);
+                let new_hl_mem = hl_mem.wrapping_sub(1);
 
                 // flags
                 self.cc.z = get_z(new_hl_mem);
                 self.cc.s = get_s(new_hl_mem);
                 self.cc.p = get_p(new_hl_mem);
-                self.cc.ac = get_ac_sub(self.memory[hl], 1, None);
+                self.cc.ac = get_ac_sub(hl_mem, 1, None);
 
-                self.memory[hl] = new_hl_mem;
+                self.write_byte(hl, new_hl_mem);
             }
             // DAD SP
             0x39 => {
-                let (new_hl, has_overflowed) = (hl as u16).overflowing_add(self.sp); // TODO: verify if `SP` is correct
+                let (new_hl, has_overflowed) = hl.overflowing_add(self.sp);
 
                 // flags
                 self.cc.cy = get_cy(has_overflowed);
@@ -824,7 +1250,11 @@ impl State8080 {
             0x83 => self.a = self.add(self.a, self.e), // ADD E
             0x84 => self.a = self.add(self.a, self.h), // ADD H
             0x85 => self.a = self.add(self.a, self.l), // ADD L
-            0x86 => self.a = self.add(self.a, self.memory[hl]), // ADD M (A = A + (HL))
+            // ADD M (A = A + (HL))
+            0x86 => {
+                let value = self.read_byte(hl);
+                self.a = self.add(self.a, value);
+            }
             0x87 => self.a = self.add(self.a, self.a), // ADD A (A = A + A)
             0x88 => self.a = self.adc(self.a, self.b), // ADC B
             0x89 => self.a = self.adc(self.a, self.c), // ADC C
@@ -832,14 +1262,20 @@ impl State8080 {
             0x8B => self.a = self.adc(self.a, self.e), // ADC E
             0x8C => self.a = self.adc(self.a, self.h), // ADC H
             0x8D => self.a = self.adc(self.a, self.l), // ADC L
-            0x8E => self.a = self.adc(self.a, self.memory[hl]), // ADC M (A = A + (HL) + CY)
+            // ADC M (A = A + (HL) + CY)
+            0x8E => {
+                let value = self.read_byte(hl);
+                self.a = self.adc(self.a, value);
+            }
             0x8F => self.a = self.adc(self.a, self.a), // ADC A (A = A + A + CY)
             0xC6 => {
-                self.a = self.add(self.a, self.memory[idx_pc_add1]);
+                let value = self.read_byte(idx_byte2);
+                self.a = self.add(self.a, value);
                 self.pc = self.pc.wrapping_add(1);
             } // ADI D8 (rhs is an immediate value)
             0xCE => {
-                self.a = self.adc(self.a, self.memory[idx_pc_add1]);
+                let value = self.read_byte(idx_byte2);
+                self.a = self.adc(self.a, value);
                 self.pc = self.pc.wrapping_add(1);
             } // ACI D8 (rhs is an immediate value PLUS the carry flag value)
             0x90 => self.a = self.sub(self.a, self.b), // SUB B
@@ -848,7 +1284,11 @@ impl State8080 {
             0x93 => self.a = self.sub(self.a, self.e), // SUB E
             0x94 => self.a = self.sub(self.a, self.h), // SUB H
             0x95 => self.a = self.sub(self.a, self.l), // SUB L
-            0x96 => self.a = self.sub(self.a, self.memory[hl]), // SUB M (A = A - (HL))
+            // SUB M (A = A - (HL))
+            0x96 => {
+                let value = self.read_byte(hl);
+                self.a = self.sub(self.a, value);
+            }
             0x97 => self.a = self.sub(self.a, self.a), // SUB A (A = A - A)
             0x98 => self.a = self.sbb(self.a, self.b), // SBB B
             0x99 => self.a = self.sbb(self.a, self.c), // SBB C
@@ -856,14 +1296,20 @@ impl State8080 {
             0x9B => self.a = self.sbb(self.a, self.e), // SBB E
             0x9C => self.a = self.sbb(self.a, self.h), // SBB H
             0x9D => self.a = self.sbb(self.a, self.l), // SBB L
-            0x9E => self.a = self.sbb(self.a, self.memory[hl]), // SBB M (A = A - (HL) - CY)
+            // SBB M (A = A - (HL) - CY)
+            0x9E => {
+                let value = self.read_byte(hl);
+                self.a = self.sbb(self.a, value);
+            }
             0x9F => self.a = self.sbb(self.a, self.a), // SBB A (A = A - A - CY)
             0xD6 => {
-                self.a = self.sub(self.a, self.memory[idx_pc_add1]);
+                let value = self.read_byte(idx_byte2);
+                self.a = self.sub(self.a, value);
                 self.pc = self.pc.wrapping_add(1);
             } // SUI D8 (rhs is an immediate value)
             0xDE => {
-                self.a = self.sbb(self.a, self.memory[idx_pc_add1]);
+                let value = self.read_byte(idx_byte2);
+                self.a = self.sbb(self.a, value);
                 self.pc = self.pc.wrapping_add(1);
             } // SBI D8 (rhs is an immediate value MINUS the carry flag value)
             // ---- logical group ----
@@ -901,40 +1347,34 @@ impl State8080 {
             }
             // DAA (special)
             0x27 => {
-                let mut a = self.a;
+                let mut correction = 0;
                 let mut cy = self.cc.cy;
                 let lsb = self.a & 0x0F;
+                let msb = self.a >> 4;
                 // If the least significant four bits of the accumulator represents a number greater than 9,
                 // or if the Auxiliary Carry bit is equal to one, the accumulator is incremented by six.
                 // Otherwise, no incrementing occurs.
                 if lsb > 9 || self.cc.ac == 1 {
-                    a = a.wrapping_add(0x06);
+                    correction |= 0x06;
                 }
 
-                let msb = a >> 4;
-                // If the most significant four bits of the NEW accumulator now represents a number greater than 9,
+                // If the most significant four bits of the accumulator now represents a number greater than 9,
                 // or if the normal carry bit is equal to one, the most significant four bits of the accumulator are incremented by six.
                 // Otherwise, no incrementing occurs.
-                if msb > 9 || self.cc.cy == 1 {
-                    self.a = self.add(a, 0x60);
-                }
-
-                if lsb > 9 {
-                    self.cc.ac = 1;
-                } else {
-                    self.cc.ac = 0;
-                }
-
-                if msb > 9 {
+                // ("now" meaning after the first step, which carries into them when `lsb > 9`)
+                if msb > 9 || (msb == 9 && lsb > 9) || self.cc.cy == 1 {
+                    correction |= 0x60;
                     cy = 1;
                 }
 
+                // both steps in one addition, AC comes out of the first one
+                self.a = self.add(self.a, correction);
                 self.cc.cy = cy; // `self.cc.cy` is modified on `self.add(lhs, rhs)`
             }
-            0x2F => self.a = !self.a,         // CMA
-            0x37 => self.cc.cy = 1,           // STC
-            0x3F => self.cc.cy = !self.cc.cy, // CMC
-            0xA0 | 0xA1 | 0xA2 | 0xA3 | 0xA4 | 0xA5 | 0xA6 | 0xA7 => {
+            0x2F => self.a = !self.a, // CMA
+            0x37 => self.cc.cy = 1,   // STC
+            0x3F => self.cc.cy ^= 1,  // CMC
+            0xA0..=0xA7 => {
                 match opcode {
                     0xA0 => {
                         self.cc.ac = get_ac_and(self.a, self.b);
@@ -961,8 +1401,9 @@ impl State8080 {
                         self.a &= self.l;
                     } // ANA L
                     0xA6 => {
-                        self.cc.ac = get_ac_and(self.a, self.memory[hl]);
-                        self.a &= self.memory[hl];
+                        let value = self.read_byte(hl);
+                        self.cc.ac = get_ac_and(self.a, value);
+                        self.a &= value;
                     } // ANA M
                     0xA7 => {
                         self.cc.ac = get_ac_and(self.a, self.a);
@@ -976,16 +1417,16 @@ impl State8080 {
                 self.cc.p = get_p(self.a);
                 self.cc.cy = get_cy(false);
             }
-            0xA8 | 0xA9 | 0xAA | 0xAB | 0xAC | 0xAD | 0xAE | 0xAF => {
+            0xA8..=0xAF => {
                 match opcode {
-                    0xA8 => self.a ^= self.b,          // XRA B
-                    0xA9 => self.a ^= self.c,          // XRA C
-                    0xAA => self.a ^= self.d,          // XRA D
-                    0xAB => self.a ^= self.e,          // XRA E
-                    0xAC => self.a ^= self.h,          // XRA H
-                    0xAD => self.a ^= self.l,          // XRA L
-                    0xAE => self.a ^= self.memory[hl], // XRA M
-                    0xAF => self.a ^= self.a,          // XRA A (this is sure 0 right???)
+                    0xA8 => self.a ^= self.b,             // XRA B
+                    0xA9 => self.a ^= self.c,             // XRA C
+                    0xAA => self.a ^= self.d,             // XRA D
+                    0xAB => self.a ^= self.e,             // XRA E
+                    0xAC => self.a ^= self.h,             // XRA H
+                    0xAD => self.a ^= self.l,             // XRA L
+                    0xAE => self.a ^= self.read_byte(hl), // XRA M
+                    0xAF => self.a ^= self.a,             // XRA A (this is sure 0 right???)
                     _ => panic!("This shouldn't be reached."),
                 }
 
@@ -993,19 +1434,19 @@ impl State8080 {
                 self.cc.s = get_s(self.a);
                 self.cc.p = get_p(self.a);
                 self.cc.cy = get_cy(false);
-                // TODO: verify if this is the correct implementation
+                // only ANA sets AC, the other logical instructions clear it
                 self.cc.ac = 0u8;
             }
-            0xB0 | 0xB1 | 0xB2 | 0xB3 | 0xB4 | 0xB5 | 0xB6 | 0xB7 => {
+            0xB0..=0xB7 => {
                 match opcode {
-                    0xB0 => self.a |= self.b,          // ORA B
-                    0xB1 => self.a |= self.c,          // ORA C
-                    0xB2 => self.a |= self.d,          // ORA D
-                    0xB3 => self.a |= self.e,          // ORA E
-                    0xB4 => self.a |= self.h,          // ORA H
-                    0xB5 => self.a |= self.l,          // ORA L
-                    0xB6 => self.a |= self.memory[hl], // ORA M
-                    0xB7 => self.a |= self.a,          // ORA A (does something happen with this???)
+                    0xB0 => self.a |= self.b,             // ORA B
+                    0xB1 => self.a |= self.c,             // ORA C
+                    0xB2 => self.a |= self.d,             // ORA D
+                    0xB3 => self.a |= self.e,             // ORA E
+                    0xB4 => self.a |= self.h,             // ORA H
+                    0xB5 => self.a |= self.l,             // ORA L
+                    0xB6 => self.a |= self.read_byte(hl), // ORA M
+                    0xB7 => self.a |= self.a, // ORA A (does something happen with this???)
                     _ => panic!("This shouldn't be reached."),
                 }
 
@@ -1013,10 +1454,10 @@ impl State8080 {
                 self.cc.s = get_s(self.a);
                 self.cc.p = get_p(self.a);
                 self.cc.cy = get_cy(false);
-                // TODO: verify if this is the correct implementation
+                // ORA clears AC as well
                 self.cc.ac = 0u8;
             }
-            0xB8 | 0xB9 | 0xBA | 0xBB | 0xBC | 0xBD | 0xBE | 0xBF => {
+            0xB8..=0xBF => {
                 let (result, has_overflowed) = match opcode {
                     0xB8 => {
                         self.cc.ac = get_ac_sub(self.a, self.b, None);
@@ -1043,8 +1484,9 @@ impl State8080 {
                         self.a.overflowing_sub(self.l)
                     } // CMP L
                     0xBE => {
-                        self.cc.ac = get_ac_sub(self.a, self.memory[hl], None);
-                        self.a.overflowing_sub(self.memory[hl])
+                        let value = self.read_byte(hl);
+                        self.cc.ac = get_ac_sub(self.a, value, None);
+                        self.a.overflowing_sub(value)
                     } // CMP M
                     0xBF => {
                         self.cc.ac = get_ac_sub(self.a, self.a, None);
@@ -1062,7 +1504,7 @@ impl State8080 {
             // ANI d8
             0xE6 => {
                 let lhs = self.a;
-                let rhs = self.memory[idx_pc_add1];
+                let rhs = self.read_byte(idx_byte2);
                 self.a = lhs & rhs;
 
                 self.cc.z = get_z(self.a);
@@ -1075,13 +1517,13 @@ impl State8080 {
             }
             // XRI d8
             0xEE => {
-                self.a ^= self.memory[idx_pc_add1];
+                self.a ^= self.read_byte(idx_byte2);
 
                 self.cc.z = get_z(self.a);
                 self.cc.s = get_s(self.a);
                 self.cc.p = get_p(self.a);
                 self.cc.cy = get_cy(false);
-                // TODO: verify if this is the correct implementation
+                // as XRA does
                 self.cc.ac = 0u8;
 
                 self.pc = self.pc.wrapping_add(1);
@@ -1089,7 +1531,7 @@ impl State8080 {
             // ORI d8
             0xF6 => {
                 let lhs = self.a;
-                let rhs = self.memory[idx_pc_add1];
+                let rhs = self.read_byte(idx_byte2);
                 self.a = lhs | rhs;
 
                 self.cc.z = get_z(self.a);
@@ -1103,13 +1545,14 @@ impl State8080 {
             }
             // CPI d8
             0xFE => {
-                let (result, has_overflowed) = self.a.overflowing_sub(self.memory[idx_pc_add1]);
+                let value = self.read_byte(idx_byte2);
+                let (result, has_overflowed) = self.a.overflowing_sub(value);
 
                 self.cc.z = get_z(result);
                 self.cc.s = get_s(result);
                 self.cc.p = get_p(result);
                 self.cc.cy = get_cy(has_overflowed);
-                self.cc.ac = get_ac_sub(self.a, self.memory[idx_pc_add1], None);
+                self.cc.ac = get_ac_sub(self.a, value, None);
 
                 self.pc = self.pc.wrapping_add(1);
             }
@@ -1117,16 +1560,16 @@ impl State8080 {
             // RNZ (if Z is 0, meaning NOT Zero on the arg(see `get_z` function))
             0xC0 => {
                 if self.cc.z == 0 {
-                    let low = self.memory[sp] as u16;
-                    let high = (self.memory[idx_sp_add1] as u16) << 8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let low = self.read_byte(sp) as u16;
+                    let high = (self.read_byte(idx_sp_add1) as u16) << 8;
                     self.pc = high | low;
                     self.sp = self.sp.wrapping_add(2);
                 }
             }
             // JNZ adr (if Z is 0, meaning Not Zero(see `get_z` function))
             0xC2 => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.z == 0 {
                     self.pc = address;
@@ -1134,24 +1577,22 @@ impl State8080 {
                     self.pc = self.pc.wrapping_add(2);
                 }
             }
-            // JMP adr
-            0xC3 => {
+            // JMP adr (0xCB is an undocumented copy)
+            0xC3 | 0xCB => {
                 // TODO: repetitive use of `address` on some instructions
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 self.pc = address;
             }
             // CNZ adr (if Z is NOT ZERO, call the address)
             0xC4 => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
-                // TODO: verify this
                 if self.cc.z != 1 {
-                    let next_pc = self.pc + 2;
-                    self.memory[idx_sp_sub1] = (next_pc >> 8) as u8;
-                    self.memory[idx_sp_sub2] = next_pc as u8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let next_pc = self.pc.wrapping_add(2);
+                    self.write_byte(idx_sp_sub1, (next_pc >> 8) as u8);
+                    self.write_byte(idx_sp_sub2, next_pc as u8);
                     self.sp = self.sp.wrapping_sub(2);
                     self.pc = address;
                 } else {
@@ -1160,31 +1601,31 @@ impl State8080 {
             }
             // RST 0 (call at address 0b00---000, where --- are values from 0b000 to 0b111, in this case is 0b000)
             0xC7 => {
-                self.memory[idx_sp_sub1] = (self.pc >> 8) as u8;
-                self.memory[idx_sp_sub2] = self.pc as u8;
+                self.write_byte(idx_sp_sub1, (self.pc >> 8) as u8);
+                self.write_byte(idx_sp_sub2, self.pc as u8);
                 self.sp = self.sp.wrapping_sub(2);
                 self.pc = 0b00000000;
             }
             // RZ (if Z is 1, meaning Zero on the arg(see `get_z` function))
             0xC8 => {
                 if self.cc.z == 1 {
-                    let low = self.memory[sp] as u16;
-                    let high = (self.memory[idx_sp_add1] as u16) << 8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let low = self.read_byte(sp) as u16;
+                    let high = (self.read_byte(idx_sp_add1) as u16) << 8;
                     self.pc = high | low;
                     self.sp = self.sp.wrapping_add(2);
                 }
             }
-            // RET
-            0xC9 => {
-                let low = self.memory[sp] as u16;
-                let high = (self.memory[idx_sp_add1] as u16) << 8;
+            // RET (0xD9 is an undocumented copy)
+            0xC9 | 0xD9 => {
+                let low = self.read_byte(sp) as u16;
+                let high = (self.read_byte(idx_sp_add1) as u16) << 8;
                 self.pc = high | low;
                 self.sp = self.sp.wrapping_add(2);
             }
             // JZ adr (if Z is NOT 0, meaning Zero on the arg(see `get_z` function))
             0xCA => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.z != 0 {
                     self.pc = address;
@@ -1194,51 +1635,49 @@ impl State8080 {
             }
             // CZ adr (if Z is 0, call the address)
             0xCC => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
-                // TODO: verify this
                 if self.cc.z != 0 {
-                    let next_pc = self.pc + 2;
-                    self.memory[idx_sp_sub1] = (next_pc >> 8) as u8;
-                    self.memory[idx_sp_sub2] = next_pc as u8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let next_pc = self.pc.wrapping_add(2);
+                    self.write_byte(idx_sp_sub1, (next_pc >> 8) as u8);
+                    self.write_byte(idx_sp_sub2, next_pc as u8);
                     self.sp = self.sp.wrapping_sub(2);
                     self.pc = address;
                 } else {
                     self.pc = self.pc.wrapping_add(2);
                 }
             }
-            // CALL adr
-            0xCD => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+            // CALL adr (0xDD, 0xED and 0xFD are undocumented copies)
+            0xCD | 0xDD | 0xED | 0xFD => {
+                let address = self.read_word(idx_byte2);
 
-                let next_pc = self.pc + 2;
-                self.memory[idx_sp_sub1] = (next_pc >> 8) as u8;
-                self.memory[idx_sp_sub2] = next_pc as u8;
+                let next_pc = self.pc.wrapping_add(2);
+                self.write_byte(idx_sp_sub1, (next_pc >> 8) as u8);
+                self.write_byte(idx_sp_sub2, next_pc as u8);
                 self.sp = self.sp.wrapping_sub(2);
                 self.pc = address;
             }
             // RST 1 (call at address 0b00---000, where --- are values from 0b000 to 0b111, in this case is 0b001)
             0xCF => {
-                self.memory[idx_sp_sub1] = (self.pc >> 8) as u8;
-                self.memory[idx_sp_sub2] = self.pc as u8;
+                self.write_byte(idx_sp_sub1, (self.pc >> 8) as u8);
+                self.write_byte(idx_sp_sub2, self.pc as u8);
                 self.sp = self.sp.wrapping_sub(2);
                 self.pc = 0b00001000;
             }
             // RNC (if CY is 0)
             0xD0 => {
                 if self.cc.cy == 0 {
-                    let low = self.memory[sp] as u16;
-                    let high = (self.memory[idx_sp_add1] as u16) << 8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let low = self.read_byte(sp) as u16;
+                    let high = (self.read_byte(idx_sp_add1) as u16) << 8;
                     self.pc = high | low;
                     self.sp = self.sp.wrapping_add(2);
                 }
             }
             // JNC adr (if CY is cleared)
             0xD2 => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.cy == 0 {
                     self.pc = address;
@@ -1248,13 +1687,13 @@ impl State8080 {
             }
             // CNC adr (if CY is ZERO, call the address)
             0xD4 => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.cy == 0 {
-                    let next_pc = self.pc + 2;
-                    self.memory[idx_sp_sub1] = (next_pc >> 8) as u8;
-                    self.memory[idx_sp_sub2] = next_pc as u8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let next_pc = self.pc.wrapping_add(2);
+                    self.write_byte(idx_sp_sub1, (next_pc >> 8) as u8);
+                    self.write_byte(idx_sp_sub2, next_pc as u8);
                     self.sp = self.sp.wrapping_sub(2);
                     self.pc = address;
                 } else {
@@ -1263,24 +1702,24 @@ impl State8080 {
             }
             // RST 2 (call at address 0b00---000, where --- are values from 0b000 to 0b111, in this case is 0b010)
             0xD7 => {
-                self.memory[idx_sp_sub1] = (self.pc >> 8) as u8;
-                self.memory[idx_sp_sub2] = self.pc as u8;
+                self.write_byte(idx_sp_sub1, (self.pc >> 8) as u8);
+                self.write_byte(idx_sp_sub2, self.pc as u8);
                 self.sp = self.sp.wrapping_sub(2);
                 self.pc = 0b00010000;
             }
             // RC (if CY is 1)
             0xD8 => {
                 if self.cc.cy == 1 {
-                    let low = self.memory[sp] as u16;
-                    let high = (self.memory[idx_sp_add1] as u16) << 8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let low = self.read_byte(sp) as u16;
+                    let high = (self.read_byte(idx_sp_add1) as u16) << 8;
                     self.pc = high | low;
                     self.sp = self.sp.wrapping_add(2);
                 }
             }
             // JC adr (if CY is NOT cleared)
             0xDA => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.cy != 0 {
                     self.pc = address;
@@ -1290,13 +1729,13 @@ impl State8080 {
             }
             // CC adr (if CY is NOT ZERO, call the address)
             0xDC => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.cy == 1 {
-                    let next_pc = self.pc + 2;
-                    self.memory[idx_sp_sub1] = (next_pc >> 8) as u8;
-                    self.memory[idx_sp_sub2] = next_pc as u8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let next_pc = self.pc.wrapping_add(2);
+                    self.write_byte(idx_sp_sub1, (next_pc >> 8) as u8);
+                    self.write_byte(idx_sp_sub2, next_pc as u8);
                     self.sp = self.sp.wrapping_sub(2);
                     self.pc = address;
                 } else {
@@ -1305,24 +1744,24 @@ impl State8080 {
             }
             // RST 3 (call at address 0b00---000, where --- are values from 0b000 to 0b111, in this case is 0b011)
             0xDF => {
-                self.memory[idx_sp_sub1] = (self.pc >> 8) as u8;
-                self.memory[idx_sp_sub2] = self.pc as u8;
+                self.write_byte(idx_sp_sub1, (self.pc >> 8) as u8);
+                self.write_byte(idx_sp_sub2, self.pc as u8);
                 self.sp = self.sp.wrapping_sub(2);
                 self.pc = 0b00011000;
             }
             // RPO (if P is 0, meaning odd)
             0xE0 => {
                 if self.cc.p == 0 {
-                    let low = self.memory[sp] as u16;
-                    let high = (self.memory[idx_sp_add1] as u16) << 8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let low = self.read_byte(sp) as u16;
+                    let high = (self.read_byte(idx_sp_add1) as u16) << 8;
                     self.pc = high | low;
                     self.sp = self.sp.wrapping_add(2);
                 }
             }
             // JPO adr (if P is odd)
             0xE2 => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.p == 0 {
                     self.pc = address;
@@ -1332,15 +1771,15 @@ impl State8080 {
             }
             // CPO adr (if P is 0, meaning odd, call the address)
             0xE4 => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.p == 0 {
+                    cycles += BRANCH_TAKEN_CYCLES;
                     // TODO: maybe initialize `next_pc` at the top to avoid redundant code?
                     // TODO: use a separate `push` and `pop` method for the stack
-                    let next_pc = self.pc + 2;
-                    self.memory[idx_sp_sub1] = (next_pc >> 8) as u8;
-                    self.memory[idx_sp_sub2] = next_pc as u8;
+                    let next_pc = self.pc.wrapping_add(2);
+                    self.write_byte(idx_sp_sub1, (next_pc >> 8) as u8);
+                    self.write_byte(idx_sp_sub2, next_pc as u8);
                     self.sp = self.sp.wrapping_sub(2);
                     self.pc = address;
                 } else {
@@ -1349,28 +1788,28 @@ impl State8080 {
             }
             // RST 4 (call at address 0b00---000, where --- are values from 0b000 to 0b111, in this case is 0b100)
             0xE7 => {
-                self.memory[idx_sp_sub1] = (self.pc >> 8) as u8;
-                self.memory[idx_sp_sub2] = self.pc as u8;
+                self.write_byte(idx_sp_sub1, (self.pc >> 8) as u8);
+                self.write_byte(idx_sp_sub2, self.pc as u8);
                 self.sp = self.sp.wrapping_sub(2);
                 self.pc = 0b00100000;
             }
             // RPE (if P is 1, meaning even)
             0xE8 => {
                 if self.cc.p == 1 {
-                    let low = self.memory[sp] as u16;
-                    let high = (self.memory[idx_sp_add1] as u16) << 8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let low = self.read_byte(sp) as u16;
+                    let high = (self.read_byte(idx_sp_add1) as u16) << 8;
                     self.pc = high | low;
                     self.sp = self.sp.wrapping_add(2);
                 }
             }
             // PCHL
             0xE9 => {
-                self.pc = hl as u16;
+                self.pc = hl;
             }
             // JPE adr (if P is even)
             0xEA => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.p == 1 {
                     self.pc = address;
@@ -1380,13 +1819,13 @@ impl State8080 {
             }
             // CPE adr (if P is 1, meaning even, call the address)
             0xEC => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.p == 1 {
-                    let next_pc = self.pc + 2;
-                    self.memory[idx_sp_sub1] = (next_pc >> 8) as u8;
-                    self.memory[idx_sp_sub2] = next_pc as u8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let next_pc = self.pc.wrapping_add(2);
+                    self.write_byte(idx_sp_sub1, (next_pc >> 8) as u8);
+                    self.write_byte(idx_sp_sub2, next_pc as u8);
                     self.sp = self.sp.wrapping_sub(2);
                     self.pc = address;
                 } else {
@@ -1395,24 +1834,24 @@ impl State8080 {
             }
             // RST 5 (call at address 0b00---000, where --- are values from 0b000 to 0b111, in this case is 0b101)
             0xEF => {
-                self.memory[idx_sp_sub1] = (self.pc >> 8) as u8;
-                self.memory[idx_sp_sub2] = self.pc as u8;
+                self.write_byte(idx_sp_sub1, (self.pc >> 8) as u8);
+                self.write_byte(idx_sp_sub2, self.pc as u8);
                 self.sp = self.sp.wrapping_sub(2);
                 self.pc = 0b00101000;
             }
             // RP (if S is 0, meaning positive)
             0xF0 => {
                 if self.cc.s == 0 {
-                    let low = self.memory[sp] as u16;
-                    let high = (self.memory[idx_sp_add1] as u16) << 8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let low = self.read_byte(sp) as u16;
+                    let high = (self.read_byte(idx_sp_add1) as u16) << 8;
                     self.pc = high | low;
                     self.sp = self.sp.wrapping_add(2);
                 }
             }
             // JP adr (jump if positive)
             0xF2 => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 // if S is 0, meaning positive
                 if self.cc.s == 0 {
@@ -1423,13 +1862,13 @@ impl State8080 {
             }
             // CP adr (if S is 0, meaning positive, call the address)
             0xF4 => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.s == 0 {
-                    let next_pc = self.pc + 2;
-                    self.memory[idx_sp_sub1] = (next_pc >> 8) as u8;
-                    self.memory[idx_sp_sub2] = next_pc as u8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let next_pc = self.pc.wrapping_add(2);
+                    self.write_byte(idx_sp_sub1, (next_pc >> 8) as u8);
+                    self.write_byte(idx_sp_sub2, next_pc as u8);
                     self.sp = self.sp.wrapping_sub(2);
                     self.pc = address;
                 } else {
@@ -1438,24 +1877,24 @@ impl State8080 {
             }
             // RST 6 (call at address 0b00---000, where --- are values from 0b000 to 0b111, in this case is 0b110)
             0xF7 => {
-                self.memory[idx_sp_sub1] = (self.pc >> 8) as u8;
-                self.memory[idx_sp_sub2] = self.pc as u8;
+                self.write_byte(idx_sp_sub1, (self.pc >> 8) as u8);
+                self.write_byte(idx_sp_sub2, self.pc as u8);
                 self.sp = self.sp.wrapping_sub(2);
                 self.pc = 0b00110000;
             }
             // RM (if S is 1, meaning Minus/negative)
             0xF8 => {
                 if self.cc.s == 1 {
-                    let low = self.memory[sp] as u16;
-                    let high = (self.memory[idx_sp_add1] as u16) << 8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let low = self.read_byte(sp) as u16;
+                    let high = (self.read_byte(idx_sp_add1) as u16) << 8;
                     self.pc = high | low;
                     self.sp = self.sp.wrapping_add(2);
                 }
             }
             // JM adr (jump if minus/negative)
             0xFA => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 // if S is 1, meaning negative
                 if self.cc.s == 1 {
@@ -1466,13 +1905,13 @@ impl State8080 {
             }
             // CM adr (if S is 1, meaning Minus/negative, call the address)
             0xFC => {
-                let address =
-                    ((self.memory[idx_pc_add2] as u16) << 8) | (self.memory[idx_pc_add1] as u16);
+                let address = self.read_word(idx_byte2);
 
                 if self.cc.s == 1 {
-                    let next_pc = self.pc + 2;
-                    self.memory[idx_sp_sub1] = (next_pc >> 8) as u8;
-                    self.memory[idx_sp_sub2] = next_pc as u8;
+                    cycles += BRANCH_TAKEN_CYCLES;
+                    let next_pc = self.pc.wrapping_add(2);
+                    self.write_byte(idx_sp_sub1, (next_pc >> 8) as u8);
+                    self.write_byte(idx_sp_sub2, next_pc as u8);
                     self.sp = self.sp.wrapping_sub(2);
                     self.pc = address;
                 } else {
@@ -1481,11 +1920,13 @@ impl State8080 {
             }
             // RST 7 (call at address 0b00---000, where --- are values from 0b000 to 0b111, in this case is 0b111)
             0xFF => {
-                self.memory[idx_sp_sub1] = (self.pc >> 8) as u8;
-                self.memory[idx_sp_sub2] = self.pc as u8;
+                self.write_byte(idx_sp_sub1, (self.pc >> 8) as u8);
+                self.write_byte(idx_sp_sub2, self.pc as u8);
                 self.sp = self.sp.wrapping_sub(2);
                 self.pc = 0b00111000;
             } // _ => panic!("Unknown opcode!"), // TODO: uncomment to determine the unimplemented opcodes
         }
+
+        cycles
     }
 }
@@ -0,0 +1,397 @@
+// Symbols from external assemblers, so disassembly and traces can say `PRINT_MSG` instead of
+// `0A3FH`. They come from symbol files or from the labels in a listing.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::iter::FromIterator;
+use std::path::Path;
+
+use crate::assembler::{is_identifier, parse_line};
+
+// how far past a symbol an address is still described as `SYMBOL+offset`
+const MAX_OFFSET: u16 = 0x100;
+
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    // upper-cased names, lookups are case-insensitive like the assemblers
+    by_name: BTreeMap<String, u16>,
+    // the first name defined for each address, as written
+    by_address: BTreeMap<u16, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, name: &str, address: u16) {
+        self.by_name.insert(name.to_uppercase(), address);
+        self.by_address
+            .entry(address)
+            .or_insert_with(|| name.to_string());
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<u16> {
+        self.by_name.get(&name.to_uppercase()).copied()
+    }
+
+    pub fn name_at(&self, address: u16) -> Option<&str> {
+        self.by_address.get(&address).map(|name| name.as_str())
+    }
+
+    // `NAME` or `NAME+offset` after the closest symbol at or below `address`
+    pub fn describe(&self, address: u16) -> Option<String> {
+        let (symbol, name) = self.by_address.range(..=address).next_back()?;
+
+        match address - symbol {
+            0 => Some(name.clone()),
+            offset if offset < MAX_OFFSET => Some(format!("{}+{}", name, offset)),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    // (address, name) ordered by address
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.by_address
+            .iter()
+            .map(|(address, name)| (*address, name.as_str()))
+    }
+
+    // Reads the common symbol file layouts, values are always hex:
+    //
+    //     0100 START     0103 LOOP       (CP/M .SYM files, any number of pairs per line)
+    //     START 0100
+    //     START EQU 0100H
+    //     START = $0100
+    //     START: 0x0100
+    pub fn parse(text: &str) -> io::Result<SymbolTable> {
+        let mut symbols = SymbolTable::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("");
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("symbol file line {}: expected address and name", number + 1),
+                )
+            };
+
+            let pairs: Vec<(&str, &str)> = match tokens.as_slice() {
+                [] => continue,
+                [name, separator, value]
+                    if ["EQU", "=", "SET"].contains(&separator.to_uppercase().as_str()) =>
+                {
+                    vec![(name, value)]
+                }
+                _ if tokens.len().is_multiple_of(2) => {
+                    tokens.chunks(2).map(|pair| (pair[0], pair[1])).collect()
+                }
+                _ => return Err(invalid()),
+            };
+
+            for (first, second) in pairs {
+                let (name, value) = match (parse_value(first), parse_value(second)) {
+                    // a name like `ADD` reads as hex too, so try the address first
+                    (Some(address), _) if is_name(second) => (second, address),
+                    (_, Some(address)) if is_name(first) => (first, address),
+                    _ => return Err(invalid()),
+                };
+                symbols.insert(name.trim_end_matches(':'), value);
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    // a listing (`.lst` or `.prn`) gives the symbols for its labels, anything else is read as a
+    // symbol file
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SymbolTable> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+
+        match path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase())
+            .as_deref()
+        {
+            Some("lst") | Some("prn") => Ok(Listing::parse(&text).symbols()),
+            _ => SymbolTable::parse(&text),
+        }
+    }
+
+    // in the CP/M .SYM layout, one symbol per line
+    pub fn to_sym(&self) -> String {
+        self.by_name
+            .iter()
+            .map(|(name, address)| format!("{:04X} {}\n", address, name))
+            .collect()
+    }
+}
+
+impl<'a> FromIterator<(&'a String, &'a u16)> for SymbolTable {
+    fn from_iter<I: IntoIterator<Item = (&'a String, &'a u16)>>(iter: I) -> Self {
+        let mut symbols = SymbolTable::new();
+        for (name, address) in iter {
+            symbols.insert(name, *address);
+        }
+        symbols
+    }
+}
+
+fn is_name(token: &str) -> bool {
+    is_identifier(token.trim_end_matches(':'))
+}
+
+// hex, bare or as 0100H, $0100 or 0x0100
+fn parse_value(token: &str) -> Option<u16> {
+    let upper = token.to_uppercase();
+    let digits = upper
+        .strip_prefix("0X")
+        .or_else(|| upper.strip_prefix('$'))
+        .or_else(|| upper.strip_suffix('H'))
+        .unwrap_or(&upper);
+
+    if digits.is_empty() || digits.len() > 5 {
+        return None;
+    }
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListingLine {
+    pub address: Option<u16>,
+    pub bytes: Vec<u8>,
+    pub source: String,
+}
+
+// An assembler listing, every line split into the address, the bytes it assembled to and the
+// source text.
+pub struct Listing {
+    pub lines: Vec<ListingLine>,
+}
+
+impl Listing {
+    // Understands the usual `address bytes source` layout, e.g.
+    //
+    //     0100 11 1A 01     START:  LXI   D,MSG
+    //     0103 0E09                 MVI   C,9
+    //     0005 =            BDOS    EQU   5
+    //
+    // The bytes are hex, one or more per group, in a field of fixed columns between the address
+    // and the source. A line number in front of the address or the source is skipped.
+    pub fn parse(text: &str) -> Listing {
+        let lines: Vec<(&str, Option<(u16, &str)>)> = text
+            .lines()
+            .map(|line| (line, split_address(line)))
+            .collect();
+
+        // The field starts where most lines with bytes start them and is as wide as the most bytes
+        // any of those lines has, counted from the end of the address so line numbers of
+        // different widths don't move it. Guessing line by line would read a label like `BEEF`
+        // on a line without bytes as a byte.
+        let guesses: Vec<(usize, usize)> = lines
+            .iter()
+            .filter_map(|(_, split)| split.and_then(|(_, rest)| guess_byte_field(rest)))
+            .collect();
+        let field = most_common(guesses.iter().map(|guess| guess.0)).map(|start| {
+            let end = guesses
+                .iter()
+                .filter(|guess| guess.0 == start)
+                .map(|guess| guess.1)
+                .max();
+            (start, end.unwrap())
+        });
+
+        let lines = lines
+            .into_iter()
+            .map(|(line, split)| match split {
+                Some((address, rest)) => parse_listing_line(address, rest, field),
+                None => ListingLine {
+                    address: None,
+                    bytes: Vec::new(),
+                    source: line.to_string(),
+                },
+            })
+            .collect();
+
+        Listing { lines }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Listing> {
+        Ok(Listing::parse(&fs::read_to_string(path)?))
+    }
+
+    // the source line that assembled to `address`
+    pub fn line_at(&self, address: u16) -> Option<&ListingLine> {
+        self.lines
+            .iter()
+            .find(|line| line.address == Some(address) && !line.bytes.is_empty())
+    }
+
+    // Labels get the address of their line, EQU names the value the listing shows.
+    pub fn symbols(&self) -> SymbolTable {
+        let mut symbols = SymbolTable::new();
+
+        for line in &self.lines {
+            let address = match line.address {
+                Some(address) => address,
+                None => continue,
+            };
+
+            if let Ok(statement) = parse_line(&line.source) {
+                if let Some(label) = statement.label {
+                    symbols.insert(label, address);
+                }
+            }
+        }
+
+        symbols
+    }
+}
+
+// the address of a listing line and the text after it, or `None` for lines without one
+fn split_address(text: &str) -> Option<(u16, &str)> {
+    let mut trimmed = text.trim_start();
+
+    // some assemblers put the line number first
+    let first_end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    if first_end > 0 && first_end != 4 && trimmed[..first_end].chars().all(|c| c.is_ascii_digit()) {
+        trimmed = trimmed[first_end..].trim_start();
+    }
+
+    let address_end = trimmed
+        .find(|c: char| c.is_whitespace() || c == ':')
+        .unwrap_or(trimmed.len());
+    let address = &trimmed[..address_end];
+    if address.len() != 4 || !address.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some((
+        u16::from_str_radix(address, 16).unwrap(),
+        trimmed[address_end..].trim_start_matches(':'),
+    ))
+}
+
+// Where the bytes of one line seem to start and end: the first group may be any distance from
+// the address, the next ones only one space apart. `None` if the line has no bytes.
+fn guess_byte_field(rest: &str) -> Option<(usize, usize)> {
+    let start = rest.len() - rest.trim_start().len();
+    if start == 0 {
+        return None;
+    }
+
+    let mut end = start;
+    loop {
+        let candidate = &rest[end..];
+        let group_end = candidate
+            .find(char::is_whitespace)
+            .unwrap_or(candidate.len());
+        if parse_hex_group(&candidate[..group_end]).is_none() {
+            break;
+        }
+        end += group_end;
+        if !rest[end..].starts_with(' ') || rest[end + 1..].starts_with(char::is_whitespace) {
+            break;
+        }
+        end += 1;
+    }
+    let end = start + rest[start..end].trim_end().len();
+
+    if end == start {
+        None
+    } else {
+        Some((start, end))
+    }
+}
+
+fn most_common(values: impl Iterator<Item = usize>) -> Option<usize> {
+    let mut counts = BTreeMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    // the first of the most common, BTreeMap iterates in order
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|&(_, count)| count)
+        .map(|(value, _)| value)
+}
+
+// `field` is where the bytes start and end, relative to `rest`
+fn parse_listing_line(address: u16, rest: &str, field: Option<(usize, usize)>) -> ListingLine {
+    let (bytes, mut source) = field
+        .and_then(|(start, end)| read_byte_field(rest, start, end))
+        .unwrap_or_else(|| (Vec::new(), rest.trim_start()));
+
+    // a line number (possibly followed by `+` for included files) in front of the source
+    let number_end = source
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(source.len());
+    if number_end > 0 {
+        let after = source[number_end..].trim_start_matches('+');
+        if after.is_empty() || after.starts_with(char::is_whitespace) {
+            source = after.trim_start();
+        }
+    }
+
+    ListingLine {
+        address: Some(address),
+        bytes,
+        source: source.trim_end().to_string(),
+    }
+}
+
+// The bytes between columns `start` and `end` of `rest` and the source after them. `None` if
+// something other than bytes (or the `=` of an EQU) sits in the field, e.g. source text on a line
+// that has no bytes.
+fn read_byte_field(rest: &str, start: usize, end: usize) -> Option<(Vec<u8>, &str)> {
+    let end = end.min(rest.len());
+    let before = rest.get(..start.min(end))?;
+    let field = rest.get(start.min(end)..end)?;
+    if !before.trim().is_empty() {
+        return None;
+    }
+
+    let mut bytes = Vec::new();
+    for (i, group) in field.split_whitespace().enumerate() {
+        match parse_hex_group(group) {
+            Some(group) => bytes.extend(group),
+            // EQU value marker
+            None if group == "=" && i == 0 => (),
+            None => return None,
+        }
+    }
+
+    // and nothing may run on from the field into the source
+    if rest[end..].starts_with(|c: char| !c.is_whitespace()) {
+        return None;
+    }
+
+    Some((bytes, rest[end..].trim_start()))
+}
+
+fn parse_hex_group(group: &str) -> Option<Vec<u8>> {
+    if group.is_empty() || !group.len().is_multiple_of(2) || group.len() > 8 {
+        return None;
+    }
+    if !group.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..group.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&group[i..i + 2], 16).ok())
+        .collect()
+}
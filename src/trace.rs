@@ -0,0 +1,497 @@
+// Instruction traces: one record per instruction executed, with the registers as they were before
+// it ran and the memory it wrote. Three formats:
+//
+// text       this emulator's own, with the disassembly:
+//                0100  3E 12     MVI   A,12H        A=00 F=02 B=00 C=00 D=00 E=00 H=00 L=00 SP=0000 CYC=0
+//            writes are appended as `[2000]=12`, instructions supplied by an interrupting device
+//            show `INT` before their bytes
+// reference  the register line several other 8080 emulators print, to diff against theirs:
+//                PC: 0100, AF: 0002, BC: 0000, DE: 0000, HL: 0000, SP: 0000, CYC: 0	(3E 12 21 00)
+//            the bytes are the four at PC, there's no disassembly and no writes
+// binary     "I8080TRC", u16 version, then per instruction (numbers little-endian):
+//                PC, A F B C D E H L, SP, cycles (u64), length (bits 0-1) | interrupt (bit 7),
+//                the instruction bytes, write count, (address, value) per write
+//
+// `TraceReader` reads all three back, for comparing traces (see `tracediff`).
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::disassembler::{decode_with, instruction_length};
+use crate::state::CpuState;
+use crate::symbols::SymbolTable;
+
+pub const BINARY_MAGIC: &[u8; 8] = b"I8080TRC";
+pub const BINARY_VERSION: u16 = 1;
+
+pub const INTERRUPT_BIT: u8 = 0x80;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    Text,
+    Reference,
+    Binary,
+}
+
+impl TraceFormat {
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name.to_lowercase().as_str() {
+            "text" => Some(TraceFormat::Text),
+            "reference" => Some(TraceFormat::Reference),
+            "binary" => Some(TraceFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub a: u8,
+    pub flags: u8, // as PUSH PSW stores them
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub cycles: u64,
+    pub bytes: Vec<u8>,
+    // the instruction came from an interrupting device rather than from memory
+    pub interrupt: bool,
+    pub writes: Vec<(u16, u8)>,
+}
+
+pub struct Tracer {
+    out: Box<dyn Write>,
+    format: TraceFormat,
+    // the instruction being executed
+    entry: Option<TraceEntry>,
+    // the 4 bytes at PC, for the reference format
+    memory: [u8; 4],
+    // the first write error, tracing stops there
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new<W: Write + 'static>(mut out: W, format: TraceFormat) -> io::Result<Tracer> {
+        if format == TraceFormat::Binary {
+            out.write_all(BINARY_MAGIC)?;
+            out.write_all(&BINARY_VERSION.to_le_bytes())?;
+        }
+
+        Ok(Tracer {
+            out: Box::new(out),
+            format,
+            entry: None,
+            memory: [0; 4],
+            error: None,
+        })
+    }
+
+    pub fn create<P: AsRef<Path>>(path: P, format: TraceFormat) -> io::Result<Tracer> {
+        Tracer::new(BufWriter::new(File::create(path)?), format)
+    }
+
+    // flushes the trace, reporting the first error writing it
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()
+    }
+
+    // `memory` is what's at PC, `opcode` what gets executed (they differ for interrupts)
+    pub(crate) fn begin(&mut self, cpu: &CpuState, opcode: u8, memory: [u8; 4], interrupt: bool) {
+        // operands are always fetched from memory, even for an instruction jammed by a device
+        let mut code = memory;
+        if interrupt {
+            code = [opcode, memory[0], memory[1], memory[2]];
+        }
+        let bytes = decode_with(cpu.pc, |address| {
+            code[address.wrapping_sub(cpu.pc) as usize & 3]
+        })
+        .bytes;
+
+        self.memory = memory;
+        self.entry = Some(TraceEntry {
+            pc: cpu.pc,
+            a: cpu.a,
+            flags: cpu.flags,
+            b: cpu.b,
+            c: cpu.c,
+            d: cpu.d,
+            e: cpu.e,
+            h: cpu.h,
+            l: cpu.l,
+            sp: cpu.sp,
+            cycles: cpu.cycles,
+            bytes,
+            interrupt,
+            writes: Vec::new(),
+        });
+    }
+
+    pub(crate) fn record_write(&mut self, address: u16, value: u8) {
+        if let Some(entry) = self.entry.as_mut() {
+            entry.writes.push((address, value));
+        }
+    }
+
+    pub(crate) fn end(&mut self, symbols: &SymbolTable) {
+        let entry = match self.entry.take() {
+            Some(entry) => entry,
+            None => return,
+        };
+        if self.error.is_some() {
+            return;
+        }
+
+        let result = match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", format_text(&entry, symbols)),
+            TraceFormat::Reference => {
+                writeln!(self.out, "{}", format_reference(&entry, &self.memory))
+            }
+            TraceFormat::Binary => self.out.write_all(&encode_binary(&entry)),
+        };
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+}
+
+pub fn format_text(entry: &TraceEntry, symbols: &SymbolTable) -> String {
+    let instruction = decode_with(entry.pc, |address| {
+        let offset = address.wrapping_sub(entry.pc) as usize;
+        entry.bytes.get(offset).copied().unwrap_or(0)
+    });
+    let bytes: Vec<String> = entry.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    let bytes = if entry.interrupt {
+        format!("INT {}", bytes.join(" "))
+    } else {
+        bytes.join(" ")
+    };
+
+    let mut line = format!(
+        "{:04X}  {:<9} {:<18} A={:02X} F={:02X} B={:02X} C={:02X} D={:02X} E={:02X} H={:02X} L={:02X} SP={:04X} CYC={}",
+        entry.pc,
+        bytes,
+        instruction.format_with(|value| symbols.name_at(value).map(str::to_string)),
+        entry.a,
+        entry.flags,
+        entry.b,
+        entry.c,
+        entry.d,
+        entry.e,
+        entry.h,
+        entry.l,
+        entry.sp,
+        entry.cycles
+    );
+    for (address, value) in &entry.writes {
+        line.push_str(&format!(" [{:04X}]={:02X}", address, value));
+    }
+
+    line
+}
+
+pub fn format_reference(entry: &TraceEntry, memory: &[u8; 4]) -> String {
+    format!(
+        "PC: {:04X}, AF: {:02X}{:02X}, BC: {:02X}{:02X}, DE: {:02X}{:02X}, HL: {:02X}{:02X}, SP: {:04X}, CYC: {}\t({:02X} {:02X} {:02X} {:02X})",
+        entry.pc,
+        entry.a,
+        entry.flags,
+        entry.b,
+        entry.c,
+        entry.d,
+        entry.e,
+        entry.h,
+        entry.l,
+        entry.sp,
+        entry.cycles,
+        memory[0],
+        memory[1],
+        memory[2],
+        memory[3]
+    )
+}
+
+pub fn encode_binary(entry: &TraceEntry) -> Vec<u8> {
+    let mut record = Vec::with_capacity(32);
+
+    record.extend_from_slice(&entry.pc.to_le_bytes());
+    record.extend_from_slice(&[
+        entry.a,
+        entry.flags,
+        entry.b,
+        entry.c,
+        entry.d,
+        entry.e,
+        entry.h,
+        entry.l,
+    ]);
+    record.extend_from_slice(&entry.sp.to_le_bytes());
+    record.extend_from_slice(&entry.cycles.to_le_bytes());
+
+    let interrupt = if entry.interrupt { INTERRUPT_BIT } else { 0 };
+    record.push(entry.bytes.len() as u8 | interrupt);
+    record.extend_from_slice(&entry.bytes);
+
+    record.push(entry.writes.len() as u8);
+    for (address, value) in &entry.writes {
+        record.extend_from_slice(&address.to_le_bytes());
+        record.push(*value);
+    }
+
+    record
+}
+
+// Reads a trace in any of the formats, telling them apart by the first bytes.
+pub struct TraceReader<R: BufRead> {
+    input: R,
+    format: TraceFormat,
+    line: usize,
+}
+
+impl TraceReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        TraceReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> TraceReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let start = input.fill_buf()?;
+
+        let format = if start.starts_with(BINARY_MAGIC) {
+            TraceFormat::Binary
+        } else if start.starts_with(b"PC:") {
+            TraceFormat::Reference
+        } else {
+            TraceFormat::Text
+        };
+
+        if format == TraceFormat::Binary {
+            let mut header = [0u8; 10];
+            input.read_exact(&mut header)?;
+            let version = u16::from_le_bytes([header[8], header[9]]);
+            if version > BINARY_VERSION {
+                return Err(invalid(format!(
+                    "binary trace version {} is too new",
+                    version
+                )));
+            }
+        }
+
+        Ok(TraceReader {
+            input,
+            format,
+            line: 0,
+        })
+    }
+
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    // the reference format doesn't have them
+    pub fn has_writes(&self) -> bool {
+        self.format != TraceFormat::Reference
+    }
+
+    fn read_text(&mut self) -> io::Result<Option<TraceEntry>> {
+        loop {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry = match self.format {
+                TraceFormat::Reference => parse_reference(&line),
+                _ => parse_text(&line),
+            };
+            return entry
+                .map(Some)
+                .ok_or_else(|| invalid(format!("line {}: not a trace line", self.line)));
+        }
+    }
+
+    fn read_binary(&mut self) -> io::Result<Option<TraceEntry>> {
+        let mut fixed = [0u8; 21];
+        // a clean end of file only between records
+        match self.input.read(&mut fixed[..1])? {
+            0 => return Ok(None),
+            _ => self.input.read_exact(&mut fixed[1..])?,
+        }
+
+        let info = fixed[20];
+        let mut bytes = vec![0u8; (info & 0x03) as usize];
+        self.input.read_exact(&mut bytes)?;
+
+        let mut count = [0u8];
+        self.input.read_exact(&mut count)?;
+        let mut writes = Vec::with_capacity(count[0] as usize);
+        for _ in 0..count[0] {
+            let mut write = [0u8; 3];
+            self.input.read_exact(&mut write)?;
+            writes.push((u16::from_le_bytes([write[0], write[1]]), write[2]));
+        }
+
+        let mut cycles = [0u8; 8];
+        cycles.copy_from_slice(&fixed[12..20]);
+
+        Ok(Some(TraceEntry {
+            pc: u16::from_le_bytes([fixed[0], fixed[1]]),
+            a: fixed[2],
+            flags: fixed[3],
+            b: fixed[4],
+            c: fixed[5],
+            d: fixed[6],
+            e: fixed[7],
+            h: fixed[8],
+            l: fixed[9],
+            sp: u16::from_le_bytes([fixed[10], fixed[11]]),
+            cycles: u64::from_le_bytes(cycles),
+            bytes,
+            interrupt: info & INTERRUPT_BIT != 0,
+            writes,
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = match self.format {
+            TraceFormat::Binary => self.read_binary(),
+            _ => self.read_text(),
+        };
+        entry.transpose()
+    }
+}
+
+// `0100  3E 12     MVI   A,12H   A=00 F=02 ... CYC=0 [2000]=12`
+fn parse_text(line: &str) -> Option<TraceEntry> {
+    let mut words = line.split_whitespace().peekable();
+    let mut entry = TraceEntry {
+        pc: u16::from_str_radix(words.next()?, 16).ok()?,
+        ..Default::default()
+    };
+
+    if words.peek() == Some(&"INT") {
+        entry.interrupt = true;
+        words.next();
+    }
+    let opcode = u8::from_str_radix(words.next()?, 16).ok()?;
+    entry.bytes.push(opcode);
+    for _ in 1..instruction_length(opcode) {
+        entry
+            .bytes
+            .push(u8::from_str_radix(words.next()?, 16).ok()?);
+    }
+
+    // the disassembly is skipped, the registers are all `NAME=value`
+    for word in words {
+        let (name, value) = match word.split_once('=') {
+            Some(field) => field,
+            None => continue,
+        };
+
+        if let Some(address) = name.strip_prefix('[').and_then(|n| n.strip_suffix(']')) {
+            let address = u16::from_str_radix(address, 16).ok()?;
+            entry
+                .writes
+                .push((address, u8::from_str_radix(value, 16).ok()?));
+            continue;
+        }
+
+        match name {
+            "A" => entry.a = u8::from_str_radix(value, 16).ok()?,
+            "F" => entry.flags = u8::from_str_radix(value, 16).ok()?,
+            "B" => entry.b = u8::from_str_radix(value, 16).ok()?,
+            "C" => entry.c = u8::from_str_radix(value, 16).ok()?,
+            "D" => entry.d = u8::from_str_radix(value, 16).ok()?,
+            "E" => entry.e = u8::from_str_radix(value, 16).ok()?,
+            "H" => entry.h = u8::from_str_radix(value, 16).ok()?,
+            "L" => entry.l = u8::from_str_radix(value, 16).ok()?,
+            "SP" => entry.sp = u16::from_str_radix(value, 16).ok()?,
+            "CYC" => entry.cycles = value.parse().ok()?,
+            // e.g. an `=` in a DB string
+            _ => (),
+        }
+    }
+
+    Some(entry)
+}
+
+// `PC: 0100, AF: 0002, BC: 0000, DE: 0000, HL: 0000, SP: 0000, CYC: 0	(3E 12 21 00)`, the
+// cycles and the bytes are optional
+fn parse_reference(line: &str) -> Option<TraceEntry> {
+    let (registers, bytes) = match line.split_once('(') {
+        Some((registers, bytes)) => (registers, Some(bytes)),
+        None => (line, None),
+    };
+
+    let mut entry = TraceEntry::default();
+    let mut seen = 0;
+    for field in registers.split(',') {
+        let (name, value) = field.split_once(':')?;
+        let value = value.trim();
+        let word = || u16::from_str_radix(value, 16).ok();
+
+        let (high, low) = match name.trim() {
+            "PC" => {
+                entry.pc = word()?;
+                seen += 1;
+                continue;
+            }
+            "SP" => {
+                entry.sp = word()?;
+                seen += 1;
+                continue;
+            }
+            "CYC" => {
+                entry.cycles = value.parse().ok()?;
+                continue;
+            }
+            "AF" => (&mut entry.a, &mut entry.flags),
+            "BC" => (&mut entry.b, &mut entry.c),
+            "DE" => (&mut entry.d, &mut entry.e),
+            "HL" => (&mut entry.h, &mut entry.l),
+            _ => continue,
+        };
+        let [hi, lo] = word()?.to_be_bytes();
+        *high = hi;
+        *low = lo;
+        seen += 1;
+    }
+    if seen != 6 {
+        return None;
+    }
+
+    if let Some(bytes) = bytes {
+        let memory = bytes
+            .trim_end()
+            .trim_end_matches(')')
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        if let Some(&opcode) = memory.first() {
+            let length = (instruction_length(opcode) as usize).min(memory.len());
+            entry.bytes = memory[..length].to_vec();
+        }
+    }
+
+    Some(entry)
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
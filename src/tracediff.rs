@@ -0,0 +1,201 @@
+// Finds where two instruction traces part ways: the first instruction whose PC, registers, flags or
+// memory writes differ, with the instructions around it. The traces can be in different formats,
+// writes are only compared when both have them.
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use crate::symbols::SymbolTable;
+use crate::trace::{format_text, TraceEntry, TraceReader};
+
+pub const DEFAULT_CONTEXT: usize = 5;
+
+pub struct DiffOptions {
+    // instructions shown before and after the first difference
+    pub context: usize,
+    // emulators don't all count cycles the same way (or at all), so they're only compared on request
+    pub cycles: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            context: DEFAULT_CONTEXT,
+            cycles: false,
+        }
+    }
+}
+
+pub enum Comparison {
+    // both traces have `count` instructions, all alike
+    Same(u64),
+    // alike for the `count` instructions both have, then one of them ends
+    Truncated { count: u64, a_ended: bool },
+    Diverged(Divergence),
+}
+
+pub struct Divergence {
+    // counting from 0, shown counting from 1 like the lines of a text trace
+    pub index: u64,
+    pub differences: Vec<String>,
+    // the instructions leading up to it (as in `a`)
+    pub before: Vec<TraceEntry>,
+    // the differing instruction and the ones after it, in each trace
+    pub after_a: Vec<TraceEntry>,
+    pub after_b: Vec<TraceEntry>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbols = SymbolTable::new();
+
+        writeln!(
+            f,
+            "first difference at instruction {}, PC {:04X}:",
+            self.index + 1,
+            self.after_a[0].pc
+        )?;
+        for difference in &self.differences {
+            writeln!(f, "    {}", difference)?;
+        }
+        writeln!(f)?;
+
+        for entry in &self.before {
+            writeln!(f, "    {}", format_text(entry, &symbols))?;
+        }
+        for (side, entries) in [("a", &self.after_a), ("b", &self.after_b)] {
+            for (i, entry) in entries.iter().enumerate() {
+                let marker = if i == 0 { '>' } else { ' ' };
+                writeln!(f, "{} {} {}", side, marker, format_text(entry, &symbols))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn diff<A: BufRead, B: BufRead>(
+    mut a: TraceReader<A>,
+    mut b: TraceReader<B>,
+    options: &DiffOptions,
+) -> io::Result<Comparison> {
+    let writes = a.has_writes() && b.has_writes();
+    let mut before = VecDeque::with_capacity(options.context + 1);
+    let mut index = 0;
+
+    loop {
+        let (entry_a, entry_b) = match (a.next().transpose()?, b.next().transpose()?) {
+            (Some(entry_a), Some(entry_b)) => (entry_a, entry_b),
+            (None, None) => return Ok(Comparison::Same(index)),
+            (entry_a, _) => {
+                return Ok(Comparison::Truncated {
+                    count: index,
+                    a_ended: entry_a.is_none(),
+                })
+            }
+        };
+
+        let differences = compare(&entry_a, &entry_b, writes, options.cycles);
+        if !differences.is_empty() {
+            let mut after_a = vec![entry_a];
+            let mut after_b = vec![entry_b];
+            for _ in 0..options.context {
+                if let Some(entry) = a.next().transpose()? {
+                    after_a.push(entry);
+                }
+                if let Some(entry) = b.next().transpose()? {
+                    after_b.push(entry);
+                }
+            }
+
+            return Ok(Comparison::Diverged(Divergence {
+                index,
+                differences,
+                before: before.into(),
+                after_a,
+                after_b,
+            }));
+        }
+
+        if options.context > 0 {
+            if before.len() == options.context {
+                before.pop_front();
+            }
+            before.push_back(entry_a);
+        }
+        index += 1;
+    }
+}
+
+// what differs between two records of the same instruction, one line each
+pub fn compare(a: &TraceEntry, b: &TraceEntry, writes: bool, cycles: bool) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    if a.pc != b.pc {
+        differences.push(format!("PC: {:04X} vs {:04X}", a.pc, b.pc));
+    }
+    let registers = [
+        ("A", a.a, b.a),
+        ("B", a.b, b.b),
+        ("C", a.c, b.c),
+        ("D", a.d, b.d),
+        ("E", a.e, b.e),
+        ("H", a.h, b.h),
+        ("L", a.l, b.l),
+    ];
+    for (name, value_a, value_b) in registers {
+        if value_a != value_b {
+            differences.push(format!("{}: {:02X} vs {:02X}", name, value_a, value_b));
+        }
+    }
+    if a.flags != b.flags {
+        differences.push(format!(
+            "F: {:02X} ({}) vs {:02X} ({})",
+            a.flags,
+            flag_names(a.flags),
+            b.flags,
+            flag_names(b.flags)
+        ));
+    }
+    if a.sp != b.sp {
+        differences.push(format!("SP: {:04X} vs {:04X}", a.sp, b.sp));
+    }
+    if cycles && a.cycles != b.cycles {
+        differences.push(format!("cycles: {} vs {}", a.cycles, b.cycles));
+    }
+    if writes && a.writes != b.writes {
+        differences.push(format!(
+            "writes: {} vs {}",
+            format_writes(&a.writes),
+            format_writes(&b.writes)
+        ));
+    }
+
+    differences
+}
+
+// the debugger's notation, `S-A-C` for sign, auxiliary carry and carry
+pub(crate) fn flag_names(flags: u8) -> String {
+    [
+        (0x80, 'S'),
+        (0x40, 'Z'),
+        (0x10, 'A'),
+        (0x04, 'P'),
+        (0x01, 'C'),
+    ]
+    .iter()
+    .map(|&(bit, name)| if flags & bit != 0 { name } else { '-' })
+    .collect()
+}
+
+fn format_writes(writes: &[(u16, u8)]) -> String {
+    if writes.is_empty() {
+        return "none".to_string();
+    }
+
+    let writes: Vec<String> = writes
+        .iter()
+        .map(|(address, value)| format!("[{:04X}]={:02X}", address, value))
+        .collect();
+    writes.join(" ")
+}
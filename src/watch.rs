@@ -0,0 +1,169 @@
+// Memory watchpoints, checked by the core on every bus access (instruction fetches included).
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    // a write that stores a different value than the one already there
+    Change,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchHit {
+    pub id: usize,
+    pub kind: WatchKind,
+    // the instruction doing the access
+    pub pc: u16,
+    pub address: u16,
+    // for reads both are the value read
+    pub old: u8,
+    pub new: u8,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            WatchKind::Read => write!(
+                f,
+                "watchpoint {}: {:04X} read {:02X} at {:04X}",
+                self.id, self.pc, self.new, self.address
+            ),
+            _ => write!(
+                f,
+                "watchpoint {}: {:04X} wrote {:02X} to {:04X} (was {:02X})",
+                self.id, self.pc, self.new, self.address, self.old
+            ),
+        }
+    }
+}
+
+type Condition = Box<dyn Fn(u8) -> bool>;
+type Callback = Box<dyn FnMut(&WatchHit)>;
+
+// Watches `start..=end`. By default a hit stops execution (see `State8080::take_watch_hit`),
+// `on_hit` turns it into a callback instead.
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+    condition: Option<Condition>,
+    callback: Option<Callback>,
+}
+
+impl Watchpoint {
+    pub fn new(start: u16, end: u16, kind: WatchKind) -> Self {
+        Watchpoint {
+            start,
+            end,
+            kind,
+            condition: None,
+            callback: None,
+        }
+    }
+
+    // only hit when the value written (or read) passes `condition`
+    pub fn when<F: Fn(u8) -> bool + 'static>(mut self, condition: F) -> Self {
+        self.condition = Some(Box::new(condition));
+        self
+    }
+
+    pub fn on_hit<F: FnMut(&WatchHit) + 'static>(mut self, callback: F) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn is_conditional(&self) -> bool {
+        self.condition.is_some()
+    }
+
+    pub fn stops(&self) -> bool {
+        self.callback.is_none()
+    }
+
+    fn matches(&self, kind: WatchKind, address: u16, old: u8, new: u8) -> bool {
+        let kind_matches = match self.kind {
+            WatchKind::Read => kind == WatchKind::Read,
+            WatchKind::Write => kind == WatchKind::Write,
+            WatchKind::Change => kind == WatchKind::Write && old != new,
+        };
+
+        kind_matches
+            && (self.start..=self.end).contains(&address)
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition(new))
+    }
+}
+
+#[derive(Default)]
+pub struct Watchpoints {
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_id: usize,
+    // the first stopping hit since the last `take_hit`
+    hit: Option<WatchHit>,
+}
+
+impl Watchpoints {
+    pub fn add(&mut self, watchpoint: Watchpoint) -> usize {
+        self.next_id += 1;
+        self.watchpoints.push((self.next_id, watchpoint));
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|(other, _)| *other != id);
+        self.watchpoints.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .map(|(id, watchpoint)| (*id, watchpoint))
+    }
+
+    pub fn take_hit(&mut self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+
+    pub fn has_hit(&self) -> bool {
+        self.hit.is_some()
+    }
+
+    // `kind` is Read or Write, a write counts as a change when `old != new`
+    pub(crate) fn check(&mut self, kind: WatchKind, pc: u16, address: u16, old: u8, new: u8) {
+        for (id, watchpoint) in self.watchpoints.iter_mut() {
+            if !watchpoint.matches(kind, address, old, new) {
+                continue;
+            }
+
+            let hit = WatchHit {
+                id: *id,
+                kind: watchpoint.kind,
+                pc,
+                address,
+                old,
+                new,
+            };
+
+            match watchpoint.callback.as_mut() {
+                Some(callback) => callback(&hit),
+                None => {
+                    if self.hit.is_none() {
+                        self.hit = Some(hit);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+// The assembler on its own: what it emits for small sources, and the errors it reports.
+use intel_8080_emu::assembler::assemble;
+
+// the bytes from the lowest address up, and where they start
+fn bytes(source: &str) -> (u16, Vec<u8>) {
+    let assembly = assemble(source).unwrap_or_else(|e| panic!("{}", e));
+    (assembly.image.lowest_address(), assembly.image.to_binary())
+}
+
+fn error(source: &str) -> String {
+    match assemble(source) {
+        Ok(_) => panic!("{:?} assembled", source),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn forward_references() {
+    let source = "
+        ORG   100H
+        JMP   START
+        DW    DATA, DATA+2
+START:  LXI   H,DATA
+        MVI   A,LOW DATA
+        MVI   B,HIGH DATA
+        RET
+DATA:   DB    1,2
+        END   START
+";
+    let assembly = assemble(source).unwrap();
+    assert_eq!(assembly.symbols["START"], 0x0107);
+    assert_eq!(assembly.symbols["DATA"], 0x010F);
+    assert_eq!(assembly.image.start, Some(0x0107));
+    assert_eq!(
+        bytes(source),
+        (
+            0x0100,
+            vec![
+                0xC3, 0x07, 0x01, // JMP START
+                0x0F, 0x01, 0x11, 0x01, // DW DATA,DATA+2
+                0x21, 0x0F, 0x01, // LXI H,DATA
+                0x3E, 0x0F, // MVI A,LOW DATA
+                0x06, 0x01, // MVI B,HIGH DATA
+                0xC9, // RET
+                0x01, 0x02, // DB 1,2
+            ]
+        )
+    );
+}
+
+#[test]
+fn dollar_is_where_the_statement_starts() {
+    let source = "
+        ORG   200H
+        DW    $,$
+        DB    LOW $,LOW $,'AB',LOW $
+HERE:   JMP   $
+        DS    2
+        DW    $-HERE
+";
+    assert_eq!(
+        bytes(source),
+        (
+            0x0200,
+            vec![
+                0x00, 0x02, 0x00, 0x02, // DW $,$
+                0x04, 0x04, b'A', b'B', 0x04, // DB
+                0xC3, 0x09, 0x02, // JMP $
+                0x00, 0x00, // DS 2
+                0x05, 0x00, // DW $-HERE
+            ]
+        )
+    );
+}
+
+#[test]
+fn expression_precedence() {
+    let cases = [
+        ("2+3*4", 14),
+        ("(2+3)*4", 20),
+        ("10-4-3", 3),
+        ("100/10/2", 5),
+        ("1 SHL 4 + 1", 17),
+        ("17 MOD 5 * 2", 4),
+        ("0F0H OR 0FH AND 3", 0xF3),
+        ("NOT 0 AND 0FFH", 0xFF),
+        ("6 XOR 3 OR 8", 13),
+        ("-1", 0xFFFF),
+        ("HIGH 1234H + 1", 0x13),
+        ("'A'+1", 0x42),
+        ("1010B + 17O + 0AH", 10 + 15 + 10),
+    ];
+
+    for (expression, expected) in cases {
+        let (_, bytes) = bytes(&format!("X EQU {}\n DW X", expression));
+        assert_eq!(
+            u16::from_le_bytes([bytes[0], bytes[1]]),
+            expected,
+            "{}",
+            expression
+        );
+    }
+}
+
+#[test]
+fn errors() {
+    assert_eq!(error("  JMP NOWHERE"), "line 1: undefined symbol NOWHERE");
+    assert_eq!(
+        error("\n  MVI A,100H"),
+        "line 2: 0100H doesn't fit in a byte"
+    );
+    assert_eq!(error("  DB 0FF7FH"), "line 1: FF7FH doesn't fit in a byte");
+    assert_eq!(error("X: NOP\nX: NOP"), "line 2: X is defined twice");
+    assert_eq!(error("X EQU 1\nx EQU 2"), "line 2: X is defined twice");
+    // ORG, DS and EQU can't wait for the second pass
+    assert_eq!(
+        error("  ORG LATER\nLATER: NOP"),
+        "line 1: undefined symbol LATER"
+    );
+    assert_eq!(error("  MVI Q,1"), "line 1: bad operands for MVI: Q,1");
+    assert_eq!(error("  RST 8"), "line 1: RST 8 doesn't exist");
+    assert_eq!(
+        error("  ORG 0FFFFH\n  DW 0"),
+        "line 2: past the end of memory"
+    );
+    assert_eq!(error("  DB 1/0"), "line 1: division by zero");
+
+    // -128 and 255 still fit
+    assert_eq!(bytes("  DB -128, 255").1, [0x80, 0xFF]);
+}
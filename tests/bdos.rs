@@ -0,0 +1,117 @@
+// The BDOS against what programs can hand it: command tails and FCB pointers are the guest's, a bad
+// one must come back as a CP/M error rather than a panic on the host.
+use std::env;
+use std::io;
+
+use intel_8080_emu::cpm::bdos::{Bdos, DEFAULT_FCB, DEFAULT_FCB2};
+use intel_8080_emu::cpm::console::Console;
+use intel_8080_emu::state::State8080;
+
+fn bdos() -> Bdos {
+    let console = Console::new(Box::new(io::empty()), Box::new(io::sink()));
+    Bdos::with_console(env::temp_dir(), console)
+}
+
+fn fcb_name(state: &State8080, fcb: u16) -> (u8, String) {
+    let memory = state.memory();
+    let name: Vec<u8> = (1..12).map(|i| memory.peek(fcb + i)).collect();
+    (memory.peek(fcb), String::from_utf8(name).unwrap())
+}
+
+#[test]
+fn only_a_to_p_are_drives() {
+    let mut state = State8080::default();
+    bdos().install(&mut state, "p:foo.txt b:bar");
+    assert_eq!(
+        fcb_name(&state, DEFAULT_FCB),
+        (16, "FOO     TXT".to_string())
+    );
+    assert_eq!(
+        fcb_name(&state, DEFAULT_FCB2),
+        (2, "BAR        ".to_string())
+    );
+
+    bdos().install(&mut state, "@:FOO Q:BAR");
+    assert_eq!(
+        fcb_name(&state, DEFAULT_FCB),
+        (0, "@:FOO      ".to_string())
+    );
+    assert_eq!(
+        fcb_name(&state, DEFAULT_FCB2),
+        (0, "Q:BAR      ".to_string())
+    );
+}
+
+#[test]
+fn fcbs_wrap_around_the_top_of_memory() {
+    let mut state = State8080::default();
+    let mut bdos = bdos();
+    bdos.install(&mut state, "");
+
+    // EX at FFFC and S2 at FFFE (over the BIOS), CR and R0..R2 past the top at 0010..0013
+    let fcb = 0xFFF0;
+    state.memory_mut().poke(0xFFFC, 1);
+    state.memory_mut().poke(0xFFFE, 0);
+    state.memory_mut().poke(0x0010, 5);
+    state.d = 0xFF;
+    state.e = 0xF0;
+
+    // set random record
+    state.c = 36;
+    bdos.call(&mut state).unwrap();
+    let memory = state.memory();
+    assert_eq!(
+        [0x11, 0x12, 0x13].map(|address| memory.peek(address)),
+        [0x85, 0x00, 0x00]
+    );
+
+    // the name runs from FFF1 to FFFB, whatever it is the file isn't there
+    for function in [15, 17, 19, 33, 35] {
+        state.c = function;
+        state.d = (fcb >> 8) as u8;
+        state.e = fcb as u8;
+        bdos.call(&mut state).unwrap();
+        assert_eq!(state.a, 0xFF, "function {}", function);
+    }
+}
+
+// function 10 into a buffer at 0200 with room for `max` characters, the rest of memory FFs
+fn read_console_buffer(input: &'static [u8], max: u8) -> Vec<u8> {
+    let mut state = State8080::default();
+    for address in 0x0200..0x0300 {
+        state.memory_mut().poke(address, 0xFF);
+    }
+    state.memory_mut().poke(0x0200, max);
+
+    let console = Console::new(Box::new(input), Box::new(io::sink()));
+    let mut bdos = Bdos::with_console(env::temp_dir(), console);
+    state.c = 10;
+    state.d = 0x02;
+    state.e = 0x00;
+    bdos.call(&mut state).unwrap();
+
+    (0x0200..0x0210)
+        .map(|address| state.memory().peek(address))
+        .collect()
+}
+
+#[test]
+fn read_console_buffer_stops_when_the_buffer_is_full() {
+    let buffer = read_console_buffer(b"HELLO\r", 3);
+    assert_eq!(buffer[..6], [3, 3, b'H', b'E', b'L', 0xFF]);
+
+    // exactly full
+    let buffer = read_console_buffer(b"HEY\r", 3);
+    assert_eq!(buffer[..6], [3, 3, b'H', b'E', b'Y', 0xFF]);
+
+    let buffer = read_console_buffer(b"HI\x08\x08OK\r", 3);
+    assert_eq!(buffer[..6], [3, 2, b'O', b'K', 0xFF, 0xFF]);
+}
+
+#[test]
+fn read_console_buffer_with_no_room() {
+    // the line is read up to the return but nothing goes in the buffer
+    let buffer = read_console_buffer(b"A LONG LINE THAT DOESN'T FIT\rNEXT", 0);
+    assert_eq!(buffer[..2], [0, 0]);
+    assert!(buffer[2..].iter().all(|&byte| byte == 0xFF));
+}
@@ -0,0 +1,338 @@
+// The CP/M 2.2 machine: booting from a system image or the system tracks of drive A:, and every
+// entry of the BIOS jump table with the tables it points at.
+use std::cell::RefCell;
+use std::io::{self, Cursor, Write};
+use std::rc::Rc;
+
+use intel_8080_emu::assembler::assemble;
+use intel_8080_emu::cpm::bios::{Bios, BiosStatus, MAX_DRIVES};
+use intel_8080_emu::cpm::console::Console;
+use intel_8080_emu::cpm::disk::{DiskImage, SECTOR_SIZE};
+use intel_8080_emu::cpm::machine::{CpmMachine, DEFAULT_CCP_BASE};
+use intel_8080_emu::state::State8080;
+
+// the BIOS of a system with the CCP at E400
+const BIOS: u16 = 0xFA00;
+const TRAPS: u16 = BIOS + 0x40;
+const XLT: u16 = BIOS + 0x60;
+const DPB: u16 = BIOS + 0x80;
+const DPH: u16 = BIOS + 0x90;
+
+// a `Write` the test can still read after handing it to the console
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn console(input: &str, output: &Shared) -> Console {
+    Console::new(
+        Box::new(Cursor::new(input.as_bytes().to_vec())),
+        Box::new(output.clone()),
+    )
+}
+
+// Stands in for the CCP: records what it was entered with, prints an A through the BIOS and warm
+// boots once with drive B: as the current disk. MARK is set before the warm boot, which reloads
+// the CCP and so clears it again.
+const SYSTEM: &str = "
+        ORG   0E400H
+        MOV   A,C
+        STA   0040H
+        LDA   0003H
+        STA   0043H
+        LXI   H,0
+        DAD   SP
+        SHLD  0044H
+        MVI   C,'A'
+        CALL  0FA0CH
+        LDA   MARK
+        STA   0042H
+        LDA   0041H
+        ORA   A
+        JNZ   DONE
+        INR   A
+        STA   0041H
+        STA   MARK
+        STA   0004H
+        JMP   0000H
+DONE:   HLT
+MARK:   DB    0
+";
+
+fn system() -> Vec<u8> {
+    assemble(SYSTEM).unwrap().image.to_binary()
+}
+
+fn check_boot(machine: &CpmMachine, output: &Shared) {
+    let memory = machine.state.memory();
+    let peek = |address: u16| memory.peek(address);
+
+    assert_eq!(String::from_utf8_lossy(&output.0.borrow()), "AA");
+    // JMP WBOOT and JMP BDOS
+    assert_eq!([0, 1, 2].map(peek), [0xC3, 0x03, 0xFA]);
+    assert_eq!([5, 6, 7].map(peek), [0xC3, 0x06, 0xEC]);
+    // drive B: came through in C, IOBYTE was cleared, the stack was at 0080, MARK was reloaded
+    assert_eq!([0x40, 0x41, 0x42, 0x43].map(peek), [1, 1, 0, 0]);
+    assert_eq!([0x44, 0x45].map(peek), [0x80, 0x00]);
+    assert!(machine.state.halted());
+}
+
+#[test]
+fn boots_from_the_system_tracks() {
+    // the cold start loader has T0 S1, the system starts at T0 S2
+    let mut tracks = vec![0; SECTOR_SIZE];
+    tracks.extend(system());
+
+    let output = Shared::default();
+    let mut machine = CpmMachine::new(DEFAULT_CCP_BASE, console("", &output)).unwrap();
+    machine.insert(0, DiskImage::from_bytes(tracks));
+    machine.run().unwrap();
+    check_boot(&machine, &output);
+}
+
+#[test]
+fn boots_from_a_system_image() {
+    let output = Shared::default();
+    let mut machine = CpmMachine::new(DEFAULT_CCP_BASE, console("", &output)).unwrap();
+    machine.set_system_image(system());
+    machine.run().unwrap();
+    check_boot(&machine, &output);
+}
+
+#[test]
+fn boot_errors() {
+    let output = Shared::default();
+    let mut machine = CpmMachine::new(DEFAULT_CCP_BASE, console("", &output)).unwrap();
+    assert_eq!(
+        machine.run().unwrap_err().to_string(),
+        "no boot disk in drive A:"
+    );
+
+    // the BIOS has to fit below the top of memory
+    for ccp in [0xE7F4, 0xE7F5, 0xEA00, 0xFFFF] {
+        let machine = CpmMachine::new(ccp, console("", &output));
+        assert_eq!(machine.is_ok(), ccp == 0xE7F4, "{:04X}", ccp);
+    }
+}
+
+fn bios(input: &str, output: &Shared) -> (Bios, State8080) {
+    let bios = Bios::new(BIOS, console(input, output));
+    let mut state = State8080::default();
+    bios.install(&mut state);
+    (bios, state)
+}
+
+fn call(bios: &mut Bios, state: &mut State8080, function: u16, bc: u16) -> BiosStatus {
+    state.b = (bc >> 8) as u8;
+    state.c = bc as u8;
+    bios.call(function, state).unwrap()
+}
+
+fn hl(state: &State8080) -> u16 {
+    u16::from_le_bytes([state.l, state.h])
+}
+
+fn peek_word(state: &State8080, address: u16) -> u16 {
+    let memory = state.memory();
+    u16::from_le_bytes([memory.peek(address), memory.peek(address + 1)])
+}
+
+#[test]
+fn jump_table_and_disk_tables() {
+    let (bios, state) = bios("", &Shared::default());
+    let memory = state.memory();
+
+    // every entry jumps to a RET the machine traps
+    for function in 0..17 {
+        let entry = BIOS + function * 3;
+        assert_eq!(memory.peek(entry), 0xC3);
+        assert_eq!(peek_word(&state, entry + 1), TRAPS + function);
+        assert_eq!(memory.peek(TRAPS + function), 0xC9);
+        assert_eq!(bios.trap(TRAPS + function), Some(function));
+    }
+    assert_eq!(bios.trap(TRAPS - 1), None);
+    assert_eq!(bios.trap(TRAPS + 17), None);
+
+    // 26 sectors per track, 1K blocks, 243 of them, 64 directory entries, 2 system tracks
+    let dpb: Vec<u8> = (0..15).map(|i| memory.peek(DPB + i)).collect();
+    assert_eq!(dpb, [26, 0, 3, 7, 0, 242, 0, 63, 0, 0xC0, 0, 16, 0, 2, 0]);
+
+    // XLT, scratch, DIRBUF, DPB, CSV and ALV for each drive, only the vectors are per drive
+    for drive in 0..MAX_DRIVES as u16 {
+        let dph = DPH + drive * 16;
+        let words: Vec<u16> = (0..8).map(|i| peek_word(&state, dph + i * 2)).collect();
+        assert_eq!(
+            words,
+            [
+                XLT,
+                0,
+                0,
+                0,
+                BIOS + 0xD0,
+                DPB,
+                BIOS + 0x150 + drive * 16,
+                BIOS + 0x190 + drive * 31
+            ]
+        );
+    }
+}
+
+#[test]
+fn boot_and_console() {
+    let output = Shared::default();
+    let (mut bios, mut state) = bios("x\n", &output);
+
+    assert_eq!(call(&mut bios, &mut state, 0, 0), BiosStatus::ColdBoot);
+    assert_eq!(call(&mut bios, &mut state, 1, 0), BiosStatus::WarmBoot);
+
+    // CONST, CONIN (the host's LF is a CR), then nothing left
+    call(&mut bios, &mut state, 2, 0);
+    assert_eq!(state.a, 0xFF);
+    call(&mut bios, &mut state, 3, 0);
+    assert_eq!(state.a, b'x');
+    call(&mut bios, &mut state, 3, 0);
+    assert_eq!(state.a, b'\r');
+    call(&mut bios, &mut state, 2, 0);
+    assert_eq!(state.a, 0);
+    assert_eq!(call(&mut bios, &mut state, 3, 0), BiosStatus::Exit);
+
+    // CONOUT strips the parity bit, LIST and PUNCH go nowhere
+    call(&mut bios, &mut state, 4, b'O' as u16 | 0x80);
+    call(&mut bios, &mut state, 5, b'L' as u16);
+    call(&mut bios, &mut state, 6, b'P' as u16);
+    call(&mut bios, &mut state, 4, b'K' as u16);
+    assert_eq!(*output.0.borrow(), b"OK");
+
+    // READER is at end of file, LISTST always ready
+    call(&mut bios, &mut state, 7, 0);
+    assert_eq!(state.a, 0x1A);
+    call(&mut bios, &mut state, 15, 0);
+    assert_eq!(state.a, 0xFF);
+}
+
+#[test]
+fn select_disk() {
+    let (mut bios, mut state) = bios("", &Shared::default());
+    bios.insert(0, DiskImage::blank());
+    bios.insert(1, DiskImage::blank());
+
+    call(&mut bios, &mut state, 9, 1);
+    assert_eq!(hl(&state), DPH + 16);
+    call(&mut bios, &mut state, 9, 0);
+    assert_eq!(hl(&state), DPH);
+
+    // empty drives and drives that don't exist
+    for drive in [2, 3, 4, 0xFF] {
+        state.h = 0xFF;
+        call(&mut bios, &mut state, 9, drive);
+        assert_eq!(hl(&state), 0, "drive {}", drive);
+    }
+}
+
+#[test]
+#[should_panic(expected = "no drive 4")]
+fn only_four_drives() {
+    let (mut bios, _) = bios("", &Shared::default());
+    bios.insert(4, DiskImage::blank());
+}
+
+#[test]
+fn sector_translation_skews_by_6() {
+    let (mut bios, mut state) = bios("", &Shared::default());
+
+    let mut physical = Vec::new();
+    for logical in 0..26 {
+        state.d = (XLT >> 8) as u8;
+        state.e = XLT as u8;
+        call(&mut bios, &mut state, 16, logical);
+        physical.push(hl(&state));
+    }
+    // 6 apart, wrapping around the 26 sectors
+    assert_eq!(
+        physical,
+        [
+            1, 7, 13, 19, 25, 5, 11, 17, 23, 3, 9, 15, 21, 2, 8, 14, 20, 26, 6, 12, 18, 24, 4, 10,
+            16, 22
+        ]
+    );
+
+    // no table, no translation
+    state.d = 0;
+    state.e = 0;
+    call(&mut bios, &mut state, 16, 0x1234);
+    assert_eq!(hl(&state), 0x1234);
+}
+
+#[test]
+fn read_and_write_sectors() {
+    // track 2 sector 5 of drive B: counts up from 0
+    let mut data = vec![0xE5; (2 * 26 + 4) * SECTOR_SIZE];
+    data.extend((0..SECTOR_SIZE).map(|i| i as u8));
+    let (mut bios, mut state) = bios("", &Shared::default());
+    bios.insert(0, DiskImage::blank());
+    bios.insert(1, DiskImage::from_bytes(data));
+
+    call(&mut bios, &mut state, 9, 1);
+    call(&mut bios, &mut state, 10, 2);
+    call(&mut bios, &mut state, 11, 5);
+    call(&mut bios, &mut state, 12, 0x3000);
+    call(&mut bios, &mut state, 13, 0);
+    assert_eq!(state.a, 0);
+    let memory = state.memory();
+    assert_eq!(
+        [0x3000, 0x3001, 0x307F, 0x3080].map(|a| memory.peek(a)),
+        [0, 1, 0x7F, 0]
+    );
+
+    // written back to track 3 sector 1, after HOME to track 0 sector 26
+    call(&mut bios, &mut state, 8, 0);
+    call(&mut bios, &mut state, 11, 26);
+    call(&mut bios, &mut state, 14, 0);
+    assert_eq!(state.a, 0);
+    call(&mut bios, &mut state, 10, 3);
+    call(&mut bios, &mut state, 11, 1);
+    call(&mut bios, &mut state, 14, 0);
+    assert_eq!(state.a, 0);
+    let disk = bios.disk(1).unwrap().bytes();
+    assert_eq!(
+        disk[25 * SECTOR_SIZE..26 * SECTOR_SIZE],
+        disk[78 * SECTOR_SIZE..79 * SECTOR_SIZE]
+    );
+    assert_eq!(disk[78 * SECTOR_SIZE + 0x7F], 0x7F);
+    // drive A: wasn't touched
+    assert!(bios
+        .disk(0)
+        .unwrap()
+        .bytes()
+        .iter()
+        .all(|&byte| byte == 0xE5));
+
+    // sectors and tracks that don't exist are errors
+    for (track, sector) in [(0, 0), (0, 27), (77, 1)] {
+        call(&mut bios, &mut state, 10, track);
+        call(&mut bios, &mut state, 11, sector);
+        call(&mut bios, &mut state, 13, 0);
+        assert_eq!(state.a, 1, "read T{} S{}", track, sector);
+        call(&mut bios, &mut state, 14, 0);
+        assert_eq!(state.a, 1, "write T{} S{}", track, sector);
+    }
+}
+
+#[test]
+fn sectors_are_whole() {
+    let mut disk = DiskImage::blank();
+    let error = disk.write_sector(0, 1, &[0; 100]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(disk.read_sector(0, 1).unwrap(), &[0xE5; SECTOR_SIZE][..]);
+
+    disk.write_sector(0, 1, &[0; SECTOR_SIZE]).unwrap();
+    assert_eq!(disk.read_sector(0, 1).unwrap(), &[0; SECTOR_SIZE][..]);
+}
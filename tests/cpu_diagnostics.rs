@@ -0,0 +1,154 @@
+// The classic 8080 CPU diagnostics, run as CP/M programs under the BDOS stub. They can't be
+// redistributed here, so point I8080_TEST_ROMS at a directory with whichever of these you have
+// (file names are matched ignoring case):
+//
+//     TST8080.COM   Microcosm Associates' 8080/8085 CPU diagnostic
+//     8080PRE.COM   the preliminary tests of the instruction exerciser
+//     CPUTEST.COM   SuperSoft Associates' CPU test
+//     8080EXM.COM   the instruction exerciser (Frank Cringle's, ported by Ian Bartholomew). Every CRC
+//                   has to match the real chip's, this is what a correct core means.
+//     cpudiag.bin   the Emulator 101 build of the Microcosm diagnostic
+//
+// Tests for missing programs pass with a note on stderr. 8080EXM runs for about 23 billion cycles
+// and is ignored by default, run it with
+//
+//     I8080_TEST_ROMS=<dir> cargo test --release --test cpu_diagnostics -- --ignored
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use intel_8080_emu::cpm::bdos::{Bdos, TPA_START};
+use intel_8080_emu::cpm::console::Console;
+use intel_8080_emu::state::State8080;
+
+const ROM_DIR_VARIABLE: &str = "I8080_TEST_ROMS";
+
+// collects what the program prints
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn find_rom(name: &str) -> Option<PathBuf> {
+    let dir = match env::var_os(ROM_DIR_VARIABLE) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!("{} isn't set, skipping {}", ROM_DIR_VARIABLE, name);
+            return None;
+        }
+    };
+
+    let found = fs::read_dir(&dir).ok().and_then(|entries| {
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|path| {
+                path.file_name()
+                    .is_some_and(|file_name| file_name.eq_ignore_ascii_case(name))
+            })
+    });
+    if found.is_none() {
+        eprintln!("no {} in {}, skipping it", name, dir.display());
+    }
+    found
+}
+
+// Runs a program at 100h until it returns to CP/M and returns what it printed. `patch` gets to fix
+// up the loaded program first.
+fn run(name: &str, max_cycles: u64, patch: fn(&mut State8080)) -> Option<String> {
+    let path = find_rom(name)?;
+
+    let output = Output::default();
+    let console = Console::new(Box::new(io::empty()), Box::new(output.clone()));
+    let mut bdos = Bdos::with_console(path.parent().unwrap(), console);
+
+    let mut state = State8080::default();
+    state.load_rom(path.to_str().unwrap(), TPA_START).unwrap();
+    bdos.install(&mut state, "");
+    patch(&mut state);
+
+    while !state.halted() && bdos.trap(&mut state).unwrap() {
+        state.emulate_cycle();
+
+        if state.cycles() > max_cycles {
+            panic!(
+                "{} still running after {} cycles, it printed:\n{}",
+                name,
+                max_cycles,
+                String::from_utf8_lossy(&output.0.borrow())
+            );
+        }
+    }
+
+    let output = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+    Some(output)
+}
+
+fn check(name: &str, output: &str, success: &str) {
+    println!("{}", output);
+
+    assert!(
+        output.contains(success) && !output.contains("ERROR") && !output.contains("FAIL"),
+        "{} failed:\n{}",
+        name,
+        output
+    );
+}
+
+fn no_patch(_: &mut State8080) {}
+
+#[test]
+fn tst8080() {
+    if let Some(output) = run("TST8080.COM", 10_000_000, no_patch) {
+        check("TST8080", &output, "CPU IS OPERATIONAL");
+    }
+}
+
+#[test]
+fn cpudiag() {
+    // This build sets up its stack at 06ADh, inside its own code (the LXI SP is at 016Eh). Emulator
+    // 101 moves it to 07ADh, so does this.
+    fn fix_stack(state: &mut State8080) {
+        if state.memory().peek(0x0170) == 0x06 {
+            state.memory_mut().poke(0x0170, 0x07);
+        }
+    }
+
+    if let Some(output) = run("cpudiag.bin", 10_000_000, fix_stack) {
+        check("cpudiag", &output, "CPU IS OPERATIONAL");
+    }
+}
+
+#[test]
+fn preliminary_exerciser() {
+    if let Some(output) = run("8080PRE.COM", 100_000_000, no_patch) {
+        check("8080PRE", &output, "Preliminary tests complete");
+    }
+}
+
+#[test]
+fn cputest() {
+    if let Some(output) = run("CPUTEST.COM", 1_000_000_000, no_patch) {
+        check("CPUTEST", &output, "CPU TESTS OK");
+    }
+}
+
+#[test]
+#[ignore]
+fn instruction_exerciser() {
+    if let Some(output) = run("8080EXM.COM", 50_000_000_000, no_patch) {
+        check("8080EXM", &output, "Tests complete");
+    }
+}
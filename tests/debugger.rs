@@ -0,0 +1,48 @@
+// Scripted debugger sessions, checked against what the debugger prints and where it leaves the CPU.
+use std::io::{self, Cursor};
+
+use intel_8080_emu::debugger::Debugger;
+use intel_8080_emu::rewind::Rewind;
+use intel_8080_emu::state::State8080;
+
+// runs `script` against `state` and returns the output
+fn session(state: &mut State8080, script: &str) -> String {
+    let mut output = Vec::new();
+    let mut debugger = Debugger::new(Cursor::new(script.as_bytes()), &mut output);
+    debugger
+        .run(state, &mut |_: &mut State8080| -> io::Result<bool> {
+            Ok(true)
+        })
+        .unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn counts_are_decimal() {
+    // memory is all NOPs
+    let mut state = State8080::default();
+    state.enable_rewind(Rewind::default());
+
+    session(&mut state, "s 10\n");
+    assert_eq!(state.pc, 10);
+    assert_eq!(state.cycles(), 40);
+
+    session(&mut state, "bs 3\n");
+    assert_eq!(state.pc, 7);
+
+    session(&mut state, "rw 12\n");
+    assert_eq!((state.pc, state.cycles()), (4, 16));
+
+    let output = session(&mut state, "s 1A\n");
+    assert!(output.contains("bad count 1A"), "{}", output);
+    assert_eq!(state.pc, 4);
+}
+
+#[test]
+fn values_can_start_with_any_character() {
+    let mut state = State8080::default();
+
+    let output = session(&mut state, "b é+1\nb +1\nb\n");
+    assert!(output.contains("bad value é"), "{}", output);
+    assert!(output.contains("0001"), "{}", output);
+}
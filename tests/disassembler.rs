@@ -0,0 +1,300 @@
+// The recursive disassembler: what it takes for code and data, where it puts labels, and source
+// that the assembler turns back into the same bytes. Also single instructions as decoded, before
+// any formatting.
+use std::cell::Cell;
+use std::rc::Rc;
+
+use intel_8080_emu::assembler::assemble;
+use intel_8080_emu::disassembler::{
+    decode, decode_memory, decode_with, instruction_length, Disassembly, Operand,
+};
+use intel_8080_emu::memory::{MappedMemory, MemoryDevice};
+use intel_8080_emu::symbols::SymbolTable;
+
+// 0100  CALL 0108H         0108  MVI  C,09H          0110  DB   08H (an undocumented NOP)
+// 0103  JMP  0100H         010A  JZ   0110H          0111  RET
+// 0106  DB   'HI'          010D  LXI  D,0106H        0112  DB   00H,0FFH
+const PROGRAM: [u8; 20] = [
+    0xCD, 0x08, 0x01, 0xC3, 0x00, 0x01, b'H', b'I', 0x0E, 0x09, 0xCA, 0x10, 0x01, 0x11, 0x06, 0x01,
+    0x08, 0xC9, 0x00, 0xFF,
+];
+
+fn reassemble(source: &str) -> (u16, Vec<u8>) {
+    let assembly = assemble(source).unwrap_or_else(|e| panic!("{}\n{}", e, source));
+    (assembly.image.lowest_address(), assembly.image.to_binary())
+}
+
+#[test]
+fn labels_go_on_branch_targets() {
+    let disassembly = Disassembly::new(&PROGRAM, 0x0100, &[0x0100]);
+    let labels: Vec<(u16, &str)> = disassembly
+        .labels()
+        .iter()
+        .map(|(address, label)| (*address, label.as_str()))
+        .collect();
+    // not on 0106, LXI D loads a number as far as the disassembler knows
+    assert_eq!(
+        labels,
+        [(0x0100, "L0100"), (0x0108, "L0108"), (0x0110, "L0110")]
+    );
+
+    // no label in the middle of an instruction either
+    let disassembly = Disassembly::new(&[0xC3, 0x01, 0x00], 0x0000, &[0x0000]);
+    assert!(disassembly.labels().is_empty());
+}
+
+#[test]
+fn data_follows_unconditional_jumps() {
+    let disassembly = Disassembly::new(&PROGRAM, 0x0100, &[0x0100]);
+    let code: Vec<bool> = (0x0100..0x0114)
+        .map(|address| disassembly.is_code(address))
+        .collect();
+    let mut expected = [true; 20];
+    // 'HI' after the JMP and the two bytes after the RET
+    for offset in [6, 7, 18, 19] {
+        expected[offset] = false;
+    }
+    assert_eq!(code, expected);
+    assert!(!disassembly.is_code(0x00FF));
+    assert!(!disassembly.is_code(0x0114));
+
+    // the conditional jump falls through too, so nothing is left once 'HI' is an entry point
+    let disassembly = Disassembly::new(&PROGRAM, 0x0100, &[0x0100, 0x0106]);
+    assert!(disassembly.is_code(0x0106));
+    assert!(disassembly.is_code(0x0107));
+}
+
+#[test]
+fn source() {
+    let disassembly = Disassembly::new(&PROGRAM, 0x0100, &[0x0100]);
+    assert_eq!(
+        disassembly.to_source(),
+        "        ORG   0100H
+L0100:  CALL  L0108
+        JMP   L0100
+        DB    48H,49H
+L0108:  MVI   C,09H
+        JZ    L0110
+        LXI   D,0106H
+L0110:  DB    08H               ; NOP
+        RET
+        DB    00H,0FFH
+        END
+"
+    );
+}
+
+#[test]
+fn source_assembles_back_to_the_same_bytes() {
+    let mut disassembly = Disassembly::new(&PROGRAM, 0x0100, &[0x0100]);
+    assert_eq!(
+        reassemble(&disassembly.to_source()),
+        (0x0100, PROGRAM.to_vec())
+    );
+
+    // with names from a symbol table, inside the program and out
+    let mut symbols = SymbolTable::new();
+    symbols.insert("MAIN", 0x0100);
+    symbols.insert("GREETING", 0x0106);
+    symbols.insert("PRINT", 0x0108);
+    symbols.insert("BDOS", 0x0005);
+    disassembly.use_symbols(&symbols);
+    let source = disassembly.to_source();
+    assert!(source.contains("GREETING: DB"), "{}", source);
+    assert!(source.contains("CALL  PRINT"), "{}", source);
+    assert_eq!(reassemble(&source), (0x0100, PROGRAM.to_vec()));
+
+    // names outside the program become EQUs, however long
+    let call = [0xCD, 0x05, 0x00, 0xC9];
+    let mut disassembly = Disassembly::new(&call, 0x0100, &[0x0100]);
+    let mut symbols = SymbolTable::new();
+    symbols.insert("BDOSENTRY", 0x0005);
+    disassembly.use_symbols(&symbols);
+    let source = disassembly.to_source();
+    assert!(source.starts_with("BDOSENTRY EQU   0005H\n"), "{}", source);
+    assert_eq!(reassemble(&source), (0x0100, call.to_vec()));
+
+    // every byte value, as code where it decodes
+    let everything: Vec<u8> = (0..=255).collect();
+    let disassembly = Disassembly::new(&everything, 0x1000, &[0x1000]);
+    assert_eq!(reassemble(&disassembly.to_source()), (0x1000, everything));
+}
+
+// 12 aliases of NOP, JMP, RET and CALL
+const UNDOCUMENTED: [u8; 12] = [
+    0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0xCB, 0xD9, 0xDD, 0xED, 0xFD,
+];
+
+// bytes, mnemonic, operands, branch target
+type Case = (&'static [u8], &'static str, &'static [Operand], Option<u16>);
+
+#[test]
+fn decoded_operands() {
+    use Operand::*;
+
+    let cases: [Case; 13] = [
+        (&[0x00], "NOP", &[], None),
+        (&[0x7E], "MOV", &[Register("A"), Register("M")], None),
+        (&[0x3E, 0x12], "MVI", &[Register("A"), Byte(0x12)], None),
+        (&[0xDB, 0x10], "IN", &[Byte(0x10)], None),
+        (
+            &[0x01, 0x34, 0x12],
+            "LXI",
+            &[Register("B"), Word(0x1234)],
+            None,
+        ),
+        (
+            &[0x31, 0x00, 0xF0],
+            "LXI",
+            &[Register("SP"), Word(0xF000)],
+            None,
+        ),
+        (&[0xF5], "PUSH", &[Register("PSW")], None),
+        // memory addresses aren't branch targets
+        (&[0x32, 0x00, 0x20], "STA", &[Address(0x2000)], None),
+        (&[0x2A, 0xFE, 0xFF], "LHLD", &[Address(0xFFFE)], None),
+        (&[0xC3, 0x00, 0x01], "JMP", &[Address(0x0100)], Some(0x0100)),
+        (&[0xDC, 0x34, 0x12], "CC", &[Address(0x1234)], Some(0x1234)),
+        (&[0xFF], "RST", &[Restart(7)], Some(0x0038)),
+        // PCHL goes somewhere, but nobody knows where from the bytes
+        (&[0xE9], "PCHL", &[], None),
+    ];
+
+    for (bytes, mnemonic, operands, target) in cases.iter() {
+        let instruction = decode(bytes, 0x0100, 0x0100).unwrap();
+        assert_eq!(instruction.address, 0x0100);
+        assert_eq!(instruction.opcode, bytes[0]);
+        assert_eq!(instruction.mnemonic, *mnemonic, "{:02X}", bytes[0]);
+        assert_eq!(instruction.operands, *operands, "{:02X}", bytes[0]);
+        assert_eq!(instruction.bytes, *bytes);
+        assert_eq!(instruction.length(), bytes.len() as u16);
+        assert_eq!(instruction.next_address(), 0x0100 + bytes.len() as u16);
+        assert_eq!(instruction.target, *target, "{:02X}", bytes[0]);
+        assert!(!instruction.undocumented);
+    }
+}
+
+#[test]
+fn every_opcode_decodes_to_its_length() {
+    for opcode in 0..=255u8 {
+        let instruction = decode_with(0x4000, |address| match address {
+            0x4000 => opcode,
+            _ => 0xAA,
+        });
+        let length = instruction_length(opcode);
+
+        assert_eq!(instruction.length(), length, "{:02X}", opcode);
+        assert!(instruction.bytes[1..].iter().all(|&byte| byte == 0xAA));
+        // the operand bytes show up in exactly one operand
+        let immediate = instruction
+            .operands
+            .iter()
+            .filter(|operand| match operand {
+                Operand::Byte(value) => *value == 0xAA,
+                Operand::Word(value) | Operand::Address(value) => *value == 0xAAAA,
+                _ => false,
+            })
+            .count();
+        assert_eq!(immediate, (length > 1) as usize, "{:02X}", opcode);
+        assert_eq!(
+            instruction.undocumented,
+            UNDOCUMENTED.contains(&opcode),
+            "{:02X}",
+            opcode
+        );
+    }
+}
+
+#[test]
+fn undocumented_opcodes_decode_as_what_they_do() {
+    let decoded = |bytes: &[u8]| decode(bytes, 0, 0).unwrap();
+
+    for opcode in &UNDOCUMENTED[..7] {
+        let nop = decoded(&[*opcode]);
+        assert_eq!((nop.mnemonic, nop.length()), ("NOP", 1));
+        assert!(nop.operands.is_empty());
+    }
+
+    let jmp = decoded(&[0xCB, 0x00, 0x02]);
+    assert_eq!((jmp.mnemonic, jmp.target), ("JMP", Some(0x0200)));
+    assert_eq!(jmp.operands, [Operand::Address(0x0200)]);
+
+    let ret = decoded(&[0xD9]);
+    assert_eq!((ret.mnemonic, ret.length()), ("RET", 1));
+
+    for opcode in &[0xDD, 0xED, 0xFD] {
+        let call = decoded(&[*opcode, 0x34, 0x12]);
+        assert_eq!((call.mnemonic, call.target), ("CALL", Some(0x1234)));
+        assert!(call.undocumented);
+    }
+
+    // the documented ones aren't flagged
+    assert!(!decoded(&[0xC3, 0, 0]).undocumented);
+    assert!(!decoded(&[0xC9]).undocumented);
+    assert!(!decoded(&[0xCD, 0, 0]).undocumented);
+}
+
+#[test]
+fn decode_stays_inside_the_code() {
+    let code = [0x3E, 0x01, 0xC3, 0x00];
+
+    assert_eq!(decode(&code, 0x0100, 0x0100).unwrap().length(), 2);
+    // before and after the code
+    assert_eq!(decode(&code, 0x0100, 0x00FF), None);
+    assert_eq!(decode(&code, 0x0100, 0x0104), None);
+    // a JMP missing its last byte
+    assert_eq!(decode(&code, 0x0100, 0x0102), None);
+    assert_eq!(decode(&[], 0x0000, 0x0000), None);
+}
+
+#[test]
+fn operands_wrap_around_the_top_of_memory() {
+    let instruction = decode_with(0xFFFF, |address| match address {
+        0xFFFF => 0xC3,
+        0x0000 => 0x34,
+        0x0001 => 0x12,
+        _ => 0x00,
+    });
+    assert_eq!(instruction.bytes, [0xC3, 0x34, 0x12]);
+    assert_eq!(instruction.target, Some(0x1234));
+    assert_eq!(instruction.next_address(), 0x0002);
+}
+
+// LXI H,1234H, counting the CPU's reads
+struct Counted {
+    reads: Rc<Cell<usize>>,
+}
+
+impl MemoryDevice for Counted {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.reads.set(self.reads.get() + 1);
+        self.peek(offset)
+    }
+
+    fn write(&mut self, _offset: u16, _value: u8) {}
+
+    fn peek(&self, offset: u16) -> u8 {
+        [0x21, 0x34, 0x12][offset as usize % 3]
+    }
+}
+
+#[test]
+fn decode_memory_only_peeks() {
+    let reads = Rc::new(Cell::new(0));
+    let mut memory = MappedMemory::new();
+    memory.map_device(
+        0x8000,
+        0x8002,
+        Counted {
+            reads: reads.clone(),
+        },
+    );
+
+    let instruction = decode_memory(&memory, 0x8000);
+    assert_eq!(instruction.mnemonic, "LXI");
+    assert_eq!(
+        instruction.operands,
+        [Operand::Register("H"), Operand::Word(0x1234)]
+    );
+    assert_eq!(reads.get(), 0);
+}
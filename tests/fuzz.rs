@@ -0,0 +1,1042 @@
+// Differential fuzzing: random machine states and short random programs are run on the core and on
+// `Reference`, a second 8080 written as plainly as possible from the manual (decoding the opcode's
+// bit fields rather than a table of 256 cases, flags from the arithmetic rather than helpers). They
+// are compared after every instruction, and a case where they disagree is shrunk to as few
+// instructions, bytes of memory and non-zero registers as still show the difference.
+//
+// A run is repeatable from its seed, more cases or a different seed can be asked for with
+//
+//     I8080_FUZZ_CASES=100000 I8080_FUZZ_SEED=12345 cargo test --release --test fuzz
+use std::cell::RefCell;
+use std::env;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use intel_8080_emu::disassembler::decode_with;
+use intel_8080_emu::memory::MemoryBus;
+use intel_8080_emu::ports::PortBus;
+use intel_8080_emu::state::{CpuState, State8080};
+
+const DEFAULT_CASES: u64 = 3000;
+const DEFAULT_SEED: u64 = 0x8080_8080;
+
+const MAX_INSTRUCTIONS: usize = 8;
+
+// what IN reads, the same made-up device on both sides
+fn input(port: u8) -> u8 {
+    port.rotate_left(3) ^ 0x5A
+}
+
+// xorshift64*, no need for anything better
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn byte(&mut self) -> u8 {
+        (self.next() >> 32) as u8
+    }
+
+    fn word(&mut self) -> u16 {
+        (self.next() >> 32) as u16
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() >> 33) as usize % n
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    // values near the edges come up a lot more than they would by chance
+    fn interesting_byte(&mut self) -> u8 {
+        const EDGES: [u8; 10] = [0x00, 0x01, 0x0F, 0x10, 0x7F, 0x80, 0x99, 0x9A, 0xFE, 0xFF];
+        if self.chance(30) {
+            EDGES[self.below(EDGES.len())]
+        } else {
+            self.byte()
+        }
+    }
+
+    fn interesting_word(&mut self) -> u16 {
+        const EDGES: [u16; 6] = [0x0000, 0x0001, 0x00FF, 0x7FFF, 0xFFFE, 0xFFFF];
+        if self.chance(20) {
+            EDGES[self.below(EDGES.len())]
+        } else {
+            self.word()
+        }
+    }
+}
+
+// clears one of a case's registers
+type Zero = dyn Fn(&mut CpuState);
+
+#[derive(Clone, Debug)]
+struct Case {
+    registers: CpuState,
+    // instructions, one after the other from PC
+    program: Vec<Vec<u8>>,
+    // more memory, under the program where they overlap
+    data: Vec<(u16, u8)>,
+}
+
+impl Case {
+    fn generate(rng: &mut Rng) -> Case {
+        let mut registers = State8080::default().cpu_state();
+        registers.a = rng.interesting_byte();
+        registers.b = rng.interesting_byte();
+        registers.c = rng.interesting_byte();
+        registers.d = rng.interesting_byte();
+        registers.e = rng.interesting_byte();
+        registers.h = rng.interesting_byte();
+        registers.l = rng.interesting_byte();
+        registers.sp = rng.interesting_word();
+        registers.pc = rng.interesting_word();
+        registers.flags = rng.byte() & 0xD5 | 0x02;
+        registers.int_enable = rng.chance(50);
+
+        let mut program = Vec::new();
+        for _ in 0..1 + rng.below(MAX_INSTRUCTIONS) {
+            let opcode = rng.byte();
+            let mut instruction = vec![opcode];
+            for _ in 1..length(opcode) {
+                instruction.push(rng.interesting_byte());
+            }
+            program.push(instruction);
+        }
+
+        // something to read around where the register pairs point
+        let mut data = Vec::new();
+        let pairs = [
+            u16::from_be_bytes([registers.b, registers.c]),
+            u16::from_be_bytes([registers.d, registers.e]),
+            u16::from_be_bytes([registers.h, registers.l]),
+            registers.sp,
+        ];
+        for pair in pairs {
+            for offset in 0..4u16 {
+                data.push((pair.wrapping_add(offset).wrapping_sub(2), rng.byte()));
+            }
+        }
+
+        Case {
+            registers,
+            program,
+            data,
+        }
+    }
+
+    fn memory(&self) -> Vec<u8> {
+        let mut memory = vec![0; 0x10000];
+        for &(address, value) in &self.data {
+            memory[address as usize] = value;
+        }
+        let mut address = self.registers.pc;
+        for byte in self.program.iter().flatten() {
+            memory[address as usize] = *byte;
+            address = address.wrapping_add(1);
+        }
+        memory
+    }
+
+    // the same case with something taken out or zeroed, the simplest changes first
+    fn simplifications(&self) -> Vec<Case> {
+        let mut cases = Vec::new();
+        let mut with = |change: &dyn Fn(&mut Case)| {
+            let mut case = self.clone();
+            change(&mut case);
+            cases.push(case);
+        };
+
+        for i in (0..self.program.len()).rev() {
+            if self.program.len() > 1 {
+                with(&|case| {
+                    case.program.remove(i);
+                });
+            }
+        }
+        for i in 0..self.data.len() {
+            with(&|case| {
+                case.data.remove(i);
+            });
+        }
+
+        let registers = self.registers;
+        let zeroed: [(bool, &Zero); 11] = [
+            (registers.a != 0, &|cpu| cpu.a = 0),
+            (registers.b != 0, &|cpu| cpu.b = 0),
+            (registers.c != 0, &|cpu| cpu.c = 0),
+            (registers.d != 0, &|cpu| cpu.d = 0),
+            (registers.e != 0, &|cpu| cpu.e = 0),
+            (registers.h != 0, &|cpu| cpu.h = 0),
+            (registers.l != 0, &|cpu| cpu.l = 0),
+            (registers.sp != 0, &|cpu| cpu.sp = 0),
+            (registers.pc != 0, &|cpu| cpu.pc = 0),
+            (registers.flags != 0x02, &|cpu| cpu.flags = 0x02),
+            (registers.int_enable, &|cpu| cpu.int_enable = false),
+        ];
+        for (nonzero, zero) in zeroed {
+            if nonzero {
+                with(&|case| zero(&mut case.registers));
+            }
+        }
+        // flags one at a time, as clearing them all often hides the difference
+        for bit in [0x80, 0x40, 0x10, 0x04, 0x01] {
+            if registers.flags & bit != 0 {
+                with(&|case| case.registers.flags &= !bit);
+            }
+        }
+
+        for (i, &(_, value)) in self.data.iter().enumerate() {
+            if value != 0 {
+                with(&|case| case.data[i].1 = 0);
+            }
+        }
+        for (i, instruction) in self.program.iter().enumerate() {
+            for (j, &operand) in instruction.iter().enumerate().skip(1) {
+                if operand != 0 {
+                    with(&|case| case.program[i][j] = 0);
+                }
+            }
+        }
+
+        cases
+    }
+}
+
+// what an instruction left behind, compared between the two
+#[derive(Clone, Debug, PartialEq)]
+struct Snapshot {
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    h: u8,
+    l: u8,
+    sp: u16,
+    pc: u16,
+    flags: u8,
+    int_enable: bool,
+    halted: bool,
+    states: u32,
+    writes: Vec<(u16, u8)>,
+    outputs: Vec<(u8, u8)>,
+}
+
+impl Snapshot {
+    // one line per field that differs
+    fn differences(&self, core: &Snapshot) -> Vec<String> {
+        // the order an instruction writes its bytes in doesn't matter, only where they end up
+        let sorted = |writes: &[(u16, u8)]| {
+            let mut writes = writes.to_vec();
+            writes.sort_by_key(|&(address, _)| address);
+            writes
+        };
+        let mut differences = Vec::new();
+        let mut differ = |name: &str, reference: String, core: String| {
+            if reference != core {
+                differences.push(format!("{}: reference {}, core {}", name, reference, core));
+            }
+        };
+
+        let registers = [
+            ("A", self.a, core.a),
+            ("B", self.b, core.b),
+            ("C", self.c, core.c),
+            ("D", self.d, core.d),
+            ("E", self.e, core.e),
+            ("H", self.h, core.h),
+            ("L", self.l, core.l),
+            ("F", self.flags, core.flags),
+        ];
+        for (name, reference, core) in registers {
+            differ(name, format!("{:02X}", reference), format!("{:02X}", core));
+        }
+        differ("SP", format!("{:04X}", self.sp), format!("{:04X}", core.sp));
+        differ("PC", format!("{:04X}", self.pc), format!("{:04X}", core.pc));
+        differ(
+            "INTE",
+            self.int_enable.to_string(),
+            core.int_enable.to_string(),
+        );
+        differ("HALT", self.halted.to_string(), core.halted.to_string());
+        differ("states", self.states.to_string(), core.states.to_string());
+        differ(
+            "writes",
+            format!("{:02X?}", sorted(&self.writes)),
+            format!("{:02X?}", sorted(&core.writes)),
+        );
+        differ(
+            "OUT",
+            format!("{:02X?}", self.outputs),
+            format!("{:02X?}", core.outputs),
+        );
+
+        differences
+    }
+}
+
+// ---- the reference model ----
+
+struct Reference {
+    // B C D E H L (M) A, as the 3-bit register fields number them
+    registers: [u8; 8],
+    sp: u16,
+    pc: u16,
+    s: bool,
+    z: bool,
+    ac: bool,
+    p: bool,
+    cy: bool,
+    int_enable: bool,
+    halted: bool,
+    memory: Vec<u8>,
+    writes: Vec<(u16, u8)>,
+    outputs: Vec<(u8, u8)>,
+    // for checking the harness itself: complement CY after this opcode
+    planted_bug: Option<u8>,
+}
+
+const A: usize = 7;
+const M: usize = 6;
+
+impl Reference {
+    fn new(case: &Case) -> Reference {
+        let cpu = &case.registers;
+        let mut reference = Reference {
+            registers: [cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, 0, cpu.a],
+            sp: cpu.sp,
+            pc: cpu.pc,
+            s: false,
+            z: false,
+            ac: false,
+            p: false,
+            cy: false,
+            int_enable: cpu.int_enable,
+            halted: false,
+            memory: case.memory(),
+            writes: Vec::new(),
+            outputs: Vec::new(),
+            planted_bug: None,
+        };
+        reference.set_flags(cpu.flags);
+        reference
+    }
+
+    fn flags(&self) -> u8 {
+        (self.s as u8) << 7
+            | (self.z as u8) << 6
+            | (self.ac as u8) << 4
+            | (self.p as u8) << 2
+            | 0x02
+            | self.cy as u8
+    }
+
+    fn set_flags(&mut self, flags: u8) {
+        self.s = flags & 0x80 != 0;
+        self.z = flags & 0x40 != 0;
+        self.ac = flags & 0x10 != 0;
+        self.p = flags & 0x04 != 0;
+        self.cy = flags & 0x01 != 0;
+    }
+
+    fn snapshot(&mut self, states: u32) -> Snapshot {
+        let r = self.registers;
+        Snapshot {
+            a: r[A],
+            b: r[0],
+            c: r[1],
+            d: r[2],
+            e: r[3],
+            h: r[4],
+            l: r[5],
+            sp: self.sp,
+            pc: self.pc,
+            flags: self.flags(),
+            int_enable: self.int_enable,
+            halted: self.halted,
+            states,
+            writes: self.writes.split_off(0),
+            outputs: self.outputs.split_off(0),
+        }
+    }
+
+    fn load(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn store(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+        self.writes.push((address, value));
+    }
+
+    fn fetch(&mut self) -> u8 {
+        let byte = self.load(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        byte
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        let low = self.fetch();
+        let high = self.fetch();
+        u16::from_le_bytes([low, high])
+    }
+
+    fn hl(&self) -> u16 {
+        u16::from_be_bytes([self.registers[4], self.registers[5]])
+    }
+
+    fn register(&self, r: usize) -> u8 {
+        if r == M {
+            self.load(self.hl())
+        } else {
+            self.registers[r]
+        }
+    }
+
+    fn set_register(&mut self, r: usize, value: u8) {
+        if r == M {
+            self.store(self.hl(), value);
+        } else {
+            self.registers[r] = value;
+        }
+    }
+
+    // BC, DE, HL and SP, as the 2-bit pair fields number them
+    fn pair(&self, rp: usize) -> u16 {
+        match rp {
+            3 => self.sp,
+            _ => u16::from_be_bytes([self.registers[2 * rp], self.registers[2 * rp + 1]]),
+        }
+    }
+
+    fn set_pair(&mut self, rp: usize, value: u16) {
+        match rp {
+            3 => self.sp = value,
+            _ => [self.registers[2 * rp], self.registers[2 * rp + 1]] = value.to_be_bytes(),
+        }
+    }
+
+    fn push(&mut self, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        self.sp = self.sp.wrapping_sub(1);
+        self.store(self.sp, high);
+        self.sp = self.sp.wrapping_sub(1);
+        self.store(self.sp, low);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let low = self.load(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let high = self.load(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        u16::from_le_bytes([low, high])
+    }
+
+    // NZ Z NC C PO PE P M
+    fn condition(&self, ccc: usize) -> bool {
+        let flag = [self.z, self.cy, self.p, self.s][ccc / 2];
+        flag == (ccc % 2 == 1)
+    }
+
+    fn set_szp(&mut self, value: u8) {
+        self.s = value & 0x80 != 0;
+        self.z = value == 0;
+        self.p = value.count_ones().is_multiple_of(2);
+    }
+
+    // A + value + carry, AC is what carries into bit 4
+    fn add(&mut self, value: u8, carry: bool) {
+        let a = self.registers[A];
+        let sum = a as u16 + value as u16 + carry as u16;
+        self.ac = (a ^ value ^ sum as u8) & 0x10 != 0;
+        self.cy = sum > 0xFF;
+        self.registers[A] = sum as u8;
+        self.set_szp(sum as u8);
+    }
+
+    // the 8080 subtracts by adding the complement, CY ends up as the borrow
+    fn subtract(&mut self, value: u8, borrow: bool) {
+        let a = self.registers[A];
+        let sum = a as u16 + !value as u16 + !borrow as u16;
+        self.ac = (a ^ !value ^ sum as u8) & 0x10 != 0;
+        self.cy = sum <= 0xFF;
+        self.registers[A] = sum as u8;
+        self.set_szp(sum as u8);
+    }
+
+    // ADD ADC SUB SBB ANA XRA ORA CMP
+    fn alu(&mut self, operation: usize, value: u8) {
+        let a = self.registers[A];
+        match operation {
+            0 => self.add(value, false),
+            1 => self.add(value, self.cy),
+            2 => self.subtract(value, false),
+            3 => self.subtract(value, self.cy),
+            7 => {
+                self.subtract(value, false);
+                self.registers[A] = a;
+            }
+            _ => {
+                let result = match operation {
+                    4 => a & value,
+                    5 => a ^ value,
+                    _ => a | value,
+                };
+                // AND sets AC from bit 3 of its operands, the others clear it
+                self.ac = operation == 4 && (a | value) & 0x08 != 0;
+                self.cy = false;
+                self.registers[A] = result;
+                self.set_szp(result);
+            }
+        }
+    }
+
+    // runs one instruction and returns the states it took
+    fn step(&mut self) -> u32 {
+        // the core idles in HLT at 4 states a step
+        if self.halted {
+            return 4;
+        }
+
+        let opcode = self.fetch();
+        let states = self.execute(opcode);
+        if self.planted_bug == Some(opcode) {
+            self.cy = !self.cy;
+        }
+        states
+    }
+
+    fn execute(&mut self, opcode: u8) -> u32 {
+        // the undocumented copies of JMP, RET and CALL
+        let opcode = match opcode {
+            0xCB => 0xC3,
+            0xD9 => 0xC9,
+            0xDD | 0xED | 0xFD => 0xCD,
+            _ => opcode,
+        };
+        let ddd = (opcode >> 3 & 7) as usize;
+        let sss = (opcode & 7) as usize;
+        let rp = (opcode >> 4 & 3) as usize;
+        let memory_operand = |r: usize, register: u32, memory: u32| {
+            if r == M {
+                memory
+            } else {
+                register
+            }
+        };
+
+        match opcode {
+            0x76 => {
+                self.halted = true;
+                7
+            }
+            // MOV
+            0x40..=0x7F => {
+                let value = self.register(sss);
+                self.set_register(ddd, value);
+                if ddd == M || sss == M {
+                    7
+                } else {
+                    5
+                }
+            }
+            // ADD ... CMP with a register
+            0x80..=0xBF => {
+                let value = self.register(sss);
+                self.alu(ddd, value);
+                memory_operand(sss, 4, 7)
+            }
+            0x00..=0x3F => match sss {
+                // NOP and its undocumented copies
+                0 => 4,
+                1 if opcode & 0x08 == 0 => {
+                    let value = self.fetch_word();
+                    self.set_pair(rp, value);
+                    10
+                }
+                // DAD
+                1 => {
+                    let sum = self.pair(2) as u32 + self.pair(rp) as u32;
+                    self.cy = sum > 0xFFFF;
+                    self.set_pair(2, sum as u16);
+                    10
+                }
+                2 => match opcode {
+                    0x02 | 0x12 => {
+                        self.store(self.pair(rp), self.registers[A]);
+                        7
+                    }
+                    0x0A | 0x1A => {
+                        self.registers[A] = self.load(self.pair(rp));
+                        7
+                    }
+                    0x22 => {
+                        let address = self.fetch_word();
+                        self.store(address, self.registers[5]);
+                        self.store(address.wrapping_add(1), self.registers[4]);
+                        16
+                    }
+                    0x2A => {
+                        let address = self.fetch_word();
+                        self.registers[5] = self.load(address);
+                        self.registers[4] = self.load(address.wrapping_add(1));
+                        16
+                    }
+                    0x32 => {
+                        let address = self.fetch_word();
+                        self.store(address, self.registers[A]);
+                        13
+                    }
+                    _ => {
+                        let address = self.fetch_word();
+                        self.registers[A] = self.load(address);
+                        13
+                    }
+                },
+                // INX and DCX
+                3 => {
+                    let step = if opcode & 0x08 == 0 { 1 } else { 0xFFFF };
+                    self.set_pair(rp, self.pair(rp).wrapping_add(step));
+                    5
+                }
+                // INR
+                4 => {
+                    let value = self.register(ddd).wrapping_add(1);
+                    self.set_register(ddd, value);
+                    self.ac = value & 0x0F == 0;
+                    self.set_szp(value);
+                    memory_operand(ddd, 5, 10)
+                }
+                // DCR
+                5 => {
+                    let value = self.register(ddd).wrapping_sub(1);
+                    self.set_register(ddd, value);
+                    self.ac = value & 0x0F != 0x0F;
+                    self.set_szp(value);
+                    memory_operand(ddd, 5, 10)
+                }
+                // MVI
+                6 => {
+                    let value = self.fetch();
+                    self.set_register(ddd, value);
+                    memory_operand(ddd, 7, 10)
+                }
+                _ => {
+                    let a = self.registers[A];
+                    match ddd {
+                        // RLC RRC RAL RAR
+                        0 => {
+                            self.cy = a & 0x80 != 0;
+                            self.registers[A] = a.rotate_left(1);
+                        }
+                        1 => {
+                            self.cy = a & 0x01 != 0;
+                            self.registers[A] = a.rotate_right(1);
+                        }
+                        2 => {
+                            self.registers[A] = a << 1 | self.cy as u8;
+                            self.cy = a & 0x80 != 0;
+                        }
+                        3 => {
+                            self.registers[A] = a >> 1 | (self.cy as u8) << 7;
+                            self.cy = a & 0x01 != 0;
+                        }
+                        // DAA, both corrections in one addition
+                        4 => {
+                            let mut correction = 0;
+                            let mut carry = self.cy;
+                            if a & 0x0F > 9 || self.ac {
+                                correction += 0x06;
+                            }
+                            if a > 0x99 || self.cy {
+                                correction += 0x60;
+                                carry = true;
+                            }
+                            self.add(correction, false);
+                            self.cy = carry;
+                        }
+                        5 => self.registers[A] = !a,
+                        6 => self.cy = true,
+                        _ => self.cy = !self.cy,
+                    }
+                    4
+                }
+            },
+            0xC0..=0xFF => match sss {
+                // Rcc
+                0 => {
+                    if self.condition(ddd) {
+                        self.pc = self.pop();
+                        11
+                    } else {
+                        5
+                    }
+                }
+                1 => match opcode {
+                    0xC9 => {
+                        self.pc = self.pop();
+                        10
+                    }
+                    0xE9 => {
+                        self.pc = self.hl();
+                        5
+                    }
+                    0xF9 => {
+                        self.sp = self.hl();
+                        5
+                    }
+                    0xF1 => {
+                        let [a, flags] = self.pop().to_be_bytes();
+                        self.registers[A] = a;
+                        self.set_flags(flags);
+                        10
+                    }
+                    _ => {
+                        let value = self.pop();
+                        self.set_pair(rp, value);
+                        10
+                    }
+                },
+                // Jcc
+                2 => {
+                    let address = self.fetch_word();
+                    if self.condition(ddd) {
+                        self.pc = address;
+                    }
+                    10
+                }
+                3 => match opcode {
+                    0xC3 => {
+                        self.pc = self.fetch_word();
+                        10
+                    }
+                    0xD3 => {
+                        let port = self.fetch();
+                        self.outputs.push((port, self.registers[A]));
+                        10
+                    }
+                    0xDB => {
+                        let port = self.fetch();
+                        self.registers[A] = input(port);
+                        10
+                    }
+                    0xE3 => {
+                        let top = u16::from_le_bytes([
+                            self.load(self.sp),
+                            self.load(self.sp.wrapping_add(1)),
+                        ]);
+                        self.store(self.sp, self.registers[5]);
+                        self.store(self.sp.wrapping_add(1), self.registers[4]);
+                        self.set_pair(2, top);
+                        18
+                    }
+                    0xEB => {
+                        let (de, hl) = (self.pair(1), self.pair(2));
+                        self.set_pair(1, hl);
+                        self.set_pair(2, de);
+                        4
+                    }
+                    0xF3 => {
+                        self.int_enable = false;
+                        4
+                    }
+                    _ => {
+                        self.int_enable = true;
+                        4
+                    }
+                },
+                // Ccc
+                4 => {
+                    let address = self.fetch_word();
+                    if self.condition(ddd) {
+                        self.push(self.pc);
+                        self.pc = address;
+                        17
+                    } else {
+                        11
+                    }
+                }
+                5 => {
+                    if opcode == 0xCD {
+                        let address = self.fetch_word();
+                        self.push(self.pc);
+                        self.pc = address;
+                        17
+                    } else {
+                        let value = match opcode {
+                            0xF5 => u16::from_be_bytes([self.registers[A], self.flags()]),
+                            _ => self.pair(rp),
+                        };
+                        self.push(value);
+                        11
+                    }
+                }
+                // ADI ... CPI
+                6 => {
+                    let value = self.fetch();
+                    self.alu(ddd, value);
+                    7
+                }
+                // RST
+                _ => {
+                    self.push(self.pc);
+                    self.pc = (ddd * 8) as u16;
+                    11
+                }
+            },
+        }
+    }
+}
+
+fn length(opcode: u8) -> usize {
+    match opcode {
+        0x01 | 0x11 | 0x21 | 0x31 | 0x22 | 0x2A | 0x32 | 0x3A | 0xC3 | 0xCD => 3,
+        0xCB | 0xDD | 0xED | 0xFD => 3,
+        _ if opcode & 0xC7 == 0xC2 || opcode & 0xC7 == 0xC4 => 3,
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E | 0xD3 | 0xDB => 2,
+        _ if opcode & 0xC7 == 0xC6 => 2,
+        _ => 1,
+    }
+}
+
+// ---- the core, wired up so that its writes and OUTs can be compared ----
+
+#[derive(Clone)]
+struct LoggedMemory(Rc<RefCell<MemoryContents>>);
+
+struct MemoryContents {
+    bytes: Vec<u8>,
+    // since the last instruction
+    writes: Vec<(u16, u8)>,
+}
+
+impl MemoryBus for LoggedMemory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let mut memory = self.0.borrow_mut();
+        memory.bytes[address as usize] = value;
+        memory.writes.push((address, value));
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.0.borrow().bytes[address as usize]
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.0.borrow_mut().bytes[address as usize] = value;
+    }
+}
+
+#[derive(Default)]
+struct LoggedPorts(Vec<(u8, u8)>);
+
+impl PortBus for LoggedPorts {
+    fn input(&mut self, port: u8) -> u8 {
+        input(port)
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.0.push((port, value));
+    }
+}
+
+fn run_core(case: &Case) -> Vec<Snapshot> {
+    let memory = LoggedMemory(Rc::new(RefCell::new(MemoryContents {
+        bytes: case.memory(),
+        writes: Vec::new(),
+    })));
+    let ports = Rc::new(RefCell::new(LoggedPorts::default()));
+    let mut state = State8080::default();
+    state.attach_memory(memory.clone());
+    state.attach_ports(ports.clone());
+    state.set_cpu_state(&case.registers);
+
+    (0..case.program.len())
+        .map(|_| {
+            let states = state.emulate_cycle();
+            let cpu = state.cpu_state();
+            Snapshot {
+                a: cpu.a,
+                b: cpu.b,
+                c: cpu.c,
+                d: cpu.d,
+                e: cpu.e,
+                h: cpu.h,
+                l: cpu.l,
+                sp: cpu.sp,
+                pc: cpu.pc,
+                flags: cpu.flags,
+                int_enable: cpu.int_enable,
+                halted: cpu.halted,
+                states,
+                writes: memory.0.borrow_mut().writes.split_off(0),
+                outputs: ports.borrow_mut().0.split_off(0),
+            }
+        })
+        .collect()
+}
+
+fn run_reference(case: &Case, planted_bug: Option<u8>) -> Vec<Snapshot> {
+    let mut reference = Reference::new(case);
+    reference.planted_bug = planted_bug;
+
+    (0..case.program.len())
+        .map(|_| {
+            let states = reference.step();
+            reference.snapshot(states)
+        })
+        .collect()
+}
+
+// the first instruction the two disagree on, counting from 0, and how
+fn divergence(case: &Case, planted_bug: Option<u8>) -> Option<(usize, Vec<String>)> {
+    let core = run_core(case);
+    let reference = run_reference(case, planted_bug);
+
+    reference
+        .iter()
+        .zip(&core)
+        .enumerate()
+        .map(|(i, (reference, core))| (i, reference.differences(core)))
+        .find(|(_, differences)| !differences.is_empty())
+}
+
+fn shrink(mut case: Case, planted_bug: Option<u8>) -> Case {
+    while let Some(simpler) = case
+        .simplifications()
+        .into_iter()
+        .find(|candidate| divergence(candidate, planted_bug).is_some())
+    {
+        case = simpler;
+    }
+    case
+}
+
+// the case as something to paste into a test, and what went wrong
+fn report(case: &Case, seed: u64, planted_bug: Option<u8>) -> String {
+    let (index, differences) = divergence(case, planted_bug).expect("the case diverges");
+    let cpu = &case.registers;
+    let mut report = String::new();
+
+    writeln!(
+        report,
+        "core and reference disagree after instruction {} (seed {:X}):",
+        index + 1,
+        seed
+    )
+    .unwrap();
+    for difference in differences {
+        writeln!(report, "    {}", difference).unwrap();
+    }
+    writeln!(
+        report,
+        "registers: A={:02X} B={:02X} C={:02X} D={:02X} E={:02X} H={:02X} L={:02X} SP={:04X} F={:02X} INTE={}",
+        cpu.a, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.sp, cpu.flags, cpu.int_enable as u8
+    )
+    .unwrap();
+    let data: Vec<String> = case
+        .data
+        .iter()
+        .map(|(address, value)| format!("[{:04X}]={:02X}", address, value))
+        .collect();
+    writeln!(
+        report,
+        "memory: {}",
+        if data.is_empty() {
+            "zeroed".to_string()
+        } else {
+            data.join(" ")
+        }
+    )
+    .unwrap();
+
+    writeln!(report, "program:").unwrap();
+    let memory = case.memory();
+    let mut address = cpu.pc;
+    for instruction in &case.program {
+        let decoded = decode_with(address, |a| memory[a as usize]);
+        let bytes: Vec<String> = instruction
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        writeln!(
+            report,
+            "    {:04X}  {:<9} {}",
+            address,
+            bytes.join(" "),
+            decoded
+        )
+        .unwrap();
+        address = address.wrapping_add(instruction.len() as u16);
+    }
+
+    report
+}
+
+fn setting(variable: &str, default: u64) -> u64 {
+    match env::var(variable) {
+        Ok(value) => {
+            let value = value.trim();
+            match value.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => value.parse(),
+            }
+            .unwrap_or_else(|_| panic!("{} isn't a number", variable))
+        }
+        Err(_) => default,
+    }
+}
+
+// Generates `cases` cases from `seed` and returns the first one that diverges, shrunk.
+fn fuzz(seed: u64, cases: u64, planted_bug: Option<u8>) -> Option<Case> {
+    let mut rng = Rng::new(seed);
+
+    (0..cases)
+        .map(|_| Case::generate(&mut rng))
+        .find(|case| divergence(case, planted_bug).is_some())
+        .map(|case| shrink(case, planted_bug))
+}
+
+#[test]
+fn core_matches_reference() {
+    let seed = setting("I8080_FUZZ_SEED", DEFAULT_SEED);
+    let cases = setting("I8080_FUZZ_CASES", DEFAULT_CASES);
+
+    if let Some(case) = fuzz(seed, cases, None) {
+        panic!("{}", report(&case, seed, None));
+    }
+}
+
+// The harness itself: with a bug planted in the reference (STC leaving CY clear), the difference has
+// to be found and shrunk all the way down to a lone STC on an all-zero machine.
+#[test]
+fn divergences_are_shrunk() {
+    let case = fuzz(DEFAULT_SEED, DEFAULT_CASES, Some(0x37)).expect("the planted bug is found");
+    let report = report(&case, DEFAULT_SEED, Some(0x37));
+
+    assert_eq!(case.program, [vec![0x37]], "{}", report);
+    assert!(case.data.is_empty(), "{}", report);
+    let cpu = case.registers;
+    assert_eq!(
+        (cpu.a, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.sp, cpu.pc, cpu.flags),
+        (0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02),
+        "{}",
+        report
+    );
+    assert!(report.contains("F: reference 02, core 03"), "{}", report);
+}
@@ -0,0 +1,192 @@
+// Drives the GDB stub over a real localhost connection, the way gdb would.
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use intel_8080_emu::assembler::assemble;
+use intel_8080_emu::gdb::GdbStub;
+use intel_8080_emu::state::State8080;
+
+const PROGRAM: &str = "
+        ORG   100H
+        MVI   A,12H      ; 0100
+        LXI   H,2000H    ; 0102
+LOOP:   MOV   M,A        ; 0105
+        INR   A          ; 0106
+        JMP   LOOP       ; 0107
+";
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn connect(port: u16) -> Client {
+        Client {
+            stream: TcpStream::connect(("127.0.0.1", port)).unwrap(),
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+
+        assert_eq!(self.read_byte(), b'+', "packet {:?} not acknowledged", data);
+    }
+
+    fn receive(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(
+            checksum,
+            data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        );
+
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.receive()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0u8];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+// runs the stub on a thread (the core isn't `Send`, so it's built there) and connects to it
+fn start() -> (Client, thread::JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let stub = thread::spawn(move || {
+        let mut state = State8080::default();
+        assemble(PROGRAM)
+            .unwrap()
+            .image
+            .write_to(state.memory_mut());
+        state.pc = 0x100;
+        state.sp = 0xF000;
+
+        GdbStub::new()
+            .serve(&listener, &mut state, &mut |_| Ok(true))
+            .unwrap();
+    });
+
+    (Client::connect(port), stub)
+}
+
+#[test]
+fn registers_memory_stepping_and_breakpoints() {
+    let (mut gdb, stub) = start();
+
+    assert_eq!(gdb.request("qSupported:multiprocess+"), "PacketSize=1000");
+    assert_eq!(gdb.request("?"), "S05");
+    assert_eq!(gdb.request("vMustReplyEmpty"), "");
+
+    // A F B C D E H L SP PC, F has its always-one bit set
+    assert_eq!(gdb.request("g"), "000200000000000000f00001");
+    assert_eq!(gdb.request("p9"), "0001");
+
+    // MVI A,12H
+    assert_eq!(gdb.request("s"), "S05");
+    assert_eq!(gdb.request("p0"), "12");
+    assert_eq!(gdb.request("p9"), "0201");
+
+    assert_eq!(gdb.request("Z0,105,1"), "OK");
+    assert_eq!(gdb.request("c"), "S05");
+    assert_eq!(gdb.request("p9"), "0501");
+    assert_eq!(gdb.request("p6"), "20");
+
+    // once around the loop, MOV M,A stored 12H on the way
+    assert_eq!(gdb.request("c"), "S05");
+    assert_eq!(gdb.request("m2000,1"), "12");
+    assert_eq!(gdb.request("p0"), "13");
+
+    assert_eq!(gdb.request("P0=55"), "OK");
+    assert_eq!(gdb.request("P8=3412"), "OK");
+    assert_eq!(gdb.request("g"), "550200000000200034120501");
+
+    assert_eq!(gdb.request("G000200000000000000f00001"), "OK");
+    assert_eq!(gdb.request("p9"), "0001");
+    assert_eq!(gdb.request("P9=0501"), "OK");
+
+    assert_eq!(gdb.request("M3000,3:a1b2c3"), "OK");
+    assert_eq!(gdb.request("m3000,3"), "a1b2c3");
+    assert_eq!(gdb.request("m3000,zz"), "E01");
+
+    // hardware breakpoints behave the same
+    assert_eq!(gdb.request("z0,105,1"), "OK");
+    assert_eq!(gdb.request("Z1,107,1"), "OK");
+    assert_eq!(gdb.request("c"), "S05");
+    assert_eq!(gdb.request("p9"), "0701");
+    assert_eq!(gdb.request("z1,107,1"), "OK");
+
+    // a write watchpoint reports the address
+    assert_eq!(gdb.request("P6=21"), "OK");
+    assert_eq!(gdb.request("Z2,2100,1"), "OK");
+    assert_eq!(gdb.request("c"), "T05watch:2100;");
+    assert_eq!(gdb.request("m2100,1"), "01");
+    assert_eq!(gdb.request("z2,2100,1"), "OK");
+
+    // access watchpoints say so, whether it was a write or a read (instruction fetches count)
+    assert_eq!(gdb.request("Z4,2100,1"), "OK");
+    assert_eq!(gdb.request("c"), "T05awatch:2100;");
+    assert_eq!(gdb.request("z4,2100,1"), "OK");
+    assert_eq!(gdb.request("Z4,107,1"), "OK");
+    assert_eq!(gdb.request("c"), "T05awatch:0107;");
+    assert_eq!(gdb.request("z4,107,1"), "OK");
+    assert_eq!(gdb.request("Z3,106,1"), "OK");
+    assert_eq!(gdb.request("c"), "T05rwatch:0106;");
+    assert_eq!(gdb.request("z3,106,1"), "OK");
+
+    // reads are cut short to what fits in a packet
+    assert_eq!(gdb.request("m0,ffff").len(), 0x1000);
+    assert_eq!(gdb.request("m100,800").len(), 0x1000);
+    assert_eq!(&gdb.request("m100,801")[..6], "3e1221");
+
+    gdb.send("k");
+    stub.join().unwrap();
+}
+
+#[test]
+fn interrupting_a_continue() {
+    let (mut gdb, stub) = start();
+
+    gdb.send("c");
+    gdb.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(gdb.receive(), "S02");
+
+    assert_eq!(gdb.request("D"), "OK");
+    stub.join().unwrap();
+}
+
+#[test]
+fn no_ack_mode() {
+    let (mut gdb, stub) = start();
+
+    assert_eq!(gdb.request("QStartNoAckMode"), "OK");
+
+    // from here on neither side sends acks
+    gdb.stream.write_all(b"$p9#a9").unwrap();
+    assert_eq!(gdb.read_byte(), b'$');
+    let mut rest = [0u8; 7];
+    gdb.stream.read_exact(&mut rest).unwrap();
+    assert_eq!(&rest, b"0001#c1");
+
+    gdb.stream.write_all(b"$k#6b").unwrap();
+    stub.join().unwrap();
+}
@@ -0,0 +1,193 @@
+// Screenshots: video RAM turned upright into a framebuffer, and the PPM and PNG files written from
+// it, checked byte by byte (PNG chunk CRCs and the zlib stream included).
+use std::env;
+use std::fs;
+use std::process;
+
+use intel_8080_emu::image::{write_png, write_ppm};
+use intel_8080_emu::invaders::framebuffer::{
+    Framebuffer, ImageFormat, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+use intel_8080_emu::invaders::VIDEO_RAM_SIZE;
+
+// bit by bit, nothing like the table the writer uses
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u64, 0u64), |(a, b), &byte| {
+        let a = (a + byte as u64) % 65521;
+        (a, (b + a) % 65521)
+    });
+    ((b << 16) | a) as u32
+}
+
+// the chunks of a PNG file, checking their CRCs on the way
+fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+    let mut chunks = Vec::new();
+    let mut rest = &png[8..];
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let kind = String::from_utf8(rest[4..8].to_vec()).unwrap();
+        let data = rest[8..8 + length].to_vec();
+        let crc = &rest[8 + length..12 + length];
+        let crc = u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]);
+        assert_eq!(crc, crc32(&rest[4..8 + length]), "{} CRC", kind);
+
+        chunks.push((kind, data));
+        rest = &rest[12 + length..];
+    }
+    chunks
+}
+
+// the stored blocks of a zlib stream put back together, checking the header and the checksum
+fn inflate_stored(stream: &[u8]) -> Vec<u8> {
+    // deflate, 32K window, header a multiple of 31
+    assert_eq!(stream[0], 0x78);
+    assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+
+    let mut data = Vec::new();
+    let mut rest = &stream[2..];
+    loop {
+        let last = rest[0] & 1 == 1;
+        assert_eq!(rest[0] & 0x06, 0, "not a stored block");
+        let length = u16::from_le_bytes([rest[1], rest[2]]);
+        assert_eq!(!length, u16::from_le_bytes([rest[3], rest[4]]));
+        data.extend_from_slice(&rest[5..5 + length as usize]);
+        rest = &rest[5 + length as usize..];
+        if last {
+            break;
+        }
+    }
+
+    assert_eq!(rest, adler32(&data).to_be_bytes());
+    data
+}
+
+#[test]
+fn crc_and_adler_of_the_test_itself() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+}
+
+#[test]
+fn video_ram_is_rotated_upright() {
+    let mut video_ram = vec![0; VIDEO_RAM_SIZE];
+    // the first byte of a column is the bottom of the screen, bit 0 first
+    video_ram[0] = 0x01;
+    video_ram[31] = 0x80;
+    video_ram[32 + 1] = 0x02;
+    video_ram[VIDEO_RAM_SIZE - 1] = 0x80;
+
+    let framebuffer = Framebuffer::from_video_ram(&video_ram);
+    let lit: Vec<(usize, usize)> = (0..SCREEN_HEIGHT)
+        .flat_map(|y| (0..SCREEN_WIDTH).map(move |x| (x, y)))
+        .filter(|&(x, y)| framebuffer.pixel(x, y))
+        .collect();
+    // top row first, left to right
+    assert_eq!(lit, [(0, 0), (223, 0), (1, 246), (0, 255)]);
+
+    assert_eq!(framebuffer.pixels().len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+    assert_eq!(framebuffer.pixels()[255 * SCREEN_WIDTH], 0xFF);
+    assert_eq!(framebuffer.pixels()[255 * SCREEN_WIDTH + 1], 0x00);
+}
+
+#[test]
+fn ppm() {
+    let mut out = Vec::new();
+    write_ppm(&mut out, 2, 2, &[0x00, 0xFF, 0x80, 0x01]).unwrap();
+    assert_eq!(
+        out,
+        b"P6\n2 2\n255\n\x00\x00\x00\xFF\xFF\xFF\x80\x80\x80\x01\x01\x01"
+    );
+}
+
+#[test]
+fn png() {
+    let mut out = Vec::new();
+    write_png(&mut out, 3, 2, &[1, 2, 3, 4, 5, 6]).unwrap();
+
+    let chunks = chunks(&out);
+    let kinds: Vec<&str> = chunks.iter().map(|(kind, _)| kind.as_str()).collect();
+    assert_eq!(kinds, ["IHDR", "IDAT", "IEND"]);
+
+    // 3x2, 8-bit grayscale, deflate, no filtering method variants, not interlaced
+    assert_eq!(chunks[0].1, [0, 0, 0, 3, 0, 0, 0, 2, 8, 0, 0, 0, 0]);
+    // each row behind a filter type 0
+    assert_eq!(inflate_stored(&chunks[1].1), [0, 1, 2, 3, 0, 4, 5, 6]);
+    assert!(chunks[2].1.is_empty());
+    // the one CRC everybody knows
+    assert_eq!(out[out.len() - 4..], [0xAE, 0x42, 0x60, 0x82]);
+}
+
+#[test]
+fn png_data_spans_several_blocks() {
+    // 301 bytes per row with the filter type, 90300 in all: a full block and a short one
+    let pixels: Vec<u8> = (0..300 * 300).map(|i| (i % 251) as u8).collect();
+    let mut out = Vec::new();
+    write_png(&mut out, 300, 300, &pixels).unwrap();
+
+    let idat = &chunks(&out)[1].1;
+    assert_eq!(idat[2], 0, "the first block isn't the last");
+    assert_eq!(u16::from_le_bytes([idat[3], idat[4]]), 0xFFFF);
+
+    let raw = inflate_stored(idat);
+    assert_eq!(raw.len(), 301 * 300);
+    for (row, line) in raw.chunks(301).enumerate() {
+        assert_eq!(line[0], 0);
+        assert_eq!(line[1..], pixels[row * 300..(row + 1) * 300]);
+    }
+}
+
+#[test]
+fn framebuffers_save_what_the_writers_write() {
+    let mut video_ram = vec![0; VIDEO_RAM_SIZE];
+    video_ram[100] = 0x5A;
+    let framebuffer = Framebuffer::from_video_ram(&video_ram);
+
+    for format in [ImageFormat::Ppm, ImageFormat::Png] {
+        let mut expected = Vec::new();
+        match format {
+            ImageFormat::Ppm => write_ppm(
+                &mut expected,
+                SCREEN_WIDTH,
+                SCREEN_HEIGHT,
+                framebuffer.pixels(),
+            ),
+            ImageFormat::Png => write_png(
+                &mut expected,
+                SCREEN_WIDTH,
+                SCREEN_HEIGHT,
+                framebuffer.pixels(),
+            ),
+        }
+        .unwrap();
+
+        let path = env::temp_dir().join(format!(
+            "i8080-framebuffer-{}.{}",
+            process::id(),
+            format.extension()
+        ));
+        framebuffer.save(&path, format).unwrap();
+        let saved = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(saved == expected, "{:?}", format);
+    }
+
+    // errors creating the file come back
+    let missing = env::temp_dir()
+        .join("i8080-no-such-directory")
+        .join("frame.png");
+    assert!(framebuffer.save(missing, ImageFormat::Png).is_err());
+}
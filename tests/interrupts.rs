@@ -0,0 +1,142 @@
+// Interrupts: when the CPU accepts what a device jams onto the bus, and what accepting it does to
+// INTE, HLT and the stack.
+use intel_8080_emu::state::State8080;
+
+const NOP: u8 = 0x00;
+const HLT: u8 = 0x76;
+const DI: u8 = 0xF3;
+const EI: u8 = 0xFB;
+const RST_1: u8 = 0xCF;
+const RST_2: u8 = 0xD7;
+
+// `program` at 0000 with the stack at 2000
+fn machine(program: &[u8]) -> State8080 {
+    let mut state = State8080::default();
+    for (address, byte) in program.iter().enumerate() {
+        state.memory_mut().poke(address as u16, *byte);
+    }
+    state.sp = 0x2000;
+    state
+}
+
+// where the last interrupt (or call) left its return address
+fn return_address(state: &State8080) -> u16 {
+    let memory = state.memory();
+    u16::from_le_bytes([memory.peek(state.sp), memory.peek(state.sp.wrapping_add(1))])
+}
+
+#[test]
+fn ei_holds_interrupts_off_for_one_more_instruction() {
+    let mut state = machine(&[EI, NOP, NOP, NOP]);
+    state.interrupt(RST_1);
+
+    state.emulate_cycle();
+    assert!(state.interrupts_enabled());
+    assert_eq!(state.pc, 0x0001);
+
+    // the instruction after EI still runs
+    state.emulate_cycle();
+    assert_eq!(state.pc, 0x0002);
+
+    assert_eq!(state.emulate_cycle(), 11);
+    assert_eq!(state.pc, 0x0008);
+    assert_eq!(state.sp, 0x1FFE);
+    assert_eq!(return_address(&state), 0x0002);
+}
+
+#[test]
+fn di_right_after_ei_keeps_the_interrupt_out() {
+    let mut state = machine(&[EI, DI, NOP, NOP, EI, NOP, NOP]);
+    state.interrupt(RST_1);
+
+    for pc in 1..=4 {
+        state.emulate_cycle();
+        assert_eq!(state.pc, pc);
+    }
+    assert!(!state.interrupts_enabled());
+    assert_eq!(state.sp, 0x2000);
+
+    // still pending, the next EI lets it in after one more instruction
+    assert_eq!(state.cpu_state().pending_interrupt, Some(RST_1));
+    state.emulate_cycle();
+    state.emulate_cycle();
+    state.emulate_cycle();
+    assert_eq!(state.pc, 0x0008);
+    assert_eq!(return_address(&state), 0x0006);
+}
+
+#[test]
+fn interrupts_wake_the_cpu_from_hlt() {
+    let mut state = machine(&[EI, HLT, NOP]);
+    state.emulate_cycle();
+    state.emulate_cycle();
+    assert!(state.halted());
+    assert_eq!(state.pc, 0x0002);
+
+    // nothing happens until the interrupt
+    state.emulate_cycle();
+    state.emulate_cycle();
+    assert!(state.halted());
+    assert_eq!(state.pc, 0x0002);
+
+    state.interrupt(RST_2);
+    state.emulate_cycle();
+    assert!(!state.halted());
+    assert_eq!(state.pc, 0x0010);
+    assert_eq!(return_address(&state), 0x0002);
+}
+
+#[test]
+fn hlt_with_interrupts_off_stays_halted() {
+    let mut state = machine(&[DI, HLT]);
+    state.emulate_cycle();
+    state.emulate_cycle();
+
+    state.interrupt(RST_2);
+    for _ in 0..10 {
+        state.emulate_cycle();
+    }
+    assert!(state.halted());
+    assert_eq!(state.pc, 0x0002);
+}
+
+#[test]
+fn accepting_an_interrupt_clears_inte() {
+    let mut state = machine(&[EI, NOP, NOP]);
+    // the handler at 0008 enables interrupts again
+    state.memory_mut().poke(0x0008, NOP);
+    state.memory_mut().poke(0x0009, EI);
+    state.memory_mut().poke(0x000A, NOP);
+    state.interrupt(RST_1);
+
+    state.emulate_cycle();
+    state.emulate_cycle();
+    state.emulate_cycle();
+    assert_eq!(state.pc, 0x0008);
+    assert!(!state.interrupts_enabled());
+
+    // a second request waits for the handler's EI
+    state.interrupt(RST_2);
+    state.emulate_cycle();
+    state.emulate_cycle();
+    state.emulate_cycle();
+    assert_eq!(state.pc, 0x000B);
+    state.emulate_cycle();
+    assert_eq!(state.pc, 0x0010);
+    assert_eq!(return_address(&state), 0x000B);
+}
+
+#[test]
+fn interrupts_are_ignored_while_inte_is_off() {
+    // INTE is off after reset
+    let mut state = machine(&[NOP; 16]);
+    assert!(!state.interrupts_enabled());
+    state.interrupt(RST_1);
+
+    for _ in 0..8 {
+        state.emulate_cycle();
+    }
+    assert_eq!(state.pc, 0x0008);
+    assert_eq!(state.sp, 0x2000);
+    assert_eq!(state.cpu_state().pending_interrupt, Some(RST_1));
+}
@@ -0,0 +1,213 @@
+// The Space Invaders I/O ports: the shift register, DIP switches and buttons as the game reads
+// them, and input scripts pressing the buttons on the right frames.
+use intel_8080_emu::invaders::input::InputScript;
+use intel_8080_emu::invaders::{Button, DipSwitches, Invaders, ROM_SIZE};
+use intel_8080_emu::ports::PortBus;
+
+// a board whose ROM is all NOPs, so frames run without the game touching the ports
+fn board(dips: DipSwitches) -> Invaders {
+    Invaders::new(vec![0; ROM_SIZE], dips)
+}
+
+fn input(invaders: &Invaders, port: u8) -> u8 {
+    invaders.io().borrow_mut().input(port)
+}
+
+fn output(invaders: &Invaders, port: u8, value: u8) {
+    invaders.io().borrow_mut().output(port, value);
+}
+
+#[test]
+fn shift_register() {
+    let invaders = board(DipSwitches::default());
+
+    // each write to port 4 shifts the new byte in at the top
+    output(&invaders, 4, 0xAB);
+    output(&invaders, 4, 0xCD);
+    // register is CDAB, port 2 picks how far from the top port 3 reads
+    let expected = [0xCD, 0x9B, 0x36, 0x6D, 0xDA, 0xB5, 0x6A, 0xD5];
+    for (offset, &value) in expected.iter().enumerate() {
+        output(&invaders, 2, offset as u8);
+        assert_eq!(input(&invaders, 3), value, "offset {}", offset);
+    }
+
+    // only the low 3 bits of the offset count
+    output(&invaders, 2, 0xF9);
+    assert_eq!(input(&invaders, 3), 0x9B);
+
+    // the older byte falls off the bottom
+    output(&invaders, 4, 0x12);
+    output(&invaders, 2, 0);
+    assert_eq!(input(&invaders, 3), 0x12);
+    output(&invaders, 2, 4);
+    assert_eq!(input(&invaders, 3), 0x2C);
+}
+
+#[test]
+fn shift_register_from_the_cpu() {
+    // MVI A,0F0H / OUT 4 / MVI A,0FH / OUT 4 / MVI A,2 / OUT 2 / IN 3 / HLT
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0xF0, 0xD3, 0x04,
+        0x3E, 0x0F, 0xD3, 0x04,
+        0x3E, 0x02, 0xD3, 0x02,
+        0xDB, 0x03, 0x76,
+    ];
+    let mut rom = vec![0; ROM_SIZE];
+    rom[..program.len()].copy_from_slice(&program);
+    let mut invaders = Invaders::new(rom, DipSwitches::default());
+
+    while !invaders.state.halted() {
+        invaders.state.emulate_cycle();
+    }
+    // 0FF0 shifted left by 2, top byte
+    assert_eq!(invaders.state.a, 0x3F);
+}
+
+#[test]
+fn dip_switches_on_port_2() {
+    let default = board(DipSwitches::default());
+    assert_eq!(input(&default, 2), 0x00);
+
+    for (ships, bits) in [(3, 0x00), (4, 0x01), (5, 0x02), (6, 0x03)].iter() {
+        let invaders = board(DipSwitches {
+            ships: *ships,
+            ..DipSwitches::default()
+        });
+        assert_eq!(input(&invaders, 2), *bits, "{} ships", ships);
+    }
+    // out of range counts are held to 3-6
+    let invaders = board(DipSwitches {
+        ships: 9,
+        ..DipSwitches::default()
+    });
+    assert_eq!(input(&invaders, 2), 0x03);
+
+    let invaders = board(DipSwitches {
+        ships: 3,
+        extra_ship_at_1000: true,
+        coin_info: false,
+    });
+    assert_eq!(input(&invaders, 2), 0x88);
+
+    // the player 2 controls and tilt share the port
+    let mut invaders = board(DipSwitches {
+        ships: 5,
+        ..DipSwitches::default()
+    });
+    invaders.set_button(Button::Tilt, true);
+    invaders.set_button(Button::P2Fire, true);
+    invaders.set_button(Button::P2Left, true);
+    invaders.set_button(Button::P2Right, true);
+    assert_eq!(input(&invaders, 2), 0x76);
+    invaders.set_button(Button::P2Fire, false);
+    assert_eq!(input(&invaders, 2), 0x66);
+}
+
+#[test]
+fn coin_and_start_on_port_1() {
+    let mut invaders = board(DipSwitches::default());
+    // bit 3 is always set
+    assert_eq!(input(&invaders, 1), 0x08);
+
+    let wiring = [
+        (Button::Coin, 0x01),
+        (Button::P2Start, 0x02),
+        (Button::P1Start, 0x04),
+        (Button::P1Fire, 0x10),
+        (Button::P1Left, 0x20),
+        (Button::P1Right, 0x40),
+    ];
+    for (button, bit) in wiring.iter() {
+        invaders.set_button(*button, true);
+        assert_eq!(input(&invaders, 1), 0x08 | bit, "{:?}", button);
+        // nothing on port 2
+        assert_eq!(input(&invaders, 2), 0x00, "{:?}", button);
+        invaders.set_button(*button, false);
+        assert_eq!(input(&invaders, 1), 0x08, "{:?}", button);
+    }
+
+    invaders.set_button(Button::Coin, true);
+    invaders.set_button(Button::P1Start, true);
+    assert_eq!(input(&invaders, 1), 0x0D);
+
+    // port 0 isn't wired to anything the game reads
+    assert_eq!(input(&invaders, 0), 0x0E);
+}
+
+#[test]
+fn sound_latches() {
+    let invaders = board(DipSwitches::default());
+    output(&invaders, 3, 0x05);
+    output(&invaders, 5, 0x11);
+    // the watchdog is ignored
+    output(&invaders, 6, 0xFF);
+    assert_eq!(invaders.io().borrow().sound_latches(), (0x05, 0x11));
+}
+
+#[test]
+fn button_names() {
+    assert_eq!(Button::from_name("coin"), Some(Button::Coin));
+    assert_eq!(Button::from_name("Start"), Some(Button::P1Start));
+    assert_eq!(Button::from_name("P2START"), Some(Button::P2Start));
+    assert_eq!(Button::from_name("fire"), Some(Button::P1Fire));
+    assert_eq!(Button::from_name("p2right"), Some(Button::P2Right));
+    assert_eq!(Button::from_name("tilt"), Some(Button::Tilt));
+    assert_eq!(Button::from_name("p3fire"), None);
+}
+
+#[test]
+fn input_scripts_press_buttons_on_their_frames() {
+    let mut script = InputScript::parse(
+        "\
+# insert a coin and start a one player game
+2 start down   # out of order, sorted by frame
+0   coin    tap
+
+3 start up
+",
+    )
+    .unwrap();
+    let mut invaders = board(DipSwitches::default());
+
+    // the coin goes in on frame 0 and comes out 4 frames later
+    let mut port1 = Vec::new();
+    for _ in 0..6 {
+        script.apply(&mut invaders);
+        port1.push(input(&invaders, 1));
+        invaders.run_frame().unwrap();
+    }
+    assert_eq!(port1, [0x09, 0x09, 0x0D, 0x09, 0x08, 0x08]);
+    assert!(script.is_finished());
+}
+
+#[test]
+fn bad_input_scripts() {
+    let cases = [
+        (
+            "60 coin",
+            "input script line 1: expected `<frame> <button> <down|up|tap>`",
+        ),
+        (
+            "\n60 coin tap extra",
+            "input script line 2: expected `<frame> <button> <down|up|tap>`",
+        ),
+        ("-1 coin tap", "input script line 1: bad frame number"),
+        ("sixty coin tap", "input script line 1: bad frame number"),
+        ("60 pause tap", "input script line 1: unknown button"),
+        (
+            "60 coin press",
+            "input script line 1: expected down, up or tap",
+        ),
+    ];
+
+    for (text, message) in cases.iter() {
+        match InputScript::parse(text) {
+            Err(error) => assert_eq!(error.to_string(), *message, "{:?}", text),
+            Ok(_) => panic!("{:?} parsed", text),
+        }
+    }
+
+    // comments and blank lines alone are an empty script
+    assert!(InputScript::parse("# nothing\n\n").unwrap().is_finished());
+}
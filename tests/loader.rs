@@ -0,0 +1,217 @@
+// Intel HEX loading: what makes a record bad, start addresses, records past 64K and programs that
+// would run into the BDOS.
+use intel_8080_emu::cpm::bdos::{check_in_tpa, BDOS_ENTRY, TPA_END, TPA_START};
+use intel_8080_emu::loader::{Image, LoadError, Segment};
+
+// one record with its checksum worked out
+fn record(kind: u8, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8, (address >> 8) as u8, address as u8, kind];
+    bytes.extend_from_slice(data);
+    let sum: u32 = bytes.iter().map(|&byte| byte as u32).sum();
+    bytes.push((0x100 - sum % 0x100) as u8);
+
+    let digits: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(":{}\n", digits.concat())
+}
+
+const EOF: &str = ":00000001FF\n";
+
+fn syntax_reason(result: Result<Image, LoadError>) -> (usize, String) {
+    match result {
+        Err(LoadError::Syntax { line, reason }) => (line, reason),
+        other => panic!("expected a syntax error, got {:?}", other),
+    }
+}
+
+#[test]
+fn data_records() {
+    let text = record(0x00, 0x0100, &[1, 2, 3])
+        + &record(0x00, 0x0103, &[4])
+        + &record(0x00, 0x0200, &[5, 6])
+        + EOF;
+    let image = Image::from_hex(&text).unwrap();
+
+    // the first two follow on from each other
+    assert_eq!(
+        image.segments,
+        [
+            Segment {
+                address: 0x0100,
+                data: vec![1, 2, 3, 4],
+            },
+            Segment {
+                address: 0x0200,
+                data: vec![5, 6],
+            },
+        ]
+    );
+    assert_eq!(image.start, None);
+    assert_eq!(Image::from_hex(&image.to_hex()).unwrap(), image);
+}
+
+#[test]
+fn bad_checksums() {
+    let text = record(0x00, 0x0100, &[0xAA]) + ":01010100AA00\n" + EOF;
+    match Image::from_hex(&text) {
+        Err(LoadError::Checksum {
+            line,
+            expected,
+            found,
+        }) => {
+            assert_eq!(line, 2);
+            assert_eq!(expected, 0x53);
+            assert_eq!(found, 0x00);
+        }
+        other => panic!("expected a checksum error, got {:?}", other),
+    }
+}
+
+#[test]
+fn the_end_of_file_record_is_required() {
+    let text = record(0x00, 0x0100, &[1]) + "\n" + &record(0x00, 0x0101, &[2]);
+    assert_eq!(
+        syntax_reason(Image::from_hex(&text)),
+        (3, "missing end of file record".to_string())
+    );
+
+    // and has to be last
+    let text = record(0x00, 0x0100, &[1]) + EOF + &record(0x00, 0x0101, &[2]);
+    assert_eq!(
+        syntax_reason(Image::from_hex(&text)),
+        (3, "record after the end of file record".to_string())
+    );
+}
+
+#[test]
+fn malformed_records() {
+    let cases = [
+        ("00000001FF", "record doesn't start with ':'"),
+        (":0000001FF", "bad hex digits"),
+        (":00000001FG", "bad hex digits"),
+        // `+1` would read as 01 if signs got through
+        (":01000000+1FE", "bad hex digits"),
+        (":0100000001FE", ""),
+        (
+            ":02000000010000FD",
+            "record length doesn't match its byte count",
+        ),
+        (":000000", "record length doesn't match its byte count"),
+        (":0100000301FB", "wrong data length for the record type"),
+        (":00000006FA", "unknown record type"),
+    ];
+
+    for (line, reason) in cases.iter() {
+        let result = Image::from_hex(&(line.to_string() + "\n" + EOF));
+        if reason.is_empty() {
+            assert!(result.is_ok(), "{}: {:?}", line, result);
+        } else {
+            assert_eq!(syntax_reason(result), (1, reason.to_string()), "{}", line);
+        }
+    }
+}
+
+#[test]
+fn start_address_records() {
+    // type 03 is CS:IP
+    let text = record(0x00, 0x0100, &[0]) + &record(0x03, 0, &[0x00, 0x10, 0x00, 0x20]) + EOF;
+    assert_eq!(Image::from_hex(&text).unwrap().start, Some(0x0120));
+
+    // type 05 is a 32-bit linear address
+    let text = record(0x05, 0, &[0x00, 0x00, 0xE4, 0x00]) + EOF;
+    assert_eq!(Image::from_hex(&text).unwrap().start, Some(0xE400));
+
+    // both have to point somewhere in the 64K
+    let text = record(0x03, 0, &[0x10, 0x00, 0x00, 0x00]) + EOF;
+    assert!(matches!(
+        Image::from_hex(&text),
+        Err(LoadError::Overflow {
+            address: 0x10000,
+            len: 1
+        })
+    ));
+    let text = record(0x05, 0, &[0x00, 0x01, 0x00, 0x00]) + EOF;
+    assert!(matches!(
+        Image::from_hex(&text),
+        Err(LoadError::Overflow {
+            address: 0x10000,
+            len: 1
+        })
+    ));
+}
+
+#[test]
+fn records_past_ffff() {
+    // right up to the last byte is fine
+    let text = record(0x00, 0xFFFE, &[1, 2]) + EOF;
+    assert_eq!(Image::from_hex(&text).unwrap().segments[0].address, 0xFFFE);
+
+    // one more isn't
+    let text = record(0x00, 0xFFFE, &[1, 2, 3]) + EOF;
+    assert!(matches!(
+        Image::from_hex(&text),
+        Err(LoadError::Overflow {
+            address: 0xFFFE,
+            len: 3
+        })
+    ));
+
+    // nor is anything an extended address moves above 64K
+    let text = record(0x04, 0, &[0x00, 0x01]) + &record(0x00, 0x0000, &[1]) + EOF;
+    assert!(matches!(
+        Image::from_hex(&text),
+        Err(LoadError::Overflow {
+            address: 0x10000,
+            len: 1
+        })
+    ));
+    let text = record(0x02, 0, &[0x10, 0x00]) + &record(0x00, 0x0000, &[1]) + EOF;
+    assert!(matches!(
+        Image::from_hex(&text),
+        Err(LoadError::Overflow {
+            address: 0x10000,
+            len: 1
+        })
+    ));
+
+    // while an extended address of 0 changes nothing
+    let text = record(0x04, 0, &[0x00, 0x00]) + &record(0x00, 0x0100, &[1]) + EOF;
+    assert_eq!(Image::from_hex(&text).unwrap().segments[0].address, 0x0100);
+
+    assert!(Image::from_binary(vec![0; 0x100], 0xFF00).is_ok());
+    assert!(matches!(
+        Image::from_binary(vec![0; 0x101], 0xFF00),
+        Err(LoadError::Overflow {
+            address: 0xFF00,
+            len: 0x101
+        })
+    ));
+}
+
+#[test]
+fn programs_have_to_stay_clear_of_the_bdos() {
+    let image = |address: u16, len: usize| {
+        Image::from_hex(&(record(0x00, address, &vec![0; len]) + EOF)).unwrap()
+    };
+
+    assert!(check_in_tpa(&image(TPA_START, 16)).is_ok());
+    // the last byte below the stack the BDOS sets up
+    assert!(check_in_tpa(&image(TPA_END - 16, 16)).is_ok());
+
+    // over the stack, or the BDOS entry itself
+    assert!(check_in_tpa(&image(TPA_END - 15, 16)).is_err());
+    assert!(check_in_tpa(&image(BDOS_ENTRY, 1)).is_err());
+    // and page zero
+    assert!(check_in_tpa(&image(0x0000, 1)).is_err());
+    assert!(check_in_tpa(&image(TPA_START - 1, 1)).is_err());
+
+    // every segment counts, not just the first
+    let text = record(0x00, TPA_START, &[0]) + &record(0x00, 0xFE00, &[0; 16]) + EOF;
+    let error = check_in_tpa(&Image::from_hex(&text).unwrap()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "16 bytes at FE00h don't fit in the TPA (0100h-{:04X}h)",
+            TPA_END - 1
+        )
+    );
+}
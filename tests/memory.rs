@@ -0,0 +1,176 @@
+// Address decoding in `MappedMemory`: which region an address lands in, mirrors, ROM, the open bus,
+// and devices only seeing side effects from the CPU's own accesses.
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use intel_8080_emu::memory::{MappedMemory, MemoryBus, MemoryDevice, OPEN_BUS};
+
+// counts its accesses, peek and poke reach `latch` without counting
+#[derive(Default)]
+struct Latch {
+    latch: u8,
+    reads: usize,
+    writes: usize,
+}
+
+impl MemoryDevice for Latch {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.reads += 1;
+        self.latch.wrapping_add(offset as u8)
+    }
+
+    fn write(&mut self, _offset: u16, value: u8) {
+        self.writes += 1;
+        self.latch = value;
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        self.latch.wrapping_add(offset as u8)
+    }
+
+    fn poke(&mut self, _offset: u16, value: u8) {
+        self.latch = value;
+    }
+}
+
+// a device with nothing to peek or poke
+struct WriteOnly(Rc<RefCell<Vec<u8>>>);
+
+impl MemoryDevice for WriteOnly {
+    fn read(&mut self, _offset: u16) -> u8 {
+        0
+    }
+
+    fn write(&mut self, _offset: u16, value: u8) {
+        self.0.borrow_mut().push(value);
+    }
+}
+
+#[test]
+fn regions_mapped_later_win() {
+    let mut memory = MappedMemory::new();
+    memory.map_ram(0x0000, 0x3FFF);
+    memory.map_rom(0x1000, vec![0xAA; 0x100]);
+
+    memory.write(0x0FFF, 1);
+    memory.write(0x1000, 2);
+    memory.write(0x1100, 3);
+    assert_eq!(memory.read(0x0FFF), 1);
+    assert_eq!(memory.read(0x1000), 0xAA);
+    assert_eq!(memory.read(0x10FF), 0xAA);
+    assert_eq!(memory.read(0x1100), 3);
+}
+
+#[test]
+fn rom_ignores_writes_but_not_pokes() {
+    let mut memory = MappedMemory::new();
+    memory.map_rom(0x0000, vec![1, 2, 3, 4]);
+
+    memory.write(0x0001, 0xFF);
+    assert_eq!(memory.read(0x0001), 2);
+
+    memory.poke(0x0001, 0xFF);
+    assert_eq!(memory.read(0x0001), 0xFF);
+    assert_eq!(memory.peek(0x0001), 0xFF);
+}
+
+#[test]
+fn unmapped_addresses_are_open_bus() {
+    let mut memory = MappedMemory::new();
+    memory.map_ram(0x2000, 0x2FFF);
+
+    memory.write(0x1000, 0x12);
+    memory.poke(0x1001, 0x34);
+    assert_eq!(memory.read(0x1000), OPEN_BUS);
+    assert_eq!(memory.peek(0x1001), OPEN_BUS);
+    assert_eq!(memory.read(0xFFFF), OPEN_BUS);
+
+    memory.set_open_bus(0x00);
+    assert_eq!(memory.read(0x1000), 0x00);
+    assert_eq!(memory.peek(0x3000), 0x00);
+}
+
+#[test]
+fn mirrors_repeat_their_target() {
+    // 1K of RAM at 0000, seen four times over in 0400-0FFF and partly at F000
+    let mut memory = MappedMemory::new();
+    memory.map_ram(0x0000, 0x03FF);
+    memory.map_mirror(0x0400, 0x0FFF, 0x0000, 0x0400);
+    memory.map_mirror(0xF000, 0xF0FF, 0x0100, 0x0080);
+
+    memory.write(0x0123, 0x55);
+    assert_eq!(memory.read(0x0523), 0x55);
+    assert_eq!(memory.read(0x0D23), 0x55);
+    assert_eq!(memory.peek(0x0923), 0x55);
+
+    // writes go through to the target too
+    memory.write(0x0FFF, 0x66);
+    assert_eq!(memory.read(0x03FF), 0x66);
+    memory.poke(0x0800, 0x77);
+    assert_eq!(memory.read(0x0000), 0x77);
+
+    // offsets wrap at the mirror's size
+    memory.write(0x0110, 0x88);
+    assert_eq!(memory.read(0xF010), 0x88);
+    assert_eq!(memory.read(0xF090), 0x88);
+}
+
+#[test]
+fn mirrors_of_mirrors_and_of_nothing_are_open_bus() {
+    let mut memory = MappedMemory::new();
+    memory.map_ram(0x0000, 0x00FF);
+    memory.map_mirror(0x0100, 0x01FF, 0x0000, 0x0100);
+    memory.map_mirror(0x0200, 0x02FF, 0x0100, 0x0100);
+    memory.map_mirror(0x0300, 0x03FF, 0x8000, 0x0100);
+
+    memory.write(0x0010, 0x42);
+    assert_eq!(memory.read(0x0110), 0x42);
+    assert_eq!(memory.read(0x0210), OPEN_BUS);
+    assert_eq!(memory.read(0x0310), OPEN_BUS);
+
+    memory.write(0x0210, 0x01);
+    memory.write(0x0310, 0x01);
+    assert_eq!(memory.read(0x0010), 0x42);
+}
+
+#[test]
+fn devices_see_only_cpu_accesses() {
+    let latch = Rc::new(RefCell::new(Latch::default()));
+    let mut memory = MappedMemory::new();
+    memory.map_ram(0x0000, 0xFFFF);
+    memory.map_device(0x8000, 0x800F, latch.clone());
+
+    memory.write(0x8000, 0x10);
+    assert_eq!(memory.read(0x8003), 0x13);
+    assert_eq!((latch.borrow().reads, latch.borrow().writes), (1, 1));
+
+    // tooling reaches the device's state without it noticing
+    memory.poke(0x8000, 0x20);
+    assert_eq!(memory.peek(0x8005), 0x25);
+    assert_eq!(latch.borrow().latch, 0x20);
+    assert_eq!((latch.borrow().reads, latch.borrow().writes), (1, 1));
+
+    // around it is still RAM
+    memory.write(0x8010, 0x99);
+    assert_eq!(memory.read(0x8010), 0x99);
+}
+
+#[test]
+fn devices_ignore_pokes_by_default() {
+    let written = Rc::new(RefCell::new(Vec::new()));
+    let mut memory = MappedMemory::new();
+    memory.map_device(0x0000, 0x0000, WriteOnly(written.clone()));
+
+    memory.poke(0x0000, 1);
+    assert_eq!(memory.peek(0x0000), OPEN_BUS);
+    assert!(written.borrow().is_empty());
+
+    memory.write(0x0000, 2);
+    assert_eq!(*written.borrow(), [2]);
+}
+
+#[test]
+#[should_panic(expected = "region end 0fff is before its start 1000")]
+fn backwards_ram_is_refused() {
+    MappedMemory::new().map_ram(0x1000, 0x0FFF);
+}
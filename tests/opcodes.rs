@@ -0,0 +1,632 @@
+// One instruction at a time: every opcode is run from a known state and the whole machine is
+// compared against what the 8080 manual says should come out of it (registers, SP, PC, each flag,
+// all 64K of memory, port writes and the number of states).
+//
+// Cases are written as `KEY=value` lists, values in hex:
+//
+//     A B C D E H L        registers
+//     BC DE HL SP PC       register pairs
+//     F=SZAPC              the flags that are set, `F=-` for none
+//     [2000]=12            a byte of memory, `[2000]=1234` is a little-endian word (34 at 2000)
+//     IN[10]=5A            what the device at port 10 returns
+//     OUT[10]=5A           an OUT expected to port 10
+//     INTE=1 HALT=1        the interrupt-enable flip-flop and the HLT state
+//
+// The state before starts out all zero with the code at 0100h (or at `PC`). The state after starts
+// out as the state before with PC past the code, so it only lists what the instruction changes.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use intel_8080_emu::ports::PortBus;
+use intel_8080_emu::state::{CpuState, State8080};
+
+const START: u16 = 0x0100;
+
+// name, code, state before, changes after, states
+#[rustfmt::skip]
+const CASES: &[(&str, &[u8], &str, &str, u32)] = &[
+    // ---- 0x00 ----
+    ("NOP", &[0x00], "A=12 F=SZAPC", "", 4),
+    ("LXI B", &[0x01, 0x34, 0x12], "", "BC=1234", 10),
+    ("STAX B", &[0x02], "A=5A BC=2000", "[2000]=5A", 7),
+    ("INX B", &[0x03], "BC=12FF", "BC=1300", 5),
+    ("INX B wraps", &[0x03], "BC=FFFF", "BC=0000", 5),
+    ("INR B", &[0x04], "B=0F", "B=10 F=A", 5),
+    ("INR B to zero keeps CY", &[0x04], "B=FF F=C", "B=00 F=ZAPC", 5),
+    ("DCR B", &[0x05], "B=01", "B=00 F=ZAP", 5),
+    ("DCR B from zero", &[0x05], "B=00 F=ZC", "B=FF F=SPC", 5),
+    ("MVI B", &[0x06, 0x42], "", "B=42", 7),
+    ("RLC", &[0x07], "A=85 F=Z", "A=0B F=ZC", 4),
+    ("RLC clears CY", &[0x07], "A=42 F=C", "A=84 F=-", 4),
+    ("*NOP 08", &[0x08], "A=12 F=SZAPC", "", 4),
+    ("DAD B", &[0x09], "BC=1111 HL=2222 F=C", "HL=3333 F=-", 10),
+    ("DAD B carries", &[0x09], "BC=0001 HL=FFFF F=SZ", "HL=0000 F=SZC", 10),
+    ("LDAX B", &[0x0A], "BC=2000 [2000]=77", "A=77", 7),
+    ("DCX B", &[0x0B], "BC=1200", "BC=11FF", 5),
+    ("DCX B wraps", &[0x0B], "BC=0000", "BC=FFFF", 5),
+    ("INR C", &[0x0C], "C=7F", "C=80 F=SA", 5),
+    ("DCR C", &[0x0D], "C=10", "C=0F F=P", 5),
+    ("MVI C", &[0x0E, 0x42], "", "C=42", 7),
+    ("RRC", &[0x0F], "A=01", "A=80 F=C", 4),
+    ("RRC clears CY", &[0x0F], "A=02 F=SC", "A=01 F=S", 4),
+    // ---- 0x10 ----
+    ("*NOP 10", &[0x10], "A=12 F=SZAPC", "", 4),
+    ("LXI D", &[0x11, 0x34, 0x12], "", "DE=1234", 10),
+    ("STAX D", &[0x12], "A=5A DE=2000", "[2000]=5A", 7),
+    ("INX D", &[0x13], "DE=00FF", "DE=0100", 5),
+    ("INX D wraps", &[0x13], "DE=FFFF F=-", "DE=0000", 5),
+    ("INR D", &[0x14], "D=3F", "D=40 F=A", 5),
+    ("DCR D", &[0x15], "D=80 F=SZAP", "D=7F F=-", 5),
+    ("MVI D", &[0x16, 0x42], "", "D=42", 7),
+    ("RAL", &[0x17], "A=80", "A=00 F=C", 4),
+    ("RAL shifts CY in", &[0x17], "A=40 F=C", "A=81 F=-", 4),
+    ("*NOP 18", &[0x18], "A=12 F=SZAPC", "", 4),
+    ("DAD D", &[0x19], "DE=8000 HL=8000", "HL=0000 F=C", 10),
+    ("LDAX D", &[0x1A], "DE=2000 [2000]=77", "A=77", 7),
+    ("DCX D", &[0x1B], "DE=0000", "DE=FFFF", 5),
+    ("INR E", &[0x1C], "E=00 F=ZP", "E=01 F=-", 5),
+    ("DCR E", &[0x1D], "E=03", "E=02 F=A", 5),
+    ("MVI E", &[0x1E, 0x42], "", "E=42", 7),
+    ("RAR", &[0x1F], "A=01", "A=00 F=C", 4),
+    ("RAR shifts CY in", &[0x1F], "A=02 F=C", "A=81 F=-", 4),
+    // ---- 0x20 ----
+    ("*NOP 20", &[0x20], "A=12 F=SZAPC", "", 4),
+    ("LXI H", &[0x21, 0x34, 0x12], "", "HL=1234", 10),
+    ("SHLD", &[0x22, 0x00, 0x20], "HL=1234", "[2000]=1234", 16),
+    ("SHLD wraps", &[0x22, 0xFF, 0xFF], "HL=1234", "[FFFF]=34 [0000]=12", 16),
+    ("INX H", &[0x23], "HL=FFFF", "HL=0000", 5),
+    ("INR H", &[0x24], "H=A5", "H=A6 F=SP", 5),
+    ("DCR H", &[0x25], "H=A6", "H=A5 F=SAP", 5),
+    ("MVI H", &[0x26, 0x42], "", "H=42", 7),
+    ("DAA both nibbles", &[0x27], "A=9B", "A=01 F=AC", 4),
+    ("DAA low nibble only", &[0x27], "A=81 F=A", "A=87 F=SP", 4),
+    ("DAA high nibble only", &[0x27], "A=00 F=C", "A=60 F=PC", 4),
+    ("DAA to zero", &[0x27], "A=9A", "A=00 F=ZAPC", 4),
+    ("DAA nothing to do", &[0x27], "A=42 F=Z", "A=42 F=P", 4),
+    ("*NOP 28", &[0x28], "A=12 F=SZAPC", "", 4),
+    ("DAD H", &[0x29], "HL=8001", "HL=0002 F=C", 10),
+    ("LHLD", &[0x2A, 0x00, 0x20], "[2000]=1234", "HL=1234", 16),
+    ("LHLD wraps", &[0x2A, 0xFF, 0xFF], "[FFFF]=34 [0000]=12", "HL=1234", 16),
+    ("DCX H", &[0x2B], "HL=0000", "HL=FFFF", 5),
+    ("INR L", &[0x2C], "L=FE", "L=FF F=SP", 5),
+    ("DCR L", &[0x2D], "L=11", "L=10 F=A", 5),
+    ("MVI L", &[0x2E, 0x42], "", "L=42", 7),
+    ("CMA", &[0x2F], "A=51 F=ZC", "A=AE", 4),
+    // ---- 0x30 ----
+    ("*NOP 30", &[0x30], "A=12 F=SZAPC", "", 4),
+    ("LXI SP", &[0x31, 0x34, 0x12], "", "SP=1234", 10),
+    ("STA", &[0x32, 0x00, 0x20], "A=5A", "[2000]=5A", 13),
+    ("INX SP", &[0x33], "SP=12FF", "SP=1300", 5),
+    ("INX SP wraps", &[0x33], "SP=FFFF", "SP=0000", 5),
+    ("INR M", &[0x34], "HL=2000 [2000]=0F", "[2000]=10 F=A", 10),
+    ("DCR M", &[0x35], "HL=2000 [2000]=01", "[2000]=00 F=ZAP", 10),
+    ("MVI M", &[0x36, 0x42], "HL=2000", "[2000]=42", 10),
+    ("STC", &[0x37], "F=SZ", "F=SZC", 4),
+    ("*NOP 38", &[0x38], "A=12 F=SZAPC", "", 4),
+    ("DAD SP", &[0x39], "HL=1234 SP=1111", "HL=2345", 10),
+    ("LDA", &[0x3A, 0x00, 0x20], "[2000]=77", "A=77", 13),
+    ("DCX SP", &[0x3B], "SP=1200", "SP=11FF", 5),
+    ("DCX SP wraps", &[0x3B], "SP=0000", "SP=FFFF", 5),
+    ("INR A", &[0x3C], "A=FF", "A=00 F=ZAP", 5),
+    ("DCR A", &[0x3D], "A=00 F=C", "A=FF F=SPC", 5),
+    ("MVI A", &[0x3E, 0x42], "", "A=42", 7),
+    ("CMC sets", &[0x3F], "F=Z", "F=ZC", 4),
+    ("CMC clears", &[0x3F], "F=SC", "F=S", 4),
+    // ---- 0x40 to 0x7F are MOVs and HLT, see `mov_cases` ----
+    ("HLT", &[0x76], "A=12 F=SZAPC", "HALT=1", 7),
+    // ---- 0x80 ----
+    ("ADD B", &[0x80], "A=12 B=34 F=SZAPC", "A=46 F=-", 4),
+    ("ADD C", &[0x81], "A=0F C=01", "A=10 F=A", 4),
+    ("ADD D", &[0x82], "A=80 D=80", "A=00 F=ZPC", 4),
+    ("ADD E", &[0x83], "A=7F E=01", "A=80 F=SA", 4),
+    ("ADD H", &[0x84], "A=FF H=FF", "A=FE F=SAC", 4),
+    ("ADD L", &[0x85], "A=01 L=02 F=C", "A=03 F=P", 4),
+    ("ADD M", &[0x86], "A=3A HL=2000 [2000]=C6", "A=00 F=ZAPC", 7),
+    ("ADD A", &[0x87], "A=88", "A=10 F=AC", 4),
+    ("ADC B", &[0x88], "A=12 B=34 F=C", "A=47 F=P", 4),
+    ("ADC C", &[0x89], "A=0F C=00 F=C", "A=10 F=A", 4),
+    ("ADC D", &[0x8A], "A=FF D=00 F=C", "A=00 F=ZAPC", 4),
+    ("ADC E", &[0x8B], "A=70 E=0F F=C", "A=80 F=SA", 4),
+    ("ADC H", &[0x8C], "A=C0 H=50", "A=10 F=C", 4),
+    ("ADC L", &[0x8D], "A=01 L=01 F=C", "A=03 F=P", 4),
+    ("ADC M", &[0x8E], "A=3A HL=2000 [2000]=C5 F=C", "A=00 F=ZAPC", 7),
+    ("ADC A", &[0x8F], "A=88 F=C", "A=11 F=APC", 4),
+    // ---- 0x90 ----
+    ("SUB B", &[0x90], "A=3E B=3E F=SC", "A=00 F=ZAP", 4),
+    ("SUB C", &[0x91], "A=10 C=01", "A=0F F=P", 4),
+    ("SUB D", &[0x92], "A=00 D=01", "A=FF F=SPC", 4),
+    ("SUB E", &[0x93], "A=80 E=01", "A=7F F=-", 4),
+    ("SUB H", &[0x94], "A=45 H=23", "A=22 F=AP", 4),
+    ("SUB L", &[0x95], "A=01 L=80", "A=81 F=SAPC", 4),
+    ("SUB M", &[0x96], "A=12 HL=2000 [2000]=34", "A=DE F=SPC", 7),
+    ("SUB A", &[0x97], "A=5A F=C", "A=00 F=ZAP", 4),
+    ("SBB B", &[0x98], "A=12 B=01 F=C", "A=10 F=A", 4),
+    ("SBB C", &[0x99], "A=10 C=00 F=C", "A=0F F=P", 4),
+    ("SBB D", &[0x9A], "A=00 D=FF F=C", "A=00 F=ZPC", 4),
+    ("SBB E", &[0x9B], "A=00 E=00 F=C", "A=FF F=SPC", 4),
+    ("SBB H", &[0x9C], "A=77 H=33 F=C", "A=43 F=A", 4),
+    ("SBB L", &[0x9D], "A=FF L=FE F=C", "A=00 F=ZAP", 4),
+    ("SBB M", &[0x9E], "A=12 HL=2000 [2000]=34 F=C", "A=DD F=SPC", 7),
+    ("SBB A", &[0x9F], "A=5A F=C", "A=FF F=SPC", 4),
+    ("SBB A without carry", &[0x9F], "A=5A", "A=00 F=ZAP", 4),
+    // ---- 0xA0 ----
+    ("ANA B", &[0xA0], "A=FC B=0F F=C", "A=0C F=AP", 4),
+    ("ANA C", &[0xA1], "A=F0 C=F0", "A=F0 F=SP", 4),
+    ("ANA D", &[0xA2], "A=F0 D=0F", "A=00 F=ZAP", 4),
+    ("ANA E", &[0xA3], "A=81 E=83", "A=81 F=SP", 4),
+    ("ANA H", &[0xA4], "A=07 H=03 F=A", "A=03 F=P", 4),
+    ("ANA L", &[0xA5], "A=08 L=00", "A=00 F=ZAP", 4),
+    ("ANA M", &[0xA6], "A=1F HL=2000 [2000]=31", "A=11 F=AP", 7),
+    ("ANA A", &[0xA7], "A=70 F=C", "A=70 F=-", 4),
+    ("XRA B", &[0xA8], "A=FF B=0F F=SZAPC", "A=F0 F=SP", 4),
+    ("XRA C", &[0xA9], "A=5A C=5A", "A=00 F=ZP", 4),
+    ("XRA D", &[0xAA], "A=01 D=02", "A=03 F=P", 4),
+    ("XRA E", &[0xAB], "A=80 E=00", "A=80 F=S", 4),
+    ("XRA H", &[0xAC], "A=12 H=21", "A=33 F=P", 4),
+    ("XRA L", &[0xAD], "A=0F L=01 F=A", "A=0E F=-", 4),
+    ("XRA M", &[0xAE], "A=AA HL=2000 [2000]=55", "A=FF F=SP", 7),
+    ("XRA A", &[0xAF], "A=77 F=SAC", "A=00 F=ZP", 4),
+    // ---- 0xB0 ----
+    ("ORA B", &[0xB0], "A=0F B=F0 F=AC", "A=FF F=SP", 4),
+    ("ORA C", &[0xB1], "A=00 C=00", "A=00 F=ZP", 4),
+    ("ORA D", &[0xB2], "A=01 D=02", "A=03 F=P", 4),
+    ("ORA E", &[0xB3], "A=40 E=00 F=Z", "A=40 F=-", 4),
+    ("ORA H", &[0xB4], "A=80 H=01", "A=81 F=SP", 4),
+    ("ORA L", &[0xB5], "A=10 L=0C", "A=1C F=-", 4),
+    ("ORA M", &[0xB6], "A=03 HL=2000 [2000]=0C", "A=0F F=P", 7),
+    ("ORA A", &[0xB7], "A=00 F=C", "A=00 F=ZP", 4),
+    ("CMP B", &[0xB8], "A=0A B=05", "F=AP", 4),
+    ("CMP C", &[0xB9], "A=05 C=0A", "F=SC", 4),
+    ("CMP D", &[0xBA], "A=42 D=42 F=C", "F=ZAP", 4),
+    ("CMP E", &[0xBB], "A=00 E=01", "F=SPC", 4),
+    ("CMP H", &[0xBC], "A=F0 H=10", "F=SA", 4),
+    ("CMP L", &[0xBD], "A=02 L=FF", "F=PC", 4),
+    ("CMP M", &[0xBE], "A=80 HL=2000 [2000]=7F F=Z", "F=-", 7),
+    ("CMP A", &[0xBF], "A=99 F=C", "F=ZAP", 4),
+    // ---- 0xC0 ----
+    ("RNZ taken", &[0xC0], "SP=EFFE [EFFE]=1234", "PC=1234 SP=F000", 11),
+    ("RNZ not taken", &[0xC0], "SP=EFFE [EFFE]=1234 F=Z", "", 5),
+    ("POP B", &[0xC1], "SP=EFFE [EFFE]=1234", "BC=1234 SP=F000", 10),
+    ("POP B wraps", &[0xC1], "SP=FFFF [FFFF]=34 [0000]=12", "BC=1234 SP=0001", 10),
+    ("JNZ taken", &[0xC2, 0x00, 0x20], "", "PC=2000", 10),
+    ("JNZ not taken", &[0xC2, 0x00, 0x20], "F=Z", "", 10),
+    ("JMP", &[0xC3, 0x00, 0x20], "", "PC=2000", 10),
+    ("CNZ taken", &[0xC4, 0x00, 0x20], "SP=F000", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("CNZ not taken", &[0xC4, 0x00, 0x20], "SP=F000 F=Z", "", 11),
+    ("PUSH B", &[0xC5], "BC=1234 SP=F000", "SP=EFFE [EFFE]=1234", 11),
+    ("PUSH B wraps", &[0xC5], "BC=1234 SP=0001", "SP=FFFF [FFFF]=34 [0000]=12", 11),
+    ("ADI", &[0xC6, 0xC6], "A=3A", "A=00 F=ZAPC", 7),
+    ("RST 0", &[0xC7], "SP=F000", "PC=0000 SP=EFFE [EFFE]=0101", 11),
+    ("RZ taken", &[0xC8], "SP=EFFE [EFFE]=1234 F=Z", "PC=1234 SP=F000", 11),
+    ("RZ not taken", &[0xC8], "SP=EFFE [EFFE]=1234", "", 5),
+    ("RET", &[0xC9], "SP=EFFE [EFFE]=1234", "PC=1234 SP=F000", 10),
+    ("RET wraps", &[0xC9], "SP=FFFF [FFFF]=34 [0000]=12", "PC=1234 SP=0001", 10),
+    ("JZ taken", &[0xCA, 0x00, 0x20], "F=Z", "PC=2000", 10),
+    ("JZ not taken", &[0xCA, 0x00, 0x20], "", "", 10),
+    ("*JMP CB", &[0xCB, 0x00, 0x20], "", "PC=2000", 10),
+    ("CZ taken", &[0xCC, 0x00, 0x20], "SP=F000 F=Z", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("CZ not taken", &[0xCC, 0x00, 0x20], "SP=F000", "", 11),
+    ("CALL", &[0xCD, 0x00, 0x20], "SP=F000", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("CALL wraps", &[0xCD, 0x00, 0x20], "SP=0001", "PC=2000 SP=FFFF [FFFF]=03 [0000]=01", 17),
+    ("ACI", &[0xCE, 0xC5], "A=3A F=C", "A=00 F=ZAPC", 7),
+    ("RST 1", &[0xCF], "SP=F000", "PC=0008 SP=EFFE [EFFE]=0101", 11),
+    // ---- 0xD0 ----
+    ("RNC taken", &[0xD0], "SP=EFFE [EFFE]=1234", "PC=1234 SP=F000", 11),
+    ("RNC not taken", &[0xD0], "SP=EFFE [EFFE]=1234 F=C", "", 5),
+    ("POP D", &[0xD1], "SP=EFFE [EFFE]=1234", "DE=1234 SP=F000", 10),
+    ("JNC taken", &[0xD2, 0x00, 0x20], "", "PC=2000", 10),
+    ("JNC not taken", &[0xD2, 0x00, 0x20], "F=C", "", 10),
+    ("CNC taken", &[0xD4, 0x00, 0x20], "SP=F000", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("CNC not taken", &[0xD4, 0x00, 0x20], "SP=F000 F=C", "", 11),
+    ("OUT", &[0xD3, 0x10], "A=5A", "OUT[10]=5A", 10),
+    ("PUSH D", &[0xD5], "DE=1234 SP=F000", "SP=EFFE [EFFE]=1234", 11),
+    ("SUI", &[0xD6, 0x01], "A=00", "A=FF F=SPC", 7),
+    ("RST 2", &[0xD7], "SP=F000", "PC=0010 SP=EFFE [EFFE]=0101", 11),
+    ("RC taken", &[0xD8], "SP=EFFE [EFFE]=1234 F=C", "PC=1234 SP=F000", 11),
+    ("RC not taken", &[0xD8], "SP=EFFE [EFFE]=1234", "", 5),
+    ("*RET D9", &[0xD9], "SP=EFFE [EFFE]=1234", "PC=1234 SP=F000", 10),
+    ("JC taken", &[0xDA, 0x00, 0x20], "F=C", "PC=2000", 10),
+    ("JC not taken", &[0xDA, 0x00, 0x20], "", "", 10),
+    ("IN", &[0xDB, 0x10], "IN[10]=5A", "A=5A", 10),
+    ("CC taken", &[0xDC, 0x00, 0x20], "SP=F000 F=C", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("CC not taken", &[0xDC, 0x00, 0x20], "SP=F000", "", 11),
+    ("*CALL DD", &[0xDD, 0x00, 0x20], "SP=F000", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("SBI", &[0xDE, 0xFF], "A=00 F=C", "A=00 F=ZPC", 7),
+    ("RST 3", &[0xDF], "SP=F000", "PC=0018 SP=EFFE [EFFE]=0101", 11),
+    // ---- 0xE0 ----
+    ("RPO taken", &[0xE0], "SP=EFFE [EFFE]=1234", "PC=1234 SP=F000", 11),
+    ("RPO not taken", &[0xE0], "SP=EFFE [EFFE]=1234 F=P", "", 5),
+    ("POP H", &[0xE1], "SP=EFFE [EFFE]=1234", "HL=1234 SP=F000", 10),
+    ("JPO taken", &[0xE2, 0x00, 0x20], "", "PC=2000", 10),
+    ("JPO not taken", &[0xE2, 0x00, 0x20], "F=P", "", 10),
+    ("XTHL", &[0xE3], "HL=1234 SP=EFFE [EFFE]=5678", "HL=5678 [EFFE]=1234", 18),
+    ("XTHL wraps", &[0xE3], "HL=1234 SP=FFFF [FFFF]=78 [0000]=56", "HL=5678 [FFFF]=34 [0000]=12", 18),
+    ("CPO taken", &[0xE4, 0x00, 0x20], "SP=F000", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("CPO not taken", &[0xE4, 0x00, 0x20], "SP=F000 F=P", "", 11),
+    ("PUSH H", &[0xE5], "HL=1234 SP=F000", "SP=EFFE [EFFE]=1234", 11),
+    ("ANI", &[0xE6, 0x0F], "A=FC F=C", "A=0C F=AP", 7),
+    ("ANI clears AC", &[0xE6, 0x70], "A=F0 F=A", "A=70 F=-", 7),
+    ("RST 4", &[0xE7], "SP=F000", "PC=0020 SP=EFFE [EFFE]=0101", 11),
+    ("RPE taken", &[0xE8], "SP=EFFE [EFFE]=1234 F=P", "PC=1234 SP=F000", 11),
+    ("RPE not taken", &[0xE8], "SP=EFFE [EFFE]=1234", "", 5),
+    ("PCHL", &[0xE9], "HL=1234", "PC=1234", 5),
+    ("JPE taken", &[0xEA, 0x00, 0x20], "F=P", "PC=2000", 10),
+    ("JPE not taken", &[0xEA, 0x00, 0x20], "", "", 10),
+    ("XCHG", &[0xEB], "DE=1234 HL=5678", "DE=5678 HL=1234", 4),
+    ("CPE taken", &[0xEC, 0x00, 0x20], "SP=F000 F=P", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("CPE not taken", &[0xEC, 0x00, 0x20], "SP=F000", "", 11),
+    ("*CALL ED", &[0xED, 0x00, 0x20], "SP=F000", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("XRI", &[0xEE, 0x0F], "A=FF F=AC", "A=F0 F=SP", 7),
+    ("RST 5", &[0xEF], "SP=F000", "PC=0028 SP=EFFE [EFFE]=0101", 11),
+    // ---- 0xF0 ----
+    ("RP taken", &[0xF0], "SP=EFFE [EFFE]=1234", "PC=1234 SP=F000", 11),
+    ("RP not taken", &[0xF0], "SP=EFFE [EFFE]=1234 F=S", "", 5),
+    ("POP PSW", &[0xF1], "SP=EFFE [EFFE]=12FF", "A=12 F=SZAPC SP=F000", 10),
+    ("POP PSW clears", &[0xF1], "SP=EFFE [EFFE]=3400 F=SZAPC", "A=34 F=- SP=F000", 10),
+    ("JP taken", &[0xF2, 0x00, 0x20], "", "PC=2000", 10),
+    ("JP not taken", &[0xF2, 0x00, 0x20], "F=S", "", 10),
+    ("DI", &[0xF3], "INTE=1", "INTE=0", 4),
+    ("CP taken", &[0xF4, 0x00, 0x20], "SP=F000", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("CP not taken", &[0xF4, 0x00, 0x20], "SP=F000 F=S", "", 11),
+    ("PUSH PSW", &[0xF5], "A=12 SP=F000 F=SZAPC", "SP=EFFE [EFFE]=12D7", 11),
+    ("PUSH PSW no flags", &[0xF5], "A=12 SP=F000", "SP=EFFE [EFFE]=1202", 11),
+    ("ORI", &[0xF6, 0x0F], "A=F0 F=AC", "A=FF F=SP", 7),
+    ("RST 6", &[0xF7], "SP=F000", "PC=0030 SP=EFFE [EFFE]=0101", 11),
+    ("RM taken", &[0xF8], "SP=EFFE [EFFE]=1234 F=S", "PC=1234 SP=F000", 11),
+    ("RM not taken", &[0xF8], "SP=EFFE [EFFE]=1234", "", 5),
+    ("SPHL", &[0xF9], "HL=1234", "SP=1234", 5),
+    ("JM taken", &[0xFA, 0x00, 0x20], "F=S", "PC=2000", 10),
+    ("JM not taken", &[0xFA, 0x00, 0x20], "", "", 10),
+    ("EI", &[0xFB], "", "INTE=1", 4),
+    ("CM taken", &[0xFC, 0x00, 0x20], "SP=F000 F=S", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("CM not taken", &[0xFC, 0x00, 0x20], "SP=F000", "", 11),
+    ("*CALL FD", &[0xFD, 0x00, 0x20], "SP=F000", "PC=2000 SP=EFFE [EFFE]=0103", 17),
+    ("CPI", &[0xFE, 0x05], "A=0A", "F=AP", 7),
+    ("CPI borrows", &[0xFE, 0x0A], "A=05", "F=SC", 7),
+    ("RST 7", &[0xFF], "SP=F000", "PC=0038 SP=EFFE [EFFE]=0101", 11),
+    // ---- instructions running off the end of memory ----
+    ("MVI A at FFFF", &[0x3E, 0x42], "PC=FFFF", "A=42 PC=0001", 7),
+    ("LXI B at FFFE", &[0x01, 0x34, 0x12], "PC=FFFE", "BC=1234 PC=0001", 10),
+    ("JMP at FFFF", &[0xC3, 0x00, 0x20], "PC=FFFF", "PC=2000", 10),
+    ("CALL at FFFE", &[0xCD, 0x00, 0x20], "PC=FFFE SP=F000", "PC=2000 SP=EFFE [EFFE]=0001", 17),
+    ("RST 7 at FFFF", &[0xFF], "PC=FFFF SP=F000", "PC=0038 SP=EFFE [EFFE]=0000", 11),
+];
+
+// the eight operands of MOV and the ALU group, in encoding order
+const OPERANDS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "M", "A"];
+
+// all the MOVs (0x40 to 0x7F but HLT) from a state with a different value in every register
+fn mov_cases() -> Vec<Case> {
+    const VALUES: [u8; 8] = [0x0B, 0x0C, 0x0D, 0x0E, 0x20, 0x00, 0x4D, 0x0A];
+    let before = "B=0B C=0C D=0D E=0E H=20 L=00 [2000]=4D A=0A F=SZAPC";
+
+    let mut cases = Vec::new();
+    for (dst, dst_name) in OPERANDS.iter().enumerate() {
+        for (src, src_name) in OPERANDS.iter().enumerate() {
+            if *dst_name == "M" && *src_name == "M" {
+                continue; // HLT
+            }
+
+            let after = match *dst_name {
+                "M" => format!("[2000]={:02X}", VALUES[src]),
+                _ => format!("{}={:02X}", dst_name, VALUES[src]),
+            };
+            let memory = *dst_name == "M" || *src_name == "M";
+            cases.push(Case {
+                name: format!("MOV {},{}", dst_name, src_name),
+                code: vec![0x40 | (dst as u8) << 3 | src as u8],
+                before: before.to_string(),
+                after,
+                cycles: if memory { 7 } else { 5 },
+            });
+        }
+    }
+    cases
+}
+
+struct Case {
+    name: String,
+    code: Vec<u8>,
+    before: String,
+    after: String,
+    cycles: u32,
+}
+
+fn cases() -> Vec<Case> {
+    let mut cases: Vec<Case> = CASES
+        .iter()
+        .map(|&(name, code, before, after, cycles)| Case {
+            name: name.to_string(),
+            code: code.to_vec(),
+            before: before.to_string(),
+            after: after.to_string(),
+            cycles,
+        })
+        .collect();
+    cases.extend(mov_cases());
+    cases
+}
+
+// the I/O ports as a case sees them
+#[derive(Default)]
+struct TestPorts {
+    inputs: HashMap<u8, u8>,
+    outputs: Vec<(u8, u8)>,
+}
+
+impl PortBus for TestPorts {
+    fn input(&mut self, port: u8) -> u8 {
+        self.inputs.get(&port).copied().unwrap_or(0xFF)
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.outputs.push((port, value));
+    }
+}
+
+// a whole machine as the `KEY=value` lists describe it
+#[derive(Clone)]
+struct Machine {
+    cpu: CpuState,
+    memory: Vec<u8>,
+    inputs: HashMap<u8, u8>,
+    outputs: Vec<(u8, u8)>,
+}
+
+impl Machine {
+    fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for item in spec.split_whitespace() {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("{:?} isn't KEY=value", item))?;
+            let byte =
+                || u8::from_str_radix(value, 16).map_err(|_| format!("bad byte in {:?}", item));
+            let word =
+                || u16::from_str_radix(value, 16).map_err(|_| format!("bad word in {:?}", item));
+
+            match key {
+                "A" => self.cpu.a = byte()?,
+                "B" => self.cpu.b = byte()?,
+                "C" => self.cpu.c = byte()?,
+                "D" => self.cpu.d = byte()?,
+                "E" => self.cpu.e = byte()?,
+                "H" => self.cpu.h = byte()?,
+                "L" => self.cpu.l = byte()?,
+                "BC" => [self.cpu.b, self.cpu.c] = word()?.to_be_bytes(),
+                "DE" => [self.cpu.d, self.cpu.e] = word()?.to_be_bytes(),
+                "HL" => [self.cpu.h, self.cpu.l] = word()?.to_be_bytes(),
+                "SP" => self.cpu.sp = word()?,
+                "PC" => self.cpu.pc = word()?,
+                "F" => self.cpu.flags = parse_flags(value)?,
+                "INTE" => self.cpu.int_enable = value == "1",
+                "HALT" => self.cpu.halted = value == "1",
+                _ if key.starts_with('[') && key.ends_with(']') => {
+                    let address = u16::from_str_radix(&key[1..key.len() - 1], 16)
+                        .map_err(|_| format!("bad address in {:?}", item))?;
+                    let bytes = match value.len() {
+                        2 => vec![byte()?],
+                        4 => word()?.to_le_bytes().to_vec(),
+                        _ => return Err(format!("{:?} is neither a byte nor a word", item)),
+                    };
+                    for (i, byte) in bytes.into_iter().enumerate() {
+                        self.memory[address.wrapping_add(i as u16) as usize] = byte;
+                    }
+                }
+                _ if key.starts_with("IN[") && key.ends_with(']') => {
+                    let port = u8::from_str_radix(&key[3..key.len() - 1], 16)
+                        .map_err(|_| format!("bad port in {:?}", item))?;
+                    self.inputs.insert(port, byte()?);
+                }
+                _ if key.starts_with("OUT[") && key.ends_with(']') => {
+                    let port = u8::from_str_radix(&key[4..key.len() - 1], 16)
+                        .map_err(|_| format!("bad port in {:?}", item))?;
+                    self.outputs.push((port, byte()?));
+                }
+                _ => return Err(format!("unknown key in {:?}", item)),
+            }
+        }
+        Ok(())
+    }
+}
+
+// `SZAPC` letters to the flags byte as PUSH PSW stores it
+fn parse_flags(letters: &str) -> Result<u8, String> {
+    let mut flags = 0x02;
+    for letter in letters.chars() {
+        flags |= match letter {
+            'S' => 0x80,
+            'Z' => 0x40,
+            'A' => 0x10,
+            'P' => 0x04,
+            'C' => 0x01,
+            '-' => 0x00,
+            _ => return Err(format!("unknown flag {:?}", letter)),
+        };
+    }
+    Ok(flags)
+}
+
+fn flag_letters(flags: u8) -> String {
+    let letters: String = [
+        (0x80, 'S'),
+        (0x40, 'Z'),
+        (0x10, 'A'),
+        (0x04, 'P'),
+        (0x01, 'C'),
+    ]
+    .iter()
+    .filter(|&&(bit, _)| flags & bit != 0)
+    .map(|&(_, letter)| letter)
+    .collect();
+
+    let unused = flags & 0x2A;
+    match (letters.is_empty(), unused == 0x02) {
+        (true, true) => "-".to_string(),
+        (false, true) => letters,
+        // bits 1, 3 and 5 are always 1, 0 and 0
+        (_, false) => format!("{} (raw {:02X})", letters, flags),
+    }
+}
+
+// Runs a case, returns what came out different from what it says, one line each.
+fn run(case: &Case) -> Result<Vec<String>, String> {
+    let mut before = Machine {
+        cpu: State8080::default().cpu_state(),
+        memory: vec![0; 0x10000],
+        inputs: HashMap::new(),
+        outputs: Vec::new(),
+    };
+    before.cpu.pc = START;
+    before.apply(&case.before)?;
+    for (i, byte) in case.code.iter().enumerate() {
+        before.memory[before.cpu.pc.wrapping_add(i as u16) as usize] = *byte;
+    }
+
+    let mut expected = before.clone();
+    expected.cpu.pc = before.cpu.pc.wrapping_add(case.code.len() as u16);
+    expected.apply(&case.after)?;
+
+    let ports = Rc::new(RefCell::new(TestPorts {
+        inputs: before.inputs.clone(),
+        outputs: Vec::new(),
+    }));
+    let mut state = State8080::default();
+    state.attach_ports(ports.clone());
+    for (address, byte) in before.memory.iter().enumerate() {
+        if *byte != 0 {
+            state.memory_mut().poke(address as u16, *byte);
+        }
+    }
+    state.set_cpu_state(&before.cpu);
+
+    let cycles = state.emulate_cycle();
+    let cpu = state.cpu_state();
+
+    let mut differences = Vec::new();
+    let mut differ = |what: &str, actual: String, expected: String| {
+        if actual != expected {
+            differences.push(format!("{} is {}, expected {}", what, actual, expected));
+        }
+    };
+
+    let registers = [
+        ("A", cpu.a, expected.cpu.a),
+        ("B", cpu.b, expected.cpu.b),
+        ("C", cpu.c, expected.cpu.c),
+        ("D", cpu.d, expected.cpu.d),
+        ("E", cpu.e, expected.cpu.e),
+        ("H", cpu.h, expected.cpu.h),
+        ("L", cpu.l, expected.cpu.l),
+    ];
+    for (name, actual, expected) in registers {
+        differ(name, format!("{:02X}", actual), format!("{:02X}", expected));
+    }
+    differ(
+        "SP",
+        format!("{:04X}", cpu.sp),
+        format!("{:04X}", expected.cpu.sp),
+    );
+    differ(
+        "PC",
+        format!("{:04X}", cpu.pc),
+        format!("{:04X}", expected.cpu.pc),
+    );
+    differ(
+        "F",
+        flag_letters(cpu.flags),
+        flag_letters(expected.cpu.flags),
+    );
+    differ(
+        "INTE",
+        cpu.int_enable.to_string(),
+        expected.cpu.int_enable.to_string(),
+    );
+    differ(
+        "HALT",
+        cpu.halted.to_string(),
+        expected.cpu.halted.to_string(),
+    );
+    differ("states", cycles.to_string(), case.cycles.to_string());
+    differ(
+        "OUT",
+        format!("{:02X?}", ports.borrow().outputs),
+        format!("{:02X?}", expected.outputs),
+    );
+
+    for (address, expected) in expected.memory.iter().enumerate() {
+        let actual = state.memory().peek(address as u16);
+        if actual != *expected {
+            differ(
+                &format!("[{:04X}]", address),
+                format!("{:02X}", actual),
+                format!("{:02X}", expected),
+            );
+        }
+    }
+
+    Ok(differences)
+}
+
+#[test]
+fn every_opcode() {
+    let mut failures = Vec::new();
+
+    for case in cases() {
+        match run(&case) {
+            Ok(differences) if differences.is_empty() => (),
+            Ok(differences) => failures.push(format!(
+                "{} ({:02X?}, before: {}):\n    {}",
+                case.name,
+                case.code,
+                case.before,
+                differences.join("\n    ")
+            )),
+            Err(e) => failures.push(format!("{}: bad case: {}", case.name, e)),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} case(s) failed:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn every_opcode_has_a_case() {
+    let cases = cases();
+    let missing: Vec<String> = (0..=255u8)
+        .filter(|opcode| !cases.iter().any(|case| case.code[0] == *opcode))
+        .map(|opcode| format!("{:02X}", opcode))
+        .collect();
+
+    assert!(missing.is_empty(), "no cases for {}", missing.join(" "));
+}
+
+#[test]
+fn strict_mode_stops_at_undocumented_opcodes() {
+    let mut state = State8080::default();
+    state.set_strict(true);
+    state.pc = START;
+    state.memory_mut().poke(START, 0x00); // NOP
+    state.memory_mut().poke(START + 1, 0xDD); // *CALL
+    state.sp = 0xF000;
+
+    assert_eq!(state.emulate_cycle(), 4);
+    assert_eq!(state.take_undocumented_opcode(), None);
+
+    // stopped in front of it, nothing executed
+    assert_eq!(state.run_cycles(1000), 0);
+    let opcode = state
+        .take_undocumented_opcode()
+        .expect("the *CALL is trapped");
+    assert_eq!((opcode.pc, opcode.opcode), (START + 1, 0xDD));
+    assert_eq!(opcode.to_string(), "undocumented opcode DD at 0101");
+    assert_eq!((state.pc, state.sp), (START + 1, 0xF000));
+
+    state.set_strict(false);
+    assert_eq!(state.emulate_cycle(), 17);
+    assert_eq!((state.pc, state.sp), (0x0000, 0xEFFE));
+}
@@ -0,0 +1,183 @@
+// Going backwards: every step back lands exactly where the CPU was before that instruction, and the
+// journal hands old history over to snapshots once it's full. BDOS calls can't be undone, so
+// history starts over after them.
+use std::cell::RefCell;
+use std::env;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use intel_8080_emu::cpm::bdos::{Bdos, TPA_START};
+use intel_8080_emu::cpm::console::Console;
+use intel_8080_emu::memory::MEMORY_SIZE;
+use intel_8080_emu::rewind::Rewind;
+use intel_8080_emu::state::{CpuState, State8080};
+
+//         LXI  SP,2000H
+//         LXI  H,1000H
+//         MVI  B,40
+// LOOP:   MOV  M,B
+//         INX  H
+//         ADD  B
+//         PUSH PSW
+//         DCR  B
+//         JNZ  LOOP
+//         HLT
+const PROGRAM: [u8; 16] = [
+    0x31, 0x00, 0x20, 0x21, 0x00, 0x10, 0x06, 0x28, 0x70, 0x23, 0x80, 0xF5, 0x05, 0xC2, 0x08, 0x00,
+];
+
+fn machine(rewind: Rewind) -> State8080 {
+    let mut state = State8080::default();
+    for (address, byte) in PROGRAM.iter().enumerate() {
+        state.memory_mut().poke(address as u16, *byte);
+    }
+    state.memory_mut().poke(PROGRAM.len() as u16, 0x76);
+    state.enable_rewind(rewind);
+    state
+}
+
+fn snapshot(state: &State8080) -> (CpuState, Vec<u8>) {
+    let memory = (0..MEMORY_SIZE)
+        .map(|address| state.memory().peek(address as u16))
+        .collect();
+    (state.cpu_state(), memory)
+}
+
+// runs `count` instructions, returning the machine as it was before each of them
+fn run(state: &mut State8080, count: usize) -> Vec<(CpuState, Vec<u8>)> {
+    let mut history = Vec::new();
+    for _ in 0..count {
+        history.push(snapshot(state));
+        state.emulate_cycle();
+    }
+    history
+}
+
+#[test]
+fn stepping_back_undoes_each_instruction() {
+    let mut state = machine(Rewind::default());
+    let history = run(&mut state, 100);
+    assert_eq!(state.rewind().unwrap().len(), 100);
+
+    for (i, before) in history.iter().enumerate().rev() {
+        assert!(state.step_back(), "instruction {}", i);
+        assert!(snapshot(&state) == *before, "instruction {}", i);
+    }
+    assert!(!state.step_back());
+    assert_eq!(state.cycles(), 0);
+}
+
+#[test]
+fn rewinding_cycles_goes_back_to_the_start() {
+    let mut state = machine(Rewind::default());
+    let start = snapshot(&state);
+    run(&mut state, 100);
+
+    let cycles = state.cycles();
+    assert_eq!(state.rewind_cycles(cycles), cycles);
+    assert!(snapshot(&state) == start);
+}
+
+#[test]
+fn full_journals_fall_back_to_snapshots() {
+    // 10 instructions one by one, then snapshots of 4, only the 2 newest kept
+    let mut state = machine(Rewind::new(10, 4, 2));
+    let history = run(&mut state, 30);
+
+    let rewind = state.rewind().unwrap();
+    assert_eq!(rewind.journal_len(), 10);
+    assert_eq!(rewind.len(), 18);
+    assert_eq!(rewind.oldest_cycle(), Some(history[12].0.cycles));
+
+    for i in (20..30).rev() {
+        assert!(state.step_back());
+        assert!(snapshot(&state) == history[i], "instruction {}", i);
+    }
+    assert_eq!(state.rewind().unwrap().journal_len(), 0);
+
+    // instructions 16 to 19, then 12 to 15, the older ones are gone
+    assert!(state.step_back());
+    assert!(snapshot(&state) == history[16]);
+    assert!(state.step_back());
+    assert!(snapshot(&state) == history[12]);
+    assert!(!state.step_back());
+    assert!(state.rewind().unwrap().is_empty());
+}
+
+#[test]
+fn journals_without_snapshots_just_end() {
+    let mut state = machine(Rewind::new(10, 4, 0));
+    let history = run(&mut state, 30);
+    assert_eq!(state.rewind().unwrap().len(), 10);
+
+    for _ in 0..10 {
+        assert!(state.step_back());
+    }
+    assert!(snapshot(&state) == history[20]);
+    assert!(!state.step_back());
+}
+
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn going_back_stops_after_a_bdos_call() {
+    //         MVI  A,7
+    //         MVI  C,2
+    //         MVI  E,'A'
+    //         CALL 5
+    //         MVI  A,1
+    //         MVI  A,2
+    //         HLT
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x07, 0x0E, 0x02, 0x1E, 0x41, 0xCD, 0x05, 0x00, 0x3E, 0x01, 0x3E, 0x02, 0x76,
+    ];
+    let output = Shared::default();
+    let console = Console::new(Box::new(io::empty()), Box::new(output.clone()));
+    let mut bdos = Bdos::with_console(env::temp_dir(), console);
+
+    let mut state = State8080::default();
+    bdos.install(&mut state, "");
+    for (i, byte) in program.iter().enumerate() {
+        state.memory_mut().poke(TPA_START + i as u16, *byte);
+    }
+    state.pc = TPA_START;
+    state.sp = 0xF000;
+    state.enable_rewind(Rewind::default());
+
+    let mut run = |state: &mut State8080| {
+        while !state.halted() {
+            assert!(bdos.trap(state).unwrap());
+            state.emulate_cycle();
+        }
+    };
+    run(&mut state);
+    assert_eq!(*output.0.borrow(), b"A");
+
+    // back to the instruction after the CALL, not into the BDOS and not before the call
+    let mut steps = 0;
+    while state.step_back() {
+        steps += 1;
+    }
+    assert_eq!(steps, 3);
+    assert_eq!(state.pc, 0x0109);
+    assert_eq!(state.sp, 0xF000);
+    assert_eq!(state.rewind().unwrap().oldest_cycle(), None);
+
+    // so running forward again doesn't print a second time
+    run(&mut state);
+    assert_eq!(state.a, 2);
+    assert_eq!(*output.0.borrow(), b"A");
+    assert_eq!(state.rewind().unwrap().len(), 3);
+}
@@ -0,0 +1,245 @@
+// Save states: a machine survives the round trip through the file format, and readers handle
+// versions and sections they don't know the way the format promises.
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use intel_8080_emu::memory::{MappedMemory, MemoryDevice};
+use intel_8080_emu::ports::PortBus;
+use intel_8080_emu::savestate::{SaveState, SaveStateError, FORMAT_VERSION};
+use intel_8080_emu::state::State8080;
+
+// remembers the last byte written and counts the writes, peeks show the byte and pokes are counted
+#[derive(Default)]
+struct Register {
+    value: u8,
+    writes: u8,
+    pokes: usize,
+}
+
+impl MemoryDevice for Register {
+    fn read(&mut self, _offset: u16) -> u8 {
+        self.value
+    }
+
+    fn write(&mut self, _offset: u16, value: u8) {
+        self.value = value;
+        self.writes += 1;
+    }
+
+    fn peek(&self, _offset: u16) -> u8 {
+        self.value
+    }
+
+    fn poke(&mut self, _offset: u16, value: u8) {
+        self.value = value;
+        self.pokes += 1;
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.value, self.writes]
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        self.value = data[0];
+        self.writes = data[1];
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Latch(u8);
+
+impl PortBus for Latch {
+    fn input(&mut self, _port: u8) -> u8 {
+        self.0
+    }
+
+    fn output(&mut self, _port: u8, value: u8) {
+        self.0 = value;
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.0]
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        self.0 = data[0];
+        Ok(())
+    }
+}
+
+fn machine() -> (State8080, Rc<RefCell<Register>>, Rc<RefCell<Latch>>) {
+    let register = Rc::new(RefCell::new(Register::default()));
+    let latch = Rc::new(RefCell::new(Latch::default()));
+
+    let mut memory = MappedMemory::new();
+    memory.map_ram(0x0000, 0x7FFF);
+    memory.map_rom(0x8000, vec![1, 2, 3, 4]);
+    memory.map_device(0x9000, 0x9000, register.clone());
+
+    let mut state = State8080::default();
+    state.attach_memory(memory);
+    state.attach_ports(latch.clone());
+    (state, register, latch)
+}
+
+fn to_bytes(save: &SaveState) -> Vec<u8> {
+    let mut data = Vec::new();
+    save.write_to(&mut data).unwrap();
+    data
+}
+
+fn from_bytes(data: &[u8]) -> Result<SaveState, SaveStateError> {
+    SaveState::read_from(&mut &data[..])
+}
+
+// a valid save state with an extra section right after the header
+fn with_section(tag: &[u8; 4]) -> Vec<u8> {
+    let (state, _, _) = machine();
+    let mut data = to_bytes(&SaveState::capture(&state));
+    let mut section = tag.to_vec();
+    section.extend_from_slice(&3u32.to_le_bytes());
+    section.extend_from_slice(&[1, 2, 3]);
+    data.splice(12..12, section);
+    data
+}
+
+#[test]
+fn round_trip() {
+    let (mut state, register, latch) = machine();
+    // MVI A,42H; OUT 1; STA 9000H; LXI SP,7000H; PUSH PSW
+    for (address, byte) in [
+        0x3E, 0x42, 0xD3, 0x01, 0x32, 0x00, 0x90, 0x31, 0x00, 0x70, 0xF5,
+    ]
+    .iter()
+    .enumerate()
+    {
+        state.memory_mut().poke(address as u16, *byte);
+    }
+    for _ in 0..5 {
+        state.emulate_cycle();
+    }
+    state.memory_mut().poke(0x8001, 0xEE);
+
+    let saved = SaveState::capture(&state);
+    assert_eq!(saved.ports, [0x42]);
+    assert_eq!(saved.devices, [2, 0, 0, 0, 0x42, 1]);
+    let data = to_bytes(&saved);
+    // format 1, readable by format 1 readers
+    assert_eq!(data[8..12], [1, 0, 1, 0]);
+    let loaded = from_bytes(&data).unwrap();
+    assert_eq!(loaded, saved);
+
+    // scramble everything and put it back
+    let cpu = state.cpu_state();
+    state.memory_mut().write(0x9000, 0x00);
+    state.memory_mut().poke(0x6FFE, 0x00);
+    state.memory_mut().poke(0x8001, 0x02);
+    state.ports_mut().output(1, 0x00);
+    state.a = 0;
+    state.sp = 0;
+    state.pc = 0x1234;
+
+    loaded.restore(&mut state).unwrap();
+    assert_eq!(state.cpu_state(), cpu);
+    assert_eq!(state.memory().peek(0x6FFF), 0x42);
+    assert_eq!(state.memory().peek(0x8001), 0xEE);
+    assert_eq!(
+        (register.borrow().value, register.borrow().writes),
+        (0x42, 1)
+    );
+    assert_eq!(latch.borrow().0, 0x42);
+
+    // the device got its state back without being poked through the memory section
+    assert_eq!(register.borrow().pokes, 0);
+}
+
+#[test]
+fn device_state_needs_the_devices() {
+    let (state, _, _) = machine();
+    let saved = SaveState::capture(&state);
+    assert!(!saved.devices.is_empty());
+
+    let latch = Rc::new(RefCell::new(Latch(0x55)));
+    let mut bare = State8080::default();
+    bare.attach_ports(latch.clone());
+    bare.memory_mut().poke(0x0000, 0x76);
+    bare.pc = 0x1234;
+    match saved.restore(&mut bare) {
+        Err(SaveStateError::Corrupt(reason)) => {
+            assert!(reason.starts_with("memory-mapped devices"), "{}", reason)
+        }
+        other => panic!("{:?}", other),
+    }
+
+    // the port state was fine, but nothing is restored from a state that doesn't fit
+    assert_eq!(latch.borrow().0, 0x55);
+    assert_eq!(bare.memory().peek(0x0000), 0x76);
+    assert_eq!(bare.pc, 0x1234);
+}
+
+#[test]
+fn bad_device_state_leaves_every_device_alone() {
+    let (mut state, register, latch) = machine();
+    register.borrow_mut().value = 0x11;
+    latch.borrow_mut().0 = 0x22;
+
+    // one device's worth of state is missing a byte
+    let mut saved = SaveState::capture(&state);
+    saved.ports = vec![0x33];
+    saved.devices = vec![2, 0, 0, 0, 0x44];
+    assert!(saved.restore(&mut state).is_err());
+    assert_eq!(register.borrow().value, 0x11);
+    assert_eq!(latch.borrow().0, 0x22);
+
+    // and one too many
+    saved.devices = vec![2, 0, 0, 0, 0x44, 1, 0, 0, 0, 0];
+    assert!(saved.restore(&mut state).is_err());
+    assert_eq!(register.borrow().value, 0x11);
+    assert_eq!(latch.borrow().0, 0x22);
+
+    saved.devices = vec![2, 0, 0, 0, 0x44, 1];
+    saved.restore(&mut state).unwrap();
+    assert_eq!(register.borrow().value, 0x44);
+    assert_eq!(latch.borrow().0, 0x33);
+}
+
+#[test]
+fn too_new_versions_are_refused() {
+    let (state, _, _) = machine();
+    let mut data = to_bytes(&SaveState::capture(&state));
+
+    // a newer writer that older readers can still handle
+    data[8..10].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    assert!(from_bytes(&data).is_ok());
+
+    data[10..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    match from_bytes(&data) {
+        Err(SaveStateError::TooNew {
+            version,
+            compatible,
+        }) => assert_eq!(
+            (version, compatible),
+            (FORMAT_VERSION + 1, FORMAT_VERSION + 1)
+        ),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn unknown_uppercase_sections_are_refused() {
+    match from_bytes(&with_section(b"XTRA")) {
+        Err(SaveStateError::UnknownSection(tag)) => assert_eq!(tag, "XTRA"),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn unknown_lowercase_sections_are_skipped() {
+    let (state, _, _) = machine();
+    assert_eq!(
+        from_bytes(&with_section(b"xtra")).unwrap(),
+        SaveState::capture(&state)
+    );
+}
@@ -0,0 +1,213 @@
+// Single-step test vectors (see `single_step` for the format). The published sets are too big to
+// keep here, point I8080_SINGLE_STEP_TESTS at a directory of them (every .json file in it is run):
+//
+//     I8080_SINGLE_STEP_TESTS=<dir> cargo test --release --test single_step
+//
+// Without it only the handful of vectors below are run.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use intel_8080_emu::json::Json;
+use intel_8080_emu::single_step::{self, RunOptions, Runner, TestVector};
+
+const VECTOR_DIR_VARIABLE: &str = "I8080_SINGLE_STEP_TESTS";
+
+// failures shown per file, the rest are only counted
+const FAILURES_SHOWN: usize = 5;
+
+const VECTORS: &str = r#"[
+    {
+        "name": "3e 42 (MVI A)",
+        "initial": {
+            "pc": 256, "sp": 61440, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 2, "h": 0, "l": 0,
+            "ram": [[256, 62], [257, 66]]
+        },
+        "final": {
+            "pc": 258, "sp": 61440, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0, "f": 2, "h": 0, "l": 0,
+            "ram": [[256, 62], [257, 66]]
+        },
+        "cycles": [[256, 62, "r"], [null, null, ""], [null, null, ""], [null, null, ""],
+                   [257, 66, "r"], [null, null, ""], [null, null, ""]]
+    },
+    {
+        "name": "c5 (PUSH B)",
+        "initial": {
+            "pc": 4096, "sp": 1, "a": 0, "b": 18, "c": 52, "d": 0, "e": 0, "f": 2, "h": 0, "l": 0,
+            "ram": [[4096, 197], [65535, 0], [0, 0]]
+        },
+        "final": {
+            "pc": 4097, "sp": 65535, "a": 0, "b": 18, "c": 52, "d": 0, "e": 0, "f": 2, "h": 0, "l": 0,
+            "ram": [[4096, 197], [65535, 52], [0, 18]]
+        }
+    },
+    {
+        "name": "db 10 (IN)",
+        "initial": {
+            "pc": 256, "sp": 0, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 215, "h": 0, "l": 0,
+            "inte": 1, "ram": [[256, 219], [257, 16]]
+        },
+        "final": {
+            "pc": 258, "sp": 0, "a": 90, "b": 0, "c": 0, "d": 0, "e": 0, "f": 215, "h": 0, "l": 0,
+            "inte": 1, "ram": [[256, 219], [257, 16]]
+        },
+        "ports": [[16, 90, "r"]]
+    }
+]"#;
+
+fn vectors() -> Vec<TestVector> {
+    let json = Json::parse(VECTORS).unwrap();
+    json.as_array()
+        .unwrap()
+        .iter()
+        .map(|test| TestVector::from_json(test).unwrap())
+        .collect()
+}
+
+#[test]
+fn passing_vectors() {
+    let failures = Runner::new(RunOptions::default()).run_all(&vectors());
+
+    let report: Vec<String> = failures.iter().map(|failure| failure.to_string()).collect();
+    assert!(failures.is_empty(), "{}", report.join(""));
+}
+
+#[test]
+fn mismatches_are_reported_field_by_field() {
+    let mut test = vectors().remove(1);
+    test.expected.sp = 0xFFFE;
+    test.expected.f = 0x03;
+    test.expected.ram[1] = (0xFFFF, 0x00);
+    test.expected.ram.remove(2);
+    test.cycles = Some(10);
+
+    let mismatches: Vec<String> = Runner::new(RunOptions::default())
+        .run(&test)
+        .iter()
+        .map(|mismatch| mismatch.to_string())
+        .collect();
+    assert_eq!(
+        mismatches,
+        [
+            "F: expected 03 (----C), got 02 (-----)",
+            "SP: expected FFFE, got FFFF",
+            "states: expected 10, got 11",
+            "[FFFF]: expected 00, got 34",
+            "[0000]: expected no write, got 12 written",
+        ]
+    );
+
+    // bus cycles can be left out of the comparison
+    let mismatches = Runner::new(RunOptions { cycles: false }).run(&test);
+    assert!(mismatches.iter().all(|mismatch| mismatch.field != "states"));
+}
+
+#[test]
+fn port_accesses_are_compared() {
+    let mut test = vectors().remove(2);
+    test.ports = Some(Vec::new());
+
+    let mismatches = Runner::new(RunOptions::default()).run(&test);
+    assert_eq!(mismatches.len(), 2, "{:?}", mismatches);
+    assert_eq!(mismatches[0].to_string(), "A: expected 5A, got FF");
+    assert_eq!(
+        mismatches[1].to_string(),
+        "ports: expected none, got IN 10=FF"
+    );
+}
+
+#[test]
+fn bad_vectors_are_rejected() {
+    let json = Json::parse(r#"{"name": "x", "initial": {"a": 256}, "final": {}}"#).unwrap();
+    let e = TestVector::from_json(&json).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        r#"test "x": bad or missing "a" in "initial""#
+    );
+
+    let e = Json::parse("[1, 2,\n  3 4]").unwrap_err();
+    assert_eq!(e.to_string(), "line 2, column 5: expected ',' or ']'");
+}
+
+#[test]
+fn json() {
+    let json =
+        Json::parse(r#" {"a": [true, false, null], "b": "\u00e9\ud83d\ude00\n", "c": -1.5e2} "#)
+            .unwrap();
+    assert_eq!(
+        json.get("a"),
+        Some(&Json::Array(vec![
+            Json::Bool(true),
+            Json::Bool(false),
+            Json::Null
+        ]))
+    );
+    assert_eq!(
+        json.get("b").and_then(Json::as_str),
+        Some("\u{e9}\u{1F600}\n")
+    );
+    assert_eq!(json.get("c"), Some(&Json::Number(-150.0)));
+    assert_eq!(json.get("c").and_then(Json::as_u64), None);
+
+    for bad in [
+        "",
+        "[1,]",
+        "{\"a\" 1}",
+        "01",
+        "\"\\x\"",
+        "tru",
+        "[1] 2",
+        "\"\\ud83d\"",
+    ] {
+        assert!(Json::parse(bad).is_err(), "{:?} parsed", bad);
+    }
+}
+
+#[test]
+fn vector_directory() {
+    let dir = match env::var_os(VECTOR_DIR_VARIABLE) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!(
+                "{} isn't set, skipping the vector files",
+                VECTOR_DIR_VARIABLE
+            );
+            return;
+        }
+    };
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no .json files in {}", dir.display());
+
+    let mut runner = Runner::new(RunOptions::default());
+    let mut report = String::new();
+    let mut failed = 0;
+    for file in &files {
+        let tests = single_step::load(file).unwrap();
+        let failures = runner.run_all(&tests);
+        if failures.is_empty() {
+            continue;
+        }
+
+        failed += failures.len();
+        report += &format!(
+            "{}: {} of {} failed\n",
+            file.display(),
+            failures.len(),
+            tests.len()
+        );
+        for failure in failures.iter().take(FAILURES_SHOWN) {
+            report += &failure.to_string();
+        }
+    }
+
+    assert!(failed == 0, "{} vector(s) failed\n{}", failed, report);
+}
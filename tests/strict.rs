@@ -0,0 +1,71 @@
+// Strict mode: an undocumented opcode stops the CPU in front of it, and every way of running a
+// program stops there too instead of spinning on it.
+use std::env;
+use std::io;
+
+use intel_8080_emu::cpm::bdos::Bdos;
+use intel_8080_emu::cpm::console::Console;
+use intel_8080_emu::invaders::{DipSwitches, Invaders, ROM_SIZE};
+use intel_8080_emu::state::{State8080, UndocumentedOpcode};
+
+// NOP; NOP; then 08, an undocumented NOP
+fn machine(strict: bool) -> State8080 {
+    let mut state = State8080::default();
+    state.memory_mut().poke(0x0002, 0x08);
+    state.set_strict(strict);
+    state
+}
+
+#[test]
+fn stops_in_front_of_undocumented_opcodes() {
+    let mut state = machine(true);
+    assert_eq!(state.run_cycles(1000), 8);
+    assert_eq!(state.pc, 0x0002);
+    assert_eq!(
+        state.take_undocumented_opcode(),
+        Some(UndocumentedOpcode {
+            pc: 0x0002,
+            opcode: 0x08
+        })
+    );
+
+    // it doesn't get any further until PC moves
+    assert_eq!(state.emulate_cycle(), 0);
+    assert_eq!(state.run_cycles(1000), 0);
+    assert_eq!((state.pc, state.cycles()), (0x0002, 8));
+    assert!(state.take_undocumented_opcode().is_some());
+    assert_eq!(state.take_undocumented_opcode(), None);
+
+    // without strict mode it's just a NOP
+    let mut state = machine(false);
+    assert_eq!(state.run_cycles(12), 12);
+    assert_eq!(state.pc, 0x0003);
+    assert_eq!(state.take_undocumented_opcode(), None);
+}
+
+#[test]
+fn bdos_programs_stop() {
+    let mut state = machine(true);
+    let console = Console::new(Box::new(io::empty()), Box::new(io::sink()));
+    let mut bdos = Bdos::with_console(env::temp_dir(), console);
+    bdos.install(&mut state, "");
+    state.memory_mut().poke(0x0102, 0x08);
+
+    let error = bdos.run(&mut state).unwrap_err();
+    assert_eq!(error.to_string(), "undocumented opcode 08 at 0102");
+    assert_eq!(state.pc, 0x0102);
+}
+
+#[test]
+fn invaders_frames_stop() {
+    // the ROM is all NOPs up to an undocumented one
+    let mut rom = vec![0x00; ROM_SIZE];
+    rom[0x1000] = 0x08;
+    let mut machine = Invaders::new(rom, DipSwitches::default());
+    machine.state.set_strict(true);
+
+    let error = machine.run_frame().unwrap_err();
+    assert_eq!(error.to_string(), "undocumented opcode 08 at 1000");
+    assert_eq!(machine.frame(), 0);
+    assert_eq!(machine.state.pc, 0x1000);
+}
@@ -0,0 +1,190 @@
+// Symbols from other assemblers: symbol files in their usual layouts, listings split into address,
+// bytes and source, and addresses described as `NAME+offset`.
+use intel_8080_emu::symbols::{Listing, ListingLine, SymbolTable};
+
+fn line(address: u16, bytes: &[u8], source: &str) -> ListingLine {
+    ListingLine {
+        address: Some(address),
+        bytes: bytes.to_vec(),
+        source: source.to_string(),
+    }
+}
+
+#[test]
+fn symbol_file_layouts() {
+    let text = "\
+0100 START     0103 LOOP
+0005 BDOS
+
+MSG 0120
+COUNT EQU 0130H   ; a comment
+limit = $0140
+PTR: 0x0150
+0160 ADD
+";
+    let symbols = SymbolTable::parse(text).unwrap();
+
+    assert_eq!(symbols.len(), 8);
+    assert_eq!(symbols.lookup("START"), Some(0x0100));
+    assert_eq!(symbols.lookup("LOOP"), Some(0x0103));
+    assert_eq!(symbols.lookup("BDOS"), Some(0x0005));
+    assert_eq!(symbols.lookup("MSG"), Some(0x0120));
+    assert_eq!(symbols.lookup("COUNT"), Some(0x0130));
+    assert_eq!(symbols.lookup("PTR"), Some(0x0150));
+    // `ADD` is hex too, but the address comes first
+    assert_eq!(symbols.lookup("ADD"), Some(0x0160));
+
+    // lookups ignore case, names keep theirs
+    assert_eq!(symbols.lookup("Limit"), Some(0x0140));
+    assert_eq!(symbols.name_at(0x0140), Some("limit"));
+
+    let addresses: Vec<u16> = symbols.iter().map(|(address, _)| address).collect();
+    assert_eq!(
+        addresses,
+        [0x0005, 0x0100, 0x0103, 0x0120, 0x0130, 0x0140, 0x0150, 0x0160]
+    );
+}
+
+#[test]
+fn bad_symbol_files() {
+    for text in [
+        "0100 START\n0103\n",
+        "START LOOP\n",
+        "0100 0103\n",
+        "START 10000\n",
+    ]
+    .iter()
+    {
+        let error = SymbolTable::parse(text).unwrap_err();
+        assert!(
+            error.to_string().starts_with("symbol file line"),
+            "{:?}",
+            text
+        );
+    }
+
+    let error = SymbolTable::parse("0100 START\n0103\n").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "symbol file line 2: expected address and name"
+    );
+}
+
+#[test]
+fn the_first_name_for_an_address_is_the_one_shown() {
+    let symbols = SymbolTable::parse("0100 START\n0100 ENTRY\n").unwrap();
+    assert_eq!(symbols.name_at(0x0100), Some("START"));
+    assert_eq!(symbols.lookup("ENTRY"), Some(0x0100));
+
+    // and .sym files come back the way they went out
+    assert_eq!(symbols.to_sym(), "0100 ENTRY\n0100 START\n");
+    let again = SymbolTable::parse(&symbols.to_sym()).unwrap();
+    assert_eq!(again.lookup("START"), Some(0x0100));
+    assert_eq!(again.lookup("ENTRY"), Some(0x0100));
+}
+
+#[test]
+fn describe() {
+    let mut symbols = SymbolTable::new();
+    symbols.insert("START", 0x0100);
+    symbols.insert("MSG", 0x0180);
+    symbols.insert("TOP", 0xFFF0);
+
+    assert_eq!(symbols.describe(0x0100).as_deref(), Some("START"));
+    assert_eq!(symbols.describe(0x0101).as_deref(), Some("START+1"));
+    // the closest symbol below wins
+    assert_eq!(symbols.describe(0x017F).as_deref(), Some("START+127"));
+    assert_eq!(symbols.describe(0x0180).as_deref(), Some("MSG"));
+    assert_eq!(symbols.describe(0x027F).as_deref(), Some("MSG+255"));
+    // too far past anything
+    assert_eq!(symbols.describe(0x0280), None);
+    // nothing below
+    assert_eq!(symbols.describe(0x00FF), None);
+    assert_eq!(symbols.describe(0xFFFF).as_deref(), Some("TOP+15"));
+
+    assert_eq!(SymbolTable::new().describe(0x0100), None);
+}
+
+#[test]
+fn listing_with_bytes_in_groups() {
+    let text = "\
+                  ; prints a message
+0005 =            BDOS    EQU   5
+0100 11 0C 01     START:  LXI   D,MSG
+0103 0E 09                MVI   C,9
+0105 CD 05 00             CALL  BDOS
+0108 C9                   RET
+0109              DEAD:
+0109 00 00 00     BEEF    DB    0,0,0
+010C 48 49 24     MSG:    DB    'HI$'
+010F              CAFE    DS    4
+0113                      END
+";
+    let listing = Listing::parse(text);
+
+    assert_eq!(listing.lines[0].address, None);
+    assert_eq!(listing.lines[0].source, text.lines().next().unwrap());
+    assert_eq!(
+        listing.lines[1..],
+        [
+            line(0x0005, &[], "BDOS    EQU   5"),
+            line(0x0100, &[0x11, 0x0C, 0x01], "START:  LXI   D,MSG"),
+            line(0x0103, &[0x0E, 0x09], "MVI   C,9"),
+            line(0x0105, &[0xCD, 0x05, 0x00], "CALL  BDOS"),
+            line(0x0108, &[0xC9], "RET"),
+            line(0x0109, &[], "DEAD:"),
+            line(0x0109, &[0, 0, 0], "BEEF    DB    0,0,0"),
+            line(0x010C, &[0x48, 0x49, 0x24], "MSG:    DB    'HI$'"),
+            line(0x010F, &[], "CAFE    DS    4"),
+            line(0x0113, &[], "END"),
+        ]
+    );
+
+    assert_eq!(
+        listing.line_at(0x0109).unwrap().source,
+        "BEEF    DB    0,0,0"
+    );
+    assert_eq!(listing.line_at(0x010F), None);
+
+    let symbols = listing.symbols();
+    assert_eq!(symbols.lookup("BDOS"), Some(0x0005));
+    assert_eq!(symbols.lookup("START"), Some(0x0100));
+    assert_eq!(symbols.lookup("MSG"), Some(0x010C));
+    // labels that look like hex are labels all the same
+    assert_eq!(symbols.lookup("DEAD"), Some(0x0109));
+    assert_eq!(symbols.lookup("BEEF"), Some(0x0109));
+    assert_eq!(symbols.lookup("CAFE"), Some(0x010F));
+    assert_eq!(symbols.len(), 6);
+}
+
+#[test]
+fn listing_with_line_numbers() {
+    // line numbers in front of the address (any width) or in front of the source
+    let text = "\
+1 0100 3E01         START:  MVI   A,1
+2 0102 C30201       LOOP:   JMP   LOOP
+10 0105              BEEF
+11 0105 00000000     FACE:   DB    0,0,0,0
+0109 76           12+     HLT
+010A              13      ADD   B
+";
+    let listing = Listing::parse(text);
+
+    assert_eq!(
+        listing.lines[..4],
+        [
+            line(0x0100, &[0x3E, 0x01], "START:  MVI   A,1"),
+            line(0x0102, &[0xC3, 0x02, 0x01], "LOOP:   JMP   LOOP"),
+            line(0x0105, &[], "BEEF"),
+            line(0x0105, &[0, 0, 0, 0], "FACE:   DB    0,0,0,0"),
+        ]
+    );
+    assert_eq!(listing.lines[4], line(0x0109, &[0x76], "HLT"));
+    // not an instruction that assembled to anything, and `ADD` stays source
+    assert_eq!(listing.lines[5], line(0x010A, &[], "ADD   B"));
+
+    let symbols = listing.symbols();
+    assert_eq!(symbols.lookup("BEEF"), Some(0x0105));
+    assert_eq!(symbols.lookup("FACE"), Some(0x0105));
+    assert_eq!(symbols.lookup("LOOP"), Some(0x0102));
+}
@@ -0,0 +1,140 @@
+// Traces written by the CPU and read back by `TraceReader`, in all three formats.
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use intel_8080_emu::state::State8080;
+use intel_8080_emu::trace::{
+    TraceEntry, TraceFormat, TraceReader, Tracer, BINARY_MAGIC, BINARY_VERSION,
+};
+
+// a `Write` the test can still read after handing it to the tracer
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// EI; LXI SP,2000H; MVI A,12H; STA 1800H, then RST 2 from a device, then INR A at 0010
+fn trace(format: TraceFormat) -> Vec<u8> {
+    let mut state = State8080::default();
+    let program = [0xFB, 0x31, 0x00, 0x20, 0x3E, 0x12, 0x32, 0x00, 0x18];
+    for (address, byte) in program.iter().enumerate() {
+        state.memory_mut().poke(address as u16, *byte);
+    }
+    state.memory_mut().poke(0x0010, 0x3C);
+
+    let out = Shared::default();
+    state.set_tracer(Tracer::new(out.clone(), format).unwrap());
+    for _ in 0..4 {
+        state.emulate_cycle();
+    }
+    state.interrupt(0xD7);
+    state.emulate_cycle();
+    state.emulate_cycle();
+    state.take_tracer().unwrap().finish().unwrap();
+
+    let data = out.0.borrow().clone();
+    data
+}
+
+fn read(data: &[u8]) -> Vec<TraceEntry> {
+    TraceReader::new(data)
+        .unwrap()
+        .collect::<io::Result<_>>()
+        .unwrap()
+}
+
+fn expected() -> Vec<TraceEntry> {
+    let entry = |pc: u16, a: u8, sp: u16, cycles: u64, bytes: &[u8]| TraceEntry {
+        pc,
+        a,
+        flags: 0x02,
+        sp,
+        cycles,
+        bytes: bytes.to_vec(),
+        ..Default::default()
+    };
+
+    let mut store = entry(0x0006, 0x12, 0x2000, 21, &[0x32, 0x00, 0x18]);
+    store.writes = vec![(0x1800, 0x12)];
+    let mut restart = entry(0x0009, 0x12, 0x2000, 34, &[0xD7]);
+    restart.interrupt = true;
+    restart.writes = vec![(0x1FFF, 0x00), (0x1FFE, 0x09)];
+
+    vec![
+        entry(0x0000, 0x00, 0x0000, 0, &[0xFB]),
+        entry(0x0001, 0x00, 0x0000, 4, &[0x31, 0x00, 0x20]),
+        entry(0x0004, 0x00, 0x2000, 14, &[0x3E, 0x12]),
+        store,
+        restart,
+        entry(0x0010, 0x12, 0x1FFE, 45, &[0x3C]),
+    ]
+}
+
+#[test]
+fn binary_round_trip() {
+    let data = trace(TraceFormat::Binary);
+    assert!(data.starts_with(BINARY_MAGIC));
+
+    let reader = TraceReader::new(&data[..]).unwrap();
+    assert_eq!(reader.format(), TraceFormat::Binary);
+    assert!(reader.has_writes());
+    assert_eq!(read(&data), expected());
+}
+
+#[test]
+fn text_round_trip() {
+    let data = trace(TraceFormat::Text);
+    let text = String::from_utf8(data.clone()).unwrap();
+    assert!(text.contains("INT D7"), "{}", text);
+    assert!(text.contains("[1800]=12"), "{}", text);
+
+    let reader = TraceReader::new(&data[..]).unwrap();
+    assert_eq!(reader.format(), TraceFormat::Text);
+    assert!(reader.has_writes());
+    assert_eq!(read(&data), expected());
+}
+
+#[test]
+fn reference_round_trip() {
+    let data = trace(TraceFormat::Reference);
+    let reader = TraceReader::new(&data[..]).unwrap();
+    assert_eq!(reader.format(), TraceFormat::Reference);
+    assert!(!reader.has_writes());
+
+    // no writes and no interrupts, the bytes are whatever was at PC
+    let expected: Vec<TraceEntry> = expected()
+        .into_iter()
+        .map(|mut entry| {
+            entry.writes.clear();
+            if entry.interrupt {
+                entry.interrupt = false;
+                entry.bytes = vec![0x00];
+            }
+            entry
+        })
+        .collect();
+    assert_eq!(read(&data), expected);
+}
+
+#[test]
+fn newer_binary_versions_are_refused() {
+    let mut data = trace(TraceFormat::Binary);
+    data[8..10].copy_from_slice(&(BINARY_VERSION + 1).to_le_bytes());
+
+    match TraceReader::new(&data[..]) {
+        Err(e) => {
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            assert!(e.to_string().contains("too new"), "{}", e);
+        }
+        Ok(_) => panic!("version {} accepted", BINARY_VERSION + 1),
+    }
+}
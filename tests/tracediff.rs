@@ -0,0 +1,162 @@
+// `tracediff::diff` on small traces written out in the text and reference formats.
+use intel_8080_emu::symbols::SymbolTable;
+use intel_8080_emu::trace::{format_reference, format_text, TraceEntry, TraceReader};
+use intel_8080_emu::tracediff::{diff, Comparison, DiffOptions, Divergence};
+
+// `count` NOPs from 0100, A counting up, 4 cycles each
+fn entries(count: usize) -> Vec<TraceEntry> {
+    (0..count)
+        .map(|i| TraceEntry {
+            pc: 0x0100 + i as u16,
+            a: i as u8,
+            flags: 0x02,
+            cycles: 4 * i as u64,
+            bytes: vec![0x00],
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn text(entries: &[TraceEntry]) -> Vec<u8> {
+    let symbols = SymbolTable::new();
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|entry| format_text(entry, &symbols) + "\n")
+        .collect();
+    lines.concat().into_bytes()
+}
+
+fn reference(entries: &[TraceEntry]) -> Vec<u8> {
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|entry| format_reference(entry, &[entry.bytes[0], 0, 0, 0]) + "\n")
+        .collect();
+    lines.concat().into_bytes()
+}
+
+fn compare(a: &[u8], b: &[u8], options: &DiffOptions) -> Comparison {
+    let a = TraceReader::new(a).unwrap();
+    let b = TraceReader::new(b).unwrap();
+    diff(a, b, options).unwrap()
+}
+
+fn diverged(comparison: Comparison) -> Divergence {
+    match comparison {
+        Comparison::Diverged(divergence) => divergence,
+        Comparison::Same(count) => panic!("same for {} instructions", count),
+        Comparison::Truncated { count, .. } => panic!("truncated after {} instructions", count),
+    }
+}
+
+fn pcs(entries: &[TraceEntry]) -> Vec<u16> {
+    entries.iter().map(|entry| entry.pc).collect()
+}
+
+#[test]
+fn same() {
+    let trace = entries(3);
+    match compare(&text(&trace), &reference(&trace), &DiffOptions::default()) {
+        Comparison::Same(count) => assert_eq!(count, 3),
+        _ => panic!("not the same"),
+    }
+}
+
+#[test]
+fn truncated() {
+    let short = text(&entries(3));
+    let long = text(&entries(5));
+
+    for (a, b, a_ends) in [(&short, &long, true), (&long, &short, false)] {
+        match compare(a, b, &DiffOptions::default()) {
+            Comparison::Truncated { count, a_ended } => assert_eq!((count, a_ended), (3, a_ends)),
+            _ => panic!("not truncated"),
+        }
+    }
+}
+
+#[test]
+fn diverged_with_context() {
+    let options = DiffOptions {
+        context: 3,
+        ..Default::default()
+    };
+    let a = entries(10);
+
+    // near the start there's less before it
+    let mut b = a.clone();
+    b[1].a = 0x80;
+    let divergence = diverged(compare(&text(&a), &text(&b), &options));
+    assert_eq!(divergence.index, 1);
+    assert_eq!(divergence.differences, ["A: 01 vs 80"]);
+    assert_eq!(pcs(&divergence.before), [0x0100]);
+    assert_eq!(pcs(&divergence.after_a), [0x0101, 0x0102, 0x0103, 0x0104]);
+    assert_eq!(divergence.after_b[0].a, 0x80);
+    assert!(divergence
+        .to_string()
+        .starts_with("first difference at instruction 2, PC 0101:\n    A: 01 vs 80\n"));
+
+    // in the middle there's the full window
+    let mut b = a.clone();
+    b[5].flags = 0x03;
+    let divergence = diverged(compare(&text(&a), &text(&b), &options));
+    assert_eq!(divergence.differences, ["F: 02 (-----) vs 03 (----C)"]);
+    assert_eq!(pcs(&divergence.before), [0x0102, 0x0103, 0x0104]);
+    assert_eq!(pcs(&divergence.after_a), [0x0105, 0x0106, 0x0107, 0x0108]);
+
+    // near the end there's less after it, in the shorter trace even less
+    let mut b = a[..9].to_vec();
+    b[8].sp = 0xFFFF;
+    let divergence = diverged(compare(&text(&a), &text(&b), &options));
+    assert_eq!(divergence.index, 8);
+    assert_eq!(pcs(&divergence.before), [0x0105, 0x0106, 0x0107]);
+    assert_eq!(pcs(&divergence.after_a), [0x0108, 0x0109]);
+    assert_eq!(pcs(&divergence.after_b), [0x0108]);
+
+    // no context at all
+    let options = DiffOptions {
+        context: 0,
+        ..Default::default()
+    };
+    let divergence = diverged(compare(&text(&a), &text(&b), &options));
+    assert!(divergence.before.is_empty());
+    assert_eq!(pcs(&divergence.after_a), [0x0108]);
+}
+
+#[test]
+fn writes_only_count_when_both_traces_have_them() {
+    let a = entries(3);
+    let mut b = a.clone();
+    b[2].writes = vec![(0x2000, 0x12)];
+
+    let divergence = diverged(compare(&text(&a), &text(&b), &DiffOptions::default()));
+    assert_eq!(divergence.index, 2);
+    assert_eq!(divergence.differences, ["writes: none vs [2000]=12"]);
+
+    // the reference format has no writes to compare
+    match compare(&reference(&a), &text(&b), &DiffOptions::default()) {
+        Comparison::Same(count) => assert_eq!(count, 3),
+        _ => panic!("writes compared against a reference trace"),
+    }
+}
+
+#[test]
+fn cycles_only_count_on_request() {
+    let a = entries(3);
+    let mut b = a.clone();
+    for entry in &mut b {
+        entry.cycles *= 2;
+    }
+
+    match compare(&text(&a), &text(&b), &DiffOptions::default()) {
+        Comparison::Same(count) => assert_eq!(count, 3),
+        _ => panic!("cycles compared by default"),
+    }
+
+    let options = DiffOptions {
+        cycles: true,
+        ..Default::default()
+    };
+    let divergence = diverged(compare(&text(&a), &text(&b), &options));
+    assert_eq!(divergence.index, 1);
+    assert_eq!(divergence.differences, ["cycles: 4 vs 8"]);
+}
@@ -0,0 +1,127 @@
+// Watchpoints as the CPU runs into them: which accesses hit, what a hit records, and `run_cycles`
+// stopping on the instruction that caused it.
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use intel_8080_emu::state::State8080;
+use intel_8080_emu::watch::{WatchHit, WatchKind, Watchpoint};
+
+// MVI A,5; STA 2000H; STA 2000H; LDA 2000H; MVI A,0; STA 2001H; JMP 0000H
+const PROGRAM: [u8; 19] = [
+    0x3E, 0x05, 0x32, 0x00, 0x20, 0x32, 0x00, 0x20, 0x3A, 0x00, 0x20, 0x3E, 0x00, 0x32, 0x01, 0x20,
+    0xC3, 0x00, 0x00,
+];
+
+fn machine() -> State8080 {
+    let mut state = State8080::default();
+    for (address, byte) in PROGRAM.iter().enumerate() {
+        state.memory_mut().poke(address as u16, *byte);
+    }
+    state
+}
+
+// runs the program once around with `watchpoint` recording its hits instead of stopping
+fn hits(watchpoint: Watchpoint) -> Vec<WatchHit> {
+    let hits = Rc::new(RefCell::new(Vec::new()));
+    let recorded = hits.clone();
+
+    let mut state = machine();
+    state.add_watchpoint(watchpoint.on_hit(move |hit| recorded.borrow_mut().push(*hit)));
+    for _ in 0..7 {
+        state.emulate_cycle();
+    }
+    assert_eq!(state.pc, 0);
+    assert_eq!(state.take_watch_hit(), None);
+
+    let hits = hits.borrow().clone();
+    hits
+}
+
+fn hit(kind: WatchKind, pc: u16, address: u16, old: u8, new: u8) -> WatchHit {
+    WatchHit {
+        id: 1,
+        kind,
+        pc,
+        address,
+        old,
+        new,
+    }
+}
+
+#[test]
+fn reads() {
+    use WatchKind::Read;
+
+    assert_eq!(
+        hits(Watchpoint::new(0x2000, 0x2001, Read)),
+        [hit(Read, 0x0008, 0x2000, 0x05, 0x05)]
+    );
+
+    // instruction fetches are reads too, operands included
+    assert_eq!(
+        hits(Watchpoint::new(0x0001, 0x0003, Read)),
+        [
+            hit(Read, 0x0000, 0x0001, 0x05, 0x05),
+            hit(Read, 0x0002, 0x0002, 0x32, 0x32),
+            hit(Read, 0x0002, 0x0003, 0x00, 0x00),
+        ]
+    );
+}
+
+#[test]
+fn writes() {
+    use WatchKind::Write;
+
+    assert_eq!(
+        hits(Watchpoint::new(0x2000, 0x2001, Write)),
+        [
+            hit(Write, 0x0002, 0x2000, 0x00, 0x05),
+            hit(Write, 0x0005, 0x2000, 0x05, 0x05),
+            hit(Write, 0x000D, 0x2001, 0x00, 0x00),
+        ]
+    );
+
+    // conditions are on the value written
+    assert_eq!(
+        hits(Watchpoint::new(0x2000, 0x2001, Write).when(|value| value == 0)),
+        [hit(Write, 0x000D, 0x2001, 0x00, 0x00)]
+    );
+}
+
+#[test]
+fn changes() {
+    use WatchKind::Change;
+
+    // storing what's already there isn't a change, the second STA and the 0 to 2001 don't count
+    assert_eq!(
+        hits(Watchpoint::new(0x2000, 0x2001, Change)),
+        [hit(Change, 0x0002, 0x2000, 0x00, 0x05)]
+    );
+}
+
+#[test]
+fn run_cycles_stops_after_the_hit() {
+    let mut state = machine();
+    let id = state.add_watchpoint(Watchpoint::new(0x2000, 0x2000, WatchKind::Write));
+
+    // MVI A,5 (7 states) and the first STA (13)
+    assert_eq!(state.run_cycles(1000), 20);
+    assert_eq!(state.pc, 0x0005);
+    let hit = state.take_watch_hit().unwrap();
+    assert_eq!((hit.id, hit.pc, hit.new), (id, 0x0002, 0x05));
+    assert_eq!(
+        hit.to_string(),
+        "watchpoint 1: 0002 wrote 05 to 2000 (was 00)"
+    );
+
+    // taking the hit lets it run on, to the next one
+    assert_eq!(state.run_cycles(1000), 13);
+    assert_eq!(state.pc, 0x0008);
+    assert_eq!(state.take_watch_hit().unwrap().pc, 0x0005);
+
+    // watchpoints with a callback never stop it
+    state.remove_watchpoint(id);
+    state.add_watchpoint(Watchpoint::new(0x2000, 0x2000, WatchKind::Read).on_hit(|_| ()));
+    assert!(state.run_cycles(1000) >= 1000);
+    assert_eq!(state.take_watch_hit(), None);
+}